@@ -0,0 +1,41 @@
+//! With the `c-reference` feature, build the original C quirc from `quirc/` for `tests/differential.rs` to compare against.
+//!
+//! The crate exports the same `quirc_*` symbols for its C interface, so the C library's are prefixed with `c_`.
+//! Its region limit is raised to match `ops::QUIRC_MAX_REGIONS`, which also makes its pixels 16-bit, like `Quirc::pixels`.
+
+
+#[cfg(feature = "c-reference")]
+extern crate cc;
+
+
+#[cfg(feature = "c-reference")]
+static SYMBOLS: &[&str] = &["quirc_version",
+                            "quirc_new",
+                            "quirc_destroy",
+                            "quirc_resize",
+                            "quirc_begin",
+                            "quirc_end",
+                            "quirc_strerror",
+                            "quirc_count",
+                            "quirc_extract",
+                            "quirc_decode",
+                            "quirc_version_db"];
+
+
+fn main() {
+    #[cfg(feature = "c-reference")]
+    {
+        let mut build = cc::Build::new();
+        for file in &["quirc.c", "identify.c", "decode.c", "version_db.c"] {
+            build.file(format!("quirc/{}", file));
+        }
+        for symbol in SYMBOLS {
+            build.define(symbol, Some(&format!("c_{}", symbol)[..]));
+        }
+        build.define("QUIRC_MAX_REGIONS", "256").warnings(false).compile("quirc-c");
+
+        println!("cargo:rerun-if-changed=quirc");
+    }
+
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,10 @@
+//! `libquirc`, a shared library exporting the C interface in `quirc_impl::ffi`,
+//! for programs written against the original `quirc.h` (see the `c-examples` directory).
+//!
+//! It's a crate of its own so that `quirc_impl` itself stays buildable without `std`,
+//! which a `cdylib` can't be.
+
+
+extern crate quirc_impl;
+
+pub use quirc_impl::ffi::*;
@@ -0,0 +1,530 @@
+//! Scan images and report how many codes were found and decoded, and how long it took;
+//! a Rust port of `c-examples/qrtest.c`, reading Netpbm images instead of JPEGs and PNGs.
+//!
+//! Usage: `qrtest [-v] [-d] [-g] [-b BASELINE] [-w BASELINE] PATH...`
+//!
+//! Each `PATH` is a PBM, PGM, PPM or PAM image, a directory (walked recursively, or, if it has one, scanned as its `manifest` says),
+//! or a manifest. A manifest lists the images relative to itself, one expected payload per line:
+//!
+//! ```text
+//! # comment
+//! upright-v1.pgm quirc
+//! two-codes.pgm first
+//! two-codes.pgm second
+//! no-code.pgm
+//! ```
+//!
+//! Payloads are the rest of the line after the whitespace following the file name, with `\\`, `\n`, `\t` and `\xHH` escapes.
+//! For images listed in a manifest, precision (how many of the decoded payloads were expected)
+//! and recall (how many of the expected payloads were decoded) are reported too.
+//!
+//! * `-v` – print the decoded data, or the error, of each code.
+//! * `-d` – dump the cells of each code.
+//! * `-g` – group capstones into codes globally (`Quirc::end_global()`); with `-v`, also print the candidate groupings it rejected, and why.
+//! * `-b BASELINE` – exit with 1 if any rate in `BASELINE` is higher than this run's total.
+//! * `-w BASELINE` – write this run's total rates to `BASELINE`.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::ffi::QuircDecodeError;
+use quirc_impl::ops::{QuircCode, QuircData, Quirc};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::process::exit;
+use std::fs;
+
+
+/// An image, and the payloads it's expected to hold.
+type ManifestEntry = (PathBuf, Vec<Vec<u8>>);
+
+/// Extensions of the files picked up when walking directories.
+static IMAGE_EXTENSIONS: &[&str] = &["pbm", "pgm", "ppm", "pnm", "pam"];
+
+struct Options {
+    verbose: bool,
+    cell_dump: bool,
+    global: bool,
+    baseline: Option<PathBuf>,
+    write_baseline: Option<PathBuf>,
+}
+
+#[derive(Default)]
+struct ResultInfo {
+    file_count: usize,
+    id_count: usize,
+    decode_count: usize,
+    /// Failures to decode an identified code, by cause.
+    failures: BTreeMap<QuircDecodeError, usize>,
+
+    /// Payloads expected by manifests.
+    expected_count: usize,
+    /// Codes decoded in images listed in manifests.
+    checked_count: usize,
+    /// Decoded payloads matching an expected one.
+    correct_count: usize,
+
+    load_time: Duration,
+    identify_time: Duration,
+    decode_time: Duration,
+    total_time: Duration,
+}
+
+impl ResultInfo {
+    fn add(&mut self, other: &ResultInfo) {
+        self.file_count += other.file_count;
+        self.id_count += other.id_count;
+        self.decode_count += other.decode_count;
+        for (&err, &count) in &other.failures {
+            *self.failures.entry(err).or_insert(0) += count;
+        }
+
+        self.expected_count += other.expected_count;
+        self.checked_count += other.checked_count;
+        self.correct_count += other.correct_count;
+
+        self.load_time += other.load_time;
+        self.identify_time += other.identify_time;
+        self.decode_time += other.decode_time;
+        self.total_time += other.total_time;
+    }
+
+    /// The rates a baseline can hold, those with a non-zero denominator.
+    fn rates(&self) -> Vec<(&'static str, f64)> {
+        [("decode_rate", self.decode_count, self.id_count),
+         ("precision", self.correct_count, self.checked_count),
+         ("recall", self.correct_count, self.expected_count)]
+            .iter()
+            .filter(|&&(_, _, of)| of != 0)
+            .map(|&(name, num, of)| (name, num as f64 / of as f64))
+            .collect()
+    }
+}
+
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1000.0 + d.subsec_nanos() as f64 / 1_000_000.0
+}
+
+fn print_result(name: &str, info: &ResultInfo) {
+    println!("{}", "-".repeat(79));
+    print!("{}: {} files, {} codes, {} decoded ({} failures)",
+           name,
+           info.file_count,
+           info.id_count,
+           info.decode_count,
+           info.id_count - info.decode_count);
+    if let Some(rate) = (info.decode_count * 100 + info.id_count / 2).checked_div(info.id_count) {
+        print!(", {}% success rate", rate);
+    }
+    println!();
+
+    if info.expected_count != 0 {
+        print!("{} expected, {} correct", info.expected_count, info.correct_count);
+        for (name, rate) in info.rates().into_iter().filter(|&(name, _)| name != "decode_rate") {
+            print!(", {} {:.1}%", name, rate * 100.0);
+        }
+        println!();
+    }
+    for (err, count) in &info.failures {
+        println!("  {:?}: {}", err, count);
+    }
+
+    println!("Total time [load: {:.1}, identify: {:.1}, decode: {:.1}, total: {:.1}]",
+             ms(info.load_time),
+             ms(info.identify_time),
+             ms(info.decode_time),
+             ms(info.total_time));
+    if info.file_count != 0 {
+        let n = info.file_count as f64;
+        println!("Average time [load: {:.1}, identify: {:.1}, decode: {:.1}, total: {:.1}]",
+                 ms(info.load_time) / n,
+                 ms(info.identify_time) / n,
+                 ms(info.decode_time) / n,
+                 ms(info.total_time) / n);
+    }
+}
+
+
+/// Any Netpbm image, converted to luma.
+fn load_image(decoder: &mut Quirc, path: &Path) -> Result<(), String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    decoder.load_netpbm(&data).map_err(|e| e.to_string())
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension().map(|e| IMAGE_EXTENSIONS.iter().any(|x| e.eq_ignore_ascii_case(x))).unwrap_or(false)
+}
+
+/// Undo the escapes allowed in manifest payloads.
+fn unescape(s: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+
+        match bytes.next() {
+            Some(b'\\') => out.push(b'\\'),
+            Some(b'n') => out.push(b'\n'),
+            Some(b't') => out.push(b'\t'),
+            Some(b'x') => {
+                let hex = [bytes.next().unwrap_or(b'?'), bytes.next().unwrap_or(b'?')];
+                let hex = String::from_utf8_lossy(&hex).into_owned();
+                out.push(u8::from_str_radix(&hex, 16).map_err(|_| format!("bad escape \\x{}", hex))?);
+            }
+            Some(c) => return Err(format!("bad escape \\{}", c as char)),
+            None => return Err("trailing \\".to_string()),
+        }
+    }
+
+    Ok(out)
+}
+
+/// The images listed in the manifest, in order of first appearance, with their expected payloads.
+fn read_manifest(path: &Path) -> Result<Vec<ManifestEntry>, String> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut images: Vec<ManifestEntry> = Vec::new();
+
+    for (i, line) in fs::read_to_string(path).map_err(|e| e.to_string())?.lines().enumerate() {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (image, payload) = match line.find(char::is_whitespace) {
+            Some(split) => (&line[..split], Some(line[split..].trim_start())),
+            None => (line, None),
+        };
+        let image = dir.join(image);
+
+        let idx = match images.iter().position(|(p, _)| *p == image) {
+            Some(idx) => idx,
+            None => {
+                images.push((image, Vec::new()));
+                images.len() - 1
+            }
+        };
+        if let Some(payload) = payload.filter(|p| !p.is_empty()) {
+            images[idx].1.push(unescape(payload).map_err(|e| format!("line {}: {}", i + 1, e))?);
+        }
+    }
+
+    Ok(images)
+}
+
+
+fn dump_cells(code: &QuircCode) {
+    print!("    {} cells, corners:", code.size);
+    for c in &code.corners {
+        print!(" ({},{})", c.x, c.y);
+    }
+    println!();
+
+    for v in 0..code.size {
+        print!("    ");
+        for u in 0..code.size {
+            let p = v * code.size + u;
+            print!("{}", if (code.cell_bitmap[(p >> 3) as usize] >> (p & 7)) & 1 != 0 { "[]" } else { "  " });
+        }
+        println!();
+    }
+}
+
+fn dump_data(data: &QuircData) {
+    println!("    Version: {}", data.version);
+    println!("    ECC level: {}", b"MLHQ"[data.ecc_level as usize] as char);
+    println!("    Mask: {}", data.mask);
+    println!("    Data type: {} ({})",
+             data.data_type,
+             match data.data_type {
+                 1 => "NUMERIC",
+                 2 => "ALPHA",
+                 4 => "BYTE",
+                 8 => "KANJI",
+                 _ => "unknown",
+             });
+    println!("    Length: {}", data.payload().len());
+    println!("    Payload: {}", String::from_utf8_lossy(data.payload()));
+    if data.eci != 0 {
+        println!("    ECI: {}", data.eci);
+    }
+}
+
+
+/// Scan one image, checking the decoded payloads against `expected`, if listed in a manifest.
+fn scan_file(decoder: &mut Quirc, path: &Path, expected: Option<&[Vec<u8>]>, options: &Options) -> Option<ResultInfo> {
+    let mut info = ResultInfo::default();
+    let filename = path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+
+    let total_start = Instant::now();
+    if let Err(e) = load_image(decoder, path) {
+        eprintln!("{}: load failed: {}", path.display(), e);
+        return None;
+    }
+    info.load_time = total_start.elapsed();
+
+    let start = Instant::now();
+    let candidates = if options.global {
+        decoder.end_global()
+    } else {
+        decoder.end();
+        Vec::new()
+    };
+    info.identify_time = start.elapsed();
+
+    let start = Instant::now();
+    let codes: Vec<_> = (0..decoder.count()).filter_map(|i| decoder.extract(i).ok()).collect();
+    let decoded: Vec<_> = codes.iter().map(QuircCode::decode).collect();
+    info.decode_time = start.elapsed();
+    info.total_time = total_start.elapsed();
+
+    info.id_count = codes.len();
+    for result in &decoded {
+        match *result {
+            Ok(_) => info.decode_count += 1,
+            Err(ref err) => *info.failures.entry(QuircDecodeError::from(*err)).or_insert(0) += 1,
+        }
+    }
+
+    let mut correct = String::from("-");
+    if let Some(expected) = expected {
+        let mut unmatched = expected.to_vec();
+        for data in decoded.iter().flat_map(|r| r.as_ref().ok()) {
+            if let Some(i) = unmatched.iter().position(|p| &p[..] == data.payload()) {
+                unmatched.swap_remove(i);
+                info.correct_count += 1;
+            }
+        }
+
+        info.expected_count = expected.len();
+        info.checked_count = info.decode_count;
+        correct = format!("{}/{}", info.correct_count, info.expected_count);
+    }
+
+    println!("  {:<30}: {:7.1} {:7.1} {:7.1} {:7.1} {:5} {:5} {:>5}",
+             filename,
+             ms(info.load_time),
+             ms(info.identify_time),
+             ms(info.decode_time),
+             ms(info.total_time),
+             info.id_count,
+             info.decode_count,
+             correct);
+
+    if options.verbose {
+        for candidate in candidates.iter().filter(|c| c.rejection.is_some()) {
+            println!("  Rejected grouping {:?}: {:?}, score {}", candidate.caps, candidate.rejection.unwrap(), candidate.score);
+        }
+    }
+
+    if options.cell_dump || options.verbose {
+        for (code, result) in codes.iter().zip(decoded.iter()) {
+            if options.cell_dump {
+                dump_cells(code);
+                println!();
+            }
+
+            if options.verbose {
+                match *result {
+                    Ok(ref data) => {
+                        println!("  Decode successful:");
+                        dump_data(data);
+                        println!();
+                    }
+                    Err(ref err) => println!("  ERROR: {}\n", err),
+                }
+            }
+        }
+    }
+
+    info.file_count = 1;
+    Some(info)
+}
+
+fn scan_manifest(decoder: &mut Quirc, path: &Path, options: &Options) -> Option<ResultInfo> {
+    let images = match read_manifest(path) {
+        Ok(images) => images,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return None;
+        }
+    };
+
+    println!("{}:", path.display());
+    let mut info = ResultInfo::default();
+    for (image, expected) in &images {
+        if let Some(sub) = scan_file(decoder, image, Some(expected), options) {
+            info.add(&sub);
+        }
+    }
+
+    print_result(&path.display().to_string(), &info);
+    println!();
+    Some(info)
+}
+
+fn scan_dir(decoder: &mut Quirc, path: &Path, options: &Options) -> Option<ResultInfo> {
+    let manifest = path.join("manifest");
+    if manifest.is_file() {
+        return scan_manifest(decoder, &manifest, options);
+    }
+
+    let mut entries: Vec<_> = match fs::read_dir(path) {
+        Ok(entries) => entries.flat_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return None;
+        }
+    };
+    entries.sort();
+
+    println!("{}:", path.display());
+    let mut info = ResultInfo::default();
+    let mut count = 0;
+    for entry in entries {
+        if entry.file_name().map(|f| f.to_string_lossy().starts_with('.')).unwrap_or(true) {
+            continue;
+        }
+        if entry.is_dir() || is_image(&entry) {
+            if let Some(sub) = test_scan(decoder, &entry, options) {
+                info.add(&sub);
+                count += 1;
+            }
+        }
+    }
+
+    if count > 1 {
+        print_result(&path.display().to_string(), &info);
+        println!();
+    }
+    if count > 0 { Some(info) } else { None }
+}
+
+fn test_scan(decoder: &mut Quirc, path: &Path, options: &Options) -> Option<ResultInfo> {
+    if path.is_dir() {
+        scan_dir(decoder, path, options)
+    } else if is_image(path) {
+        scan_file(decoder, path, None, options)
+    } else if path.is_file() {
+        scan_manifest(decoder, path, options)
+    } else {
+        eprintln!("{}: not found", path.display());
+        None
+    }
+}
+
+
+/// The rates in the baseline that this run's fell below.
+fn check_baseline(path: &Path, info: &ResultInfo) -> Result<Vec<String>, String> {
+    let rates = info.rates();
+    let mut regressions = Vec::new();
+
+    for line in fs::read_to_string(path).map_err(|e| e.to_string())?.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let (name, baseline) = match (fields.next(), fields.next().map(str::parse::<f64>)) {
+            (Some(name), Some(Ok(baseline))) => (name, baseline),
+            _ => return Err(format!("bad line \"{}\"", line)),
+        };
+        match rates.iter().find(|&&(n, _)| n == name) {
+            Some(&(_, rate)) if rate + 1e-9 < baseline => regressions.push(format!("{} {:.6} < baseline {:.6}", name, rate, baseline)),
+            Some(_) => {}
+            None => regressions.push(format!("{} not measured", name)),
+        }
+    }
+
+    Ok(regressions)
+}
+
+fn write_baseline(path: &Path, info: &ResultInfo) -> Result<(), String> {
+    let mut out = String::from("# Written by qrtest -w; each rate must not drop below its value here.\n");
+    for (name, rate) in info.rates() {
+        out.push_str(&format!("{} {:.6}\n", name, rate));
+    }
+
+    fs::write(path, out).map_err(|e| e.to_string())
+}
+
+
+fn usage() -> ! {
+    eprintln!("Usage: qrtest [-v] [-d] [-g] [-b BASELINE] [-w BASELINE] PATH...");
+    exit(2);
+}
+
+fn main() {
+    let mut options = Options {
+        verbose: false,
+        cell_dump: false,
+        global: false,
+        baseline: None,
+        write_baseline: None,
+    };
+    let mut paths = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "-v" => options.verbose = true,
+            "-d" => options.cell_dump = true,
+            "-g" => options.global = true,
+            "-b" => options.baseline = Some(args.next().unwrap_or_else(|| usage()).into()),
+            "-w" => options.write_baseline = Some(args.next().unwrap_or_else(|| usage()).into()),
+            _ if arg.starts_with('-') => usage(),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    println!("quirc test program");
+    println!("Library version: {}", env!("CARGO_PKG_VERSION"));
+    println!();
+
+    println!("  {:<30}  {:>31} {:>17}", "", "Time (ms)", "Count");
+    println!("  {:<30}  {:>7} {:>7} {:>7} {:>7} {:>5} {:>5} {:>5}", "Filename", "Load", "ID", "Dec", "Total", "ID", "Dec", "OK");
+    println!("{}", "-".repeat(79));
+
+    let mut decoder = Quirc::new();
+    let mut sum = ResultInfo::default();
+    let mut count = 0;
+    for path in &paths {
+        if let Some(info) = test_scan(&mut decoder, path, &options) {
+            sum.add(&info);
+            count += 1;
+        }
+    }
+
+    if count > 1 {
+        print_result("TOTAL", &sum);
+    }
+
+    if let Some(ref path) = options.write_baseline {
+        if let Err(e) = write_baseline(path, &sum) {
+            eprintln!("{}: {}", path.display(), e);
+            exit(2);
+        }
+    }
+
+    if let Some(ref path) = options.baseline {
+        match check_baseline(path, &sum) {
+            Ok(ref regressions) if regressions.is_empty() => {}
+            Ok(regressions) => {
+                for r in regressions {
+                    println!("REGRESSION: {}", r);
+                }
+                exit(1);
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                exit(2);
+            }
+        }
+    }
+}
@@ -0,0 +1,429 @@
+//! Scan image files, or standard input, for QR codes and print what they hold.
+//!
+//! Usage: `quirc [--json] [--all | --first] [PATH...]`
+//!
+//! Each `PATH` is an image, a directory (searched recursively for images), or a glob pattern
+//! (`*`, `?`, `[...]` and `[!...]` within a path component, `**` for any number of directories),
+//! so that patterns work the same where the shell doesn't expand them. `-`, or no paths at all, reads an image from standard input.
+//!
+//! Images are recognised by their contents: PBM, PGM, PPM and PAM, in their ASCII and binary variants, always;
+//! PNG, JPEG, BMP, TIFF, GIF and WebP with the `image` feature.
+//!
+//! By default, the payload of each decoded code is printed on its own line, prefixed with its file's path if there's more than one,
+//! and the codes that failed to decode are reported on the standard error.
+//! With `--json`, an array with an object for each image is printed instead, with every code's corners, version, ECC level, mask, ECI,
+//! segments and payload, or its error.
+//!
+//! * `--all` (default) – print every code found in each image.
+//! * `--first` – stop at the first code in each image which decodes.
+//!
+//! The exit code is:
+//!
+//! * 0 if at least one code was decoded from every image,
+//! * 1 if some image held no code which decoded,
+//! * 2 on usage errors,
+//! * 3 if some path couldn't be read, matched nothing, or wasn't an image in a supported format.
+
+
+extern crate quirc_impl;
+#[cfg(feature = "image")]
+extern crate image;
+
+use quirc_impl::ops::{DecodeError, QuircCode, QuircData, Quirc};
+use std::path::{Component, Path, PathBuf};
+use std::io::{self, Read, Write};
+use std::process::exit;
+use std::fs;
+
+
+const EXIT_NOT_FOUND: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_UNREADABLE: i32 = 3;
+
+/// Extensions of the files picked up when searching directories.
+static IMAGE_EXTENSIONS: &[&str] = &["pbm", "pgm", "ppm", "pnm", "pam", "png", "jpg", "jpeg", "bmp", "tif", "tiff", "gif", "webp"];
+
+
+struct Options {
+    json: bool,
+    first: bool,
+    paths: Vec<String>,
+}
+
+/// One image's codes, each decoded or not.
+struct Scanned {
+    name: String,
+    width: usize,
+    height: usize,
+    codes: Vec<(QuircCode, Result<QuircData, DecodeError>)>,
+}
+
+
+fn usage() -> ! {
+    eprintln!("Usage: quirc [--json] [--all | --first] [PATH...]");
+    exit(EXIT_USAGE);
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        json: false,
+        first: false,
+        paths: Vec::new(),
+    };
+
+    let mut only_paths = false;
+    for arg in std::env::args().skip(1) {
+        match &arg[..] {
+            _ if only_paths => options.paths.push(arg),
+            "--json" => options.json = true,
+            "--all" => options.first = false,
+            "--first" => options.first = true,
+            "--" => only_paths = true,
+            "-h" | "--help" => {
+                println!("Usage: quirc [--json] [--all | --first] [PATH...]");
+                exit(0);
+            }
+            "-V" | "--version" => {
+                println!("quirc {}", env!("CARGO_PKG_VERSION"));
+                exit(0);
+            }
+            _ if arg.starts_with("--") => usage(),
+            _ => options.paths.push(arg),
+        }
+    }
+
+    if options.paths.is_empty() {
+        options.paths.push("-".to_string());
+    }
+    options
+}
+
+
+/// Whether `name` matches the shell-style `pattern`, without `/`s in either.
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some(b'*') => (0..=name.len()).any(|skip| glob_match(&pattern[1..], &name[skip..])),
+        Some(b'?') => !name.is_empty() && glob_match(&pattern[1..], &name[1..]),
+        Some(b'[') if pattern.contains(&b']') => {
+            let negated = pattern.get(1) == Some(&b'!');
+            let set_start = if negated { 2 } else { 1 };
+            // A ']' right after the opening bracket is part of the set
+            let set_end = match pattern[set_start + 1..].iter().position(|&b| b == b']') {
+                Some(end) => set_start + 1 + end,
+                None => return pattern[0] == *name.first().unwrap_or(&0) && glob_match(&pattern[1..], &name[1..]),
+            };
+
+            let c = match name.first() {
+                Some(&c) => c,
+                None => return false,
+            };
+            let set = &pattern[set_start..set_end];
+            let mut found = false;
+            let mut i = 0;
+            while i < set.len() {
+                if i + 2 < set.len() && set[i + 1] == b'-' {
+                    found |= set[i] <= c && c <= set[i + 2];
+                    i += 3;
+                } else {
+                    found |= set[i] == c;
+                    i += 1;
+                }
+            }
+
+            found != negated && glob_match(&pattern[set_end + 1..], &name[1..])
+        }
+        Some(&p) => name.first() == Some(&p) && glob_match(&pattern[1..], &name[1..]),
+    }
+}
+
+fn is_glob(s: &str) -> bool {
+    s.contains(['*', '?', '['])
+}
+
+/// `dir` and every directory under it.
+fn subdirectories(dir: &Path, out: &mut Vec<PathBuf>) {
+    out.push(dir.to_path_buf());
+
+    let mut entries: Vec<_> = fs::read_dir(dir).into_iter().flatten().flat_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_dir()).collect();
+    entries.sort();
+    for entry in entries {
+        subdirectories(&entry, out);
+    }
+}
+
+/// The existing paths matching the pattern, sorted.
+fn expand_glob(pattern: &str) -> Vec<PathBuf> {
+    let mut matches = vec![PathBuf::new()];
+
+    for component in Path::new(pattern).components() {
+        let component = match component {
+            Component::Normal(c) => c.to_string_lossy().into_owned(),
+            other => {
+                for m in &mut matches {
+                    m.push(other.as_os_str());
+                }
+                continue;
+            }
+        };
+
+        let mut next = Vec::new();
+        for base in &matches {
+            let dir = if base.as_os_str().is_empty() { Path::new(".") } else { base };
+
+            if component == "**" {
+                let mut dirs = Vec::new();
+                subdirectories(dir, &mut dirs);
+                next.extend(dirs.into_iter().map(|d| if base.as_os_str().is_empty() { d.strip_prefix(".").unwrap_or(&d).to_path_buf() } else { d }));
+            } else if is_glob(&component) {
+                let mut entries: Vec<_> = fs::read_dir(dir)
+                    .into_iter()
+                    .flatten()
+                    .flat_map(|e| e.ok())
+                    .map(|e| e.file_name().to_string_lossy().into_owned())
+                    .filter(|name| (!name.starts_with('.') || component.starts_with('.')) && glob_match(component.as_bytes(), name.as_bytes()))
+                    .collect();
+                entries.sort();
+                next.extend(entries.into_iter().map(|name| base.join(name)));
+            } else {
+                let path = base.join(&component);
+                if path.exists() {
+                    next.push(path);
+                }
+            }
+        }
+        matches = next;
+    }
+
+    matches.retain(|m| !m.as_os_str().is_empty());
+    matches.sort();
+    matches.dedup();
+    matches
+}
+
+/// The image files in and under `dir`, sorted.
+fn images_under(dir: &Path, out: &mut Vec<PathBuf>) {
+    let mut entries: Vec<_> = fs::read_dir(dir).into_iter().flatten().flat_map(|e| e.ok()).map(|e| e.path()).collect();
+    entries.sort();
+
+    for entry in entries {
+        if entry.file_name().map(|n| n.to_string_lossy().starts_with('.')).unwrap_or(true) {
+            continue;
+        }
+
+        if entry.is_dir() {
+            images_under(&entry, out);
+        } else if entry.extension().map(|e| IMAGE_EXTENSIONS.iter().any(|x| e.eq_ignore_ascii_case(x))).unwrap_or(false) {
+            out.push(entry);
+        }
+    }
+}
+
+
+#[cfg(feature = "image")]
+fn load_other(decoder: &mut Quirc, data: &[u8]) -> Result<(), String> {
+    decoder.load_image(&image::load_from_memory(data).map_err(|e| e.to_string())?);
+    Ok(())
+}
+
+#[cfg(not(feature = "image"))]
+fn load_other(_: &mut Quirc, _: &[u8]) -> Result<(), String> {
+    Err("not a Netpbm image (other formats need the image feature)".to_string())
+}
+
+fn scan(decoder: &mut Quirc, name: String, data: &[u8], options: &Options) -> Result<Scanned, String> {
+    if data.len() >= 2 && data[0] == b'P' && (b'1'..=b'7').contains(&data[1]) {
+        decoder.load_netpbm(data).map_err(|e| e.to_string())?;
+    } else {
+        load_other(decoder, data)?;
+    }
+    decoder.end();
+
+    let (width, height) = decoder.size();
+    let mut codes = Vec::new();
+    for result in decoder.results() {
+        let decoded = result.data.is_ok();
+
+        codes.push((result.code, result.data));
+        if decoded && options.first {
+            break;
+        }
+    }
+
+    Ok(Scanned {
+        name,
+        width,
+        height,
+        codes,
+    })
+}
+
+
+fn json_string(s: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for c in String::from_utf8_lossy(s).chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_hex(s: &[u8]) -> String {
+    let hex: String = s.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+}
+
+fn json_code(code: &QuircCode, data: &Result<QuircData, DecodeError>) -> String {
+    let corners: Vec<_> = code.corners.iter().map(|c| format!("[{}, {}]", c.x, c.y)).collect();
+    let mut out = format!("{{\"corners\": [{}], \"size\": {}", corners.join(", "), code.size);
+
+    match *data {
+        Ok(ref data) => {
+            let segments: Vec<_> = data.segments()
+                .into_iter()
+                .map(|segment| {
+                    use quirc_impl::ops::encode::Segment;
+
+                    let (mode, bytes) = match segment {
+                        Segment::Numeric(b) => ("numeric", b),
+                        Segment::Alpha(b) => ("alpha", b),
+                        Segment::Byte(b) => ("byte", b),
+                        Segment::Kanji(b) => ("kanji", b),
+                        Segment::Eci(eci) => return format!("{{\"mode\": \"eci\", \"eci\": {}}}", eci),
+                    };
+                    format!("{{\"mode\": \"{}\", \"data\": {}, \"data_hex\": {}}}", mode, json_string(bytes), json_hex(bytes))
+                })
+                .collect();
+
+            out.push_str(&format!(", \"version\": {}, \"ecc_level\": \"{}\", \"mask\": {}, \"data_type\": {}, \"eci\": {}, \"segments\": [{}], \
+                                   \"payload\": {}, \"payload_hex\": {}, \"error\": null}}",
+                                  data.version,
+                                  b"MLHQ"[data.ecc_level as usize] as char,
+                                  data.mask,
+                                  data.data_type,
+                                  data.eci,
+                                  segments.join(", "),
+                                  json_string(data.payload()),
+                                  json_hex(data.payload())));
+        }
+        Err(ref err) => {
+            if let Some(version) = err.version {
+                out.push_str(&format!(", \"version\": {}", version));
+            }
+            out.push_str(&format!(", \"error\": {}}}", json_string(err.to_string().as_bytes())));
+        }
+    }
+
+    out
+}
+
+
+fn main() {
+    let options = parse_args();
+    let mut decoder = Quirc::new();
+    let mut exit_code = 0;
+
+    // Expand the paths into images, keeping the errors in order
+    let mut inputs: Vec<Result<String, String>> = Vec::new();
+    for path in &options.paths {
+        if path == "-" {
+            inputs.push(Ok(path.clone()));
+        } else if Path::new(path).is_dir() {
+            let mut images = Vec::new();
+            images_under(Path::new(path), &mut images);
+            if images.is_empty() {
+                inputs.push(Err(format!("{}: no images", path)));
+            }
+            inputs.extend(images.into_iter().map(|p| Ok(p.display().to_string())));
+        } else if is_glob(path) && !Path::new(path).exists() {
+            let mut matches = Vec::new();
+            for m in expand_glob(path) {
+                if m.is_dir() {
+                    images_under(&m, &mut matches);
+                } else {
+                    matches.push(m);
+                }
+            }
+            if matches.is_empty() {
+                inputs.push(Err(format!("{}: no matches", path)));
+            }
+            inputs.extend(matches.into_iter().map(|p| Ok(p.display().to_string())));
+        } else {
+            inputs.push(Ok(path.clone()));
+        }
+    }
+    let prefix = inputs.len() > 1;
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut json_images = Vec::new();
+    for input in inputs {
+        let scanned = input.and_then(|name| {
+            let data = if name == "-" {
+                let mut data = Vec::new();
+                io::stdin().read_to_end(&mut data).map(|_| data)
+            } else {
+                fs::read(&name)
+            };
+
+            match data {
+                Ok(data) => scan(&mut decoder, name.clone(), &data, &options).map_err(|e| format!("{}: {}", name, e)),
+                Err(e) => Err(format!("{}: {}", name, e)),
+            }
+        });
+
+        match scanned {
+            Ok(scanned) => {
+                if !scanned.codes.iter().any(|(_, data)| data.is_ok()) && exit_code == 0 {
+                    exit_code = EXIT_NOT_FOUND;
+                }
+
+                if options.json {
+                    let codes: Vec<_> = scanned.codes.iter().map(|(code, data)| json_code(code, data)).collect();
+                    json_images.push(format!("{{\"file\": {}, \"width\": {}, \"height\": {}, \"codes\": [{}]}}",
+                                             json_string(scanned.name.as_bytes()),
+                                             scanned.width,
+                                             scanned.height,
+                                             codes.join(", ")));
+                } else {
+                    for (i, (_, data)) in scanned.codes.iter().enumerate() {
+                        match *data {
+                            Ok(ref data) => {
+                                if prefix {
+                                    let _ = write!(out, "{}: ", scanned.name);
+                                }
+                                let _ = out.write_all(data.payload());
+                                let _ = out.write_all(b"\n");
+                            }
+                            Err(ref err) => eprintln!("{}: code {}: {}", scanned.name, i, err),
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                if options.json {
+                    let (name, err) = e.split_at(e.find(": ").unwrap_or(0));
+                    json_images.push(format!("{{\"file\": {}, \"error\": {}}}",
+                                             json_string(name.as_bytes()),
+                                             json_string(err.trim_start_matches(": ").as_bytes())));
+                }
+                exit_code = EXIT_UNREADABLE;
+            }
+        }
+    }
+
+    if options.json {
+        let _ = writeln!(out, "[{}]", json_images.join(",\n "));
+    }
+    let _ = out.flush();
+    exit(exit_code);
+}
@@ -1,5 +1,15 @@
+//! The C interface, A{B,P}I compatible with the original `quirc`.
+//!
+//! All `unsafe` functions herein expect valid pointers obtained from the functions in this module, as their C counterparts do.
+
+#![allow(clippy::missing_safety_doc)]
+
+
+use self::super::ops::{DecodeErrorKind, DecodeError, QuircPoint, QuircCode, QuircData, Quirc};
+use self::super::util::{Datastream, decode_code};
+use self::super::ops::version_db::QUIRC_MAX_VERSION;
 use self::super::constants::{QUIRC_MAX_PAYLOAD, QUIRC_MAX_BITMAP};
-use self::super::ops::{QuircPoint, QuircCode, QuircData, Quirc};
+use std::convert::TryFrom;
 use std::boxed::Box;
 use libc::c_int;
 use std::ptr;
@@ -21,7 +31,7 @@ impl Drop for FfiQuirc {
     fn drop(&mut self) {
         if !self.inner.is_null() {
             unsafe {
-                Box::from_raw(self.inner);
+                drop(Box::from_raw(self.inner));
             }
             self.inner = ptr::null_mut();
         }
@@ -101,17 +111,28 @@ pub struct FfiQuircData {
     pub eci: u32,
 }
 
-impl From<QuircData> for FfiQuircData {
-    fn from(data: QuircData) -> FfiQuircData {
-        FfiQuircData {
+/// Fails with `QuircErrorDataOverflow` if the payload doesn't fit along with its nul terminator.
+impl TryFrom<QuircData> for FfiQuircData {
+    type Error = QuircDecodeError;
+
+    fn try_from(data: QuircData) -> Result<FfiQuircData, QuircDecodeError> {
+        let payload_len = data.payload.len();
+        if payload_len >= QUIRC_MAX_PAYLOAD {
+            return Err(QuircDecodeError::QuircErrorDataOverflow);
+        }
+
+        let mut payload = [0u8; QUIRC_MAX_PAYLOAD];
+        payload[..payload_len].copy_from_slice(&data.payload);
+
+        Ok(FfiQuircData {
             version: data.version as c_int,
             ecc_level: data.ecc_level as c_int,
             mask: data.mask as c_int,
             data_type: data.data_type as c_int,
-            payload: data.payload,
-            payload_len: data.payload_len as c_int,
+            payload,
+            payload_len: payload_len as c_int,
             eci: data.eci,
-        }
+        })
     }
 }
 
@@ -130,6 +151,26 @@ pub enum QuircDecodeError {
     QuircErrorDataUnderflow,
 }
 
+impl From<DecodeErrorKind> for QuircDecodeError {
+    fn from(kind: DecodeErrorKind) -> QuircDecodeError {
+        match kind {
+            DecodeErrorKind::InvalidGridSize => QuircDecodeError::QuircErrorInvalidGridSize,
+            DecodeErrorKind::InvalidVersion => QuircDecodeError::QuircErrorInvalidVersion,
+            DecodeErrorKind::FormatEcc => QuircDecodeError::QuircErrorFormatEcc,
+            DecodeErrorKind::DataEcc => QuircDecodeError::QuircErrorDataEcc,
+            DecodeErrorKind::UnknownDataType => QuircDecodeError::QuircErrorUnknownDataType,
+            DecodeErrorKind::DataOverflow => QuircDecodeError::QuircErrorDataOverflow,
+            DecodeErrorKind::DataUnderflow => QuircDecodeError::QuircErrorDataUnderflow,
+        }
+    }
+}
+
+impl From<DecodeError> for QuircDecodeError {
+    fn from(err: DecodeError) -> QuircDecodeError {
+        err.kind.into()
+    }
+}
+
 
 /// Obtain the library version string.
 #[no_mangle]
@@ -148,7 +189,7 @@ pub extern "C" fn quirc_new() -> *mut FfiQuirc {
 /// Destroy a QR-code recognizer.
 #[no_mangle]
 pub unsafe extern "C" fn quirc_destroy(whom: *mut FfiQuirc) {
-    Box::from_raw(whom);
+    drop(Box::from_raw(whom));
 }
 
 /// Resize the QR-code recognizer. The size of an image must be
@@ -221,29 +262,41 @@ pub unsafe extern "C" fn quirc_extract(from_whom: *const FfiQuirc, index: c_int,
         return;
     }
 
-    if let Some(out) = (*((*from_whom).inner)).extract(index as usize) {
+    if let Ok(out) = (*((*from_whom).inner)).extract(index as usize) {
         *code = out.into();
     }
 }
 
 /// Decode a QR-code, returning the payload data.
+///
+/// Like in C quirc, `data` is left untouched if the grid size is invalid,
+/// and otherwise holds whatever was decoded before an error.
 #[no_mangle]
 pub unsafe extern "C" fn quirc_decode(code: *const FfiQuircCode, data: *mut FfiQuircData) -> QuircDecodeError {
-    let mut out_data = QuircData {
-        version: 0,
-        ecc_level: 0,
-        mask: 0,
-
-        data_type: 0,
+    let size = (*code).size;
+    if size.wrapping_sub(17) % 4 != 0 {
+        return QuircDecodeError::QuircErrorInvalidGridSize;
+    }
 
-        payload: [0u8; QUIRC_MAX_PAYLOAD],
-        payload_len: 0,
+    ptr::write_bytes(data, 0, 1);
+    (*data).version = size.wrapping_sub(17) / 4;
+    if (*data).version < 1 || (*data).version as usize > QUIRC_MAX_VERSION {
+        return QuircDecodeError::QuircErrorInvalidVersion;
+    }
 
-        eci: 0,
+    let code = QuircCode::from(*code);
+    let mut out_data = match code.blank_data() {
+        Ok(out_data) => out_data,
+        Err(err) => return err.into(),
     };
+    let result = decode_code(&code, &mut out_data, &mut Datastream::new());
+    match FfiQuircData::try_from(out_data) {
+        Ok(out_data) => *data = out_data,
+        Err(err) => return err,
+    }
 
-    let err = QuircCode::from(*code).decode(&mut out_data);
-    *data = out_data.into();
-
-    err
+    match result {
+        Ok(()) => QuircDecodeError::QuircSuccess,
+        Err(err) => err.into(),
+    }
 }
@@ -3,12 +3,86 @@
 //! the FFI to which is used by [the `quirc` crate](https://crates.io/crates/quirc).
 //!
 //! The crate also provides the C interface, which is A{B,P}I compatible with the original `quirc`
-//! (see the `c-examples` directory for more).
+//! (see the `c-examples` directory for more);
+//! the `capi` crate in this workspace builds it as the `libquirc` shared library to link them against
+//! (`cargo build --release -p quirc-impl-capi`).
+//!
+//! The `quirc` binary scans image files for codes and prints their payloads, as text or JSON;
+//! `qrtest` reports detection and decode rates over a corpus, as `c-examples/qrtest.c` does.
+//!
+//! # Usage
+//!
+//! `scan()` finds and decodes every code in a grayscale image, one byte per pixel:
+//!
+//! ```
+//! # let (width, height) = (64, 48);
+//! # let pixels = vec![255u8; width * height];
+//! for result in quirc_impl::scan(&pixels, width, height) {
+//!     match result.data {
+//!         Ok(data) => println!("{:?}: {}", result.corners, String::from_utf8_lossy(data.payload())),
+//!         Err(e) => eprintln!("{:?}: {}", result.corners, e),
+//!     }
+//! }
+//! ```
+//!
+//! To scan many images, keep a `Quirc` instead, so that its buffers are reused:
+//! `resize()` it to each image, copy the pixels into `begin()`, call `end()`, and iterate over `results()`.
+//! `extract()` and `QuircCode::decode()` give finer control, and `Quirc::process_image()` scans `image` crate images directly.
+//! `Quirc::end_pyramid()` replaces `end()` to also search scaled copies of the image (see `ops::pyramid`),
+//! for large scans and codes with cells only a pixel or two wide.
+//! `Quirc::end_roi()` and `end_rois()` only search where a code is expected, when that's already known.
+//! `Quirc::end_global()` groups capstones into codes globally rather than greedily (see `ops::grouping`), for sheets of tightly packed labels.
+//! `Quirc::end_recover()` also recovers codes with one capstone hidden or out of frame, inferring it from the other two;
+//! those are flagged `inferred` in their `ScanResult`.
+//! `scan_tiled()` scans images too large to hold in memory a tile at a time (see `ops::tiled`), reading the pixels as it goes.
+//! For video, a `QuircTracker` (see `ops::tracker`) follows codes from frame to frame instead of searching every frame in full,
+//! and a `QuircFusion` (see `ops::fusion`) decodes the codes it follows from all their frames together.
+//!
+//! # Features
+//!
+//! * `std` (default) – the C interface, `std::error::Error` implementations, the synthetic scene generator (`ops::scene`),
+//!   and the video tracker (`ops::tracker`, `ops::fusion`).
+//!   Implies `alloc`.
+//! * `alloc` – everything that needs a heap: `Quirc`, `Decoder`, `QuircData`, the functions reading the codestream,
+//!   the encoder (`ops::encode`), the renderer (`ops::render`) and the Netpbm codec (`ops::netpbm`).
+//! * `fixed-point` – identify codes with Q16.16 perspective transforms (see `util::fixed_point`) instead of `f64` ones,
+//!   for targets without an FPU. The sampled grids match the `f64` path's on the corpus in `tests/corpus`.
+//! * `image` – scanning any `image::GenericImageView` (`Quirc::process_image()`) or image file (`decode_image_file()`),
+//!   which covers formats other than Netpbm (PNG, JPEG, BMP, TIFF, GIF, WebP) in the `quirc` binary too. Implies `std`.
+//! * `rayon` – `Quirc::par_end()` and `par_results()`, which threshold, scan and decode on the rayon thread pool,
+//!   with the same results as `end()` and `results()`. Implies `std`.
+//! * `c-reference` – build the original C quirc in `quirc/` alongside, for `tests/differential.rs` to compare against;
+//!   needs a C compiler. Implies `std`.
+//!
+//! With neither, the crate only depends on `core`: what remains are the Galois field, BCH and Reed-Solomon routines,
+//! the version database, the constants, and decoding `QuircCode`s with `QuircCode::decode_buf()`,
+//! into scratch and payload buffers of the caller's.
 
+#![no_std]
 
+#[cfg(feature = "std")]
+extern crate std;
+#[cfg(feature = "alloc")]
+#[macro_use]
+extern crate alloc;
+#[cfg(feature = "std")]
 extern crate libc;
+#[cfg(feature = "image")]
+extern crate image;
+#[cfg(feature = "image")]
+extern crate num_traits;
+#[cfg(feature = "rayon")]
+extern crate rayon;
 
 pub mod constants;
 pub mod util;
+#[cfg(feature = "std")]
 pub mod ffi;
 pub mod ops;
+
+#[cfg(feature = "alloc")]
+pub use util::scan;
+#[cfg(feature = "alloc")]
+pub use util::scan_tiled;
+#[cfg(feature = "image")]
+pub use util::decode_image_file;
@@ -0,0 +1,40 @@
+//! QR-code encoding
+//!
+//! The output is a `QuircCode` with the same `cell_bitmap` layout `QuircCode::decode()` reads,
+//! and decoding it gives back the data that went in.
+//!
+//! `encode()`, `encode_text()` and `encode_shift_jis()` split the data into the segments taking up the fewest bits
+//! (see `optimal_segments()`); `encode_segments()` takes them as given.
+
+
+pub use self::super::super::util::{encode, encode_text, encode_shift_jis, encode_segments, optimal_segments};
+
+
+/// A run of data in a single mode.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Segment<'d> {
+    /// ASCII digits.
+    Numeric(&'d [u8]),
+    /// ASCII digits, upper-case letters, and ` $%*+-./:`.
+    Alpha(&'d [u8]),
+    /// Arbitrary bytes.
+    Byte(&'d [u8]),
+    /// Shift JIS double-byte characters, in the ranges `0x8140..=0x9FFC` and `0xE040..=0xEBBF`.
+    Kanji(&'d [u8]),
+    /// Switch the interpretation of the following segments to the given ECI (see the `QUIRC_ECI_*` constants).
+    Eci(u32),
+}
+
+/// How to lay out the symbol.
+///
+/// `Default` picks everything automatically.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EncodeOptions {
+    /// Between 1 and 40, or `None` for the smallest that fits.
+    pub version: Option<u32>,
+    /// One of the `QUIRC_ECC_LEVEL_*` constants, or `None` for the strongest that fits in the version
+    /// which ECC level M (or, failing that, L) needs.
+    pub ecc_level: Option<u8>,
+    /// Between 0 and 7, or `None` for the one with the lowest `MaskPenalty`.
+    pub mask: Option<u8>,
+}
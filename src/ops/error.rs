@@ -0,0 +1,227 @@
+//! Errors returned by the Rust API.
+
+
+#[cfg(feature = "std")]
+use std::error::Error;
+#[cfg(feature = "image")]
+use std::io;
+#[cfg(feature = "image")]
+use image;
+use core::fmt;
+
+
+/// The various decoder errors which may occur.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DecodeErrorKind {
+    InvalidGridSize,
+    InvalidVersion,
+    FormatEcc,
+    DataEcc,
+    UnknownDataType,
+    DataOverflow,
+    DataUnderflow,
+}
+
+impl DecodeErrorKind {
+    /// The same message `quirc_strerror()` returns for this error.
+    pub fn description(&self) -> &'static str {
+        match *self {
+            DecodeErrorKind::InvalidGridSize => "Invalid grid size",
+            DecodeErrorKind::InvalidVersion => "Invalid version",
+            DecodeErrorKind::FormatEcc => "Format data ECC failure",
+            DecodeErrorKind::DataEcc => "ECC failure",
+            DecodeErrorKind::UnknownDataType => "Unknown data type",
+            DecodeErrorKind::DataOverflow => "Data overflow",
+            DecodeErrorKind::DataUnderflow => "Data underflow",
+        }
+    }
+}
+
+impl fmt::Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+
+/// A decoding failure, with whatever was known about the code by the time it happened.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DecodeError {
+    pub kind: DecodeErrorKind,
+
+    /// The version implied by the grid size, if it was valid.
+    pub version: Option<u32>,
+    /// The raw (unmasked, uncorrected) format bits of each copy that was read.
+    pub format_bits: [Option<u16>; 2],
+    /// The index of the RS block which failed to correct, in codestream order.
+    pub block: Option<u32>,
+}
+
+impl DecodeError {
+    /// An error of the specified kind with no context.
+    pub fn new(kind: DecodeErrorKind) -> DecodeError {
+        DecodeError {
+            kind,
+            version: None,
+            format_bits: [None, None],
+            block: None,
+        }
+    }
+}
+
+impl From<DecodeErrorKind> for DecodeError {
+    fn from(kind: DecodeErrorKind) -> DecodeError {
+        DecodeError::new(kind)
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.kind.fmt(f)?;
+
+        if let Some(block) = self.block {
+            write!(f, " in block {}", block)?;
+        }
+        if let Some(version) = self.version {
+            write!(f, " (version {}", version)?;
+
+            let mut separator = ", format bits ";
+            for bits in self.format_bits.iter().flatten() {
+                write!(f, "{}{:#06x}", separator, bits)?;
+                separator = "/";
+            }
+            f.write_str(")")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for DecodeError {}
+
+
+/// Returned when extracting a code which wasn't identified.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ExtractError {
+    /// The requested index.
+    pub index: usize,
+    /// How many codes were actually identified.
+    pub count: usize,
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "No code at index {}, only {} identified", self.index, self.count)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for ExtractError {}
+
+
+/// Why some data couldn't be encoded.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EncodeError {
+    /// The requested version isn't between 1 and 40.
+    InvalidVersion(u32),
+    /// The requested ECC level isn't one of the `QUIRC_ECC_LEVEL_*` constants.
+    InvalidEccLevel(u8),
+    /// The requested mask isn't between 0 and 7.
+    InvalidMask(u8),
+    /// The ECI designator is over 999999.
+    InvalidEci(u32),
+    /// The byte at `offset` into `segment` can't be represented in that segment's mode.
+    InvalidCharacter {
+        segment: usize,
+        offset: usize,
+    },
+    /// The data doesn't fit, even in the last version tried, at the last ECC level tried.
+    DataTooLong {
+        version: u32,
+        ecc_level: u8,
+    },
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EncodeError::InvalidVersion(version) => write!(f, "Invalid version {}", version),
+            EncodeError::InvalidEccLevel(ecc_level) => write!(f, "Invalid ECC level {}", ecc_level),
+            EncodeError::InvalidMask(mask) => write!(f, "Invalid mask {}", mask),
+            EncodeError::InvalidEci(eci) => write!(f, "Invalid ECI designator {}", eci),
+            EncodeError::InvalidCharacter { segment, offset } => write!(f, "Invalid character at offset {} of segment {}", offset, segment),
+            EncodeError::DataTooLong { version, ecc_level } => write!(f, "Data too long for version {} at ECC level {}", version, ecc_level),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for EncodeError {}
+
+
+/// Why a Netpbm image couldn't be read.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NetpbmError {
+    /// The data doesn't start with one of `P1` to `P7`.
+    UnknownFormat,
+    /// The named header field is missing or malformed, or the width or height is 0.
+    InvalidHeader(&'static str),
+    /// The maxval is 0, or over 65535.
+    InvalidMaxval(u32),
+    /// The PAM depth isn't between 1 and 4.
+    UnsupportedDepth(u32),
+    /// The sample starting at `offset` into the data is malformed or over the maxval.
+    InvalidSample {
+        offset: usize,
+    },
+    /// The data ends before the last sample.
+    Truncated,
+}
+
+impl fmt::Display for NetpbmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NetpbmError::UnknownFormat => f.write_str("Not a Netpbm image"),
+            NetpbmError::InvalidHeader(field) => write!(f, "Invalid {} in header", field),
+            NetpbmError::InvalidMaxval(maxval) => write!(f, "Invalid maxval {}", maxval),
+            NetpbmError::UnsupportedDepth(depth) => write!(f, "Unsupported depth {}", depth),
+            NetpbmError::InvalidSample { offset } => write!(f, "Invalid sample at offset {}", offset),
+            NetpbmError::Truncated => f.write_str("Image data truncated"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for NetpbmError {}
+
+
+/// Why `decode_image_file()` couldn't read an image.
+#[cfg(feature = "image")]
+#[derive(Debug)]
+pub enum ImageError {
+    /// The file couldn't be read.
+    Io(io::Error),
+    /// The file isn't an image in a supported format, or is corrupt.
+    Decode(image::ImageError),
+}
+
+#[cfg(feature = "image")]
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ImageError::Io(ref e) => write!(f, "Couldn't read image: {}", e),
+            ImageError::Decode(ref e) => write!(f, "Couldn't decode image: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "image")]
+impl Error for ImageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ImageError::Io(ref e) => Some(e),
+            ImageError::Decode(ref e) => Some(e),
+        }
+    }
+}
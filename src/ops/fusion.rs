@@ -0,0 +1,37 @@
+//! Fusing a code's cells across video frames
+//!
+//! Glare, reflections and motion blur can spoil a different part of a code in every frame, so that no single frame decodes.
+//! A `QuircFusion` sums how dark each cell of a tracked code looked in every frame it was followed through,
+//! and decodes the cells by majority instead.
+
+
+use self::super::ScanResult;
+use std::vec::Vec;
+
+
+/// A tracked code, decoded from every frame it's been seen in so far.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct FusedCode {
+    /// The tracker's ID for the code.
+    pub id: u64,
+    /// How many frames the cells were fused from, this one included.
+    pub frames: u32,
+    /// The fused cells, with the corners in the latest frame, and what came of decoding them.
+    pub result: ScanResult,
+}
+
+/// The running totals for one tracked code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct CellVotes {
+    pub(crate) id: u64,
+    pub(crate) grid_size: u32,
+    pub(crate) frames: u32,
+    /// Per cell, in the order of `QuircCode::cell_bitmap`, how many more of its samples have been dark than light.
+    pub(crate) votes: Vec<i32>,
+}
+
+/// Accumulates the cells of the codes a `QuircTracker` follows, keyed by their IDs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QuircFusion {
+    pub(crate) codes: Vec<CellVotes>,
+}
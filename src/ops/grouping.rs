@@ -0,0 +1,39 @@
+//! Global capstone grouping
+//!
+//! `end()` groups capstones greedily: each takes the best horizontal and vertical neighbours still free, and keeps them if
+//! the timing patterns between them check out. On sheets of tightly packed labels a capstone's neighbours in the next code along
+//! can look as good as its own, and once taken they're lost to the code they belong to.
+//!
+//! `Quirc::end_global()` scores every candidate triple instead, by its shape, how alike its capstones' sizes are,
+//! and how well its timing patterns agree, and picks the set of non-overlapping triples with the most codes, and the lowest total score among those.
+//! Every candidate is returned, with why it wasn't used if it wasn't.
+//!
+//! The search for that set is cut off after a fixed budget of steps, settling for the best set found by then,
+//! so with many overlapping candidates the set picked can fall short of the best one.
+
+
+/// Why a candidate triple wasn't made into a grid.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GroupRejection {
+    /// Its sides are too different in length, as `end()` tests.
+    Shape,
+    /// Its capstones are too different in size to be one code's, or too far apart or too close for the grid size its timing patterns make for.
+    ModuleSize,
+    /// Neither timing pattern could be scanned.
+    Timing,
+    /// A better assignment used one of its capstones.
+    Conflict,
+    /// It was picked, but the grid couldn't be set up, or there was no room left for it.
+    Setup,
+}
+
+/// A triple of capstones that could make up a code, and how it fared.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GroupCandidate {
+    /// Indices of the capstones: the horizontal neighbour, the corner, and the vertical neighbour, as `test_grouping()` would pass them.
+    pub caps: [u32; 3],
+    /// Lower is better; the sum of the shape, size and timing errors, each in thousandths.
+    pub score: u32,
+    /// `None` if it was made into a grid.
+    pub rejection: Option<GroupRejection>,
+}
@@ -1,15 +1,63 @@
 pub mod version_db;
-
+#[cfg(feature = "alloc")]
+pub mod encode;
+#[cfg(feature = "alloc")]
+pub mod render;
+#[cfg(feature = "alloc")]
+pub mod netpbm;
+#[cfg(feature = "alloc")]
+pub mod pyramid;
+#[cfg(feature = "alloc")]
+pub mod grouping;
+#[cfg(feature = "alloc")]
+pub mod tiled;
+#[cfg(feature = "std")]
+pub mod scene;
+#[cfg(feature = "std")]
+pub mod tracker;
+#[cfg(feature = "std")]
+pub mod fusion;
+mod error;
+
+#[cfg(feature = "std")]
 use self::super::ffi::{FfiQuircPoint, FfiQuircCode, FfiQuircData};
-use self::super::constants::{QUIRC_MAX_PAYLOAD, QUIRC_MAX_BITMAP};
-use std::{hash, cmp, fmt, u16};
-
-
+#[cfg(feature = "alloc")]
+use self::super::util::Datastream;
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use self::super::constants::{QUIRC_DATA_TYPE_NUMERIC, QUIRC_DATA_TYPE_ALPHA, QUIRC_DATA_TYPE_BYTE};
+use self::super::constants::QUIRC_MAX_BITMAP;
+#[cfg(feature = "alloc")]
+use self::encode::Segment;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::{hash, cmp, fmt};
+
+pub use self::error::{DecodeErrorKind, DecodeError, ExtractError, EncodeError, NetpbmError};
+#[cfg(feature = "image")]
+pub use self::error::ImageError;
+
+
+#[cfg(feature = "alloc")]
 pub(crate) const QUIRC_MAX_REGIONS: usize = 256;
+#[cfg(feature = "alloc")]
 pub(crate) const QUIRC_MAX_CAPSTONES: usize = 32;
+#[cfg(feature = "alloc")]
 pub(crate) const QUIRC_MAX_GRIDS: usize = 8;
 pub(crate) const QUIRC_PERSPECTIVE_PARAMS: usize = 8;
 
+/// The parameters of a perspective transform from grid to image space.
+///
+/// `f64`s by default, fixed-point with the `fixed-point` feature (see `util::fixed_point` for the format).
+#[cfg(not(feature = "fixed-point"))]
+pub type QuircPerspective = [f64; QUIRC_PERSPECTIVE_PARAMS];
+/// The parameters of a perspective transform from grid to image space.
+///
+/// `f64`s by default, fixed-point with the `fixed-point` feature (see `util::fixed_point` for the format).
+#[cfg(feature = "fixed-point")]
+pub type QuircPerspective = [i64; QUIRC_PERSPECTIVE_PARAMS];
+
 
 /// This structure describes a location in the input image buffer.
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -18,6 +66,7 @@ pub struct QuircPoint {
     pub y: isize,
 }
 
+#[cfg(feature = "std")]
 impl From<FfiQuircPoint> for QuircPoint {
     fn from(code: FfiQuircPoint) -> QuircPoint {
         QuircPoint {
@@ -27,6 +76,32 @@ impl From<FfiQuircPoint> for QuircPoint {
     }
 }
 
+/// A rectangle of the input image buffer, such as a region of interest for `Quirc::end_roi()`.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct QuircRect {
+    /// Left column.
+    pub x: usize,
+    /// Top row.
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// The instruction set thresholding and pixel setup are vectorised with.
+///
+/// `SimdLevel::detect()` picks the best one the CPU has; they all give exactly the same pixels.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SimdLevel {
+    /// Plain Rust, on any target.
+    Scalar,
+    /// x86 SSE2.
+    Sse2,
+    /// x86 AVX2.
+    Avx2,
+    /// AArch64 NEON.
+    Neon,
+}
+
 
 /// This structure is used to return information about detected QR codes
 /// in the input image.
@@ -49,6 +124,7 @@ pub struct QuircCode {
     pub cell_bitmap: [u8; QUIRC_MAX_BITMAP],
 }
 
+#[cfg(feature = "std")]
 impl From<FfiQuircCode> for QuircCode {
     fn from(code: FfiQuircCode) -> QuircCode {
         QuircCode {
@@ -71,10 +147,9 @@ impl fmt::Debug for QuircCode {
 
 impl cmp::PartialEq for QuircCode {
     fn eq(&self, other: &QuircCode) -> bool {
-        self.corners == other.corners &&                  // align
-        self.size == other.size &&                        // align
-        self.cell_bitmap[..] == other.cell_bitmap[..] &&  // align
-        true
+        self.corners == other.corners &&  // align
+        self.size == other.size &&        // align
+        self.cell_bitmap[..] == other.cell_bitmap[..]
     }
 }
 
@@ -106,7 +181,8 @@ impl hash::Hash for QuircCode {
 
 /// This structure is used to return information about detected QR codes
 /// in the input image.
-#[derive(Copy, Clone)]
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct QuircData {
     /// Various parameters of the QR-code. These can mostly be
     /// ignored if you only care about the data.
@@ -118,15 +194,77 @@ pub struct QuircData {
     /// code.
     pub data_type: u8,
 
-    /// Data payload. For the Kanji datatype, payload is encoded as
-    /// Shift-JIS. For all other datatypes, payload is ASCII text.
-    pub payload: [u8; QUIRC_MAX_PAYLOAD],
-    pub payload_len: usize,
+    /// Data payload, see `payload()`.
+    pub(crate) payload: Vec<u8>,
+    /// Each segment, in order, see `segments()`.
+    pub(crate) segments: Vec<SegmentSpan>,
 
     /// ECI assignment number
     pub eci: u32,
+
+    /// Set if the format information was unreadable and had to be recovered,
+    /// see `QuircCode::decode_recover_format()`.
+    pub format_recovery: Option<QuircFormatRecovery>,
 }
 
+/// A segment of a `QuircData`, as recorded while decoding.
+#[cfg(feature = "alloc")]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum SegmentSpan {
+    /// Data in the given mode, ending this far into the payload.
+    Data { mode: u8, end: u32 },
+    /// An ECI designator.
+    Eci(u32),
+}
+
+#[cfg(feature = "alloc")]
+impl QuircData {
+    /// Data payload. For the Kanji datatype, payload is encoded as
+    /// Shift-JIS. For all other datatypes, payload is ASCII text.
+    ///
+    /// Unlike in the C API, there is no nul terminator.
+    #[inline(always)]
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Take ownership of the payload.
+    #[inline(always)]
+    pub fn into_payload(self) -> Vec<u8> {
+        self.payload
+    }
+
+    /// The segments the payload was decoded from, in order, as `encode::encode_segments()` would take them.
+    ///
+    /// Empty for data converted from `FfiQuircData`, which doesn't record them.
+    pub fn segments(&self) -> Vec<Segment<'_>> {
+        let mut start = 0;
+
+        self.segments
+            .iter()
+            .map(|&span| {
+                let (mode, end) = match span {
+                    SegmentSpan::Data { mode, end } => (mode, end),
+                    SegmentSpan::Eci(eci) => return Segment::Eci(eci),
+                };
+
+                // The payload may have been truncated to QUIRC_MAX_PAYLOAD - 1
+                let end = (end as usize).min(self.payload.len());
+                let data = &self.payload[start..end];
+                start = end;
+
+                match mode {
+                    QUIRC_DATA_TYPE_NUMERIC => Segment::Numeric(data),
+                    QUIRC_DATA_TYPE_ALPHA => Segment::Alpha(data),
+                    QUIRC_DATA_TYPE_BYTE => Segment::Byte(data),
+                    _ => Segment::Kanji(data),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "std")]
 impl From<FfiQuircData> for QuircData {
     fn from(data: FfiQuircData) -> QuircData {
         QuircData {
@@ -134,75 +272,142 @@ impl From<FfiQuircData> for QuircData {
             ecc_level: data.ecc_level as u8,
             mask: data.mask as u8,
             data_type: data.data_type as u8,
-            payload: data.payload,
-            payload_len: data.payload_len as usize,
+            payload: data.payload[..data.payload_len as usize].to_vec(),
+            segments: Vec::new(),
             eci: data.eci,
+            format_recovery: None,
         }
     }
 }
 
-impl fmt::Debug for QuircData {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("QuircData")
-            .field("version", &self.version)
-            .field("ecc_level", &self.ecc_level)
-            .field("mask", &self.mask)
-            .field("data_type", &self.data_type)
-            .field("payload", &&self.payload[..])
-            .field("payload_len", &self.payload_len)
-            .field("eci", &self.eci)
-            .finish()
-    }
+
+/// Decoded data like `QuircData`, but with the payload in a buffer of the caller's, for decoding without an allocator.
+///
+/// Returned by `QuircCode::decode_buf()`.
+#[derive(Debug, Hash, PartialEq, Eq)]
+pub struct QuircDataBuf<'a> {
+    /// Various parameters of the QR-code. These can mostly be
+    /// ignored if you only care about the data.
+    pub version: u32,
+    pub ecc_level: u8,
+    pub mask: u8,
+
+    /// This field is the highest-valued data type found in the QR
+    /// code.
+    pub data_type: u8,
+
+    /// The caller's buffer, of which the first `payload_len` bytes are the payload, see `payload()`.
+    pub(crate) payload: &'a mut [u8],
+    pub(crate) payload_len: usize,
+
+    /// ECI assignment number
+    pub eci: u32,
 }
 
-impl cmp::PartialEq for QuircData {
-    fn eq(&self, other: &QuircData) -> bool {
-        self.version == other.version &&          // align
-        self.ecc_level == other.ecc_level &&      // align
-        self.mask == other.mask &&                // align
-        self.data_type == other.data_type &&      // align
-        self.payload[..] == other.payload[..] &&  // align
-        self.payload_len == other.payload_len &&  // align
-        self.eci == other.eci &&                  // align
-        true
+impl<'a> QuircDataBuf<'a> {
+    /// Data payload. For the Kanji datatype, payload is encoded as
+    /// Shift-JIS. For all other datatypes, payload is ASCII text.
+    ///
+    /// Unlike in the C API, there is no nul terminator.
+    #[inline(always)]
+    pub fn payload(&self) -> &[u8] {
+        &self.payload[..self.payload_len]
     }
 }
 
-impl cmp::Eq for QuircData {}
+/// Where decoding (`util::decode_code()` & co.) puts what it reads from a code: a `QuircData`, or a `QuircDataBuf`.
+pub trait DecodeTarget {
+    /// The version implied by the grid size.
+    fn version(&self) -> u32;
+    fn ecc_level(&self) -> u8;
+    fn mask(&self) -> u8;
+    /// Record the ECC level and mask read from the format information.
+    fn set_format(&mut self, ecc_level: u8, mask: u8);
+
+    /// How long the payload may grow; decoding fails with `DecodeErrorKind::DataOverflow` past that.
+    fn payload_capacity(&self) -> usize;
+    fn payload_len(&self) -> usize;
+    /// Append a byte to the payload, which is shorter than `payload_capacity()`.
+    fn push_payload(&mut self, byte: u8);
+    fn set_eci(&mut self, eci: u32);
+    /// Called after each segment is decoded, with its mode.
+    fn end_segment(&mut self, mode: u8);
+}
 
-impl cmp::Ord for QuircData {
-    fn cmp(&self, other: &QuircData) -> cmp::Ordering {
-        self.version
-            .cmp(&other.version)
-            .then(self.ecc_level.cmp(&other.ecc_level))
-            .then(self.mask.cmp(&other.mask))
-            .then(self.data_type.cmp(&other.data_type))
-            .then(self.payload[..].cmp(&other.payload))
-            .then(self.payload_len.cmp(&other.payload_len))
-            .then(self.eci.cmp(&other.eci))
-    }
+
+/// The ECC level and mask combination found by exhaustive search when both copies
+/// of the format information failed to correct.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct QuircFormatRecovery {
+    pub ecc_level: u8,
+    pub mask: u8,
+
+    /// Whether this was the only combination that fully corrected
+    /// with consistent terminator and padding.
+    pub confident: bool,
 }
 
-impl cmp::PartialOrd for QuircData {
-    fn partial_cmp(&self, other: &QuircData) -> Option<cmp::Ordering> {
-        Some(self.cmp(other))
-    }
+/// The ISO/IEC 18004 mask penalty scores of a symbol; lower is better.
+///
+/// See `QuircCode::mask_penalty()` and `QuircCode::mask_penalties()`.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MaskPenalty {
+    /// Runs of five or more same-coloured cells in a row or column: 3, plus 1 for each cell past the fifth.
+    pub n1: u32,
+    /// 2x2 blocks of the same colour: 3 each.
+    pub n2: u32,
+    /// Dark-light-dark-dark-dark-light-dark patterns with four light cells on either side: 40 each.
+    pub n3: u32,
+    /// 10 for every full 5% the proportion of dark cells strays from half.
+    pub n4: u32,
 }
 
-impl hash::Hash for QuircData {
-    fn hash<H: hash::Hasher>(&self, state: &mut H) {
-        self.version.hash(state);
-        self.ecc_level.hash(state);
-        self.mask.hash(state);
-        self.data_type.hash(state);
-        self.payload.hash(state);
-        self.payload_len.hash(state);
-        self.eci.hash(state);
+impl MaskPenalty {
+    /// The score mask selection minimises.
+    #[inline]
+    pub fn total(&self) -> u32 {
+        self.n1 + self.n2 + self.n3 + self.n4
     }
 }
 
 
 
+/// A reusable decoding context, owning the scratch buffers used to read and correct the codestream.
+///
+/// `QuircCode::decode()` allocates a new one each time.
+#[cfg(feature = "alloc")]
+pub struct Decoder {
+    pub(crate) ds: Datastream<Box<[u8]>>,
+}
+
+/// A code identified in an image, and what came of decoding it.
+///
+/// Returned by `scan()`, `Quirc::results()` and `Quirc::process_image()`.
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct ScanResult {
+    /// The four corners of the QR-code, from top left, clockwise; the same as `code.corners`.
+    pub corners: [QuircPoint; 4],
+    /// The extracted cells.
+    pub code: QuircCode,
+    /// The payload, or why it couldn't be read.
+    pub data: Result<QuircData, DecodeError>,
+    /// Whether one of the capstones was inferred from the other two rather than found (see `Quirc::end_recover()`),
+    /// so that error correction had a corner's worth of cells to make up for.
+    pub inferred: bool,
+}
+
+/// An iterator over the codes identified in the last processed image, decoding each as it goes.
+///
+/// See `Quirc::results()`.
+#[cfg(feature = "alloc")]
+pub struct QuircResults<'a> {
+    pub(crate) quirc: &'a Quirc,
+    pub(crate) index: usize,
+    pub(crate) decoder: Decoder,
+}
+
+
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct QuircRegion {
     pub seed: QuircPoint,
@@ -217,7 +422,7 @@ pub struct QuircCapstone {
 
     pub corners: [QuircPoint; 4],
     pub center: QuircPoint,
-    pub c: [f64; QUIRC_PERSPECTIVE_PARAMS],
+    pub c: QuircPerspective,
 
     pub qr_grid: i32,
 }
@@ -238,10 +443,14 @@ pub struct QuircGrid {
 
     /// Grid size and perspective transform
     pub grid_size: u32,
-    pub c: [f64; QUIRC_PERSPECTIVE_PARAMS],
+    pub c: QuircPerspective,
+
+    /// Whether one of the capstones was inferred from the other two, by `Quirc::end_recover()`
+    pub inferred: bool,
 }
 
 
+#[cfg(feature = "alloc")]
 #[derive(Clone)]
 pub struct Quirc {
     pub(crate) image: Vec<u8>,
@@ -261,6 +470,15 @@ pub struct Quirc {
     pub(crate) grids: [QuircGrid; QUIRC_MAX_GRIDS],
 }
 
+#[cfg(feature = "alloc")]
+impl Default for Quirc {
+    /// Same as `Quirc::new()`.
+    fn default() -> Quirc {
+        Quirc::new()
+    }
+}
+
+#[cfg(feature = "alloc")]
 impl Quirc {
     /// Construct a new QR-code recognizer.
     pub fn new() -> Quirc {
@@ -287,7 +505,7 @@ impl Quirc {
 
                 corners: [NULL_POINT; 4],
                 center: NULL_POINT,
-                c: [0f64; QUIRC_PERSPECTIVE_PARAMS],
+                c: [Default::default(); QUIRC_PERSPECTIVE_PARAMS],
 
                 qr_grid: 0,
             }; QUIRC_MAX_CAPSTONES],
@@ -304,7 +522,9 @@ impl Quirc {
                 vscan: 0,
 
                 grid_size: 0,
-                c: [0f64; QUIRC_PERSPECTIVE_PARAMS],
+                c: [Default::default(); QUIRC_PERSPECTIVE_PARAMS],
+
+                inferred: false,
             }; QUIRC_MAX_GRIDS],
         }
     }
@@ -334,6 +554,7 @@ impl Quirc {
 
 
 // These traits are implemented manually, because rustc is at a loss for big arrays.
+#[cfg(feature = "alloc")]
 impl fmt::Debug for Quirc {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Quirc")
@@ -352,6 +573,7 @@ impl fmt::Debug for Quirc {
     }
 }
 
+#[cfg(feature = "alloc")]
 impl cmp::PartialEq for Quirc {
     fn eq(&self, other: &Quirc) -> bool {
         self.image == other.image &&                  // align
@@ -369,6 +591,7 @@ impl cmp::PartialEq for Quirc {
     }
 }
 
+#[cfg(feature = "alloc")]
 impl cmp::PartialOrd for Quirc {
     fn partial_cmp(&self, other: &Quirc) -> Option<cmp::Ordering> {
         self.image[..]
@@ -0,0 +1,37 @@
+//! Reading and writing Netpbm images
+//!
+//! PBM, PGM and PPM, both ASCII (`P1` to `P3`) and binary (`P4` to `P6`), and PAM (`P7`), with maxvals up to 65535;
+//! enough to get images into a `Quirc` (see `Quirc::load_netpbm()`) and debugging images out (see `Quirc::pixels_netpbm()`)
+//! without a third-party decoder.
+
+
+use alloc::vec::Vec;
+
+
+/// The formats `NetpbmImage::write()` produces.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NetpbmFormat {
+    /// Black and white, thresholded at half the maxval.
+    Pbm,
+    /// Grayscale; colour is converted to Rec. 601 luma.
+    Pgm,
+    /// RGB; grayscale is replicated into every channel.
+    Ppm,
+    /// The samples as they are, with the tuple type matching the depth. There is no ASCII PAM.
+    Pam,
+}
+
+/// A decoded image, any of the formats read as samples of the same kind.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NetpbmImage {
+    pub width: usize,
+    pub height: usize,
+    /// Samples per pixel: 1 for grayscale, 2 for grayscale with alpha, 3 for RGB, 4 for RGB with alpha.
+    pub depth: usize,
+    /// Between 1 and 65535.
+    ///
+    /// PBMs are read with maxval 1, 0 being black and 1 white, as in PAM's `BLACKANDWHITE` tuple type.
+    pub maxval: u16,
+    /// `width * height * depth` samples, each at most `maxval`, row by row.
+    pub samples: Vec<u16>,
+}
@@ -0,0 +1,41 @@
+//! Multi-scale detection
+//!
+//! `Quirc::end_pyramid()` looks for codes in scaled copies of the image as well as the image itself:
+//! a downscaled level is quicker to search on large images and finds codes too big for the adaptive threshold,
+//! and an upscaled one finds codes whose cells are only a pixel or two wide.
+//! Whatever level a code is found on, its perspective transform is refined and its cells sampled at full resolution,
+//! so corners are always in image space.
+//!
+//! By default the levels are searched from the coarsest, stopping at the first that finds anything,
+//! so large codes in large images are found without thresholding the whole image.
+
+
+/// Which levels to search.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PyramidOptions {
+    /// Search the image itself, as `end()` does.
+    ///
+    /// Turning this off and relying on `downscale` is the fast path for large images with large codes.
+    pub native: bool,
+    /// Search a level this many times smaller, each pixel the average of a `downscale`×`downscale` block; 1 for none.
+    pub downscale: u32,
+    /// Search a level this many times larger, interpolated bilinearly; 1 for none.
+    pub upscale: u32,
+    /// Only upscale images of at most this many pixels.
+    pub max_upscale_pixels: usize,
+    /// Search every level, even once one has found codes.
+    pub exhaustive: bool,
+}
+
+impl Default for PyramidOptions {
+    /// A quarter-size level, then the image itself, then a double-size level for images of up to a megapixel, until one finds codes.
+    fn default() -> PyramidOptions {
+        PyramidOptions {
+            native: true,
+            downscale: 4,
+            upscale: 2,
+            max_upscale_pixels: 1 << 20,
+            exhaustive: false,
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Rendering QR-codes as images and text
+//!
+//! Any `QuircCode` can be rendered, whether it came from the encoder or from `Quirc::extract()`,
+//! see `QuircCode::render_pbm()`, `render_pgm()`, `render_png()`, `render_svg()` and `render_text()`.
+
+
+/// How to draw the cells.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RenderOptions {
+    /// Width and height of a cell in pixels; text output always uses one character column and half a row.
+    pub module_size: u32,
+    /// Width of the light margin around the symbol, in cells.
+    pub quiet_zone: u32,
+    /// RGB colour of dark cells; PBM output is always black, and PGM output uses its luma.
+    pub foreground: [u8; 3],
+    /// RGB colour of light cells and the quiet zone; PBM output is always white, and PGM output uses its luma.
+    pub background: [u8; 3],
+    /// Draw dark cells in the background colour and light ones in the foreground colour.
+    ///
+    /// Text output draws dark cells as blocks, which show up light on most terminals, unless this is set.
+    pub invert: bool,
+}
+
+impl Default for RenderOptions {
+    /// 4-pixel cells, the 4-cell quiet zone the standard requires, black on white.
+    fn default() -> RenderOptions {
+        RenderOptions {
+            module_size: 4,
+            quiet_zone: 4,
+            foreground: [0x00, 0x00, 0x00],
+            background: [0xff, 0xff, 0xff],
+            invert: false,
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Synthetic scenes for robustness testing
+//!
+//! `Scene::generate()` encodes payloads, warps the symbols into a grayscale image, degrades it,
+//! and records where each symbol ended up, for comparing against what `Quirc` finds.
+//!
+//! Everything is driven by `SceneOptions::seed`, so a scene can be regenerated exactly.
+
+
+use self::super::encode::EncodeOptions;
+use self::super::QuircPoint;
+use std::vec::Vec;
+
+
+/// Where and how to draw one symbol.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScenePlacement {
+    pub payload: Vec<u8>,
+    pub encode: EncodeOptions,
+
+    /// Centre of the symbol, in pixels.
+    pub center: (f64, f64),
+    /// Width of a cell, in pixels, before the perspective warp.
+    pub module_size: f64,
+    /// Clockwise, in degrees.
+    pub rotation: f64,
+    /// Perspective foreshortening: how much shorter the right edge is than the left one,
+    /// and the bottom edge than the top one, as fractions of the side. Negative values lengthen them instead.
+    pub tilt: (f64, f64),
+}
+
+impl Default for ScenePlacement {
+    /// An upright, flat, 4-pixel-per-cell symbol at (160, 160), encoded with the default options.
+    fn default() -> ScenePlacement {
+        ScenePlacement {
+            payload: Vec::new(),
+            encode: EncodeOptions::default(),
+            center: (160.0, 160.0),
+            module_size: 4.0,
+            rotation: 0.0,
+            tilt: (0.0, 0.0),
+        }
+    }
+}
+
+/// The image and the degradations applied to it after drawing the symbols, in the order they're applied.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SceneOptions {
+    pub width: usize,
+    pub height: usize,
+    /// Gray level of light cells and the background.
+    pub light: u8,
+    /// Gray level of dark cells.
+    pub dark: u8,
+
+    /// Fraction of each symbol's area covered by random mid-gray patches.
+    pub occlusion: f64,
+    /// Standard deviation of the Gaussian blur, in pixels; 0 for none.
+    pub blur: f64,
+    /// How much darker one side of the image is than the other, as a fraction of the brightness, in a random direction.
+    pub illumination: f64,
+    /// Standard deviation of the Gaussian noise, in gray levels.
+    pub noise: f64,
+    /// Quantise each 8x8 block's DCT with the JPEG luminance table at this quality (1-100); 0 for none.
+    pub jpeg_quality: u8,
+
+    pub seed: u64,
+}
+
+impl Default for SceneOptions {
+    /// A clean, 320x320, black-on-white scene.
+    fn default() -> SceneOptions {
+        SceneOptions {
+            width: 320,
+            height: 320,
+            light: 0xff,
+            dark: 0x00,
+            occlusion: 0.0,
+            blur: 0.0,
+            illumination: 0.0,
+            noise: 0.0,
+            jpeg_quality: 0,
+            seed: 0,
+        }
+    }
+}
+
+/// What was drawn, and where.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct SceneTruth {
+    /// The corners of the symbol, from top left, clockwise, as in `QuircCode::corners`.
+    pub corners: [QuircPoint; 4],
+    pub version: u32,
+    pub payload: Vec<u8>,
+}
+
+/// A generated grayscale image, one byte per pixel, row by row, with what's in it.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Scene {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+    pub truth: Vec<SceneTruth>,
+}
@@ -0,0 +1,33 @@
+//! Tiled scanning
+//!
+//! `scan_tiled()` scans an image too large to hold in memory a tile at a time, reading each tile's pixels as it goes,
+//! so that only one tile's worth of buffers is ever allocated.
+//! Tiles overlap so that most codes are whole in at least one of them; codes found in several are reported once.
+//! Capstones left over once every tile's been searched, those of codes straddling tiles,
+//! are grouped across the tile borders into triples that could be a code's, and each triple is searched for again in a window around it.
+//! Corners are always in the coordinates of the whole image.
+
+
+/// How to cut up the image.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TileOptions {
+    pub tile_width: usize,
+    pub tile_height: usize,
+    /// How many pixels neighbouring tiles share; codes up to this big are whole in some tile wherever they are.
+    pub overlap: usize,
+    /// The most pixels a window around capstones straddling tiles may have; codes needing bigger ones aren't looked for.
+    pub max_window: usize,
+}
+
+impl Default for TileOptions {
+    /// 2048×2048 tiles overlapping by 256 pixels, and windows of up to 4096×4096 pixels,
+    /// for buffers of at most 12 MiB for tiles and 48 MiB for windows.
+    fn default() -> TileOptions {
+        TileOptions {
+            tile_width: 2048,
+            tile_height: 2048,
+            overlap: 256,
+            max_window: 4096 * 4096,
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Following codes through video
+//!
+//! A `QuircTracker` remembers where each code was in the last frame, and in the next one only refits those grids
+//! to the pixels around them, rather than searching the whole frame again. The full detector runs every few frames,
+//! and whenever a code is lost, to pick up new codes.
+//!
+//! Every code keeps the ID it was first seen with for as long as it's followed, so results can be debounced across frames.
+
+
+use self::super::{QuircPerspective, ScanResult, Quirc};
+use std::vec::Vec;
+
+
+/// How the tracker balances following codes against searching for them.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TrackerOptions {
+    /// Run the full detector at least every this many frames, even if nothing's been lost; 1 runs it every frame.
+    pub detect_interval: u32,
+    /// How far, in pixels, a code may move between frames and still be followed.
+    pub search_radius: usize,
+    /// How well, as a percentage of a perfect fit, a refitted grid has to match its frame to count as followed.
+    pub min_fitness: u32,
+}
+
+impl Default for TrackerOptions {
+    /// The full detector every 15 frames, up to 16 pixels of movement, and a 60% fit.
+    fn default() -> TrackerOptions {
+        TrackerOptions {
+            detect_interval: 15,
+            search_radius: 16,
+            min_fitness: 60,
+        }
+    }
+}
+
+/// A code in the latest frame.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct TrackedCode {
+    /// Unique to this code for as long as it's followed; IDs aren't reused.
+    pub id: u64,
+    /// How many frames ago the code was first seen; 0 when it's new.
+    pub age: u64,
+    /// Whether the full detector found the code in this frame, rather than it being followed from the last one.
+    pub detected: bool,
+    pub result: ScanResult,
+}
+
+/// A code being followed: its grid in the last frame it was seen in.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct Track {
+    pub(crate) id: u64,
+    pub(crate) first_frame: u64,
+    pub(crate) grid_size: u32,
+    pub(crate) c: QuircPerspective,
+}
+
+/// Finds codes in a sequence of frames and follows them from one to the next.
+#[derive(Clone, Debug)]
+pub struct QuircTracker {
+    pub(crate) quirc: Quirc,
+    pub(crate) options: TrackerOptions,
+    pub(crate) tracks: Vec<Track>,
+    pub(crate) next_id: u64,
+    pub(crate) frame: u64,
+    pub(crate) last_detection: u64,
+}
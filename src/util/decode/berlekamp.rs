@@ -17,12 +17,12 @@ pub fn berlekamp_massey(s: &[u8], big_n: usize, gf: &GaloisField) -> [u8; MAX_PO
     for n in 0..big_n {
         let mut d = s[n];
 
-        for i in 0..big_l + 1 {
+        for i in 1..big_l + 1 {
             if !(big_c[i] != 0 && s[n - i] != 0) {
                 continue;
             }
 
-            d ^= gf.exp[((gf.log[big_c[i] as usize] + gf.log[s[n - i] as usize]) % gf.p as u8) as usize];
+            d ^= gf.exp[(gf.log[big_c[i] as usize] as usize + gf.log[s[n - i] as usize] as usize) % gf.p];
         }
 
         let mult = gf.exp[(gf.p - gf.log[b as usize] as usize + gf.log[d as usize] as usize) % gf.p];
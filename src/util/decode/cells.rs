@@ -0,0 +1,94 @@
+//! Code layout
+
+
+use self::super::super::super::ops::version_db::{QUIRC_MAX_ALIGNMENT, QUIRC_VERSION_DB};
+
+
+/// Where each bit of the first copy of the format information lives, LSB first.
+pub static FORMAT_XS: [u32; 15] = [8, 8, 8, 8, 8, 8, 8, 8, 7, 5, 4, 3, 2, 1, 0];
+pub static FORMAT_YS: [u32; 15] = [0, 1, 2, 3, 4, 5, 7, 8, 8, 8, 8, 8, 8, 8, 8];
+
+
+pub fn mask_bit(mask: u8, i: u32, j: u32) -> u8 {
+    (match mask {
+        0 => (i + j).is_multiple_of(2),
+        1 => i.is_multiple_of(2),
+        2 => j.is_multiple_of(3),
+        3 => (i + j).is_multiple_of(3),
+        4 => ((i / 2) + (j / 3)).is_multiple_of(2),
+        5 => ((i * j) % 2 + (i * j) % 3) == 0,
+        6 => ((i * j) % 2 + (i * j) % 3).is_multiple_of(2),
+        7 => ((i * j) % 3 + (i + j) % 2).is_multiple_of(2),
+        _ => false,
+    }) as u8
+}
+
+pub fn reserved_cell(version: u32, i: u32, j: u32) -> u8 {
+    let ver = &QUIRC_VERSION_DB[version as usize];
+    let size = version * 4 + 17;
+
+    // Finder + format: top left
+    if i < 9 && j < 9 {
+        return 1;
+    }
+
+    // Finder + format: bottom left
+    if i + 8 >= size && j < 9 {
+        return 1;
+    }
+
+    // Finder + format: top right
+    if i < 9 && j + 8 >= size {
+        return 1;
+    }
+
+    // Exclude timing patterns
+    if i == 6 || j == 6 {
+        return 1;
+    }
+
+    // Exclude version info, if it exists. Version info sits adjacent to
+    // the top-right and bottom-left finders in three rows, bounded by
+    // the timing pattern.
+    if version >= 7 {
+        if i < 6 && j + 11 >= size {
+            return 1;
+        }
+        if i + 11 >= size && j < 6 {
+            return 1;
+        }
+    }
+
+    let mut ai = -1i32;
+    let mut aj = -1i32;
+    let mut a = 0i32;
+
+    // Exclude alignment patterns
+    while a < QUIRC_MAX_ALIGNMENT as i32 && ver.apat[a as usize] != 0 {
+        let p = ver.apat[a as usize];
+
+        if (p as i32 - i as i32).abs() < 3 {
+            ai = a;
+        }
+        if (p as i32 - j as i32).abs() < 3 {
+            aj = a;
+        }
+
+        a += 1;
+    }
+
+    if ai >= 0 && aj >= 0 {
+        a -= 1;
+        if ai > 0 && ai < a {
+            return 1;
+        }
+        if aj > 0 && aj < a {
+            return 1;
+        }
+        if aj == a && ai == a {
+            return 1;
+        }
+    }
+
+    0
+}
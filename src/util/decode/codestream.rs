@@ -0,0 +1,582 @@
+//! Reading, correcting and decoding the codestream, without an allocator
+
+
+pub use self::super::super::super::constants::{QUIRC_DATA_TYPE_NUMERIC, QUIRC_DATA_TYPE_ALPHA, QUIRC_DATA_TYPE_KANJI, QUIRC_DATA_TYPE_BYTE};
+use self::super::super::super::ops::version_db::{QUIRC_MAX_VERSION, QUIRC_VERSION_DB, QuircRsParams};
+pub use self::super::super::super::ops::{DecodeErrorKind, DecodeError, DecodeTarget, QuircDataBuf, QuircCode};
+use self::super::{MAX_POLY, FORMAT_MASK, correct_format, block_syndromes, correct_block, reserved_cell, mask_bit, FORMAT_XS, FORMAT_YS};
+use core::ops::DerefMut;
+
+
+/// Scratch buffers for reading and correcting the codestream.
+///
+/// `Datastream::new()` allocates them, `Datastream::with_buffers()` takes them from the caller.
+pub struct Datastream<B> {
+    pub raw: B,
+    pub data_bits: u32,
+    pub ptr: u32,
+
+    pub data: B,
+}
+
+impl<B: DerefMut<Target = [u8]>> Datastream<B> {
+    /// Use the specified buffers, which have to hold the 3706 codewords of a version 40 code each,
+    /// for example `&mut [u8]`s into static arrays.
+    pub fn with_buffers(raw: B, data: B) -> Datastream<B> {
+        let codewords = QUIRC_VERSION_DB[QUIRC_MAX_VERSION].data_bytes as usize;
+        assert!(raw.len() >= codewords && data.len() >= codewords,
+                "Datastream buffers of {} and {} bytes, need {}",
+                raw.len(),
+                data.len(),
+                codewords);
+
+        Datastream {
+            raw,
+            data_bits: 0,
+            ptr: 0,
+
+            data,
+        }
+    }
+
+    /// Prepare for reading another code, without reallocating.
+    pub fn reset(&mut self) {
+        for b in self.raw.iter_mut() {
+            *b = 0;
+        }
+        self.data_bits = 0;
+        self.ptr = 0;
+    }
+
+    fn bits_remaining(&self) -> usize {
+        (self.data_bits - self.ptr) as usize
+    }
+
+    fn take_bits(&mut self, mut len: usize) -> u32 {
+        let mut ret = 0;
+
+        while len != 0 && self.ptr < self.data_bits {
+            let b = self.data[(self.ptr >> 3) as usize];
+            let bitpos = self.ptr & 7;
+
+            ret <<= 1;
+            if (b << bitpos) & 0x80 != 0 {
+                ret |= 1;
+            }
+
+            self.ptr += 1;
+            len -= 1;
+        }
+
+        ret
+    }
+}
+
+impl QuircCode {
+    #[inline]
+    fn grid_bit(&self, x: u32, y: u32) -> u8 {
+        let p = y * self.size + x;
+
+        (self.cell_bitmap[(p >> 3) as usize] >> (p & 7)) & 1
+    }
+
+    /// Read the raw format bits, before unmasking and correction, from the specified copy.
+    fn format_bits(&self, which: u32) -> u16 {
+        let mut format = 0u16;
+
+        if which != 0 {
+            for i in 0..7 {
+                format = (format << 1) | self.grid_bit(8, self.size - 1 - i) as u16;
+            }
+            for i in 0..8 {
+                format = (format << 1) | self.grid_bit(self.size - 8 + i, 8) as u16;
+            }
+        } else {
+            for i in (0..15).rev() {
+                format = (format << 1) | self.grid_bit(FORMAT_XS[i], FORMAT_YS[i]) as u16;
+            }
+        }
+
+        format
+    }
+
+    fn read_bit<D: DecodeTarget, B: DerefMut<Target = [u8]>>(&self, data: &D, ds: &mut Datastream<B>, i: u32, j: u32) {
+        let bitpos = (ds.data_bits & 7) as usize;
+        let bytepos = (ds.data_bits >> 3) as usize;
+        let mut v = self.grid_bit(j, i);
+
+        v ^= mask_bit(data.mask(), i, j);
+
+        if v != 0 {
+            ds.raw[bytepos] |= 0x80 >> bitpos;
+        }
+
+        ds.data_bits += 1;
+    }
+
+    pub(crate) fn read_data<D: DecodeTarget, B: DerefMut<Target = [u8]>>(&self, data: &D, ds: &mut Datastream<B>) {
+        let mut y = self.size as isize - 1;
+        let mut x = self.size as isize - 1;
+        let mut dir = -1;
+
+        while x > 0 {
+            if x == 6 {
+                x -= 1;
+            }
+
+            if reserved_cell(data.version(), y as u32, x as u32) == 0 {
+                self.read_bit(data, ds, y as u32, x as u32);
+            }
+
+            if reserved_cell(data.version(), y as u32, x as u32 - 1) == 0 {
+                self.read_bit(data, ds, y as u32, x as u32 - 1);
+            }
+
+            y += dir;
+            if y < 0 || y >= self.size as isize {
+                dir = -dir;
+                x -= 2;
+                y += dir;
+            }
+        }
+    }
+
+    /// Check the grid size, returning the version it implies.
+    pub(crate) fn grid_version(&self) -> Result<u32, DecodeError> {
+        if self.size < 17 || !(self.size - 17).is_multiple_of(4) {
+            return Err(DecodeErrorKind::InvalidGridSize.into());
+        }
+
+        let version = (self.size - 17) / 4;
+        if version < 1 || version as usize > QUIRC_MAX_VERSION {
+            return Err(DecodeError {
+                version: Some(version),
+                ..DecodeErrorKind::InvalidVersion.into()
+            });
+        }
+
+        Ok(version)
+    }
+
+    /// Decode a QR-code without allocating, using the specified scratch buffers,
+    /// and putting the payload into the specified buffer.
+    ///
+    /// A payload longer than the buffer fails with `DecodeErrorKind::DataOverflow`.
+    pub fn decode_buf<'a, B: DerefMut<Target = [u8]>>(&self, ds: &mut Datastream<B>, payload: &'a mut [u8]) -> Result<QuircDataBuf<'a>, DecodeError> {
+        let mut data = QuircDataBuf {
+            version: self.grid_version()?,
+            ecc_level: 0,
+            mask: 0,
+
+            data_type: 0,
+
+            payload,
+            payload_len: 0,
+
+            eci: 0,
+        };
+
+        decode_code(self, &mut data, ds)?;
+        Ok(data)
+    }
+}
+
+impl DecodeError {
+    /// Fill in what `decode_code()` knew about the code when a lower-level step failed.
+    fn in_context(self, context: &DecodeError) -> DecodeError {
+        DecodeError {
+            kind: self.kind,
+            block: self.block,
+            ..*context
+        }
+    }
+}
+
+impl<'a> DecodeTarget for QuircDataBuf<'a> {
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn ecc_level(&self) -> u8 {
+        self.ecc_level
+    }
+
+    fn mask(&self) -> u8 {
+        self.mask
+    }
+
+    fn set_format(&mut self, ecc_level: u8, mask: u8) {
+        self.ecc_level = ecc_level;
+        self.mask = mask;
+    }
+
+    fn payload_capacity(&self) -> usize {
+        self.payload.len()
+    }
+
+    fn payload_len(&self) -> usize {
+        self.payload_len
+    }
+
+    fn push_payload(&mut self, byte: u8) {
+        self.payload[self.payload_len] = byte;
+        self.payload_len += 1;
+    }
+
+    fn set_eci(&mut self, eci: u32) {
+        self.eci = eci;
+    }
+
+    fn end_segment(&mut self, mode: u8) {
+        if (mode & (mode - 1)) == 0 && mode > self.data_type {
+            self.data_type = mode;
+        }
+    }
+}
+
+
+/// Decode a QR-code into data with the version implied by its grid size,
+/// leaving whatever was decoded before an error in it, like C quirc does.
+pub fn decode_code<D: DecodeTarget, B: DerefMut<Target = [u8]>>(code: &QuircCode, data: &mut D, ds: &mut Datastream<B>) -> Result<(), DecodeError> {
+    ds.reset();
+    let mut context = DecodeError {
+        version: Some(data.version()),
+        ..DecodeErrorKind::FormatEcc.into()
+    };
+
+    // Read format information -- try both locations
+    let format_bits = code.format_bits(0);
+    context.format_bits[0] = Some(format_bits);
+    if read_format(data, format_bits).is_err() {
+        let format_bits = code.format_bits(1);
+        context.format_bits[1] = Some(format_bits);
+        read_format(data, format_bits).map_err(|e| e.in_context(&context))?;
+    }
+
+    code.read_data(data, ds);
+    codestream_ecc(data, ds).map_err(|e| e.in_context(&context))?;
+    decode_payload(data, ds).map_err(|e| e.in_context(&context))
+}
+
+/// Unmask and correct the format bits, and extract the ECC level and mask from them.
+pub fn read_format<D: DecodeTarget>(data: &mut D, mut format: u16) -> Result<(), DecodeError> {
+    format ^= FORMAT_MASK;
+
+    correct_format(&mut format)?;
+
+    let fdata: u16 = format >> 10;
+    data.set_format((fdata >> 3) as u8, (fdata & 7) as u8);
+
+    Ok(())
+}
+
+pub fn codestream_ecc<D: DecodeTarget, B: DerefMut<Target = [u8]>>(data: &D, ds: &mut Datastream<B>) -> Result<(), DecodeError> {
+    let ver = &QUIRC_VERSION_DB[data.version() as usize];
+    let sb_ecc = &ver.ecc[data.ecc_level() as usize];
+    let lb_count = (ver.data_bytes - sb_ecc.bs * sb_ecc.ns) / (sb_ecc.bs + 1);
+    let bc = lb_count + sb_ecc.ns;
+    let ecc_offset = sb_ecc.dw * bc + lb_count;
+    let mut dst_offset = 0;
+
+    let mut lb_ecc = *sb_ecc;
+    lb_ecc.dw += 1;
+    lb_ecc.bs += 1;
+
+    for i in 0..bc {
+        let dst = &mut ds.data[dst_offset..];
+        let ecc = if i < sb_ecc.ns { sb_ecc } else { &lb_ecc };
+        let num_ec = ecc.bs - ecc.dw;
+
+        for j in 0..ecc.dw {
+            dst[j as usize] = ds.raw[codestream_data_offset(sb_ecc, bc, i, j)];
+        }
+        for j in 0..num_ec {
+            dst[(ecc.dw + j) as usize] = ds.raw[(ecc_offset + j * bc + i) as usize];
+        }
+
+        correct_block(dst, ecc).map_err(|e| DecodeError { block: Some(i), ..e })?;
+
+        dst_offset += ecc.dw as usize;
+    }
+
+    ds.data_bits = dst_offset as u32 * 8;
+
+    Ok(())
+}
+
+/// Where data codeword `j` of block `i` sits in the interleaved codestream.
+///
+/// Only the large blocks (those after the first `sb_ecc.ns`) have a codeword `sb_ecc.dw`,
+/// so the last column of data codewords is `bc - sb_ecc.ns` long.
+pub fn codestream_data_offset(sb_ecc: &QuircRsParams, bc: u32, i: u32, j: u32) -> usize {
+    (if j < sb_ecc.dw {
+        j * bc + i
+    } else {
+        sb_ecc.dw * bc + i - sb_ecc.ns
+    }) as usize
+}
+
+/// Count the nonzero RS syndromes over all blocks, without correcting anything.
+pub fn codestream_syndrome_weight<D: DecodeTarget, B: DerefMut<Target = [u8]>>(data: &D, ds: &Datastream<B>) -> usize {
+    let ver = &QUIRC_VERSION_DB[data.version() as usize];
+    let sb_ecc = &ver.ecc[data.ecc_level() as usize];
+    let lb_count = (ver.data_bytes - sb_ecc.bs * sb_ecc.ns) / (sb_ecc.bs + 1);
+    let bc = lb_count + sb_ecc.ns;
+    let ecc_offset = sb_ecc.dw * bc + lb_count;
+    let mut weight = 0;
+
+    let mut lb_ecc = *sb_ecc;
+    lb_ecc.dw += 1;
+    lb_ecc.bs += 1;
+
+    let mut block = [0u8; 256];
+    let mut s = [0u8; MAX_POLY];
+    for i in 0..bc {
+        let ecc = if i < sb_ecc.ns { sb_ecc } else { &lb_ecc };
+        let num_ec = ecc.bs - ecc.dw;
+
+        for j in 0..ecc.dw {
+            block[j as usize] = ds.raw[codestream_data_offset(sb_ecc, bc, i, j)];
+        }
+        for j in 0..num_ec {
+            block[(ecc.dw + j) as usize] = ds.raw[(ecc_offset + j * bc + i) as usize];
+        }
+
+        block_syndromes(&block, ecc.bs as usize, num_ec as usize, &mut s);
+        weight += s.iter().filter(|&&b| b != 0).count();
+    }
+
+    weight
+}
+
+/// Check whether what follows the payload looks like what an encoder would put there:
+/// a terminator, zero bits up to the codeword boundary, then alternating `0xec`/`0x11` pad codewords.
+///
+/// Expects `ds` to be just past `decode_payload()`.
+pub fn padding_consistent<B: DerefMut<Target = [u8]>>(ds: &Datastream<B>) -> bool {
+    let bit = |pos: u32| (ds.data[(pos >> 3) as usize] << (pos & 7)) & 0x80 != 0;
+
+    // Either we stopped at a terminator, or there weren't enough bits left for one
+    if ds.bits_remaining() >= 4 && (ds.ptr - 4..ds.ptr).any(&bit) {
+        return false;
+    }
+
+    let pad_start = (ds.ptr + 7) & !7;
+    if (ds.ptr..pad_start.min(ds.data_bits)).any(&bit) {
+        return false;
+    }
+
+    (pad_start >> 3..ds.data_bits >> 3).enumerate().all(|(i, pos)| ds.data[pos as usize] == if i % 2 == 0 { 0xec } else { 0x11 })
+}
+
+pub fn numeric_tuple<D: DecodeTarget, B: DerefMut<Target = [u8]>>(data: &mut D, ds: &mut Datastream<B>, bits: usize, digits: usize) -> bool {
+    if ds.bits_remaining() < bits {
+        return true;
+    }
+
+    let mut tuple = ds.take_bits(bits);
+    let mut chars = [0u8; 3];
+
+    for i in (0..digits).rev() {
+        chars[i] = (tuple % 10 + b'0' as u32) as u8;
+        tuple /= 10;
+    }
+    for &c in &chars[..digits] {
+        data.push_payload(c);
+    }
+
+    false
+}
+
+pub fn decode_numeric<D: DecodeTarget, B: DerefMut<Target = [u8]>>(data: &mut D, ds: &mut Datastream<B>) -> Result<(), DecodeError> {
+    let bits = if data.version() < 10 {
+        10
+    } else if data.version() < 27 {
+        12
+    } else {
+        14
+    };
+
+    let mut count = ds.take_bits(bits);
+    if data.payload_len() + count as usize > data.payload_capacity() {
+        return Err(DecodeErrorKind::DataOverflow.into());
+    }
+
+    while count >= 3 {
+        if numeric_tuple(data, ds, 10, 3) {
+            return Err(DecodeErrorKind::DataUnderflow.into());
+        }
+        count -= 3;
+    }
+
+    if count >= 2 {
+        if numeric_tuple(data, ds, 7, 2) {
+            return Err(DecodeErrorKind::DataUnderflow.into());
+        }
+        count -= 2;
+    }
+
+    if count != 0 && numeric_tuple(data, ds, 4, 1) {
+        return Err(DecodeErrorKind::DataUnderflow.into());
+    }
+
+    Ok(())
+}
+
+/// The characters of alphanumeric mode, by value.
+pub static ALPHA_MAP: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+pub fn alpha_tuple<D: DecodeTarget, B: DerefMut<Target = [u8]>>(data: &mut D, ds: &mut Datastream<B>, bits: usize, digits: usize) -> bool {
+    if ds.bits_remaining() < bits {
+        return true;
+    }
+
+    let mut tuple = ds.take_bits(bits);
+    let mut chars = [0u8; 2];
+
+    for i in 0..digits {
+        chars[digits - i - 1] = ALPHA_MAP[(tuple % 45) as usize];
+        tuple /= 45;
+    }
+    for &c in &chars[..digits] {
+        data.push_payload(c);
+    }
+
+    false
+}
+
+pub fn decode_alpha<D: DecodeTarget, B: DerefMut<Target = [u8]>>(data: &mut D, ds: &mut Datastream<B>) -> Result<(), DecodeError> {
+    let bits = if data.version() < 10 {
+        9
+    } else if data.version() < 27 {
+        11
+    } else {
+        13
+    };
+
+    let mut count = ds.take_bits(bits);
+    if data.payload_len() + count as usize > data.payload_capacity() {
+        return Err(DecodeErrorKind::DataOverflow.into());
+    }
+
+    while count >= 2 {
+        if alpha_tuple(data, ds, 11, 2) {
+            return Err(DecodeErrorKind::DataUnderflow.into());
+        }
+        count -= 2;
+    }
+
+    if count != 0 && alpha_tuple(data, ds, 6, 1) {
+        return Err(DecodeErrorKind::DataUnderflow.into());
+    }
+
+    Ok(())
+}
+
+pub fn decode_byte<D: DecodeTarget, B: DerefMut<Target = [u8]>>(data: &mut D, ds: &mut Datastream<B>) -> Result<(), DecodeError> {
+    let bits = if data.version() < 10 { 8 } else { 16 };
+
+    let count = ds.take_bits(bits);
+    if data.payload_len() + count as usize > data.payload_capacity() {
+        return Err(DecodeErrorKind::DataOverflow.into());
+    }
+    if ds.bits_remaining() < count as usize * 8 {
+        return Err(DecodeErrorKind::DataUnderflow.into());
+    }
+
+    for _ in 0..count {
+        data.push_payload(ds.take_bits(8) as u8);
+    }
+
+    Ok(())
+}
+
+pub fn decode_kanji<D: DecodeTarget, B: DerefMut<Target = [u8]>>(data: &mut D, ds: &mut Datastream<B>) -> Result<(), DecodeError> {
+    let bits = if data.version() < 10 {
+        8
+    } else if data.version() < 27 {
+        10
+    } else {
+        12
+    };
+
+    let count = ds.take_bits(bits);
+    if data.payload_len() + count as usize * 2 > data.payload_capacity() {
+        return Err(DecodeErrorKind::DataOverflow.into());
+    }
+    if ds.bits_remaining() < count as usize * 13 {
+        return Err(DecodeErrorKind::DataUnderflow.into());
+    }
+
+    for _ in 0..count {
+        let d = ds.take_bits(13);
+        let ms_big_b = d / 0xc0;
+        let ls_big_b = d % 0xc0;
+        let intermediate = (ms_big_b << 8) | ls_big_b;
+
+        let sjw = if intermediate + 0x8140 <= 0x9ffc {
+            // bytes are in the range 0x8140 to 0x9FFC
+            intermediate + 0x8140
+        } else {
+            // bytes are in the range 0xE040 to 0xEBBF
+            intermediate + 0xc140
+        };
+
+        data.push_payload(((sjw >> 8) & 0xff) as u8);
+        data.push_payload((sjw & 0xff) as u8);
+    }
+
+    Ok(())
+}
+
+pub fn decode_eci<D: DecodeTarget, B: DerefMut<Target = [u8]>>(data: &mut D, ds: &mut Datastream<B>) -> Result<(), DecodeError> {
+    if ds.bits_remaining() < 8 {
+        return Err(DecodeErrorKind::DataUnderflow.into());
+    }
+
+    let mut eci = ds.take_bits(8);
+    data.set_eci(eci);
+
+    if (eci & 0xc0) == 0x80 {
+        if ds.bits_remaining() < 8 {
+            return Err(DecodeErrorKind::DataUnderflow.into());
+        }
+
+        eci = ((eci & 0x3f) << 8) | ds.take_bits(8);
+    } else if (eci & 0xe0) == 0xc0 {
+        if ds.bits_remaining() < 16 {
+            return Err(DecodeErrorKind::DataUnderflow.into());
+        }
+
+        eci = ((eci & 0x1f) << 16) | ds.take_bits(16);
+    }
+    data.set_eci(eci);
+
+    Ok(())
+}
+
+/// Decode the segments up to the terminator, or the end of the codestream,
+/// failing with `DecodeErrorKind::DataOverflow` rather than growing the payload past `data.payload_capacity()`.
+pub fn decode_payload<D: DecodeTarget, B: DerefMut<Target = [u8]>>(data: &mut D, ds: &mut Datastream<B>) -> Result<(), DecodeError> {
+    while ds.bits_remaining() >= 4 {
+        let tpe = ds.take_bits(4) as u8;
+
+        match tpe {
+            QUIRC_DATA_TYPE_NUMERIC => decode_numeric(data, ds),
+
+            QUIRC_DATA_TYPE_ALPHA => decode_alpha(data, ds),
+
+            QUIRC_DATA_TYPE_BYTE => decode_byte(data, ds),
+
+            QUIRC_DATA_TYPE_KANJI => decode_kanji(data, ds),
+
+            7 => decode_eci(data, ds),
+
+            _ => return Ok(()),
+        }?;
+
+        data.end_segment(tpe);
+    }
+
+    Ok(())
+}
@@ -5,14 +5,14 @@
 
 use self::super::{GF256_EXP, GF256_LOG, MAX_POLY, GF256, berlekamp_massey, poly_eval};
 use self::super::super::super::ops::version_db::QuircRsParams;
-use self::super::super::super::ffi::QuircDecodeError;
+use self::super::super::super::ops::{DecodeErrorKind, DecodeError};
 
 
 pub fn block_syndromes(data: &[u8], bs: usize, npar: usize, s: &mut [u8]) -> bool {
     let mut nonzero = false;
 
-    for i in 0..MAX_POLY {
-        s[i] = 0;
+    for b in &mut s[..MAX_POLY] {
+        *b = 0;
     }
 
     for i in 0..npar {
@@ -31,12 +31,12 @@ pub fn block_syndromes(data: &[u8], bs: usize, npar: usize, s: &mut [u8]) -> boo
         }
     }
 
-    return nonzero;
+    nonzero
 }
 
 pub fn eloc_poly(omega: &mut [u8], s: &[u8], sigma: &[u8], npar: usize) {
-    for i in 0..MAX_POLY {
-        omega[i] = 0;
+    for b in &mut omega[..MAX_POLY] {
+        *b = 0;
     }
 
     for i in 0..npar {
@@ -64,13 +64,13 @@ pub fn eloc_poly(omega: &mut [u8], s: &[u8], sigma: &[u8], npar: usize) {
     }
 }
 
-pub fn correct_block(data: &mut [u8], ecc: &QuircRsParams) -> QuircDecodeError {
+pub fn correct_block(data: &mut [u8], ecc: &QuircRsParams) -> Result<(), DecodeError> {
     let npar = ecc.bs - ecc.dw;
 
     // Compute syndrome vector
     let mut s = [0u8; MAX_POLY];
-    if !block_syndromes(&data, ecc.bs as usize, npar as usize, &mut s) {
-        return QuircDecodeError::QuircSuccess;
+    if !block_syndromes(data, ecc.bs as usize, npar as usize, &mut s) {
+        return Ok(());
     }
 
     let sigma = berlekamp_massey(&s, npar as usize, &GF256);
@@ -95,15 +95,15 @@ pub fn correct_block(data: &mut [u8], ecc: &QuircRsParams) -> QuircDecodeError {
         if poly_eval(&sigma, xinv, &GF256) == 0 {
             let sd_x = poly_eval(&sigma_deriv, xinv, &GF256) as usize;
             let omega_x = poly_eval(&omega, xinv, &GF256) as usize;
-            let error = GF256_EXP[((255 - GF256_LOG[sd_x] + GF256_LOG[omega_x]) % 255) as usize];
+            let error = GF256_EXP[(255 - GF256_LOG[sd_x] as usize + GF256_LOG[omega_x] as usize) % 255];
 
             data[(ecc.bs - i - 1) as usize] ^= error;
         }
     }
 
     if block_syndromes(data, ecc.bs as usize, npar as usize, &mut s) {
-        QuircDecodeError::QuircErrorDataEcc
+        Err(DecodeErrorKind::DataEcc.into())
     } else {
-        QuircDecodeError::QuircSuccess
+        Ok(())
     }
 }
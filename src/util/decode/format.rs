@@ -4,19 +4,24 @@
 
 
 use self::super::{GF16_EXP, MAX_POLY, GF16, berlekamp_massey, poly_eval};
-use self::super::super::super::ffi::QuircDecodeError;
+use self::super::super::super::ops::{DecodeErrorKind, DecodeError};
 
 
 pub const FORMAT_MAX_ERROR: usize = 3;
 pub const FORMAT_SYNDROMES: usize = FORMAT_MAX_ERROR * 2;
 pub const FORMAT_BITS: usize = 15;
 
+/// The BCH(15, 5) generator polynomial, `x^10 + x^8 + x^5 + x^4 + x^2 + x + 1`.
+pub const FORMAT_GENERATOR: u16 = 0x537;
+/// XORed onto the format bits, so they're never all zero.
+pub const FORMAT_MASK: u16 = 0x5412;
+
 
 pub fn format_syndromes(u: u16, s: &mut [u8]) -> bool {
     let mut nonzero = false;
 
-    for i in 0..MAX_POLY {
-        s[i] = 0;
+    for b in &mut s[..MAX_POLY] {
+        *b = 0;
     }
 
     for i in 0..FORMAT_SYNDROMES {
@@ -36,14 +41,14 @@ pub fn format_syndromes(u: u16, s: &mut [u8]) -> bool {
     nonzero
 }
 
-pub fn correct_format(f_ret: &mut u16) -> QuircDecodeError {
+pub fn correct_format(f_ret: &mut u16) -> Result<(), DecodeError> {
     let mut u = *f_ret;
     let mut s = [0u8; MAX_POLY];
 
     // Evaluate U (received codeword) at each of alpha_1 .. alpha_6
     // to get S_1 .. S_6 (but we index them from 0).
     if !format_syndromes(u, &mut s) {
-        return QuircDecodeError::QuircSuccess;
+        return Ok(());
     }
 
     let sigma = berlekamp_massey(&s, FORMAT_SYNDROMES, &GF16);
@@ -56,9 +61,9 @@ pub fn correct_format(f_ret: &mut u16) -> QuircDecodeError {
     }
 
     if format_syndromes(u, &mut s) {
-        return QuircDecodeError::QuircErrorFormatEcc;
+        return Err(DecodeErrorKind::FormatEcc.into());
     }
 
     *f_ret = u;
-    QuircDecodeError::QuircSuccess
+    Ok(())
 }
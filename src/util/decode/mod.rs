@@ -1,5 +1,8 @@
+mod cells;
+mod codestream;
 mod galois;
 mod format;
+#[cfg(feature = "alloc")]
 mod decoder;
 mod err_corr;
 mod berlekamp;
@@ -9,9 +12,10 @@ pub use self::galois::{GaloisField, GF256_EXP, GF256_LOG, GF16_EXP, GF16_LOG, GF
 pub use self::err_corr::{block_syndromes, correct_block, eloc_poly};
 pub use self::polynominal::{poly_eval, poly_add};
 pub use self::berlekamp::berlekamp_massey;
-pub use self::decoder::{Datastream, codestream_ecc, decode_payload, decode_numeric, reserved_cell, numeric_tuple, decode_alpha, decode_kanji, decode_byte,
-                        alpha_tuple, decode_eci, mask_bit};
-pub use self::format::{FORMAT_MAX_ERROR, FORMAT_SYNDROMES, FORMAT_BITS, format_syndromes, correct_format};
+pub use self::cells::{FORMAT_XS, FORMAT_YS, reserved_cell, mask_bit};
+pub use self::codestream::{ALPHA_MAP, Datastream, decode_code, read_format, codestream_ecc, codestream_data_offset, codestream_syndrome_weight, decode_payload,
+                           padding_consistent, decode_numeric, numeric_tuple, decode_alpha, decode_kanji, decode_byte, alpha_tuple, decode_eci};
+pub use self::format::{FORMAT_MAX_ERROR, FORMAT_SYNDROMES, FORMAT_BITS, FORMAT_GENERATOR, FORMAT_MASK, format_syndromes, correct_format};
 
 
 pub const MAX_POLY: usize = 64;
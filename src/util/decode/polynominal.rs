@@ -9,11 +9,10 @@ pub fn poly_add(dst: &mut [u8], src: &[u8], c: u8, shift: isize, gf: &GaloisFiel
         return;
     }
 
-    let log_c = gf.log[c as usize];
+    let log_c = gf.log[c as usize] as usize;
 
-    for i in 0..MAX_POLY {
+    for (i, &v) in src[..MAX_POLY].iter().enumerate() {
         let p = i as isize + shift;
-        let v = src[i];
 
         if p < 0 || p >= MAX_POLY as isize {
             continue;
@@ -22,7 +21,7 @@ pub fn poly_add(dst: &mut [u8], src: &[u8], c: u8, shift: isize, gf: &GaloisFiel
             continue;
         }
 
-        dst[p as usize] ^= gf.exp[(gf.log[v as usize] + log_c) as usize % gf.p];
+        dst[p as usize] ^= gf.exp[(gf.log[v as usize] as usize + log_c) % gf.p];
     }
 }
 
@@ -34,8 +33,7 @@ pub fn poly_eval(s: &[u8], x: u8, gf: &GaloisField) -> u8 {
     let mut sum = 0u8;
     let log_x = gf.log[x as usize] as usize;
 
-    for i in 0..MAX_POLY {
-        let c = s[i];
+    for (i, &c) in s[..MAX_POLY].iter().enumerate() {
 
         if c == 0 {
             continue;
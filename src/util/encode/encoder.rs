@@ -0,0 +1,188 @@
+//! Symbol assembly
+
+
+use self::super::super::super::constants::{QUIRC_MAX_BITMAP, QUIRC_ECC_LEVEL_M, QUIRC_ECC_LEVEL_L, QUIRC_ECC_LEVEL_H, QUIRC_ECC_LEVEL_Q, QUIRC_ECI_UTF_8};
+use self::super::super::super::ops::version_db::QUIRC_MAX_VERSION;
+use self::super::super::super::ops::encode::{EncodeOptions, Segment};
+use self::super::super::super::ops::{QuircCode, QuircPoint, EncodeError};
+use self::super::{VERSION_RANGE_STARTS, Bitstream, version_range, segment_chars, segment_bits, write_segment, optimal_segments, data_codewords,
+                  build_codestream, draw_function_patterns, write_data, write_format, mask_penalty};
+use alloc::vec::Vec;
+
+
+/// From the strongest to the weakest.
+static ECC_LEVELS_BY_STRENGTH: [u8; 4] = [QUIRC_ECC_LEVEL_H, QUIRC_ECC_LEVEL_Q, QUIRC_ECC_LEVEL_M, QUIRC_ECC_LEVEL_L];
+
+
+/// Encode arbitrary bytes in as few bits as possible, switching between numeric, alphanumeric and byte mode as needed.
+pub fn encode(data: &[u8], options: &EncodeOptions) -> Result<QuircCode, EncodeError> {
+    encode_optimal(data, None, false, options)
+}
+
+/// Encode UTF-8 text like `encode()`, designating the UTF-8 ECI first if it isn't plain ASCII.
+pub fn encode_text(text: &str, options: &EncodeOptions) -> Result<QuircCode, EncodeError> {
+    let eci = if text.is_ascii() {
+        None
+    } else {
+        Some(QUIRC_ECI_UTF_8 as u32)
+    };
+
+    encode_optimal(text.as_bytes(), eci, false, options)
+}
+
+/// Encode Shift JIS text like `encode()`, also using kanji mode for the double-byte characters it covers.
+pub fn encode_shift_jis(data: &[u8], options: &EncodeOptions) -> Result<QuircCode, EncodeError> {
+    encode_optimal(data, None, true, options)
+}
+
+/// Encode the segments, in order, into one symbol.
+pub fn encode_segments(segments: &[Segment], options: &EncodeOptions) -> Result<QuircCode, EncodeError> {
+    check_options(options)?;
+    for (i, segment) in segments.iter().enumerate() {
+        segment_chars(segment, i)?;
+    }
+
+    encode_ranges([segments, segments, segments], options)
+}
+
+fn check_options(options: &EncodeOptions) -> Result<(), EncodeError> {
+    if let Some(version) = options.version {
+        if version < 1 || version as usize > QUIRC_MAX_VERSION {
+            return Err(EncodeError::InvalidVersion(version));
+        }
+    }
+    if let Some(ecc_level) = options.ecc_level {
+        if ecc_level > 3 {
+            return Err(EncodeError::InvalidEccLevel(ecc_level));
+        }
+    }
+    if let Some(mask) = options.mask {
+        if mask > 7 {
+            return Err(EncodeError::InvalidMask(mask));
+        }
+    }
+
+    Ok(())
+}
+
+/// Segment the data optimally for each range of character count widths, and encode whichever fits first.
+fn encode_optimal(data: &[u8], eci: Option<u32>, kanji: bool, options: &EncodeOptions) -> Result<QuircCode, EncodeError> {
+    check_options(options)?;
+
+    let ranges: Vec<Vec<Segment>> = VERSION_RANGE_STARTS.iter()
+        .map(|&version| eci.map(Segment::Eci).into_iter().chain(optimal_segments(data, version, kanji)).collect())
+        .collect();
+
+    encode_ranges([&ranges[0], &ranges[1], &ranges[2]], options)
+}
+
+/// Encode the (valid) segments meant for the range of the version chosen, see `version_range()`.
+fn encode_ranges(ranges: [&[Segment]; 3], options: &EncodeOptions) -> Result<QuircCode, EncodeError> {
+    let (version, ecc_level, bits) = choose_version(&ranges, options)?;
+    let segments = ranges[version_range(version)];
+
+    // Terminator, then pad to a byte boundary, then with alternating pad codewords
+    let capacity = data_codewords(version, ecc_level);
+    let mut bs = Bitstream::new();
+    for segment in segments {
+        write_segment(segment, version, &mut bs);
+    }
+    debug_assert_eq!(bs.bits, bits);
+
+    bs.push_bits(0, (capacity * 8 - bs.bits).min(4));
+    bs.push_bits(0, (8 - bs.bits % 8) % 8);
+    for pad in [0xec, 0x11].iter().cycle().take(capacity - bs.data.len()) {
+        bs.push_bits(*pad, 8);
+    }
+
+    let raw = build_codestream(version, ecc_level, &bs.data);
+
+    let size = version * 4 + 17;
+    let mut code = QuircCode {
+        corners: [QuircPoint { x: 0, y: 0 },
+                  QuircPoint {
+                      x: size as isize,
+                      y: 0,
+                  },
+                  QuircPoint {
+                      x: size as isize,
+                      y: size as isize,
+                  },
+                  QuircPoint {
+                      x: 0,
+                      y: size as isize,
+                  }],
+        size,
+        cell_bitmap: [0; QUIRC_MAX_BITMAP],
+    };
+
+    let mask = match options.mask {
+        Some(mask) => mask,
+        None => choose_mask(&mut code, version, ecc_level, &raw),
+    };
+
+    draw_function_patterns(&mut code, version);
+    write_data(&mut code, version, mask, &raw);
+    write_format(&mut code, ecc_level, mask);
+
+    Ok(code)
+}
+
+/// Bits taken up by the segments for the given version, if their character counts fit.
+fn total_bits(ranges: &[&[Segment]; 3], version: u32) -> Option<usize> {
+    ranges[version_range(version)].iter().map(|s| segment_bits(s, version)).sum()
+}
+
+/// The smallest version the segments fit in at the requested ECC level,
+/// or, if none was requested, at M (or L if that fails), and then the strongest ECC level that still fits in it.
+fn choose_version(ranges: &[&[Segment]; 3], options: &EncodeOptions) -> Result<(u32, u8, usize), EncodeError> {
+    let (first, last) = match options.version {
+        Some(version) => (version, version),
+        None => (1, QUIRC_MAX_VERSION as u32),
+    };
+    let fits = |version, ecc_level| total_bits(ranges, version).filter(|&bits| bits <= data_codewords(version, ecc_level) * 8);
+
+    let search_levels: &[u8] = match options.ecc_level {
+        Some(ref ecc_level) => core::slice::from_ref(ecc_level),
+        None => &[QUIRC_ECC_LEVEL_M, QUIRC_ECC_LEVEL_L],
+    };
+
+    for &search_level in search_levels {
+        for version in first..last + 1 {
+            if let Some(bits) = fits(version, search_level) {
+                if options.ecc_level.is_some() {
+                    return Ok((version, search_level, bits));
+                }
+
+                for &ecc_level in &ECC_LEVELS_BY_STRENGTH {
+                    if let Some(bits) = fits(version, ecc_level) {
+                        return Ok((version, ecc_level, bits));
+                    }
+                }
+            }
+        }
+    }
+
+    Err(EncodeError::DataTooLong {
+        version: last,
+        ecc_level: *search_levels.last().unwrap(),
+    })
+}
+
+/// The mask with the lowest penalty score, the lowest-numbered one on ties.
+fn choose_mask(code: &mut QuircCode, version: u32, ecc_level: u8, raw: &[u8]) -> u8 {
+    let mut best = (u32::MAX, 0);
+
+    for mask in 0..8 {
+        draw_function_patterns(code, version);
+        write_data(code, version, mask, raw);
+        write_format(code, ecc_level, mask);
+
+        let penalty = mask_penalty(code).total();
+        if penalty < best.0 {
+            best = (penalty, mask);
+        }
+    }
+
+    best.1
+}
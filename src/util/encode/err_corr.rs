@@ -0,0 +1,102 @@
+//! Code stream error correction
+//!
+//! The inverse of `codestream_ecc()`: split the data into blocks, append their RS codewords, and interleave them.
+
+
+use self::super::super::super::ops::version_db::{QUIRC_VERSION_DB, QuircRsParams};
+use self::super::super::decode::{GF256_EXP, GF256_LOG, MAX_POLY, codestream_data_offset};
+use alloc::vec::Vec;
+
+
+fn gf_mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+
+    GF256_EXP[(GF256_LOG[a as usize] as usize + GF256_LOG[b as usize] as usize) % 255]
+}
+
+
+/// How many data codewords a version holds at an ECC level, over all blocks.
+pub fn data_codewords(version: u32, ecc_level: u8) -> usize {
+    let ver = &QUIRC_VERSION_DB[version as usize];
+    let sb_ecc = &ver.ecc[ecc_level as usize];
+    let lb_count = (ver.data_bytes - sb_ecc.bs * sb_ecc.ns) / (sb_ecc.bs + 1);
+
+    (sb_ecc.dw * (lb_count + sb_ecc.ns) + lb_count) as usize
+}
+
+/// The monic generator polynomial with roots `α^0` through `α^(npar - 1)`, highest coefficient first, the leading 1 omitted.
+pub fn rs_generator_poly(npar: usize) -> [u8; MAX_POLY] {
+    let mut gen = [0u8; MAX_POLY];
+    gen[npar - 1] = 1;
+
+    // Multiply by (x - α^i) in turn, keeping the leading term implicit
+    let mut root = 1u8;
+    for _ in 0..npar {
+        for j in 0..npar {
+            gen[j] = gf_mul(gen[j], root);
+            if j + 1 < npar {
+                gen[j] ^= gen[j + 1];
+            }
+        }
+        root = gf_mul(root, 0x02);
+    }
+
+    gen
+}
+
+/// The `npar` RS codewords which, appended to `data`, make a block `block_syndromes()` finds clean.
+pub fn rs_remainder(data: &[u8], gen: &[u8], npar: usize, out: &mut [u8]) {
+    for b in out[..npar].iter_mut() {
+        *b = 0;
+    }
+
+    for &d in data {
+        let factor = d ^ out[0];
+
+        for j in 0..npar - 1 {
+            out[j] = out[j + 1] ^ gf_mul(gen[j], factor);
+        }
+        out[npar - 1] = gf_mul(gen[npar - 1], factor);
+    }
+}
+
+/// Lay out `data` (exactly `data_codewords()` long) and its RS codewords in the order `read_data()` returns them.
+pub fn build_codestream(version: u32, ecc_level: u8, data: &[u8]) -> Vec<u8> {
+    let ver = &QUIRC_VERSION_DB[version as usize];
+    let sb_ecc = &ver.ecc[ecc_level as usize];
+    let lb_count = (ver.data_bytes - sb_ecc.bs * sb_ecc.ns) / (sb_ecc.bs + 1);
+    let bc = lb_count + sb_ecc.ns;
+    let ecc_offset = sb_ecc.dw * bc + lb_count;
+    let mut src_offset = 0;
+
+    let lb_ecc = QuircRsParams {
+        bs: sb_ecc.bs + 1,
+        dw: sb_ecc.dw + 1,
+        ns: sb_ecc.ns,
+    };
+
+    let npar = (sb_ecc.bs - sb_ecc.dw) as usize;
+    let gen = rs_generator_poly(npar);
+    let mut parity = [0u8; MAX_POLY];
+    let mut raw = vec![0u8; ver.data_bytes as usize];
+
+    for i in 0..bc {
+        let ecc = if i < sb_ecc.ns { sb_ecc } else { &lb_ecc };
+        let block = &data[src_offset..src_offset + ecc.dw as usize];
+
+        for j in 0..ecc.dw {
+            raw[codestream_data_offset(sb_ecc, bc, i, j)] = block[j as usize];
+        }
+
+        rs_remainder(block, &gen, npar, &mut parity);
+        for j in 0..npar as u32 {
+            raw[(ecc_offset + j * bc + i) as usize] = parity[j as usize];
+        }
+
+        src_offset += ecc.dw as usize;
+    }
+
+    raw
+}
@@ -0,0 +1,186 @@
+//! Code layout
+//!
+//! Draws what `reserved_cell()` skips, and the data `read_data()` reads, into a `QuircCode`.
+
+
+use self::super::super::super::ops::version_db::{QUIRC_MAX_ALIGNMENT, QUIRC_VERSION_DB};
+use self::super::super::super::ops::QuircCode;
+use self::super::super::decode::{FORMAT_GENERATOR, FORMAT_MASK, FORMAT_XS, FORMAT_YS, reserved_cell, mask_bit};
+
+
+/// BCH generator of the version information, `x^12 + x^11 + x^10 + x^9 + x^8 + x^5 + x^2 + 1`.
+pub const VERSION_GENERATOR: u32 = 0x1f25;
+
+
+#[inline]
+fn set_cell(code: &mut QuircCode, x: u32, y: u32, dark: bool) {
+    let p = y * code.size + x;
+
+    if dark {
+        code.cell_bitmap[(p >> 3) as usize] |= 1 << (p & 7);
+    } else {
+        code.cell_bitmap[(p >> 3) as usize] &= !(1 << (p & 7));
+    }
+}
+
+/// Remainder of `data << bits` divided by `generator`, appended to `data`.
+fn bch_encode(data: u32, bits: u32, generator: u32) -> u32 {
+    let mut rem = data << bits;
+
+    for i in (bits..32).rev() {
+        if rem & (1 << i) != 0 {
+            rem ^= generator << (i - bits);
+        }
+    }
+
+    (data << bits) | rem
+}
+
+/// The format bits, masked, as `format_bits()` reads them.
+pub fn format_bits(ecc_level: u8, mask: u8) -> u16 {
+    bch_encode(((ecc_level as u32) << 3) | mask as u32, 10, FORMAT_GENERATOR as u32) as u16 ^ FORMAT_MASK
+}
+
+/// The 18 version bits of versions 7 and up.
+pub fn version_bits(version: u32) -> u32 {
+    bch_encode(version, 12, VERSION_GENERATOR)
+}
+
+
+fn draw_finder(code: &mut QuircCode, cx: i32, cy: i32) {
+    for dy in -4..5 {
+        for dx in -4..5 {
+            let x = cx + dx;
+            let y = cy + dy;
+            if x < 0 || y < 0 || x >= code.size as i32 || y >= code.size as i32 {
+                continue;
+            }
+
+            let ring = dx.abs().max(dy.abs());
+            set_cell(code, x as u32, y as u32, ring != 2 && ring != 4);
+        }
+    }
+}
+
+fn draw_alignment(code: &mut QuircCode, cx: u32, cy: u32) {
+    for dy in -2i32..3 {
+        for dx in -2i32..3 {
+            set_cell(code, (cx as i32 + dx) as u32, (cy as i32 + dy) as u32, dx.abs().max(dy.abs()) != 1);
+        }
+    }
+}
+
+/// Clear the code and draw the finder, timing and alignment patterns, the dark module, and the version information.
+///
+/// The format information is left blank.
+pub fn draw_function_patterns(code: &mut QuircCode, version: u32) {
+    let ver = &QUIRC_VERSION_DB[version as usize];
+    let size = version * 4 + 17;
+
+    code.size = size;
+    for b in code.cell_bitmap.iter_mut() {
+        *b = 0;
+    }
+
+    // Timing patterns
+    for i in 8..size - 8 {
+        set_cell(code, i, 6, i % 2 == 0);
+        set_cell(code, 6, i, i % 2 == 0);
+    }
+
+    draw_finder(code, 3, 3);
+    draw_finder(code, size as i32 - 4, 3);
+    draw_finder(code, 3, size as i32 - 4);
+
+    // Alignment patterns, except where they'd overlap the finders
+    let apat_count = ver.apat.iter().take(QUIRC_MAX_ALIGNMENT).take_while(|&&p| p != 0).count();
+    for a in 0..apat_count {
+        for b in 0..apat_count {
+            if (a == 0 && (b == 0 || b == apat_count - 1)) || (a == apat_count - 1 && b == 0) {
+                continue;
+            }
+
+            draw_alignment(code, ver.apat[a], ver.apat[b]);
+        }
+    }
+
+    set_cell(code, 8, size - 8, true);
+
+    if version >= 7 {
+        let bits = version_bits(version);
+
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 != 0;
+            set_cell(code, size - 11 + i % 3, i / 3, dark);
+            set_cell(code, i / 3, size - 11 + i % 3, dark);
+        }
+    }
+}
+
+/// Place the codestream, MSB first, in the cells `reserved_cell()` leaves free, applying `mask`.
+///
+/// Remainder cells past the end of the codestream are written as zeroes.
+pub fn write_data(code: &mut QuircCode, version: u32, mask: u8, raw: &[u8]) {
+    let mut y = code.size as isize - 1;
+    let mut x = code.size as isize - 1;
+    let mut dir = -1;
+    let mut bit = 0;
+
+    let mut write_bit = |code: &mut QuircCode, i: u32, j: u32| {
+        let mut v = if bit < raw.len() * 8 {
+            (raw[bit >> 3] >> (7 - (bit & 7))) & 1
+        } else {
+            0
+        };
+        v ^= mask_bit(mask, i, j);
+
+        set_cell(code, j, i, v != 0);
+        bit += 1;
+    };
+
+    while x > 0 {
+        if x == 6 {
+            x -= 1;
+        }
+
+        if reserved_cell(version, y as u32, x as u32) == 0 {
+            write_bit(code, y as u32, x as u32);
+        }
+
+        if reserved_cell(version, y as u32, x as u32 - 1) == 0 {
+            write_bit(code, y as u32, x as u32 - 1);
+        }
+
+        y += dir;
+        if y < 0 || y >= code.size as isize {
+            dir = -dir;
+            x -= 2;
+            y += dir;
+        }
+    }
+}
+
+/// Write both copies of the format information.
+pub fn write_format(code: &mut QuircCode, ecc_level: u8, mask: u8) {
+    let format = format_bits(ecc_level, mask);
+    let size = code.size;
+
+    for i in 0..15 {
+        set_cell(code, FORMAT_XS[i], FORMAT_YS[i], (format >> i) & 1 != 0);
+    }
+
+    for i in 0..7 {
+        set_cell(code, 8, size - 1 - i, (format >> (14 - i)) & 1 != 0);
+    }
+    for i in 0..8 {
+        set_cell(code, size - 8 + i, 8, (format >> (7 - i)) & 1 != 0);
+    }
+}
+
+/// How many cells are dark.
+pub fn dark_modules(code: &QuircCode) -> usize {
+    let cells = (code.size * code.size) as usize;
+
+    code.cell_bitmap[..cells / 8].iter().map(|b| b.count_ones() as usize).sum::<usize>() +
+    (code.cell_bitmap[cells / 8] & ((1u16 << (cells % 8)) - 1) as u8).count_ones() as usize
+}
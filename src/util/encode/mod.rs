@@ -0,0 +1,14 @@
+mod segments;
+mod segmentation;
+mod err_corr;
+mod layout;
+mod penalty;
+mod encoder;
+
+pub use self::segments::{ECI_MODE, VERSION_RANGE_STARTS, Bitstream, version_range, char_count_bits, alpha_value, kanji_value, segment_chars, segment_bits,
+                         write_segment};
+pub use self::segmentation::optimal_segments;
+pub use self::err_corr::{data_codewords, rs_generator_poly, rs_remainder, build_codestream};
+pub use self::layout::{VERSION_GENERATOR, format_bits, version_bits, draw_function_patterns, write_data, write_format, dark_modules};
+pub use self::penalty::mask_penalty;
+pub use self::encoder::{encode, encode_text, encode_shift_jis, encode_segments};
@@ -0,0 +1,118 @@
+//! Mask penalty scoring
+//!
+//! The four rules of ISO/IEC 18004 section 7.8.3.1, applied to the whole symbol.
+//! Cells outside the symbol count as light, as the quiet zone is.
+
+
+use self::super::super::super::ops::{MaskPenalty, DecodeError, QuircCode, QuircData};
+use self::super::super::decode::{reserved_cell, mask_bit};
+use self::super::{write_format, dark_modules};
+
+
+/// A finder pattern's 1:1:3:1:1 run, which rule N3 looks for next to four light cells.
+static FINDER_LIKE: [bool; 7] = [true, false, true, true, true, false, true];
+
+
+impl QuircCode {
+    /// Score the cells as they are.
+    pub fn mask_penalty(&self) -> MaskPenalty {
+        mask_penalty(self)
+    }
+
+    /// Score the symbol as if it had been masked with each of the eight masks instead,
+    /// given its ECC level and mask from decoding it. The version follows from the grid size.
+    ///
+    /// A symbol whose own mask scores much worse than the best was likely generated without evaluating them.
+    pub fn mask_penalties(&self, data: &QuircData) -> Result<[MaskPenalty; 8], DecodeError> {
+        let version = self.grid_version()?;
+        let mut penalties = [MaskPenalty::default(); 8];
+
+        for mask in 0..8 {
+            let mut remasked = *self;
+
+            for i in 0..self.size {
+                for j in 0..self.size {
+                    if reserved_cell(version, i, j) == 0 && mask_bit(data.mask, i, j) != mask_bit(mask, i, j) {
+                        let p = i * self.size + j;
+                        remasked.cell_bitmap[(p >> 3) as usize] ^= 1 << (p & 7);
+                    }
+                }
+            }
+            write_format(&mut remasked, data.ecc_level, mask);
+
+            penalties[mask as usize] = mask_penalty(&remasked);
+        }
+
+        Ok(penalties)
+    }
+}
+
+
+#[inline]
+fn cell(code: &QuircCode, x: i32, y: i32) -> bool {
+    if x < 0 || y < 0 || x >= code.size as i32 || y >= code.size as i32 {
+        return false;
+    }
+
+    let p = y as u32 * code.size + x as u32;
+    (code.cell_bitmap[(p >> 3) as usize] >> (p & 7)) & 1 != 0
+}
+
+/// Score a line of cells, `at(k)` being the `k`th, for rules N1 and N3.
+fn line_penalty<F: Fn(i32) -> bool>(size: i32, at: F, penalty: &mut MaskPenalty) {
+    let mut run = 1;
+    for k in 1..size + 1 {
+        if k < size && at(k) == at(k - 1) {
+            run += 1;
+        } else {
+            if run >= 5 {
+                penalty.n1 += 3 + (run - 5);
+            }
+            run = 1;
+        }
+    }
+
+    for k in 0..size - 6 {
+        if (0..7).all(|d| at(k + d) == FINDER_LIKE[d as usize]) {
+            let light_before = (k - 4..k).all(|d| !at(d));
+            let light_after = (k + 7..k + 11).all(|d| !at(d));
+
+            if light_before || light_after {
+                penalty.n3 += 40;
+            }
+        }
+    }
+}
+
+/// Apply all four rules to the symbol's cells.
+pub fn mask_penalty(code: &QuircCode) -> MaskPenalty {
+    let size = code.size as i32;
+    let mut penalty = MaskPenalty::default();
+    if size == 0 {
+        return penalty;
+    }
+
+    for y in 0..size {
+        line_penalty(size, |x| cell(code, x, y), &mut penalty);
+    }
+    for x in 0..size {
+        line_penalty(size, |y| cell(code, x, y), &mut penalty);
+    }
+
+    for y in 0..size - 1 {
+        for x in 0..size - 1 {
+            let c = cell(code, x, y);
+            if cell(code, x + 1, y) == c && cell(code, x, y + 1) == c && cell(code, x + 1, y + 1) == c {
+                penalty.n2 += 3;
+            }
+        }
+    }
+
+    // k full 5% steps away from half dark
+    let total = (code.size * code.size) as usize;
+    let dark = dark_modules(code);
+    let deviation = (dark * 20).max(total * 10) - (dark * 20).min(total * 10);
+    penalty.n4 = (deviation / total) as u32 * 10;
+
+    penalty
+}
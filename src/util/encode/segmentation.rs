@@ -0,0 +1,149 @@
+//! Optimal segmentation
+//!
+//! A shortest-path search over the data, through the states "inside a segment of mode X,
+//! with Y characters past the last full group".
+//! Tracking the partial numeric and alphanumeric groups makes every step cost a whole number of bits,
+//! so the result is exact rather than rounded from fractional per-character costs.
+
+
+use self::super::super::super::constants::{QUIRC_DATA_TYPE_NUMERIC, QUIRC_DATA_TYPE_ALPHA, QUIRC_DATA_TYPE_BYTE, QUIRC_DATA_TYPE_KANJI};
+use self::super::super::super::ops::encode::Segment;
+use self::super::{char_count_bits, alpha_value, kanji_value};
+use alloc::vec::Vec;
+
+
+/// Numeric with 1, 2 and 0 digits past the last group of three, alphanumeric with 1 and 0 past the last pair, byte, and kanji.
+const STATES: usize = 7;
+static STATE_MODES: [u8; STATES] = [QUIRC_DATA_TYPE_NUMERIC,
+                                    QUIRC_DATA_TYPE_NUMERIC,
+                                    QUIRC_DATA_TYPE_NUMERIC,
+                                    QUIRC_DATA_TYPE_ALPHA,
+                                    QUIRC_DATA_TYPE_ALPHA,
+                                    QUIRC_DATA_TYPE_BYTE,
+                                    QUIRC_DATA_TYPE_KANJI];
+
+
+#[derive(Copy, Clone)]
+struct Step {
+    bits: usize,
+    /// The state before this character, `None` at the start of the data.
+    from: Option<usize>,
+    /// Whether this character starts a new segment.
+    header: bool,
+}
+
+/// The state after appending a character of `mode` to a segment in `state` (or to a new one, if `None`), and what it costs.
+fn transition(mode: u8, state: Option<usize>) -> (usize, usize) {
+    match (mode, state) {
+        (QUIRC_DATA_TYPE_NUMERIC, Some(0)) => (1, 3),
+        (QUIRC_DATA_TYPE_NUMERIC, Some(1)) => (2, 3),
+        (QUIRC_DATA_TYPE_NUMERIC, _) => (0, 4),
+        (QUIRC_DATA_TYPE_ALPHA, Some(3)) => (4, 5),
+        (QUIRC_DATA_TYPE_ALPHA, _) => (3, 6),
+        (QUIRC_DATA_TYPE_BYTE, _) => (5, 8),
+        (_, _) => (6, 13),
+    }
+}
+
+/// How many bytes the character at `pos` takes up in `mode`, if it can be represented at all.
+fn char_width(data: &[u8], pos: usize, mode: u8) -> Option<usize> {
+    let c = data[pos];
+
+    match mode {
+        QUIRC_DATA_TYPE_NUMERIC if c.is_ascii_digit() => Some(1),
+        QUIRC_DATA_TYPE_ALPHA if alpha_value(c).is_some() => Some(1),
+        QUIRC_DATA_TYPE_BYTE => Some(1),
+        QUIRC_DATA_TYPE_KANJI if pos + 1 < data.len() && kanji_value(c, data[pos + 1]).is_some() => Some(2),
+        _ => None,
+    }
+}
+
+/// The segments taking up the fewest bits in versions with the same character count widths as `version`.
+///
+/// Kanji mode is only used if `kanji` is set, for byte pairs `decode_kanji()` gives back unchanged.
+/// Segments with more characters than their count field holds are split, at the cost of an extra header each.
+pub fn optimal_segments<'d>(data: &'d [u8], version: u32, kanji: bool) -> Vec<Segment<'d>> {
+    let modes: &[u8] = if kanji {
+        &[QUIRC_DATA_TYPE_NUMERIC, QUIRC_DATA_TYPE_ALPHA, QUIRC_DATA_TYPE_BYTE, QUIRC_DATA_TYPE_KANJI]
+    } else {
+        &[QUIRC_DATA_TYPE_NUMERIC, QUIRC_DATA_TYPE_ALPHA, QUIRC_DATA_TYPE_BYTE]
+    };
+
+    // best[pos][state]: the cheapest way to have encoded data[..pos], ending in `state`
+    let mut best: Vec<[Option<Step>; STATES]> = vec![[None; STATES]; data.len() + 1];
+
+    for pos in 0..data.len() {
+        let reached: Vec<(usize, Option<usize>)> = if pos == 0 {
+            vec![(0, None)]
+        } else {
+            (0..STATES).filter_map(|s| best[pos][s].map(|step| (step.bits, Some(s)))).collect()
+        };
+
+        for (bits, from) in reached {
+            for &mode in modes {
+                let width = match char_width(data, pos, mode) {
+                    Some(width) => width,
+                    None => continue,
+                };
+                let continues = from.map(|from| STATE_MODES[from] == mode).unwrap_or(false);
+
+                let mut relax = |to: usize, bits: usize, header: bool| {
+                    let slot = &mut best[pos + width][to];
+                    if slot.map(|step| bits < step.bits).unwrap_or(true) {
+                        *slot = Some(Step { bits, from, header });
+                    }
+                };
+
+                if continues {
+                    let (to, cost) = transition(mode, from);
+                    relax(to, bits + cost, false);
+                }
+
+                let (to, cost) = transition(mode, None);
+                relax(to, bits + 4 + char_count_bits(mode, version) + cost, true);
+            }
+        }
+    }
+
+    // Walk back from the cheapest final state, cutting at each header
+    let mut segments = Vec::new();
+    let mut state = (0..STATES).filter(|&s| best[data.len()][s].is_some()).min_by_key(|&s| best[data.len()][s].map(|step| step.bits));
+    let mut end = data.len();
+    let mut pos = data.len();
+
+    while let Some(s) = state {
+        let step = best[pos][s].unwrap();
+        pos -= if STATE_MODES[s] == QUIRC_DATA_TYPE_KANJI { 2 } else { 1 };
+
+        if step.header {
+            push_split(&mut segments, STATE_MODES[s], &data[pos..end], version);
+            end = pos;
+        }
+        state = step.from;
+    }
+
+    segments.reverse();
+    segments
+}
+
+/// Append the segment, in reverse order, split into pieces whose character counts fit the count field.
+fn push_split<'d>(segments: &mut Vec<Segment<'d>>, mode: u8, data: &'d [u8], version: u32) {
+    let max_chars = (1usize << char_count_bits(mode, version)) - 1;
+    // Keep full groups together, so splitting only adds the header
+    let max_bytes = match mode {
+        QUIRC_DATA_TYPE_NUMERIC => max_chars / 3 * 3,
+        QUIRC_DATA_TYPE_ALPHA => max_chars / 2 * 2,
+        QUIRC_DATA_TYPE_KANJI => max_chars * 2,
+        _ => max_chars,
+    };
+
+    let pieces: Vec<&[u8]> = data.chunks(max_bytes).collect();
+    for piece in pieces.into_iter().rev() {
+        segments.push(match mode {
+            QUIRC_DATA_TYPE_NUMERIC => Segment::Numeric(piece),
+            QUIRC_DATA_TYPE_ALPHA => Segment::Alpha(piece),
+            QUIRC_DATA_TYPE_KANJI => Segment::Kanji(piece),
+            _ => Segment::Byte(piece),
+        });
+    }
+}
@@ -0,0 +1,208 @@
+//! Data segments
+
+
+use self::super::super::super::constants::{QUIRC_DATA_TYPE_NUMERIC, QUIRC_DATA_TYPE_ALPHA, QUIRC_DATA_TYPE_BYTE, QUIRC_DATA_TYPE_KANJI};
+use self::super::super::super::ops::encode::Segment;
+use self::super::super::super::ops::EncodeError;
+use self::super::super::decode::ALPHA_MAP;
+use alloc::vec::Vec;
+
+
+/// The mode indicator of ECI segments; the other modes' are their `QUIRC_DATA_TYPE_*`.
+pub const ECI_MODE: u8 = 7;
+
+
+/// The codestream being written, MSB first.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct Bitstream {
+    pub data: Vec<u8>,
+    pub bits: usize,
+}
+
+impl Bitstream {
+    pub fn new() -> Bitstream {
+        Bitstream::default()
+    }
+
+    /// Append the low `count` bits of `value`, most significant first.
+    pub fn push_bits(&mut self, value: u32, count: usize) {
+        for i in (0..count).rev() {
+            if self.bits.is_multiple_of(8) {
+                self.data.push(0);
+            }
+            if (value >> i) & 1 != 0 {
+                self.data[self.bits / 8] |= 0x80 >> (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+    }
+}
+
+
+/// The lowest version of each range sharing the same character count widths.
+pub static VERSION_RANGE_STARTS: [u32; 3] = [1, 10, 27];
+
+
+/// Which of the `VERSION_RANGE_STARTS` ranges the version is in.
+pub fn version_range(version: u32) -> usize {
+    VERSION_RANGE_STARTS.iter().rposition(|&start| version >= start).unwrap_or(0)
+}
+
+/// The width of the character count of the given mode, as read by `decode_numeric()` & co.
+pub fn char_count_bits(data_type: u8, version: u32) -> usize {
+    let range = version_range(version);
+
+    match data_type {
+        QUIRC_DATA_TYPE_NUMERIC => [10, 12, 14][range],
+        QUIRC_DATA_TYPE_ALPHA => [9, 11, 13][range],
+        QUIRC_DATA_TYPE_BYTE => [8, 16, 16][range],
+        QUIRC_DATA_TYPE_KANJI => [8, 10, 12][range],
+        _ => 0,
+    }
+}
+
+/// The value of a character in alphanumeric mode.
+pub fn alpha_value(c: u8) -> Option<u32> {
+    ALPHA_MAP.iter().position(|&a| a == c).map(|v| v as u32)
+}
+
+/// The 13-bit value of a Shift JIS character in kanji mode; the inverse of `decode_kanji()`.
+pub fn kanji_value(hi: u8, lo: u8) -> Option<u32> {
+    let sjw = ((hi as u32) << 8) | lo as u32;
+
+    let intermediate = if (0x8140..=0x9ffc).contains(&sjw) {
+        sjw - 0x8140
+    } else if (0xe040..=0xebbf).contains(&sjw) {
+        sjw - 0xc140
+    } else {
+        return None;
+    };
+
+    if intermediate & 0xff >= 0xc0 {
+        return None;
+    }
+
+    Some((intermediate >> 8) * 0xc0 + (intermediate & 0xff))
+}
+
+
+/// Check that the contents of a segment can be represented in its mode,
+/// and return how many characters its count field will hold.
+///
+/// `index` is only used to report errors.
+pub fn segment_chars(segment: &Segment, index: usize) -> Result<usize, EncodeError> {
+    let invalid = |offset| EncodeError::InvalidCharacter {
+        segment: index,
+        offset,
+    };
+
+    match *segment {
+        Segment::Numeric(data) => {
+            match data.iter().position(|c| !c.is_ascii_digit()) {
+                Some(offset) => Err(invalid(offset)),
+                None => Ok(data.len()),
+            }
+        }
+        Segment::Alpha(data) => {
+            match data.iter().position(|&c| alpha_value(c).is_none()) {
+                Some(offset) => Err(invalid(offset)),
+                None => Ok(data.len()),
+            }
+        }
+        Segment::Byte(data) => Ok(data.len()),
+        Segment::Kanji(data) => {
+            for i in (0..data.len()).step_by(2) {
+                if i + 1 >= data.len() || kanji_value(data[i], data[i + 1]).is_none() {
+                    return Err(invalid(i));
+                }
+            }
+            Ok(data.len() / 2)
+        }
+        Segment::Eci(eci) => {
+            if eci > 999999 {
+                Err(EncodeError::InvalidEci(eci))
+            } else {
+                Ok(0)
+            }
+        }
+    }
+}
+
+/// How many bits a (valid) segment takes up in the given version,
+/// or `None` if its character count doesn't fit in the count field.
+pub fn segment_bits(segment: &Segment, version: u32) -> Option<usize> {
+    let (data_type, chars, data_bits) = match *segment {
+        Segment::Numeric(data) => (QUIRC_DATA_TYPE_NUMERIC, data.len(), data.len() / 3 * 10 + [0, 4, 7][data.len() % 3]),
+        Segment::Alpha(data) => (QUIRC_DATA_TYPE_ALPHA, data.len(), data.len() / 2 * 11 + data.len() % 2 * 6),
+        Segment::Byte(data) => (QUIRC_DATA_TYPE_BYTE, data.len(), data.len() * 8),
+        Segment::Kanji(data) => (QUIRC_DATA_TYPE_KANJI, data.len() / 2, data.len() / 2 * 13),
+        Segment::Eci(eci) => {
+            return Some(4 +
+                        if eci < 1 << 7 {
+                8
+            } else if eci < 1 << 14 {
+                16
+            } else {
+                24
+            })
+        }
+    };
+
+    let count_bits = char_count_bits(data_type, version);
+    if chars >= 1 << count_bits {
+        return None;
+    }
+
+    Some(4 + count_bits + data_bits)
+}
+
+/// Append a (valid) segment, whose character count fits in the given version, to the codestream.
+pub fn write_segment(segment: &Segment, version: u32, bs: &mut Bitstream) {
+    match *segment {
+        Segment::Numeric(data) => {
+            bs.push_bits(QUIRC_DATA_TYPE_NUMERIC as u32, 4);
+            bs.push_bits(data.len() as u32, char_count_bits(QUIRC_DATA_TYPE_NUMERIC, version));
+
+            for group in data.chunks(3) {
+                let value = group.iter().fold(0, |acc, &c| acc * 10 + (c - b'0') as u32);
+                bs.push_bits(value, [0, 4, 7, 10][group.len()]);
+            }
+        }
+        Segment::Alpha(data) => {
+            bs.push_bits(QUIRC_DATA_TYPE_ALPHA as u32, 4);
+            bs.push_bits(data.len() as u32, char_count_bits(QUIRC_DATA_TYPE_ALPHA, version));
+
+            for pair in data.chunks(2) {
+                let value = pair.iter().fold(0, |acc, &c| acc * 45 + alpha_value(c).unwrap_or(0));
+                bs.push_bits(value, if pair.len() == 2 { 11 } else { 6 });
+            }
+        }
+        Segment::Byte(data) => {
+            bs.push_bits(QUIRC_DATA_TYPE_BYTE as u32, 4);
+            bs.push_bits(data.len() as u32, char_count_bits(QUIRC_DATA_TYPE_BYTE, version));
+
+            for &b in data {
+                bs.push_bits(b as u32, 8);
+            }
+        }
+        Segment::Kanji(data) => {
+            bs.push_bits(QUIRC_DATA_TYPE_KANJI as u32, 4);
+            bs.push_bits((data.len() / 2) as u32, char_count_bits(QUIRC_DATA_TYPE_KANJI, version));
+
+            for pair in data.chunks(2) {
+                bs.push_bits(kanji_value(pair[0], pair[1]).unwrap_or(0), 13);
+            }
+        }
+        Segment::Eci(eci) => {
+            bs.push_bits(ECI_MODE as u32, 4);
+
+            if eci < 1 << 7 {
+                bs.push_bits(eci, 8);
+            } else if eci < 1 << 14 {
+                bs.push_bits(0x8000 | eci, 16);
+            } else {
+                bs.push_bits(0xc00000 | eci, 24);
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Multi-frame cell fusion
+
+
+use self::super::super::ops::fusion::{FusedCode, CellVotes, QuircFusion};
+use self::super::super::ops::tracker::{TrackedCode, QuircTracker};
+use self::super::super::constants::QUIRC_MAX_BITMAP;
+use self::super::super::ops::{ExtractError, ScanResult, QuircPerspective, QuircCode, Decoder, Quirc};
+use std::vec::Vec;
+
+
+/// The cell bitmap with a cell dark wherever its vote is positive.
+pub fn vote_bitmap(votes: &[i32]) -> [u8; QUIRC_MAX_BITMAP] {
+    let mut bitmap = [0u8; QUIRC_MAX_BITMAP];
+    for (i, &vote) in votes.iter().enumerate() {
+        if vote > 0 {
+            bitmap[i >> 3] |= 1 << (i & 7);
+        }
+    }
+    bitmap
+}
+
+
+impl Quirc {
+    /// How dark each cell of the code specified by the given index looks, from -9 (all light) to 9 (all dark),
+    /// sampled at nine points across it, in the order of `QuircCode::cell_bitmap`.
+    ///
+    /// Cells outside the image are 0.
+    pub fn cell_values(&self, index: usize) -> Result<Vec<i8>, ExtractError> {
+        if index >= self.num_grids {
+            return Err(ExtractError {
+                index,
+                count: self.num_grids,
+            });
+        }
+
+        let qr = &self.grids[index];
+        Ok(self.grid_cell_values(&qr.c, qr.grid_size))
+    }
+
+    /// `cell_values()` for a grid that needn't be one of the recognizer's.
+    pub(crate) fn grid_cell_values(&self, c: &QuircPerspective, grid_size: u32) -> Vec<i8> {
+        let size = grid_size as usize;
+        (0..size * size).map(|i| self.fitness_cell(c, i % size, i / size) as i8).collect()
+    }
+}
+
+impl QuircFusion {
+    /// An accumulator with nothing in it.
+    pub fn new() -> QuircFusion {
+        QuircFusion { codes: Vec::new() }
+    }
+
+    /// Forget everything accumulated.
+    pub fn reset(&mut self) {
+        self.codes.clear();
+    }
+
+    /// Add the cells of the codes the tracker just returned, and decode each from all the frames it's been followed through.
+    ///
+    /// `codes` has to be what the last `track()` returned. Codes no longer followed are forgotten,
+    /// as is what was accumulated for a code whose size changed.
+    pub fn add(&mut self, tracker: &QuircTracker, codes: &[TrackedCode]) -> Vec<FusedCode> {
+        let mut decoder = Decoder::new();
+
+        let mut fused = Vec::with_capacity(codes.len());
+        let mut kept = Vec::with_capacity(codes.len());
+        for (index, code) in codes.iter().enumerate() {
+            let values = match tracker.cell_values(index) {
+                Ok(values) => values,
+                Err(_) => continue,
+            };
+
+            let mut entry = match self.codes.iter().position(|c| c.id == code.id) {
+                Some(i) if self.codes[i].grid_size == code.result.code.size => self.codes.swap_remove(i),
+                _ => {
+                    CellVotes {
+                        id: code.id,
+                        grid_size: code.result.code.size,
+                        frames: 0,
+                        votes: vec![0; values.len()],
+                    }
+                }
+            };
+            for (vote, &value) in entry.votes.iter_mut().zip(&values) {
+                *vote += value as i32;
+            }
+            entry.frames += 1;
+
+            let cells = QuircCode {
+                corners: code.result.corners,
+                size: entry.grid_size,
+                cell_bitmap: vote_bitmap(&entry.votes),
+            };
+            fused.push(FusedCode {
+                id: code.id,
+                frames: entry.frames,
+                result: ScanResult {
+                    corners: cells.corners,
+                    data: decoder.decode(&cells),
+                    code: cells,
+                    inferred: code.result.inferred,
+                },
+            });
+            kept.push(entry);
+        }
+
+        self.codes = kept;
+        fused
+    }
+}
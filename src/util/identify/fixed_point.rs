@@ -0,0 +1,156 @@
+//! Fixed-point linear algebra routines
+//!
+//! These mirror `perspective_setup()`, `perspective_map()` and `perspective_unmap()`,
+//! but only use `i64` arithmetic, for targets where `f64` is emulated.
+//!
+//! Grid coordinates and the affine parameters (`c[0]`-`c[5]`) are Q16.16.
+//! The projective parameters (`c[6]` and `c[7]`) are usually tiny, so they're Q32.32 instead.
+
+
+use self::super::super::super::ops::QuircPoint;
+
+
+/// Fractional bits of grid coordinates and the affine parameters.
+pub const FIXED_FRAC_BITS: u32 = 16;
+/// Fractional bits of the projective parameters.
+pub const PROJECTIVE_FRAC_BITS: u32 = 32;
+
+/// 1.0 in Q16.16.
+pub const FIXED_ONE: i64 = 1 << FIXED_FRAC_BITS;
+
+
+/// `num / den`, rounded to nearest, with `frac_bits` fractional bits.
+///
+/// Saturates instead of overflowing, and returns 0 for a zero denominator.
+pub fn fixed_div(num: i64, den: i64, frac_bits: u32) -> i64 {
+    wide_div(num as i128, den as i128, frac_bits)
+}
+
+/// `fixed_div()` of products too wide for `i64`.
+pub fn wide_div(num: i128, den: i128, frac_bits: u32) -> i64 {
+    if den == 0 {
+        return 0;
+    }
+
+    let negative = (num < 0) != (den < 0);
+    let num = num.unsigned_abs();
+    let den = den.unsigned_abs();
+
+    let mut q = num / den;
+    let mut r = num % den;
+
+    if q >= 1 << (62 - frac_bits) {
+        return if negative { i64::MIN } else { i64::MAX };
+    }
+
+    // Long division for the fractional bits, plus one to round with
+    for _ in 0..frac_bits + 1 {
+        r <<= 1;
+        q <<= 1;
+        if r >= den {
+            r -= den;
+            q |= 1;
+        }
+    }
+    let q = ((q + 1) >> 1) as i64;
+
+    if negative { -q } else { q }
+}
+
+pub fn perspective_setup(rect: &[QuircPoint], w: i64, h: i64) -> [i64; 8] {
+    let x0 = rect[0].x as i128;
+    let y0 = rect[0].y as i128;
+    let x1 = rect[1].x as i128;
+    let y1 = rect[1].y as i128;
+    let x2 = rect[2].x as i128;
+    let y2 = rect[2].y as i128;
+    let x3 = rect[3].x as i128;
+    let y3 = rect[3].y as i128;
+
+    // The corners are whole pixels, so everything's exact up until the divisions
+    let (w, h) = (w as i128, h as i128);
+    let wden = w * (x2 * y3 - x3 * y2 + (x3 - x2) * y1 + x1 * (y2 - y3));
+    let hden = h * (x2 * y3 + x1 * (y2 - y3) - x3 * y2 + (x3 - x2) * y1);
+
+    let mut c = [0i64; 8];
+
+    c[0] = wide_div(x1 * (x2 * y3 - x3 * y2) + x0 * (-x2 * y3 + x3 * y2 + (x2 - x3) * y1) + x1 * (x3 - x2) * y0,
+                    wden,
+                    FIXED_FRAC_BITS);
+    c[1] = wide_div(-(x0 * (x2 * y3 + x1 * (y2 - y3) - x2 * y1) - x1 * x3 * y2 + x2 * x3 * y1 + (x1 * x3 - x2 * x3) * y0),
+                    hden,
+                    FIXED_FRAC_BITS);
+    c[2] = (x0 << FIXED_FRAC_BITS) as i64;
+    c[3] = wide_div(y0 * (x1 * (y3 - y2) - x2 * y3 + x3 * y2) + y1 * (x2 * y3 - x3 * y2) + x0 * y1 * (y2 - y3),
+                    wden,
+                    FIXED_FRAC_BITS);
+    c[4] = wide_div(x0 * (y1 * y3 - y2 * y3) + x1 * y2 * y3 - x2 * y1 * y3 + y0 * (x3 * y2 - x1 * y2 + (x2 - x3) * y1),
+                    hden,
+                    FIXED_FRAC_BITS);
+    c[5] = (y0 << FIXED_FRAC_BITS) as i64;
+    c[6] = wide_div(x1 * (y3 - y2) + x0 * (y2 - y3) + (x2 - x3) * y1 + (x3 - x2) * y0, wden, PROJECTIVE_FRAC_BITS);
+    c[7] = wide_div(-x2 * y3 + x1 * y3 + x3 * y2 + x0 * (y1 - y2) - x3 * y1 + (x2 - x1) * y0,
+                    hden,
+                    PROJECTIVE_FRAC_BITS);
+
+    c
+}
+
+pub fn perspective_map(c: &[i64], u: i64, v: i64) -> QuircPoint {
+    assert!(c.len() >= 8);
+    let c = wide(c);
+    let (u, v) = (u as i128, v as i128);
+
+    // All Q32.32
+    let den = ((c[6] * u + c[7] * v) >> FIXED_FRAC_BITS) + (1 << PROJECTIVE_FRAC_BITS);
+    let x = c[0] * u + c[1] * v + (c[2] << FIXED_FRAC_BITS);
+    let y = c[3] * u + c[4] * v + (c[5] << FIXED_FRAC_BITS);
+
+    QuircPoint {
+        x: fixed_round(wide_div(x, den, FIXED_FRAC_BITS)),
+        y: fixed_round(wide_div(y, den, FIXED_FRAC_BITS)),
+    }
+}
+
+/// Round Q16.16 to nearest, with ties to even, like `rint()`.
+pub fn fixed_round(x: i64) -> isize {
+    const HALF: i64 = FIXED_ONE / 2;
+
+    let whole = x >> FIXED_FRAC_BITS;
+    let frac = x & (FIXED_ONE - 1);
+
+    (if frac == HALF {
+        whole + (whole & 1)
+    } else if frac > HALF {
+        whole + 1
+    } else {
+        whole
+    }) as isize
+}
+
+pub fn perspective_unmap(c: &[i64], in_p: &QuircPoint) -> (i64, i64) {
+    assert!(c.len() >= 8);
+    let c = wide(c);
+    let x = in_p.x as i128;
+    let y = in_p.y as i128;
+    let y_fixed = y << FIXED_FRAC_BITS;
+
+    // Products of an affine and a projective parameter are shifted back down to Q32.32
+    let den = ((-c[0] * c[7] + c[1] * c[6]) >> FIXED_FRAC_BITS) * y + ((c[3] * c[7] - c[4] * c[6]) >> FIXED_FRAC_BITS) * x + c[0] * c[4] - c[1] * c[3];
+
+    let u = -(c[1] * (y_fixed - c[5]) - ((c[2] * c[7]) >> FIXED_FRAC_BITS) * y + (((c[5] * c[7]) >> FIXED_FRAC_BITS) - (c[4] << FIXED_FRAC_BITS)) * x +
+              c[2] * c[4]);
+    let v = c[0] * (y_fixed - c[5]) - ((c[2] * c[6]) >> FIXED_FRAC_BITS) * y + (((c[5] * c[6]) >> FIXED_FRAC_BITS) - (c[3] << FIXED_FRAC_BITS)) * x +
+            c[2] * c[3];
+
+    (wide_div(u, den, FIXED_FRAC_BITS), wide_div(v, den, FIXED_FRAC_BITS))
+}
+
+/// The parameters widened to `i128`, so that no product of two of them, or of one and a coordinate, can overflow.
+fn wide(c: &[i64]) -> [i128; 8] {
+    let mut w = [0i128; 8];
+    for (w, &c) in w.iter_mut().zip(c) {
+        *w = c as i128;
+    }
+    w
+}
@@ -31,8 +31,8 @@ impl Quirc {
             }
 
             /* Fill the extent */
-            for i in left..(right + 1) {
-                row[i] = to;
+            for pixel in &mut row[left..right + 1] {
+                *pixel = to;
             }
         }
 
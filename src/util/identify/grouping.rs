@@ -0,0 +1,238 @@
+//! Global capstone grouping
+
+
+use self::super::super::super::ops::grouping::{GroupCandidate, GroupRejection};
+use self::super::super::super::ops::{QUIRC_MAX_GRIDS, QuircCapstone, QuircPoint, Quirc};
+use self::super::perspective::{self, Coord, tenths};
+use alloc::vec::Vec;
+
+
+/// How many assignments `select_groups()` tries before settling for the best one found so far.
+const SELECT_BUDGET: usize = 1 << 16;
+
+/// How far, in thousandths, the squared distance from the corner capstone to either neighbour
+/// may be from what the timing patterns' grid size and the capstones' size make for.
+const MAX_MODULE_ERROR: u32 = 400;
+
+
+/// The indices of the groups making up the best assignment of capstones to at most `max` codes:
+/// the one with the most groups not sharing a capstone, and the lowest total score of those.
+///
+/// Groups are tried best score first, so the first assignment found is the greedy one,
+/// and that's improved on for as long as the search budget lasts.
+///
+/// The search gives up after 2^16 partial assignments and returns the best one found by then, which may not be the best there is.
+///
+/// # Panics
+///
+/// If a capstone index is 64 or more, or `scores` isn't as long as `groups`.
+pub fn select_groups(groups: &[[u32; 3]], scores: &[u32], max: usize) -> Vec<usize> {
+    assert!(groups.iter().flatten().all(|&c| c < 64), "capstone indices have to be below 64");
+    assert_eq!(groups.len(), scores.len());
+
+    let mut order: Vec<usize> = (0..groups.len()).collect();
+    order.sort_by_key(|&i| scores[i]);
+
+    let mut search = GroupSearch {
+        groups,
+        scores,
+        order: &order,
+        max,
+        budget: SELECT_BUDGET,
+        chosen: Vec::new(),
+        best: Vec::new(),
+        best_score: 0,
+    };
+    search.search(0, 0, 0);
+    search.best
+}
+
+
+struct GroupSearch<'a> {
+    groups: &'a [[u32; 3]],
+    scores: &'a [u32],
+    order: &'a [usize],
+    max: usize,
+    budget: usize,
+    chosen: Vec<usize>,
+    best: Vec<usize>,
+    best_score: u64,
+}
+
+impl<'a> GroupSearch<'a> {
+    /// Try adding each group from `order[pos..]` not using a capstone in `used` to those chosen so far, scoring `score` together.
+    fn search(&mut self, pos: usize, used: u64, score: u64) {
+        if self.budget == 0 {
+            return;
+        }
+        self.budget -= 1;
+
+        let count = self.chosen.len();
+        if count > self.best.len() || (count == self.best.len() && score < self.best_score) {
+            self.best = self.chosen.clone();
+            self.best_score = score;
+        }
+
+        // Even taking every group left can't beat the best
+        let reachable = count + (self.order.len() - pos).min(self.max - count);
+        if reachable < self.best.len() || (reachable == self.best.len() && score >= self.best_score) {
+            return;
+        }
+
+        for p in pos..self.order.len() {
+            let i = self.order[p];
+            let mask = self.groups[i].iter().fold(0u64, |m, &c| m | 1 << c);
+            if used & mask != 0 {
+                continue;
+            }
+
+            self.chosen.push(i);
+            self.search(p + 1, used | mask, score + self.scores[i] as u64);
+            self.chosen.pop();
+        }
+    }
+}
+
+
+impl Quirc {
+    /// Process the image like `end()`, but group capstones into codes globally (see `ops::grouping`) instead of greedily.
+    ///
+    /// Returns every candidate triple considered, in the order found, with why it was rejected if it was.
+    pub fn end_global(&mut self) -> Vec<GroupCandidate> {
+        self.pixels_setup();
+        self.threshold();
+
+        for y in 0..self.h {
+            self.finder_scan(y);
+        }
+
+        self.group_globally()
+    }
+
+    /// Score every candidate triple of the capstones found, and make grids of the best assignment of them.
+    pub(crate) fn group_globally(&mut self) -> Vec<GroupCandidate> {
+        let mut candidates = Vec::new();
+        for i in 0..self.num_capstones {
+            self.group_candidates(i as u32, &mut candidates);
+        }
+
+        let valid: Vec<usize> = (0..candidates.len()).filter(|&i| candidates[i].rejection.is_none()).collect();
+        let groups: Vec<[u32; 3]> = valid.iter().map(|&i| candidates[i].caps).collect();
+        let scores: Vec<u32> = valid.iter().map(|&i| candidates[i].score).collect();
+        let mut picked: Vec<usize> = select_groups(&groups, &scores, QUIRC_MAX_GRIDS).into_iter().map(|g| valid[g]).collect();
+        picked.sort();
+
+        for &i in &valid {
+            candidates[i].rejection = Some(GroupRejection::Conflict);
+        }
+        for i in picked {
+            let [h, corner, v] = candidates[i].caps;
+            let before = self.num_grids;
+            self.record_qr_grid(h, corner, v);
+            candidates[i].rejection = if self.num_grids > before {
+                None
+            } else {
+                Some(GroupRejection::Setup)
+            };
+        }
+
+        candidates
+    }
+
+    /// Add a candidate for each horizontal and vertical neighbour `test_grouping()` would consider for capstone `i` to `candidates`.
+    fn group_candidates(&self, i: u32, candidates: &mut Vec<GroupCandidate>) {
+        let mut hlist: Vec<(u32, Coord)> = Vec::new();
+        let mut vlist: Vec<(u32, Coord)> = Vec::new();
+
+        for j in 0..self.num_capstones {
+            let (u, v) = match self.neighbour_offsets(i as usize, j) {
+                Some(offsets) => offsets,
+                None => continue,
+            };
+
+            if perspective::under_fifth(u, v) {
+                hlist.push((j as u32, v));
+            }
+            if perspective::under_fifth(v, u) {
+                vlist.push((j as u32, u));
+            }
+        }
+
+        for &(h, hd) in &hlist {
+            for &(v, vd) in &vlist {
+                let mut candidate = GroupCandidate {
+                    caps: [h, i, v],
+                    score: 0,
+                    rejection: None,
+                };
+
+                let corner = self.capstones[i as usize].center;
+                let (a, c) = (offset(&corner, &self.capstones[h as usize].center), offset(&corner, &self.capstones[v as usize].center));
+                let (la, lc) = (a.0 * a.0 + a.1 * a.1, c.0 * c.0 + c.1 * c.1);
+                let dot = a.0 * c.0 + a.1 * c.1;
+                let shape = thousandths((la - lc).abs(), la.max(lc)) + thousandths(dot * dot, la * lc);
+
+                let sides = [side_sq(&self.capstones[h as usize]), side_sq(&self.capstones[i as usize]), side_sq(&self.capstones[v as usize])];
+                let (smallest, largest) = (*sides.iter().min().unwrap(), *sides.iter().max().unwrap());
+                let sizes = thousandths(largest - smallest, largest);
+
+                candidate.score = shape + sizes;
+                if perspective::ratio_error(hd, vd) > tenths(25) {
+                    candidate.rejection = Some(GroupRejection::Shape);
+                } else if largest > 4 * smallest {
+                    candidate.rejection = Some(GroupRejection::ModuleSize);
+                } else {
+                    match self.group_timing(h, i, v) {
+                        Some((hscan, vscan, grid_size)) => {
+                            // The capstone centres are 7 cells short of the grid size apart, each cell a seventh of a capstone
+                            let expected = (grid_size as i128 - 7) * (grid_size as i128 - 7) * sides.iter().sum::<i128>() / 3;
+                            let module = thousandths((la * 49 - expected).abs(), expected.max(la * 49)) +
+                                         thousandths((lc * 49 - expected).abs(), expected.max(lc * 49));
+                            candidate.score += module;
+
+                            if hscan >= 0 && vscan >= 0 {
+                                candidate.score += thousandths((hscan - vscan).abs() as i128, hscan.max(vscan) as i128);
+                            } else {
+                                candidate.score += 500;
+                            }
+
+                            if module > 2 * MAX_MODULE_ERROR {
+                                candidate.rejection = Some(GroupRejection::ModuleSize);
+                            }
+                        }
+                        None => candidate.rejection = Some(GroupRejection::Timing),
+                    }
+                }
+
+                candidates.push(candidate);
+            }
+        }
+    }
+
+    /// Scan the timing patterns between capstones A, B and C as `record_qr_grid()` would,
+    /// returning the horizontal and vertical scans and the grid size they make for.
+    ///
+    /// Works on copies of the capstones, so that only the triples picked in the end are rotated, when they're recorded.
+    fn group_timing(&self, a: u32, b: u32, c: u32) -> Option<(i32, i32, u32)> {
+        self.trial_grid(a, b, c).map(|grid| (grid.hscan, grid.vscan, grid.grid_size))
+    }
+}
+
+
+fn offset(from: &QuircPoint, to: &QuircPoint) -> (i128, i128) {
+    ((to.x - from.x) as i128, (to.y - from.y) as i128)
+}
+
+fn side_sq(cap: &QuircCapstone) -> i128 {
+    let (a, b) = (offset(&cap.corners[0], &cap.corners[1]), offset(&cap.corners[0], &cap.corners[3]));
+    (a.0 * a.0 + a.1 * a.1).max(b.0 * b.0 + b.1 * b.1)
+}
+
+/// `num / den` in thousandths, at most a thousand thousand.
+fn thousandths(num: i128, den: i128) -> u32 {
+    if den <= 0 {
+        0
+    } else {
+        (num * 1000 / den).min(1_000_000) as u32
+    }
+}
@@ -72,8 +72,23 @@ pub fn perspective_map(c: &[f64], u: f64, v: f64) -> QuircPoint {
     let y = (c[3] * u + c[4] * v + c[5]) / den;
 
     QuircPoint {
-        x: x.round() as isize,
-        y: y.round() as isize,
+        x: round(x),
+        y: round(y),
+    }
+}
+
+/// `rint()` in the default rounding mode, i.e. to nearest, ties to even.
+#[inline]
+pub(crate) fn round(x: f64) -> isize {
+    let whole = x as isize;
+    let frac = x - whole as f64;
+
+    if frac > 0.5 || (frac == 0.5 && whole & 1 == 1) {
+        whole + 1
+    } else if frac < -0.5 || (frac == -0.5 && whole & 1 == 1) {
+        whole - 1
+    } else {
+        whole
     }
 }
 
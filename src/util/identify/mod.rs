@@ -1,5 +1,40 @@
 mod linear_algebra;
+pub mod fixed_point;
+#[cfg(feature = "alloc")]
+mod perspective;
+#[cfg(feature = "alloc")]
 mod thresholding;
+#[cfg(feature = "alloc")]
 mod floodfill;
+#[cfg(feature = "alloc")]
+mod pyramid;
+#[cfg(feature = "alloc")]
+mod roi;
+#[cfg(feature = "alloc")]
+mod grouping;
+#[cfg(feature = "alloc")]
+mod recovery;
+#[cfg(feature = "alloc")]
+mod simd;
+#[cfg(feature = "rayon")]
+mod parallel;
 
 pub use self::linear_algebra::{perspective_setup, perspective_unmap, perspective_map, line_intersect};
+#[cfg(feature = "alloc")]
+pub use self::thresholding::{QUIRC_PIXEL_WHITE, QUIRC_PIXEL_BLACK, QUIRC_PIXEL_REGION, FinderRuns, threshold_s, threshold_rows, threshold_state};
+#[cfg(feature = "alloc")]
+pub use self::pyramid::{QUIRC_CAPSTONE_SYNTHETIC, LevelScale, level_to_image, downscale_image, upscale_image};
+#[cfg(feature = "alloc")]
+pub use self::roi::clip_rect;
+#[cfg(feature = "alloc")]
+pub use self::grouping::select_groups;
+#[cfg(feature = "alloc")]
+pub use self::recovery::inferred_grid_size;
+#[cfg(feature = "alloc")]
+pub use self::simd::{widen_pixels, binarise_row, threshold_rows_with};
+#[cfg(feature = "alloc")]
+pub use self::perspective::{grid_corners, grid_setup, grid_contains, grid_contains_point};
+#[cfg(feature = "std")]
+pub(crate) use self::perspective::{Coord, fraction, halve, round, mul};
+#[cfg(feature = "rayon")]
+pub use self::parallel::band_rows;
@@ -0,0 +1,69 @@
+//! Multithreaded identification
+
+
+use self::super::super::super::ops::{SimdLevel, Quirc};
+use self::super::thresholding::{FinderRuns, threshold_state};
+use self::super::simd::{threshold_rows_with, widen_pixels};
+use rayon::prelude::*;
+use std::vec::Vec;
+
+
+/// How many rows each band `par_end()` splits an image `h` rows high into gets: a few bands per thread, but not thin ones.
+pub fn band_rows(h: usize, threads: usize) -> usize {
+    (h / (threads.max(1) * 4)).max(16)
+}
+
+
+impl Quirc {
+    /// Process the image like `end()`, spread across the rayon thread pool, with exactly the same result.
+    ///
+    /// The image is thresholded and scanned for capstones in bands of rows on separate threads,
+    /// each picking up the thresholding averages where the band above it would have left them (see `threshold_state()`).
+    /// The capstone candidates are then traced in the same order `end()` finds them in,
+    /// so a capstone straddling bands is traced whole and regions and capstones are numbered the same.
+    pub fn par_end(&mut self) {
+        let (w, h) = (self.w, self.h);
+        if w == 0 || h == 0 {
+            return self.end();
+        }
+        let rows = band_rows(h, rayon::current_num_threads());
+
+        let image = &self.image;
+        let level = SimdLevel::detect();
+        let mut scratch = vec![vec![0u64; w]; h.div_ceil(rows)];
+        let candidates: Vec<Vec<_>> = self.pixels
+            .par_chunks_mut(rows * w)
+            .zip(scratch.par_iter_mut())
+            .enumerate()
+            .map(|(band, (pixels, row_average))| {
+                let top = band * rows;
+                widen_pixels(level, &image[top * w..], pixels);
+
+                let mut avg = threshold_state(image, w, 0, w, 0, top);
+                threshold_rows_with(level, pixels, row_average, w, 0, w, top, &mut avg);
+
+                let mut found = Vec::new();
+                for (y, row) in (top..).zip(pixels.chunks(w)) {
+                    let mut runs = FinderRuns::new();
+                    for (x, &pixel) in row.iter().enumerate() {
+                        if let Some(pb) = runs.next(pixel != 0) {
+                            found.push((x, y, pb));
+                        }
+                    }
+                }
+                found
+            })
+            .collect();
+
+        // Leave the scratch row as end() would
+        self.row_average.copy_from_slice(&scratch[scratch.len() - 1]);
+
+        for (x, y, pb) in candidates.into_iter().flatten() {
+            self.test_capstone(x, y, &pb);
+        }
+
+        for i in 0..self.num_capstones {
+            self.test_grouping(i as u32);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Perspective arithmetic for the identification pipeline
+//!
+//! `f64` by default, Q16.16 (see `fixed_point`) with the `fixed-point` feature.
+//! Everything here behaves the same either way, up to rounding.
+
+
+use self::super::super::super::ops::{QuircPerspective, QuircPoint};
+#[cfg(feature = "fixed-point")]
+use self::super::fixed_point::{self, FIXED_FRAC_BITS, FIXED_ONE, fixed_div};
+#[cfg(not(feature = "fixed-point"))]
+use self::super::linear_algebra;
+
+
+/// A coordinate in grid space, in cells.
+#[cfg(not(feature = "fixed-point"))]
+pub type Coord = f64;
+/// A coordinate in grid space, in cells.
+#[cfg(feature = "fixed-point")]
+pub type Coord = i64;
+
+
+/// `n` cells.
+#[cfg(not(feature = "fixed-point"))]
+pub fn cells(n: u32) -> Coord {
+    n as f64
+}
+/// `n` cells.
+#[cfg(feature = "fixed-point")]
+pub fn cells(n: u32) -> Coord {
+    (n as i64) << FIXED_FRAC_BITS
+}
+
+/// `n` tenths of a cell.
+#[cfg(not(feature = "fixed-point"))]
+pub fn tenths(n: u32) -> Coord {
+    n as f64 / 10.0
+}
+/// `n` tenths of a cell.
+#[cfg(feature = "fixed-point")]
+pub fn tenths(n: u32) -> Coord {
+    (((n as i64) << FIXED_FRAC_BITS) + 5) / 10
+}
+
+
+#[cfg(not(feature = "fixed-point"))]
+pub fn setup(rect: &[QuircPoint], w: u32, h: u32) -> QuircPerspective {
+    linear_algebra::perspective_setup(rect, w as f64, h as f64)
+}
+#[cfg(feature = "fixed-point")]
+pub fn setup(rect: &[QuircPoint], w: u32, h: u32) -> QuircPerspective {
+    fixed_point::perspective_setup(rect, w as i64, h as i64)
+}
+
+#[cfg(not(feature = "fixed-point"))]
+pub fn map(c: &QuircPerspective, u: Coord, v: Coord) -> QuircPoint {
+    linear_algebra::perspective_map(c, u, v)
+}
+#[cfg(feature = "fixed-point")]
+pub fn map(c: &QuircPerspective, u: Coord, v: Coord) -> QuircPoint {
+    fixed_point::perspective_map(c, u, v)
+}
+
+#[cfg(not(feature = "fixed-point"))]
+pub fn unmap(c: &QuircPerspective, p: &QuircPoint) -> (Coord, Coord) {
+    linear_algebra::perspective_unmap(c, p)
+}
+#[cfg(feature = "fixed-point")]
+pub fn unmap(c: &QuircPerspective, p: &QuircPoint) -> (Coord, Coord) {
+    fixed_point::perspective_unmap(c, p)
+}
+
+
+/// The initial step `jiggle_perspective()` takes for a parameter: 2% of it.
+#[cfg(not(feature = "fixed-point"))]
+pub fn jiggle_step(param: f64) -> f64 {
+    param * 0.02
+}
+/// The initial step `jiggle_perspective()` takes for a parameter: 2% of it.
+#[cfg(feature = "fixed-point")]
+pub fn jiggle_step(param: i64) -> i64 {
+    param / 50
+}
+
+#[cfg(not(feature = "fixed-point"))]
+pub fn halve(param: f64) -> f64 {
+    param * 0.5
+}
+#[cfg(feature = "fixed-point")]
+pub fn halve(param: i64) -> i64 {
+    param / 2
+}
+
+
+/// Whether `a` is less than a fifth of `b`.
+#[cfg(not(feature = "fixed-point"))]
+pub fn under_fifth(a: Coord, b: Coord) -> bool {
+    a < 0.2 * b
+}
+/// Whether `a` is less than a fifth of `b`.
+#[cfg(feature = "fixed-point")]
+pub fn under_fifth(a: Coord, b: Coord) -> bool {
+    a * 5 < b
+}
+
+/// How far `a / b` is from 1.
+#[cfg(not(feature = "fixed-point"))]
+pub fn ratio_error(a: Coord, b: Coord) -> Coord {
+    (1.0 - a / b).abs()
+}
+/// How far `a / b` is from 1.
+#[cfg(feature = "fixed-point")]
+pub fn ratio_error(a: Coord, b: Coord) -> Coord {
+    (FIXED_ONE - fixed_div(a, b, FIXED_FRAC_BITS)).abs()
+}
+
+
+/// `num / den`, for pixel offsets and factors as well as coordinates.
+#[cfg(all(feature = "std", not(feature = "fixed-point")))]
+pub fn fraction(num: isize, den: isize) -> Coord {
+    num as f64 / den as f64
+}
+/// `num / den`, for pixel offsets and factors as well as coordinates.
+#[cfg(all(feature = "std", feature = "fixed-point"))]
+pub fn fraction(num: isize, den: isize) -> Coord {
+    fixed_div(num as i64, den as i64, FIXED_FRAC_BITS)
+}
+
+/// `a * b`.
+#[cfg(all(feature = "std", not(feature = "fixed-point")))]
+pub fn mul(a: Coord, b: Coord) -> Coord {
+    a * b
+}
+/// `a * b`, rounded to nearest.
+#[cfg(all(feature = "std", feature = "fixed-point"))]
+pub fn mul(a: Coord, b: Coord) -> Coord {
+    ((a as i128 * b as i128 + (FIXED_ONE / 2) as i128) >> FIXED_FRAC_BITS) as i64
+}
+
+/// `a` rounded to the nearest whole number, with ties to even.
+#[cfg(all(feature = "std", not(feature = "fixed-point")))]
+pub fn round(a: Coord) -> isize {
+    linear_algebra::round(a)
+}
+/// `a` rounded to the nearest whole number, with ties to even.
+#[cfg(all(feature = "std", feature = "fixed-point"))]
+pub fn round(a: Coord) -> isize {
+    fixed_point::fixed_round(a)
+}
+
+/// The image coordinates of the corners of a `size`-cell grid, from top left, clockwise.
+pub fn grid_corners(c: &QuircPerspective, size: u32) -> [QuircPoint; 4] {
+    [map(c, cells(0), cells(0)), map(c, cells(size), cells(0)), map(c, cells(size), cells(size)), map(c, cells(0), cells(size))]
+}
+
+/// The transform of a `size`-cell grid with the given corners, from top left, clockwise.
+pub fn grid_setup(corners: &[QuircPoint; 4], size: u32) -> QuircPerspective {
+    setup(corners, size, size)
+}
+
+/// Whether the centre of grid `b` is inside grid `a`.
+pub fn grid_contains(a: &QuircPerspective, a_size: u32, b: &QuircPerspective, b_size: u32) -> bool {
+    grid_contains_point(a, a_size, &map(b, tenths(5 * b_size), tenths(5 * b_size)))
+}
+
+/// Whether image point `p` is inside the `size`-cell grid `c`.
+pub fn grid_contains_point(c: &QuircPerspective, size: u32, p: &QuircPoint) -> bool {
+    let (u, v) = unmap(c, p);
+    u >= cells(0) && v >= cells(0) && u < cells(size) && v < cells(size)
+}
@@ -0,0 +1,255 @@
+//! Image pyramids
+
+
+use self::super::super::super::ops::pyramid::PyramidOptions;
+use self::super::super::super::ops::{QUIRC_MAX_CAPSTONES, QUIRC_MAX_GRIDS, QuircCapstone, QuircPoint, QuircGrid, Quirc};
+use self::super::perspective;
+use self::super::QUIRC_PIXEL_WHITE;
+use alloc::vec::Vec;
+
+
+/// The `ring` and `stone` of a capstone found on a pyramid level, whose regions aren't in the image.
+pub const QUIRC_CAPSTONE_SYNTHETIC: u32 = u32::MAX;
+
+/// How a pyramid level relates to the image: a level coordinate times `.0` over `.1` is an image coordinate,
+/// with pixel centres lined up.
+pub type LevelScale = (usize, usize);
+
+/// The image coordinates of a point on a level.
+pub fn level_to_image(p: &QuircPoint, scale: LevelScale) -> QuircPoint {
+    let (num, den) = (scale.0 as isize, scale.1 as isize);
+    let to_image = |v: isize| ((2 * v + 1) * num).div_euclid(2 * den);
+
+    QuircPoint {
+        x: to_image(p.x),
+        y: to_image(p.y),
+    }
+}
+
+/// Shrink by `factor`, averaging each block; the remainder of rows and columns that don't fill a block is dropped.
+pub fn downscale_image(image: &[u8], w: usize, h: usize, factor: usize) -> (Vec<u8>, usize, usize) {
+    let (lw, lh) = (w / factor, h / factor);
+    let mut sums = vec![0u32; lw];
+    let mut level = Vec::with_capacity(lw * lh);
+
+    for ly in 0..lh {
+        for s in sums.iter_mut() {
+            *s = 0;
+        }
+        for row in image[ly * factor * w..].chunks(w).take(factor) {
+            for (lx, s) in sums.iter_mut().enumerate() {
+                *s += row[lx * factor..(lx + 1) * factor].iter().map(|&p| p as u32).sum::<u32>();
+            }
+        }
+
+        let area = (factor * factor) as u32;
+        level.extend(sums.iter().map(|&s| ((s + area / 2) / area) as u8));
+    }
+
+    (level, lw, lh)
+}
+
+/// Enlarge by `factor`, interpolating bilinearly between pixel centres and clamping at the edges.
+pub fn upscale_image(image: &[u8], w: usize, h: usize, factor: usize) -> (Vec<u8>, usize, usize) {
+    let (lw, lh) = (w * factor, h * factor);
+    let steps = 2 * factor;
+    // The source pixel and the weight of the next one, in `steps`ths, for each level coordinate
+    let source = |l: usize, len: usize| {
+        let pos = (2 * l + 1).saturating_sub(factor);
+        let i = (pos / steps).min(len - 1);
+        let frac = if i + 1 < len { pos % steps } else { 0 };
+        (i, frac as u32)
+    };
+    let columns: Vec<_> = (0..lw).map(|lx| source(lx, w)).collect();
+
+    let mut level = Vec::with_capacity(lw * lh);
+    for ly in 0..lh {
+        let (y, fy) = source(ly, h);
+        let rows = [&image[y * w..(y + 1) * w], &image[(y + (fy != 0) as usize) * w..][..w]];
+
+        for &(x, fx) in &columns {
+            let x1 = x + (fx != 0) as usize;
+            let top = rows[0][x] as u32 * (steps as u32 - fx) + rows[0][x1] as u32 * fx;
+            let bottom = rows[1][x] as u32 * (steps as u32 - fx) + rows[1][x1] as u32 * fx;
+            let total = (steps * steps) as u32;
+            level.push(((top * (steps as u32 - fy) + bottom * fy + total / 2) / total) as u8);
+        }
+    }
+
+    (level, lw, lh)
+}
+
+/// The image coordinates of the corners of a grid found on a level, from top left, clockwise.
+fn level_corners(qr: &QuircGrid, scale: LevelScale) -> [QuircPoint; 4] {
+    perspective::grid_corners(&qr.c, qr.grid_size).map(|p| level_to_image(&p, scale))
+}
+
+impl Quirc {
+    /// Process the image like `end()`, but search the levels of an image pyramid too.
+    ///
+    /// The rows around each code found on a level are searched at full resolution, so that its corners are as precise as if it'd been
+    /// found there; if that doesn't find it, the level's grid is scaled up and refined against the image instead.
+    ///
+    /// Unless `options.exhaustive`, the downscaled level is searched first, then the image itself, then the upscaled level,
+    /// and the search stops at the first that finds any codes.
+    /// Otherwise every level is searched, and codes found on more than one are only reported once,
+    /// preferring the native level, then the downscaled one.
+    pub fn end_pyramid(&mut self, options: &PyramidOptions) {
+        if options.exhaustive {
+            if options.native {
+                self.end();
+                self.search_downscaled(options, &mut vec![true; self.h]);
+                self.search_upscaled(options, &mut vec![true; self.h]);
+            } else {
+                let mut scanned = self.blank_pixels();
+                self.search_downscaled(options, &mut scanned);
+                self.search_upscaled(options, &mut scanned);
+            }
+            return;
+        }
+
+        let mut scanned = self.blank_pixels();
+        self.search_downscaled(options, &mut scanned);
+        if self.num_grids != 0 {
+            return;
+        }
+
+        if options.native {
+            // Start over, so that the image is thresholded as a whole
+            self.begin();
+            self.end();
+            if self.num_grids != 0 {
+                return;
+            }
+            scanned = vec![true; self.h];
+        }
+        self.search_upscaled(options, &mut scanned);
+    }
+
+    /// Blank the thresholded image, so that only the rows around codes are thresholded, and return which rows have been.
+    fn blank_pixels(&mut self) -> Vec<bool> {
+        for p in self.pixels.iter_mut() {
+            *p = QUIRC_PIXEL_WHITE;
+        }
+        vec![false; self.h]
+    }
+
+    fn search_downscaled(&mut self, options: &PyramidOptions, scanned: &mut [bool]) {
+        let factor = options.downscale as usize;
+        if factor > 1 && self.w >= factor && self.h >= factor {
+            let (level, lw, lh) = downscale_image(&self.image, self.w, self.h, factor);
+            self.search_level(&level, lw, lh, (factor, 1), scanned);
+        }
+    }
+
+    fn search_upscaled(&mut self, options: &PyramidOptions, scanned: &mut [bool]) {
+        let factor = options.upscale as usize;
+        if factor > 1 && self.w * self.h <= options.max_upscale_pixels && self.w * self.h > 0 {
+            let (level, lw, lh) = upscale_image(&self.image, self.w, self.h, factor);
+            self.search_level(&level, lw, lh, (1, factor), scanned);
+        }
+    }
+
+    fn search_level(&mut self, level: &[u8], w: usize, h: usize, scale: LevelScale, scanned: &mut [bool]) {
+        let mut quirc = Quirc::new();
+        quirc.resize(w, h);
+        quirc.begin().copy_from_slice(level);
+        quirc.end();
+
+        for i in 0..quirc.num_grids {
+            // Scan the rows the code covers, and a margin of an eighth of its height either side
+            let corners = level_corners(&quirc.grids[i], scale);
+            let (top, bottom) = corners.iter().fold((isize::MAX, isize::MIN), |(t, b), p| (t.min(p.y), b.max(p.y)));
+            let margin = (bottom - top) / 8 + 1;
+            let (from, to) = ((top - margin).max(0) as usize, ((bottom + margin + 1).max(0) as usize).min(self.h));
+            let first_capstone = self.num_capstones;
+
+            // Threshold each run of rows not yet scanned in one go, then scan them
+            let mut y = from;
+            while y < to {
+                if scanned[y] {
+                    y += 1;
+                    continue;
+                }
+
+                let run = scanned[y..to].iter().position(|&s| s).map_or(to, |n| y + n);
+                for (p, &i) in self.pixels[y * self.w..run * self.w].iter_mut().zip(&self.image[y * self.w..run * self.w]) {
+                    *p = i as u16;
+                }
+                self.threshold_window(0, self.w, y, run);
+
+                for seen in &mut scanned[y..run] {
+                    *seen = true;
+                }
+                for row in y..run {
+                    self.finder_scan(row);
+                }
+                y = run;
+            }
+            for j in first_capstone..self.num_capstones {
+                self.test_grouping(j as u32);
+            }
+
+            self.import_grid(&quirc, i, scale);
+        }
+    }
+
+    /// Add a grid found on a pyramid level, unless it's already been found or there's no room for it,
+    /// refining its perspective transform against this image.
+    fn import_grid(&mut self, level: &Quirc, index: usize, scale: LevelScale) {
+        if self.num_grids >= QUIRC_MAX_GRIDS || self.num_capstones + 3 > QUIRC_MAX_CAPSTONES {
+            return;
+        }
+
+        let qr = &level.grids[index];
+        let size = qr.grid_size;
+        let corners = level_corners(qr, scale);
+
+        let c = perspective::grid_setup(&corners, size);
+        if self.grids[..self.num_grids].iter().any(|other| perspective::grid_contains(&other.c, other.grid_size, &c, size)) {
+            return;
+        }
+
+        let qr_index = self.num_grids;
+        let mut caps = [0; 3];
+        for (cap, &level_cap) in caps.iter_mut().zip(&qr.caps) {
+            let stone = &level.capstones[level_cap as usize];
+            let corners = [level_to_image(&stone.corners[0], scale),
+                           level_to_image(&stone.corners[1], scale),
+                           level_to_image(&stone.corners[2], scale),
+                           level_to_image(&stone.corners[3], scale)];
+
+            *cap = self.num_capstones as u32;
+            self.capstones[self.num_capstones] = QuircCapstone {
+                ring: QUIRC_CAPSTONE_SYNTHETIC,
+                stone: QUIRC_CAPSTONE_SYNTHETIC,
+
+                corners,
+                center: level_to_image(&stone.center, scale),
+                c: perspective::setup(&corners, 7, 7),
+
+                qr_grid: qr_index as i32,
+            };
+            self.num_capstones += 1;
+        }
+
+        self.grids[qr_index] = QuircGrid {
+            caps,
+
+            align_region: -1,
+            align: level_to_image(&qr.align, scale),
+
+            tpep: [level_to_image(&qr.tpep[0], scale), level_to_image(&qr.tpep[1], scale), level_to_image(&qr.tpep[2], scale)],
+            hscan: qr.hscan,
+            vscan: qr.vscan,
+
+            grid_size: size,
+            c,
+
+            inferred: qr.inferred,
+        };
+        self.num_grids += 1;
+
+        self.jiggle_perspective(qr_index);
+    }
+}
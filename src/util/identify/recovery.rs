@@ -0,0 +1,187 @@
+//! Recovering codes with a capstone missing
+
+
+use self::super::super::super::ops::{QUIRC_MAX_CAPSTONES, QUIRC_MAX_GRIDS, QuircPoint, Quirc};
+use self::super::super::super::ops::version_db::QUIRC_MAX_VERSION;
+use self::super::perspective::{self, tenths};
+
+
+/// How well, as a percentage of a perfect fit, a grid with an inferred capstone has to match the image to be kept.
+const MIN_INFERRED_FITNESS: i64 = 50;
+
+
+/// The grid size of a code whose capstones, `side_sq` pixels squared across, have centres `distance_sq` pixels squared apart,
+/// along a side, or across the diagonal.
+pub fn inferred_grid_size(distance_sq: i64, side_sq: i64, diagonal: bool) -> u32 {
+    // Capstones are 7 cells across, and their centres 7 cells short of the grid size apart along a side
+    let cells_sq = distance_sq * 49 / side_sq.max(1) / if diagonal { 2 } else { 1 };
+    let mut cells = 0;
+    while (cells + 1) * (cells + 1) <= cells_sq {
+        cells += 1;
+    }
+
+    let version = ((cells + 7 - 17 + 2) / 4).max(1).min(QUIRC_MAX_VERSION as i64);
+    (version * 4 + 17) as u32
+}
+
+
+impl Quirc {
+    /// Process the image like `end()`, then look for codes with only two of their capstones visible,
+    /// inferring the third from them and the timing pattern between them, or from the alignment pattern on version 2 and up.
+    ///
+    /// The cells under the missing capstone are left to error correction; such codes are marked `inferred` in `results()`.
+    pub fn end_recover(&mut self) {
+        self.end();
+        self.recover_pairs();
+    }
+
+    /// Whether the code specified by the given index had a capstone inferred by `end_recover()`.
+    pub fn inferred(&self, index: usize) -> bool {
+        index < self.num_grids && self.grids[index].inferred
+    }
+
+    /// Try every pair of capstones not in a grid as two of a code's.
+    pub(crate) fn recover_pairs(&mut self) {
+        let found = self.num_capstones;
+        for i in 0..found {
+            for j in i + 1..found {
+                if self.num_grids >= QUIRC_MAX_GRIDS || self.num_capstones >= QUIRC_MAX_CAPSTONES {
+                    return;
+                }
+                if self.capstones[i].qr_grid < 0 && self.capstones[j].qr_grid < 0 {
+                    self.recover_pair(i as u32, j as u32);
+                }
+            }
+        }
+    }
+
+    /// Make a grid of capstones `i` and `j` and a third where it fits the image best, if that fits well enough.
+    ///
+    /// If they're side by side, the third is at either end, on either side; if they're diagonally opposite, it's the corner, on either side.
+    fn recover_pair(&mut self, i: u32, j: u32) {
+        let (p, q) = (self.capstones[i as usize], self.capstones[j as usize]);
+        let (p_side, q_side) = (side_sq(&p.corners), side_sq(&q.corners));
+        if 2 * p_side.min(q_side) < p_side.max(q_side) {
+            return;
+        }
+
+        let (u, v) = match self.neighbour_offsets(i as usize, j as usize) {
+            Some(offsets) => offsets,
+            None => return,
+        };
+
+        let d = QuircPoint {
+            x: q.center.x - p.center.x,
+            y: q.center.y - p.center.y,
+        };
+        let diagonal = !perspective::under_fifth(u, v) && !perspective::under_fifth(v, u);
+        if diagonal && perspective::ratio_error(u, v) > tenths(2) {
+            return;
+        }
+        let grid_size = inferred_grid_size((d.x * d.x + d.y * d.y) as i64, p_side.max(q_side), diagonal);
+
+        // Each hypothesis: the corner capstone the missing one is copied from, where it goes, and whether it's the corner itself
+        let mut hypotheses = [(i, QuircPoint { x: 0, y: 0 }, false); 4];
+        let count = if diagonal {
+            let (mx, my) = (p.center.x + d.x / 2, p.center.y + d.y / 2);
+            hypotheses[0] = (i, QuircPoint { x: mx - d.y / 2, y: my + d.x / 2 }, true);
+            hypotheses[1] = (i, QuircPoint { x: mx + d.y / 2, y: my - d.x / 2 }, true);
+            2
+        } else {
+            for (h, &(corner, sign)) in [(i, 1), (i, -1), (j, 1), (j, -1)].iter().enumerate() {
+                let at = self.capstones[corner as usize].center;
+                hypotheses[h] = (corner, QuircPoint { x: at.x - sign * d.y, y: at.y + sign * d.x }, false);
+            }
+            4
+        };
+
+        let mut best = None;
+        for (h, &(from, at, is_corner)) in hypotheses[..count].iter().enumerate() {
+            if let Some(fitness) = self.infer_grid(i, j, from, &at, is_corner, grid_size) {
+                self.unrecord_inferred();
+                if best.map(|(_, f)| fitness > f).unwrap_or(true) {
+                    best = Some((h, fitness));
+                }
+            }
+        }
+
+        if let Some((h, fitness)) = best {
+            if fitness >= MIN_INFERRED_FITNESS {
+                let (from, at, is_corner) = hypotheses[h];
+                self.infer_grid(i, j, from, &at, is_corner, grid_size);
+            }
+        }
+    }
+
+    /// Add a copy of capstone `from` centred on `at`, and record a grid of it with capstones `i` and `j`, `at` being the corner if `is_corner`,
+    /// and `grid_size` cells across unless the timing pattern between two real capstones says otherwise.
+    /// Returns how well the grid fits the image, as a percentage of a perfect fit.
+    fn infer_grid(&mut self, i: u32, j: u32, from: u32, at: &QuircPoint, is_corner: bool, grid_size: u32) -> Option<i64> {
+        let mut cap = self.capstones[from as usize];
+        for corner in &mut cap.corners {
+            corner.x += at.x - cap.center.x;
+            corner.y += at.y - cap.center.y;
+        }
+        cap.center = *at;
+        cap.c = perspective::setup(&cap.corners, 7, 7);
+        cap.qr_grid = -1;
+
+        let inferred = self.num_capstones as u32;
+        self.capstones[inferred as usize] = cap;
+        self.num_capstones += 1;
+
+        let index = self.num_grids;
+        self.num_grids += 1;
+        let (a, b, c) = if is_corner {
+            (i, inferred, j)
+        } else if from == i {
+            (j, i, inferred)
+        } else {
+            (i, j, inferred)
+        };
+        let hd = self.orient_grid(index, a, b, c);
+        for &cap in &self.grids[index].caps {
+            self.capstones[cap as usize].qr_grid = index as i32;
+        }
+        self.grids[index].inferred = true;
+
+        // Only a timing pattern between two real capstones can be trusted: B to C is horizontal and B to A vertical
+        self.measure_timing_pattern(index);
+        let grid = self.grids[index];
+        let scan = if grid.caps[0] == inferred {
+            grid.hscan
+        } else if grid.caps[2] == inferred {
+            grid.vscan
+        } else {
+            -1
+        };
+        self.grids[index].grid_size = if scan >= 0 {
+            ((scan * 2 + 13 - 15) / 4 * 4 + 17) as u32
+        } else {
+            grid_size
+        };
+
+        if self.grids[index].grid_size < 21 || !self.locate_grid(index, &hd) {
+            self.unrecord_inferred();
+            return None;
+        }
+
+        let size = self.grids[index].grid_size;
+        Some(self.fitness_grid(&self.grids[index]) * 100 / Quirc::fitness_max(size))
+    }
+
+    /// Undo the last `infer_grid()`.
+    fn unrecord_inferred(&mut self) {
+        let index = self.num_grids - 1;
+        self.unrecord_qr_grid(index);
+        self.grids[index].inferred = false;
+        self.num_capstones -= 1;
+    }
+}
+
+
+fn side_sq(corners: &[QuircPoint; 4]) -> i64 {
+    let (dx, dy) = ((corners[1].x - corners[0].x) as i64, (corners[1].y - corners[0].y) as i64);
+    let (ex, ey) = ((corners[3].x - corners[0].x) as i64, (corners[3].y - corners[0].y) as i64);
+    (dx * dx + dy * dy).max(ex * ex + ey * ey)
+}
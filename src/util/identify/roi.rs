@@ -0,0 +1,63 @@
+//! Region-of-interest scanning
+
+
+use self::super::super::super::ops::{QuircRect, SimdLevel, Quirc};
+use self::super::simd::widen_pixels;
+use self::super::QUIRC_PIXEL_WHITE;
+
+
+/// The part of `rect` inside a `w`×`h` image, as columns `left..right` and rows `top..bottom`.
+pub fn clip_rect(rect: &QuircRect, w: usize, h: usize) -> (usize, usize, usize, usize) {
+    let left = rect.x.min(w);
+    let top = rect.y.min(h);
+
+    (left, rect.x.saturating_add(rect.width).min(w), top, rect.y.saturating_add(rect.height).min(h))
+}
+
+
+impl Quirc {
+    /// Blank the thresholded image, then threshold the regions of interest.
+    pub(crate) fn threshold_rois(&mut self, rois: &[QuircRect]) {
+        for p in self.pixels.iter_mut() {
+            *p = QUIRC_PIXEL_WHITE;
+        }
+
+        let level = SimdLevel::detect();
+        for roi in rois {
+            let (left, right, top, bottom) = clip_rect(roi, self.w, self.h);
+            for y in top..bottom {
+                let row = y * self.w;
+                widen_pixels(level, &self.image[row + left..row + right], &mut self.pixels[row + left..row + right]);
+            }
+            self.threshold_window(left, right, top, bottom);
+        }
+    }
+
+    /// Process the image like `end()`, but only threshold and look for capstones inside `roi`.
+    ///
+    /// Codes reaching out of the region can still be found if all three capstones are inside it.
+    /// Corners are in the coordinates of the whole image.
+    pub fn end_roi(&mut self, roi: &QuircRect) {
+        self.end_rois(&[*roi]);
+    }
+
+    /// Process the image like `end()`, but only threshold and look for capstones inside the regions of interest,
+    /// which may overlap and reach past the edges of the image.
+    ///
+    /// The rest of the image is treated as white, so a code's capstones have to be in one region, or in overlapping ones.
+    pub fn end_rois(&mut self, rois: &[QuircRect]) {
+        // Threshold every region before looking for capstones, so that no region overwrites another's flood fills
+        self.threshold_rois(rois);
+
+        for roi in rois {
+            let (left, right, top, bottom) = clip_rect(roi, self.w, self.h);
+            for y in top..bottom {
+                self.finder_scan_span(y, left, right);
+            }
+        }
+
+        for i in 0..self.num_capstones {
+            self.test_grouping(i as u32);
+        }
+    }
+}
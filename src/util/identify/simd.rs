@@ -0,0 +1,297 @@
+//! Vectorised pixel setup and thresholding
+//!
+//! The moving averages thresholding runs along each row depend on every pixel before, so they stay scalar,
+//! but divide by a reciprocal instead of with a division.
+//! Widening the image into the pixel buffer and comparing each pixel to its average are done a vector at a time.
+
+
+use self::super::super::super::ops::SimdLevel;
+use self::super::thresholding::{THRESHOLD_T, threshold_s};
+#[cfg(target_arch = "x86")]
+use core::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+#[cfg(target_arch = "aarch64")]
+use core::arch::aarch64::*;
+
+
+/// Whether the CPU running this has an x86 feature, or, without `std` to ask it with, whether the target was compiled with it.
+macro_rules! x86_feature {
+    ($feature:tt) => {{
+        #[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+        let has = ::std::arch::is_x86_feature_detected!($feature);
+        #[cfg(all(not(feature = "std"), any(target_arch = "x86", target_arch = "x86_64")))]
+        let has = cfg!(target_feature = $feature);
+        #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+        let has = false;
+        has
+    }};
+}
+
+/// `x86_feature!()`, for AArch64.
+macro_rules! aarch64_feature {
+    ($feature:tt) => {{
+        #[cfg(all(feature = "std", target_arch = "aarch64"))]
+        let has = ::std::arch::is_aarch64_feature_detected!($feature);
+        #[cfg(all(not(feature = "std"), target_arch = "aarch64"))]
+        let has = cfg!(target_feature = $feature);
+        #[cfg(not(target_arch = "aarch64"))]
+        let has = false;
+        has
+    }};
+}
+
+
+impl SimdLevel {
+    /// The best level the CPU running this has.
+    ///
+    /// Without `std` this is only what the target was compiled for.
+    pub fn detect() -> SimdLevel {
+        [SimdLevel::Avx2, SimdLevel::Sse2, SimdLevel::Neon].iter().cloned().find(|l| l.available()).unwrap_or(SimdLevel::Scalar)
+    }
+
+    /// Whether the CPU running this has this level.
+    pub fn available(self) -> bool {
+        match self {
+            SimdLevel::Scalar => true,
+            SimdLevel::Sse2 => x86_feature!("sse2"),
+            SimdLevel::Avx2 => x86_feature!("avx2"),
+            SimdLevel::Neon => aarch64_feature!("neon"),
+        }
+    }
+}
+
+
+/// Copy `image` into `pixels`, each 8-bit pixel into 16 bits, as far as the shorter of the two goes.
+///
+/// A level the CPU doesn't have falls back to `SimdLevel::Scalar`.
+pub fn widen_pixels(level: SimdLevel, image: &[u8], pixels: &mut [u16]) {
+    let len = image.len().min(pixels.len());
+    let (image, pixels) = (&image[..len], &mut pixels[..len]);
+
+    let done = match level {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Avx2 if level.available() => unsafe { widen_avx2(image, pixels) },
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Sse2 if level.available() => unsafe { widen_sse2(image, pixels) },
+        #[cfg(target_arch = "aarch64")]
+        SimdLevel::Neon if level.available() => unsafe { widen_neon(image, pixels) },
+        _ => 0,
+    };
+
+    for (p, &i) in pixels[done..].iter_mut().zip(&image[done..]) {
+        *p = i as u16;
+    }
+}
+
+/// Threshold each pixel of `row` against its entry in `row_average`, the sum of the two moving averages over it,
+/// in an image `w` pixels wide.
+///
+/// A pixel is black if it's below `(100 - THRESHOLD_T)`% of the mean of the averages, the same as `threshold_rows()`,
+/// worked out with a multiplication instead of a division: `p < ⌊a·95 / d⌋` exactly when `(p + 1)·d ≤ a·95`.
+pub fn binarise_row(level: SimdLevel, row: &mut [u16], row_average: &[u64], w: usize) {
+    let len = row.len().min(row_average.len());
+    let (row, row_average) = (&mut row[..len], &row_average[..len]);
+    let d = (200 * threshold_s(w)) as u64;
+
+    // The vector multiplications are 32 by 32 bits
+    let done = match level {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Avx2 if level.available() && d <= u32::MAX as u64 => unsafe { binarise_avx2(row, row_average, d) },
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Sse2 if level.available() && d <= u32::MAX as u64 => unsafe { binarise_sse2(row, row_average, d) },
+        #[cfg(target_arch = "aarch64")]
+        SimdLevel::Neon if level.available() && d <= u32::MAX as u64 => unsafe { binarise_neon(row, row_average, d) },
+        _ => 0,
+    };
+
+    for (p, &a) in row[done..].iter_mut().zip(&row_average[done..]) {
+        *p = ((*p as u64 + 1) * d <= a * (100 - THRESHOLD_T) as u64) as u16;
+    }
+}
+
+/// `threshold_rows()`, vectorised with `level` where it can be, with exactly the same result.
+///
+/// # Panics
+///
+/// If `row_average` is shorter than `right`.
+// threshold_rows()'s arguments and the level, so that the two stay interchangeable
+#[allow(clippy::too_many_arguments)]
+pub fn threshold_rows_with(level: SimdLevel, rows: &mut [u16], row_average: &mut [u64], w: usize, left: usize, right: usize, top: usize,
+                           avg: &mut (usize, usize)) {
+    let (mut avg_w, mut avg_u) = *avg;
+    let threshold_s = threshold_s(w) as u64;
+
+    // ⌊a·(s - 1) / s⌋ as ⌊a·(s - 1)·m / 2⁶⁴⌋, with m = ⌊2⁶⁴ / s⌋ + 1 (or 2⁶⁴ / s if that's whole).
+    // That's exact while a·(s - 1)·s < 2⁶⁴, which the averages, never above 255·s, keep to for s up to 2¹⁸
+    let m = if threshold_s.is_power_of_two() {
+        (1u128 << 64) / threshold_s as u128
+    } else {
+        (1u128 << 64) / threshold_s as u128 + 1
+    };
+    let reciprocal = threshold_s < 1 << 18;
+    let decay = |avg: usize| {
+        let scaled = avg as u64 * (threshold_s - 1);
+        (if reciprocal {
+            ((scaled as u128 * m) >> 64) as u64
+        } else {
+            scaled / threshold_s
+        }) as usize
+    };
+
+    for y in top..top + rows.len() / w.max(1) {
+        let row = &mut rows[(y - top) * w..];
+
+        row_average[left..right].fill(0);
+
+        for x in left..right {
+            let (w, u) = if y & 1 == 1 {
+                (x, right - 1 - (x - left))
+            } else {
+                (right - 1 - (x - left), x)
+            };
+
+            avg_w = decay(avg_w) + row[w] as usize;
+            avg_u = decay(avg_u) + row[u] as usize;
+
+            row_average[w] += avg_w as u64;
+            row_average[u] += avg_u as u64;
+        }
+
+        binarise_row(level, &mut row[left..right], &row_average[left..right], w);
+    }
+
+    *avg = (avg_w, avg_u);
+}
+
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn widen_sse2(image: &[u8], pixels: &mut [u16]) -> usize {
+    let zero = _mm_setzero_si128();
+
+    let mut i = 0;
+    while i + 16 <= image.len() {
+        let bytes = _mm_loadu_si128(image.as_ptr().add(i) as *const __m128i);
+        _mm_storeu_si128(pixels.as_mut_ptr().add(i) as *mut __m128i, _mm_unpacklo_epi8(bytes, zero));
+        _mm_storeu_si128(pixels.as_mut_ptr().add(i + 8) as *mut __m128i, _mm_unpackhi_epi8(bytes, zero));
+        i += 16;
+    }
+    i
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn widen_avx2(image: &[u8], pixels: &mut [u16]) -> usize {
+    let mut i = 0;
+    while i + 16 <= image.len() {
+        let bytes = _mm_loadu_si128(image.as_ptr().add(i) as *const __m128i);
+        _mm256_storeu_si256(pixels.as_mut_ptr().add(i) as *mut __m256i, _mm256_cvtepu8_epi16(bytes));
+        i += 16;
+    }
+    i
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn widen_neon(image: &[u8], pixels: &mut [u16]) -> usize {
+    let mut i = 0;
+    while i + 16 <= image.len() {
+        let bytes = vld1q_u8(image.as_ptr().add(i));
+        vst1q_u16(pixels.as_mut_ptr().add(i), vmovl_u8(vget_low_u8(bytes)));
+        vst1q_u16(pixels.as_mut_ptr().add(i + 8), vmovl_u8(vget_high_u8(bytes)));
+        i += 16;
+    }
+    i
+}
+
+
+/// 1 in each 64-bit lane where `(p + 1)·d ≤ a·95`, else 0, with `p + 1` and `d` in the low 32 bits of theirs.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn black_sse2(p: __m128i, a: __m128i, d: __m128i) -> __m128i {
+    let a95 = _mm_sub_epi64(_mm_add_epi64(_mm_slli_epi64(a, 6), _mm_slli_epi64(a, 5)), a);
+    let diff = _mm_sub_epi64(a95, _mm_mul_epu32(p, d));
+    _mm_srli_epi64(_mm_andnot_si128(diff, _mm_set1_epi32(-1)), 63)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn binarise_sse2(row: &mut [u16], row_average: &[u64], d: u64) -> usize {
+    let zero = _mm_setzero_si128();
+    let one = _mm_set1_epi16(1);
+    let d = _mm_set1_epi64x(d as i64);
+
+    let mut i = 0;
+    while i + 8 <= row.len() {
+        let p = _mm_add_epi16(_mm_loadu_si128(row.as_ptr().add(i) as *const __m128i), one);
+        let (lo, hi) = (_mm_unpacklo_epi16(p, zero), _mm_unpackhi_epi16(p, zero));
+
+        let mut black = [zero; 4];
+        for (j, p) in [_mm_unpacklo_epi32(lo, zero), _mm_unpackhi_epi32(lo, zero), _mm_unpacklo_epi32(hi, zero), _mm_unpackhi_epi32(hi, zero)]
+            .iter()
+            .enumerate() {
+            let a = _mm_loadu_si128(row_average.as_ptr().add(i + j * 2) as *const __m128i);
+            black[j] = black_sse2(*p, a, d);
+        }
+
+        // The 0s and 1s are as good as 32-bit, then 16-bit, pairs of 16-bit ones
+        let packed = _mm_packs_epi32(_mm_packs_epi32(black[0], black[1]), _mm_packs_epi32(black[2], black[3]));
+        _mm_storeu_si128(row.as_mut_ptr().add(i) as *mut __m128i, packed);
+        i += 8;
+    }
+    i
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn binarise_avx2(row: &mut [u16], row_average: &[u64], d: u64) -> usize {
+    let one = _mm256_set1_epi64x(1);
+    let ones = _mm256_set1_epi32(-1);
+    let d = _mm256_set1_epi64x(d as i64);
+    let low_halves = _mm256_setr_epi32(0, 2, 4, 6, 1, 3, 5, 7);
+
+    let mut i = 0;
+    while i + 8 <= row.len() {
+        let mut black = [_mm_setzero_si128(); 2];
+        for (j, black) in black.iter_mut().enumerate() {
+            let p = _mm256_add_epi64(_mm256_cvtepu16_epi64(_mm_loadl_epi64(row.as_ptr().add(i + j * 4) as *const __m128i)), one);
+            let a = _mm256_loadu_si256(row_average.as_ptr().add(i + j * 4) as *const __m256i);
+
+            let a95 = _mm256_sub_epi64(_mm256_add_epi64(_mm256_slli_epi64(a, 6), _mm256_slli_epi64(a, 5)), a);
+            let diff = _mm256_sub_epi64(a95, _mm256_mul_epu32(p, d));
+            let lanes = _mm256_srli_epi64(_mm256_andnot_si256(diff, ones), 63);
+
+            // The low halves of the four lanes into the low 128 bits
+            *black = _mm256_castsi256_si128(_mm256_permutevar8x32_epi32(lanes, low_halves));
+        }
+
+        _mm_storeu_si128(row.as_mut_ptr().add(i) as *mut __m128i, _mm_packs_epi32(black[0], black[1]));
+        i += 8;
+    }
+    i
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn binarise_neon(row: &mut [u16], row_average: &[u64], d: u64) -> usize {
+    let d = vdup_n_u32(d as u32);
+
+    let mut i = 0;
+    while i + 8 <= row.len() {
+        let p = vaddq_u16(vld1q_u16(row.as_ptr().add(i)), vdupq_n_u16(1));
+        let (lo, hi) = (vmovl_u16(vget_low_u16(p)), vmovl_u16(vget_high_u16(p)));
+
+        let mut black = [vdup_n_u32(0); 4];
+        for (j, p) in [vget_low_u32(lo), vget_high_u32(lo), vget_low_u32(hi), vget_high_u32(hi)].iter().enumerate() {
+            let a = vld1q_u64(row_average.as_ptr().add(i + j * 2));
+            let a95 = vsubq_u64(vaddq_u64(vshlq_n_u64::<6>(a), vshlq_n_u64::<5>(a)), a);
+            black[j] = vmovn_u64(vshrq_n_u64::<63>(vcleq_u64(vmull_u32(*p, d), a95)));
+        }
+
+        let packed = vcombine_u16(vmovn_u32(vcombine_u32(black[0], black[1])), vmovn_u32(vcombine_u32(black[2], black[3])));
+        vst1q_u16(row.as_mut_ptr().add(i), packed);
+        i += 8;
+    }
+    i
+}
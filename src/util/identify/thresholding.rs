@@ -2,64 +2,195 @@
 
 
 use self::super::super::super::ops::{QUIRC_PERSPECTIVE_PARAMS, QUIRC_MAX_CAPSTONES, QUIRC_MAX_REGIONS, QUIRC_MAX_GRIDS, QuircCapstone, QuircRegion, QuircPoint,
-                                     QuircCode, QuircGrid, Quirc};
+                                     ExtractError, QuircCode, QuircGrid, QuircPerspective, SimdLevel, Quirc};
 use self::super::super::super::ops::version_db::{QUIRC_MAX_ALIGNMENT, QUIRC_MAX_VERSION, QUIRC_VERSION_DB};
-use self::super::{perspective_setup, perspective_unmap, perspective_map, line_intersect};
+use self::super::perspective::{self, Coord, cells, tenths};
+use self::super::line_intersect;
+use self::super::simd::{threshold_rows_with, widen_pixels};
 use self::super::super::super::constants::QUIRC_MAX_BITMAP;
-use std::{mem, ptr};
+use core::{mem, ptr};
 
 
 const THRESHOLD_S_MIN: usize = 1;
 const THRESHOLD_S_DEN: usize = 8;
-const THRESHOLD_T: usize = 5;
+pub(crate) const THRESHOLD_T: usize = 5;
 
-const QUIRC_PIXEL_WHITE: u16 = 0;
-const QUIRC_PIXEL_BLACK: u16 = 1;
-const QUIRC_PIXEL_REGION: u16 = 2;
+pub const QUIRC_PIXEL_WHITE: u16 = 0;
+pub const QUIRC_PIXEL_BLACK: u16 = 1;
+pub const QUIRC_PIXEL_REGION: u16 = 2;
 
 
-impl Quirc {
-    fn threshold(&mut self) {
-        let mut avg_w = 0usize;
-        let mut avg_u = 0usize;
-        let mut threshold_s = self.w / THRESHOLD_S_DEN;
+/// The divisor of the moving averages thresholding compares pixels against, for an image `w` pixels wide.
+pub fn threshold_s(w: usize) -> usize {
+    // Ensure a sane, non-zero value for threshold_s.
+    //
+    // threshold_s can be zero if the image width is small. We need to avoid
+    // SIGFPE as it will be used as divisor.
+    (w / THRESHOLD_S_DEN).max(THRESHOLD_S_MIN)
+}
+
+/// Threshold columns `left` up to `right` of `rows`, consecutive rows of an image `w` pixels wide starting at row `top`,
+/// which have to have been set up already.
+///
+/// The two moving averages, one running each way along the rows, carry over from one row to the next:
+/// `avg` holds them as they were at the end of the row before, and is left with them at the end of the last one.
+/// `row_average` is scratch space at least `right` long.
+///
+/// # Panics
+///
+/// If `row_average` is shorter than `right`.
+pub fn threshold_rows(rows: &mut [u16], row_average: &mut [u64], w: usize, left: usize, right: usize, top: usize, avg: &mut (usize, usize)) {
+    let (mut avg_w, mut avg_u) = *avg;
+    let threshold_s = threshold_s(w);
+
+    for y in top..top + rows.len() / w.max(1) {
+        let row = &mut rows[(y - top) * w..];
+
+        row_average[left..right].fill(0);
+
+        for x in left..right {
+            let (w, u) = if y & 1 == 1 {
+                (x, right - 1 - (x - left))
+            } else {
+                (right - 1 - (x - left), x)
+            };
 
-        // Ensure a sane, non-zero value for threshold_s.
-        //
-        // threshold_s can be zero if the image width is small. We need to avoid
-        // SIGFPE as it will be used as divisor.
-        threshold_s = threshold_s.max(THRESHOLD_S_MIN);
+            avg_w = (avg_w * (threshold_s - 1)) / threshold_s + row[w] as usize;
+            avg_u = (avg_u * (threshold_s - 1)) / threshold_s + row[u] as usize;
 
-        for y in 0..self.h {
-            let mut row = &mut self.pixels[y + 1 * self.w..];
+            row_average[w] += avg_w as u64;
+            row_average[u] += avg_u as u64;
+        }
 
-            // There has to be a better way of doing this
-            unsafe {
-                ptr::write_bytes(self.row_average.as_mut_ptr(), 0u8, self.row_average.len());
+        for x in left..right {
+            if (row[x] as u64) < row_average[x] * ((100 - THRESHOLD_T) as u64) / ((200 * threshold_s) as u64) {
+                row[x] = QUIRC_PIXEL_BLACK;
+            } else {
+                row[x] = QUIRC_PIXEL_WHITE;
             }
+        }
+    }
+
+    *avg = (avg_w, avg_u);
+}
 
-            for x in 0..self.w {
+/// The moving averages `threshold_rows()` reaches the start of row `y` with,
+/// thresholding columns `left` up to `right` of `image`, `w` pixels wide, from row `top`, without going through every row before.
+///
+/// Each step scales an average down by `(s - 1) / s` and rounds it down, which keeps any two in order,
+/// so once the lowest and highest an average could have been a few rows back have met, that's its value wherever it started.
+/// This is usually two or three rows back.
+pub fn threshold_state(image: &[u8], w: usize, left: usize, right: usize, top: usize, y: usize) -> (usize, usize) {
+    let threshold_s = threshold_s(w);
+    let step = |avg: usize, pixel: u8| (avg * (threshold_s - 1)) / threshold_s + pixel as usize;
+
+    let mut back = 1;
+    loop {
+        let from = y.saturating_sub(back).max(top);
+        let mut low = (0, 0);
+        let mut high = if from == top { (0, 0) } else { (0xff * threshold_s, 0xff * threshold_s) };
+
+        for y in from..y {
+            let row = &image[y * w..];
+            for x in left..right {
                 let (w, u) = if y & 1 == 1 {
-                    (x, self.w - 1 - x)
+                    (x, right - 1 - (x - left))
                 } else {
-                    (self.w - 1 - x, x)
+                    (right - 1 - (x - left), x)
                 };
 
-                avg_w = (avg_w * (threshold_s - 1)) / threshold_s + row[w] as usize;
-                avg_u = (avg_u * (threshold_s - 1)) / threshold_s + row[u] as usize;
+                low = (step(low.0, row[w]), step(low.1, row[u]));
+                high = (step(high.0, row[w]), step(high.1, row[u]));
+            }
+        }
+
+        if low == high {
+            return low;
+        }
+        back *= 2;
+    }
+}
+
+
+/// Run lengths along a row being scanned for capstones.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct FinderRuns {
+    last_color: bool,
+    run_length: usize,
+    run_count: usize,
+    pb: [usize; 5],
+}
+
+impl Default for FinderRuns {
+    /// Same as `FinderRuns::new()`.
+    fn default() -> FinderRuns {
+        FinderRuns::new()
+    }
+}
+
+impl FinderRuns {
+    /// Nothing scanned yet.
+    pub fn new() -> FinderRuns {
+        FinderRuns {
+            last_color: false,
+            run_length: 0,
+            run_count: 0,
+            pb: [0; 5],
+        }
+    }
 
-                self.row_average[w] += avg_w as u64;
-                self.row_average[u] += avg_u as u64;
+    /// Take the next pixel along the row, dark or not, returning the last five run lengths
+    /// if it ends a dark run closing a 1:1:3:1:1 pattern.
+    pub fn next(&mut self, color: bool) -> Option<[usize; 5]> {
+        static CHECK: [usize; 5] = [1, 1, 3, 1, 1];
+
+        let mut found = None;
+        if self.run_length != 0 && color != self.last_color {
+            let pb = &mut self.pb;
+            unsafe {
+                ptr::copy(pb[1..].as_ptr(), pb.as_mut_ptr(), 4);
             }
+            pb[4] = self.run_length;
 
-            for x in 0..self.w {
-                if (row[x] as u64) < self.row_average[x] * ((100 - THRESHOLD_T) as u64) / ((200 * threshold_s) as u64) {
-                    row[x] = QUIRC_PIXEL_BLACK;
-                } else {
-                    row[x] = QUIRC_PIXEL_WHITE;
+            self.run_length = 0;
+            self.run_count += 1;
+
+            if !color && self.run_count >= 5 {
+                let mut ok = true;
+
+                let avg = (pb[0] + pb[1] + pb[3] + pb[4]) / 4;
+                let err = avg * 3 / 4;
+
+                for i in 0..5 {
+                    if (pb[i] < CHECK[i] * avg - err) || (pb[i] > CHECK[i] * avg + err) {
+                        ok = false;
+                    }
+                }
+
+                if ok {
+                    found = Some(*pb);
                 }
             }
         }
+
+        self.run_length += 1;
+        self.last_color = color;
+        found
+    }
+}
+
+
+impl Quirc {
+    pub(crate) fn threshold(&mut self) {
+        self.threshold_window(0, self.w, 0, self.h);
+    }
+
+    /// Threshold columns `left` up to `right` of rows `top` up to `bottom`, which have to have been set up already.
+    ///
+    /// The averaging window stays proportional to the width of the whole image.
+    pub(crate) fn threshold_window(&mut self, left: usize, right: usize, top: usize, bottom: usize) {
+        let w = self.w;
+        threshold_rows_with(SimdLevel::detect(), &mut self.pixels[top * w..bottom * w], &mut self.row_average, w, left, right, top, &mut (0, 0));
     }
 
 
@@ -88,23 +219,23 @@ impl Quirc {
         self.flood_fill_seed(x, y, pixel, region as u16, |_, left, right| count += (right - left + 1) as u32, 0);
 
         self.num_regions += 1;
-        self.regions[self.num_regions] = QuircRegion {
+        self.regions[region] = QuircRegion {
             seed: QuircPoint {
                 x: x as isize,
                 y: y as isize,
             },
-            count: count,
+            count,
             capstone: -1,
         };
 
-        return Some(region);
+        Some(region)
     }
 
     fn find_region_corners(&mut self, rcode: usize, mut reference: QuircPoint, corners: &mut [QuircPoint]) {
         assert!(corners.len() >= 4);
 
         let region = self.regions[rcode];
-        let mut scores = [0i64; 4];
+        let mut scores = [-1isize, 0, 0, 0];
 
         // find_one_corner
         self.flood_fill_seed(region.seed.x as usize,
@@ -112,16 +243,15 @@ impl Quirc {
                              rcode as u16,
                              QUIRC_PIXEL_BLACK,
                              |y, left, right| {
-            let xs = [left, right];
             let dy = y as isize - reference.y;
 
-            for i in 0..2 {
-                let dx = xs[i] as isize - reference.x;
+            for &x in &[left, right] {
+                let dx = x as isize - reference.x;
                 let d = dx * dx + dy * dy;
 
-                if d > scores[0] as isize {
-                    scores[0] = d as i64;
-                    corners[0].x = xs[i] as isize;
+                if d > scores[0] {
+                    scores[0] = d;
+                    corners[0].x = x as isize;
                     corners[0].y = y as isize;
                 }
             }
@@ -131,40 +261,38 @@ impl Quirc {
         reference.x = corners[0].x - reference.x;
         reference.y = corners[0].y - reference.y;
 
-        for mut i in 0..4 {
-            corners[i] = region.seed;
-
-            i = (region.seed.x * reference.x + region.seed.y * reference.y) as usize;
-            scores[0] = i as i64;
-            scores[2] = -(i as i64);
-            i = (region.seed.x * -reference.y + region.seed.y * reference.x) as usize;
-            scores[1] = i as i64;
-            scores[3] = -(i as i64);
-
-            // find_other_corners
-            self.flood_fill_seed(region.seed.x as usize,
-                                 region.seed.y as usize,
-                                 QUIRC_PIXEL_BLACK,
-                                 rcode as u16,
-                                 |y, left, right| {
-                let xs = [left, right];
-
-                for i in 0..2 {
-                    let up = xs[i] as isize * reference.x + y as isize * reference.y;
-                    let right = xs[i] as isize * -reference.y + y as isize * reference.x;
-                    let in_scores = [up, right, -up, -right];
-
-                    for j in 0..4 {
-                        if in_scores[j] > scores[j] as isize {
-                            scores[j] = in_scores[j] as i64;
-                            corners[j].x = xs[i] as isize;
-                            corners[j].y = y as isize;
-                        }
+        for corner in &mut corners[..4] {
+            *corner = region.seed;
+        }
+
+        let i = region.seed.x * reference.x + region.seed.y * reference.y;
+        scores[0] = i;
+        scores[2] = -i;
+        let i = region.seed.x * -reference.y + region.seed.y * reference.x;
+        scores[1] = i;
+        scores[3] = -i;
+
+        // find_other_corners
+        self.flood_fill_seed(region.seed.x as usize,
+                             region.seed.y as usize,
+                             QUIRC_PIXEL_BLACK,
+                             rcode as u16,
+                             |y, left, right| {
+            for &x in &[left, right] {
+                let up = x as isize * reference.x + y as isize * reference.y;
+                let right = x as isize * -reference.y + y as isize * reference.x;
+                let in_scores = [up, right, -up, -right];
+
+                for j in 0..4 {
+                    if in_scores[j] > scores[j] {
+                        scores[j] = in_scores[j];
+                        corners[j].x = x as isize;
+                        corners[j].y = y as isize;
                     }
                 }
-            },
-                                 0);
-        }
+            }
+        },
+                             0);
     }
 
     fn record_capstone(&mut self, ring: usize, stone: usize) {
@@ -178,7 +306,7 @@ impl Quirc {
 
             corners: [QuircPoint { x: 0, y: 0 }; 4],
             center: QuircPoint { x: 0, y: 0 },
-            c: [0f64; QUIRC_PERSPECTIVE_PARAMS],
+            c: [Default::default(); QUIRC_PERSPECTIVE_PARAMS],
 
             qr_grid: -1,
         };
@@ -191,14 +319,14 @@ impl Quirc {
         self.find_region_corners(ring, stone_seed, &mut capstone.corners);
 
         // Set up the perspective transform and find the center
-        capstone.c = perspective_setup(&capstone.corners, 7.0, 7.0);
-        capstone.center = perspective_map(&capstone.c, 3.5, 3.5);
+        capstone.c = perspective::setup(&capstone.corners, 7, 7);
+        capstone.center = perspective::map(&capstone.c, tenths(35), tenths(35));
 
         self.capstones[self.num_capstones] = capstone;
         self.num_capstones += 1;
     }
 
-    fn test_capstone(&mut self, x: usize, y: usize, pb: &[usize]) {
+    pub(crate) fn test_capstone(&mut self, x: usize, y: usize, pb: &[usize]) {
         macro_rules! try_opt {
             ($opt:expr) => {
                 if let Some(data) = $opt {
@@ -234,7 +362,7 @@ impl Quirc {
 
             /* Ratio should ideally be 37.5 */
             let ratio = stone_reg.count * 100 / ring_reg.count;
-            if ratio < 10 || ratio > 70 {
+            if !(10..=70).contains(&ratio) {
                 return;
             }
         }
@@ -242,46 +370,22 @@ impl Quirc {
         self.record_capstone(ring_left, stone);
     }
 
-    fn finder_scan(&mut self, y: usize) {
-        static CHECK: [usize; 5] = [1, 1, 3, 1, 1];
-
-        let mut last_color = false;
-        let mut run_length = 0;
-        let mut run_count = 0;
-        let mut pb = [0usize; 5];
-
-        for x in 0..self.w {
-            let color = self.pixels[y * self.w + x] != 0;
-
-            if x != 0 && color != last_color {
-                unsafe {
-                    ptr::copy(pb[1..].as_ptr(), pb.as_mut_ptr(), 4);
-                }
-                pb[4] = run_length;
-
-                run_length = 0;
-                run_count += 1;
-
-                if !color && run_count >= 5 {
-                    let mut ok = true;
+    pub(crate) fn finder_scan(&mut self, y: usize) {
+        self.finder_scan_span(y, 0, self.w);
+    }
 
-                    let avg = (pb[0] + pb[1] + pb[3] + pb[4]) / 4;
-                    let err = avg * 3 / 4;
+    /// Look for capstones in columns `left` up to `right` of row `y`, as if the rest of the row were white.
+    pub(crate) fn finder_scan_span(&mut self, y: usize, left: usize, right: usize) {
+        let mut runs = FinderRuns::new();
 
-                    for i in 0..5 {
-                        if (pb[i] < CHECK[i] * avg - err) || (pb[i] > CHECK[i] * avg + err) {
-                            ok = false;
-                        }
-                    }
+        // A span short of the right edge ends with a white pixel, to close any run reaching it
+        let end = if right < self.w { right + 1 } else { right };
+        for x in left..end {
+            let color = x < right && self.pixels[y * self.w + x] != 0;
 
-                    if ok {
-                        self.test_capstone(x, y, &pb);
-                    }
-                }
+            if let Some(pb) = runs.next(color) {
+                self.test_capstone(x, y, &pb);
             }
-
-            run_length += 1;
-            last_color = color;
         }
     }
 
@@ -296,10 +400,10 @@ impl Quirc {
 
         // Guess another two corners of the alignment pattern so that we
         // can estimate its size.
-        let (u, v) = perspective_unmap(&c0, &b);
-        let a = perspective_map(&c0, u, v + 1.0);
-        let (u, v) = perspective_unmap(&c2, &b);
-        let c = perspective_map(&c2, u + 1.0, v);
+        let (u, v) = perspective::unmap(&c0, &b);
+        let a = perspective::map(&c0, u, v + cells(1));
+        let (u, v) = perspective::unmap(&c2, &b);
+        let c = perspective::map(&c2, u + cells(1), v);
 
         let size_estimate = ((a.x - b.x) * -(c.y - b.y) + (a.y - b.y) * (c.x - b.x)).abs();
 
@@ -349,13 +453,11 @@ impl Quirc {
             return None;
         }
 
-        let (dom, nondom) = if n.abs() > d.abs() {
+        // Whether x is the dominant axis, rather than y
+        let x_dom = n.abs() > d.abs();
+        if x_dom {
             mem::swap(&mut n, &mut d);
-            (&mut x as *mut isize, &mut y as *mut isize)
-        } else {
-            (&mut y as *mut isize, &mut x as *mut isize)
-        };
-        let (dom, nondom) = unsafe { (&mut *dom, &mut *nondom) }; // save me from this nothing I've become
+        }
 
         let nondom_step = if n < 0 {
             n = -n;
@@ -371,8 +473,6 @@ impl Quirc {
             1
         };
 
-        x = p0.x;
-        y = p0.y;
         for _ in 0..(d + 1) {
             if y < 0 || y >= self.h as isize || x < 0 || x >= self.w as isize {
                 break;
@@ -388,14 +488,22 @@ impl Quirc {
             }
 
             a += n;
-            *dom += dom_step;
+            if x_dom {
+                x += dom_step;
+            } else {
+                y += dom_step;
+            }
             if a >= d {
-                *nondom += nondom_step;
+                if x_dom {
+                    y += nondom_step;
+                } else {
+                    x += nondom_step;
+                }
                 a -= d;
             }
         }
 
-        return Some(count);
+        Some(count)
     }
 
     /// Try the measure the timing pattern for a given QR code. This does
@@ -406,14 +514,21 @@ impl Quirc {
     /// For each capstone, we find a point in the middle of the ring band
     /// which is nearest the centre of the code. Using these points, we do
     /// a horizontal and a vertical timing scan.
-    fn measure_timing_pattern(&mut self, index: usize) -> i32 {
+    pub(crate) fn measure_timing_pattern(&mut self, index: usize) -> i32 {
         let mut qr = self.grids[index];
+        let caps = [self.capstones[qr.caps[0] as usize].c, self.capstones[qr.caps[1] as usize].c, self.capstones[qr.caps[2] as usize].c];
 
-        for i in 0..3 {
-            static US: [f64; 3] = [6.5, 6.5, 0.5];
-            static VS: [f64; 3] = [0.5, 6.5, 6.5];
+        let result = self.timing_grid(&mut qr, &caps);
+        self.grids[index] = qr;
+        result
+    }
 
-            qr.tpep[i] = perspective_map(&self.capstones[qr.caps[i] as usize].c, US[i], VS[i]);
+    /// `measure_timing_pattern()` for `qr`, whose capstones' perspective transforms are `caps`, in the order of `qr.caps`.
+    fn timing_grid(&self, qr: &mut QuircGrid, caps: &[QuircPerspective; 3]) -> i32 {
+        let us = [tenths(65), tenths(65), tenths(5)];
+        let vs = [tenths(5), tenths(65), tenths(65)];
+        for i in 0..3 {
+            qr.tpep[i] = perspective::map(&caps[i], us[i], vs[i]);
         }
 
         qr.hscan = self.timing_scan(&qr.tpep[1], &qr.tpep[2]).map(|v| v as i32).unwrap_or(-1);
@@ -426,7 +541,6 @@ impl Quirc {
 
         /* If neither scan worked, we can't go any further. */
         if scan < 0 {
-            self.grids[index] = qr;
             return -1;
         }
 
@@ -435,15 +549,33 @@ impl Quirc {
         let ver = (size - 15) / 4;
         qr.grid_size = (ver * 4 + 17) as u32;
 
-        self.grids[index] = qr;
-        return 0;
+        0
+    }
+
+    /// Orient capstones A, B and C and measure their timing patterns as `record_qr_grid()` would,
+    /// on copies of the capstones, leaving them and the grids as they are; `None` if neither timing pattern could be scanned.
+    pub(crate) fn trial_grid(&self, a: u32, b: u32, c: u32) -> Option<QuircGrid> {
+        let (mut qr, h0, hd) = self.oriented_grid(a, b, c);
+
+        let mut caps = [[Default::default(); QUIRC_PERSPECTIVE_PARAMS]; 3];
+        for (perspective, &i) in caps.iter_mut().zip(&qr.caps) {
+            let mut cap = self.capstones[i as usize];
+            cap.rotate_capstone(&h0, &hd);
+            *perspective = cap.c;
+        }
+
+        if self.timing_grid(&mut qr, &caps) < 0 {
+            None
+        } else {
+            Some(qr)
+        }
     }
 
     /// Read a cell from a grid using the currently set perspective
     /// transform. Returns +/- 1 for black/white, 0 for cells which are
     /// out of image bounds.
-    fn read_cell(&self, index: usize, x: usize, y: usize) -> i8 {
-        let p = perspective_map(&self.grids[index].c, x as f64 + 0.5, y as f64 + 0.5);
+    fn read_cell(&self, c: &QuircPerspective, x: usize, y: usize) -> i8 {
+        let p = perspective::map(c, cells(x as u32) + tenths(5), cells(y as u32) + tenths(5));
         if p.y < 0 || p.y >= self.h as isize || p.x < 0 || p.x >= self.w as isize {
             0
         } else if self.pixels[(p.y * self.w as isize + p.x) as usize] != 0 {
@@ -453,14 +585,13 @@ impl Quirc {
         }
     }
 
-    fn fitness_cell(&self, index: usize, x: usize, y: usize) -> i64 {
+    pub(crate) fn fitness_cell(&self, c: &QuircPerspective, x: usize, y: usize) -> i64 {
+        let offsets = [tenths(3), tenths(5), tenths(7)];
         let mut score = 0i64;
 
         for v in 0..3 {
             for u in 0..3 {
-                static OFFSETS: [f64; 3] = [0.3, 0.5, 0.7];
-
-                let p = perspective_map(&self.grids[index].c, x as f64 + OFFSETS[u], y as f64 + OFFSETS[v]);
+                let p = perspective::map(c, cells(x as u32) + offsets[u], cells(y as u32) + offsets[v]);
                 if p.y < 0 || p.y >= self.h as isize || p.x < 0 || p.x >= self.w as isize {
                     continue;
                 }
@@ -473,56 +604,56 @@ impl Quirc {
             }
         }
 
-        return score;
+        score
     }
 
-    fn fitness_ring(&self, index: usize, cx: usize, cy: usize, radius: usize) -> i64 {
+    fn fitness_ring(&self, c: &QuircPerspective, cx: usize, cy: usize, radius: usize) -> i64 {
         let mut score = 0;
         for i in 0..radius * 2 {
-            score += self.fitness_cell(index, cx - radius + i, cy - radius);
-            score += self.fitness_cell(index, cx - radius, cy + radius - i);
-            score += self.fitness_cell(index, cx + radius, cy - radius + i);
-            score += self.fitness_cell(index, cx + radius - i, cy + radius);
+            score += self.fitness_cell(c, cx - radius + i, cy - radius);
+            score += self.fitness_cell(c, cx - radius, cy + radius - i);
+            score += self.fitness_cell(c, cx + radius, cy - radius + i);
+            score += self.fitness_cell(c, cx + radius - i, cy + radius);
         }
         score
     }
 
-    fn fitness_apat(&self, index: usize, cx: usize, cy: usize) -> i64 {
-        self.fitness_cell(index, cx, cy) - self.fitness_ring(index, cx, cy, 1) + self.fitness_ring(index, cx, cy, 2)
+    fn fitness_apat(&self, c: &QuircPerspective, cx: usize, cy: usize) -> i64 {
+        self.fitness_cell(c, cx, cy) - self.fitness_ring(c, cx, cy, 1) + self.fitness_ring(c, cx, cy, 2)
     }
 
-    fn fitness_capstone(&self, index: usize, mut x: usize, mut y: usize) -> i64 {
+    fn fitness_capstone(&self, c: &QuircPerspective, mut x: usize, mut y: usize) -> i64 {
         x += 3;
         y += 3;
 
-        self.fitness_cell(index, x, y) + self.fitness_ring(index, x, y, 1) - self.fitness_ring(index, x, y, 2) + self.fitness_ring(index, x, y, 3)
+        self.fitness_cell(c, x, y) + self.fitness_ring(c, x, y, 1) - self.fitness_ring(c, x, y, 2) + self.fitness_ring(c, x, y, 3)
     }
 
     /// Compute a fitness score for the currently configured perspective
     /// transform, using the features we expect to find by scanning the
     /// grid.
-    fn fitness_all(&self, index: usize) -> i64 {
-        let qr = &self.grids[index];
+    pub(crate) fn fitness_grid(&self, qr: &QuircGrid) -> i64 {
+        let c = &qr.c;
         let version = (qr.grid_size - 17) / 4;
-        let info = &QUIRC_VERSION_DB[version as usize];
         let mut score = 0i64;
 
         // Check the timing pattern
-        for i in 0..qr.grid_size {
+        for i in 0..qr.grid_size - 14 {
             let expect = if i & 1 == 1 { 1 } else { -1 };
 
-            score += self.fitness_cell(index, (i as usize) + 7, 6) * expect;
-            score += self.fitness_cell(index, 6, (i as usize) + 7) * expect;
+            score += self.fitness_cell(c, (i as usize) + 7, 6) * expect;
+            score += self.fitness_cell(c, 6, (i as usize) + 7) * expect;
         }
 
         // Check capstones
-        score += self.fitness_capstone(index, 0, 0);
-        score += self.fitness_capstone(index, (qr.grid_size - 7) as usize, 0);
-        score += self.fitness_capstone(index, 0, (qr.grid_size - 7) as usize);
+        score += self.fitness_capstone(c, 0, 0);
+        score += self.fitness_capstone(c, (qr.grid_size - 7) as usize, 0);
+        score += self.fitness_capstone(c, 0, (qr.grid_size - 7) as usize);
 
         if version as usize > QUIRC_MAX_VERSION {
             return score;
         }
+        let info = &QUIRC_VERSION_DB[version as usize];
 
         // Check alignment patterns
         let mut ap_count = 0;
@@ -530,53 +661,74 @@ impl Quirc {
             ap_count += 1;
         }
 
-        for i in 1..ap_count - 1 {
-            score += self.fitness_apat(index, 6, info.apat[i] as usize);
-            score += self.fitness_apat(index, info.apat[i] as usize, 6);
+        for i in 1..ap_count.max(1) - 1 {
+            score += self.fitness_apat(c, 6, info.apat[i] as usize);
+            score += self.fitness_apat(c, info.apat[i] as usize, 6);
         }
 
         for i in 1..ap_count {
             for j in 1..ap_count {
-                score += self.fitness_apat(index, info.apat[i] as usize, info.apat[j] as usize);
+                score += self.fitness_apat(c, info.apat[i] as usize, info.apat[j] as usize);
             }
         }
 
-        return score;
+        score
     }
 
-    fn jiggle_perspective(&mut self, index: usize) {
-        let mut best = self.fitness_all(index);
-        let mut adjustments = [0f64; 8];
+    /// The score `fitness_grid()` gives a perfectly fitted grid of this size with every cell inside the image.
+    pub(crate) fn fitness_max(grid_size: u32) -> i64 {
+        let version = (grid_size - 17) / 4;
+        // Timing patterns, and a cell and three rings of 8, 16 and 24 cells for each capstone
+        let mut cells = 2 * (grid_size as i64 - 14) + 3 * 49;
 
-        {
-            let qr = &self.grids[index];
-            for i in 0..8 {
-                adjustments[i] = qr.c[i] * 0.02;
-            }
+        if version as usize <= QUIRC_MAX_VERSION {
+            let info = &QUIRC_VERSION_DB[version as usize];
+            let ap_count = info.apat.iter().take(QUIRC_MAX_ALIGNMENT).take_while(|&&a| a != 0).count() as i64;
+
+            // Alignment patterns: a cell and two rings of 8 and 16 cells
+            cells += 25 * ((ap_count.max(1) - 2).max(0) * 2 + (ap_count - 1).max(0) * (ap_count - 1).max(0));
+        }
+
+        cells * 9
+    }
+
+    pub(crate) fn jiggle_perspective(&mut self, index: usize) {
+        let mut qr = self.grids[index];
+        self.jiggle_grid(&mut qr);
+        self.grids[index].c = qr.c;
+    }
+
+    /// `jiggle_perspective()` for a grid that needn't be one of the recognizer's.
+    pub(crate) fn jiggle_grid(&self, qr: &mut QuircGrid) {
+        let mut best = self.fitness_grid(qr);
+        let mut adjustments = qr.c;
+
+        for adjustment in &mut adjustments {
+            *adjustment = perspective::jiggle_step(*adjustment);
         }
 
         for _ in 0..5 {
             for i in 0..16 {
                 let j = i >> 1;
-                let old = self.grids[index].c[j];
+                let old = qr.c[j];
                 let step = adjustments[j];
 
                 let new = {
                     if i & 1 == 1 { old + step } else { old - step }
                 };
 
-                self.grids[index].c[j] = new;
-                let test = self.fitness_all(index);
+                qr.c[j] = new;
+                let test = self.fitness_grid(qr);
 
                 if test > best {
                     best = test;
                 } else {
-                    self.grids[index].c[j] = old;
+                    qr.c[j] = old;
                 }
             }
 
-            for i in 0..8 {
-                adjustments[i] *= 0.5;
+            for adjustment in &mut adjustments {
+                *adjustment = perspective::halve(*adjustment);
             }
         }
     }
@@ -588,22 +740,34 @@ impl Quirc {
         {
             let qr = &mut self.grids[index];
             // Set up the perspective map for reading the grid
-            qr.c = perspective_setup(&[self.capstones[qr.caps[1] as usize].corners[0], // align
-                                       self.capstones[qr.caps[2] as usize].corners[0], // align
-                                       qr.align, // align
-                                       self.capstones[qr.caps[0] as usize].corners[0]],
-                                     (qr.grid_size - 7) as f64,
-                                     (qr.grid_size - 7) as f64);
+            qr.c = perspective::setup(&[self.capstones[qr.caps[1] as usize].corners[0], // align
+                                        self.capstones[qr.caps[2] as usize].corners[0], // align
+                                        qr.align, // align
+                                        self.capstones[qr.caps[0] as usize].corners[0]],
+                                      qr.grid_size - 7,
+                                      qr.grid_size - 7);
         }
 
         self.jiggle_perspective(index);
     }
 
-    fn record_qr_grid(&mut self, mut a: u32, b: u32, mut c: u32) {
-        if self.num_grids >= QUIRC_MAX_GRIDS {
-            return;
+    /// Set grid `index` up with capstones A, B and C, in clockwise order, each rotated so that corner 0 is top-left with respect to it,
+    /// and return the hypotenuse direction the rotations were made against.
+    pub(crate) fn orient_grid(&mut self, index: usize, a: u32, b: u32, c: u32) -> QuircPoint {
+        let (qr, h0, hd) = self.oriented_grid(a, b, c);
+        self.grids[index] = qr;
+
+        // Rotate each capstone so that corner 0 is top-left with respect
+        // to the grid.
+        for &i in &qr.caps {
+            self.capstones[i as usize].rotate_capstone(&h0, &hd);
         }
 
+        hd
+    }
+
+    /// A blank grid of capstones A, B and C, in clockwise order, and the corner and hypotenuse direction to rotate them against.
+    fn oriented_grid(&self, mut a: u32, b: u32, mut c: u32) -> (QuircGrid, QuircPoint, QuircPoint) {
         // Construct the hypotenuse line from A to C. B should be to
         // the left of this line.
         let h0 = self.capstones[a as usize].center;
@@ -620,11 +784,7 @@ impl Quirc {
             hd.y = -hd.y;
         }
 
-        // Record the grid and its components
-        let qr_index = self.num_grids;
-        self.num_grids += 1;
-
-        let mut qr = QuircGrid {
+        let qr = QuircGrid {
             caps: [a, b, c],
 
             align_region: -1,
@@ -635,114 +795,155 @@ impl Quirc {
             vscan: 0,
 
             grid_size: 0,
-            c: [0f64; QUIRC_PERSPECTIVE_PARAMS],
+            c: [Default::default(); QUIRC_PERSPECTIVE_PARAMS],
+
+            inferred: false,
         };
-        self.grids[self.num_grids] = qr;
 
-        // Rotate each capstone so that corner 0 is top-left with respect
-        // to the grid.
-        for i in 0..3 {
-            let cap = &mut self.capstones[qr.caps[i] as usize];
-            cap.rotate_capstone(&h0, &hd);
-            cap.qr_grid = qr_index as i32;
+        (qr, h0, hd)
+    }
+
+    pub(crate) fn record_qr_grid(&mut self, a: u32, b: u32, c: u32) {
+        if self.num_grids >= QUIRC_MAX_GRIDS {
+            return;
+        }
+
+        // Record the grid and its components
+        let qr_index = self.num_grids;
+        self.num_grids += 1;
+
+        let hd = self.orient_grid(qr_index, a, b, c);
+        for &i in &self.grids[qr_index].caps {
+            self.capstones[i as usize].qr_grid = qr_index as i32;
         }
 
         // Check the timing pattern. This doesn't require a perspective
         // transform.
-        if self.measure_timing_pattern(qr_index) < 0 ||
-           // Make an estimate based for the alignment pattern based on extending
-           // lines from capstones A and C.
-           line_intersect(&self.capstones[a as usize].corners[0],
-             &self.capstones[a as usize].corners[1],
-             &self.capstones[c as usize].corners[0],
-             &self.capstones[c as usize].corners[3]).is_none() {
-            // We've been unable to complete setup for this grid. Undo what we've
-            // recorded and pretend it never happened.
-            for i in 0..3 {
-                self.capstones[qr.caps[i] as usize].qr_grid = -1;
-            }
-            self.num_grids -= 1;
-            return;
+        if self.measure_timing_pattern(qr_index) < 0 {
+            return self.unrecord_qr_grid(qr_index);
+        }
+
+        if !self.locate_grid(qr_index, &hd) {
+            self.unrecord_qr_grid(qr_index);
+        }
+    }
+
+    /// Find the alignment pattern of grid `qr_index`, whose capstones have been oriented against `hd` and size measured,
+    /// and set up its perspective transform; false if there's no estimate to start from.
+    pub(crate) fn locate_grid(&mut self, qr_index: usize, hd: &QuircPoint) -> bool {
+        let [a, _, c] = self.grids[qr_index].caps;
+
+        // Make an estimate based for the alignment pattern based on extending
+        // lines from capstones A and C.
+        match line_intersect(&self.capstones[a as usize].corners[0],
+                             &self.capstones[a as usize].corners[1],
+                             &self.capstones[c as usize].corners[0],
+                             &self.capstones[c as usize].corners[3]) {
+            Some(align) => self.grids[qr_index].align = align,
+            None => return false,
         }
 
         // On V2+ grids, we should use the alignment pattern.
-        if qr.grid_size > 21 {
+        if self.grids[qr_index].grid_size > 21 {
             // Try to find the actual location of the alignment pattern.
             self.find_alignment_pattern(qr_index);
 
             // Find the point of the alignment pattern closest to the
             // top-left of the QR grid.
-            if qr.align_region >= 0 {
-                let reg_seed = self.regions[qr.align_region as usize].seed;
-
+            let align_region = self.grids[qr_index].align_region;
+            if align_region >= 0 {
                 // Start from some point inside the alignment pattern
-                self.grids[self.num_grids].align = reg_seed;
-                qr.align = reg_seed;
-
-                let mut score = -hd.y * qr.align.x + hd.x * qr.align.y;
+                let reg_seed = self.regions[align_region as usize].seed;
+                let mut align = reg_seed;
+                let mut score = -hd.y * align.x + hd.x * align.y;
 
                 self.flood_fill_seed(reg_seed.x as usize,
                                      reg_seed.y as usize,
-                                     qr.align_region as u16,
+                                     align_region as u16,
                                      QUIRC_PIXEL_BLACK,
                                      |_, _, _| {},
                                      0);
+                // find_leftmost_to_line
                 self.flood_fill_seed(reg_seed.x as usize,
                                      reg_seed.y as usize,
                                      QUIRC_PIXEL_BLACK,
-                                     qr.align_region as u16,
+                                     align_region as u16,
                                      |y, left, right| {
-                    let xs = [left, right];
-
-                    for i in 0..2 {
-                        let d = -hd.y * (xs[i] as isize) + hd.x * (y as isize);
+                    for &x in &[left, right] {
+                        let d = -hd.y * (x as isize) + hd.x * (y as isize);
 
                         if d < score {
                             score = d;
-                            qr.align.x = xs[i] as isize;
-                            qr.align.y = y as isize;
+                            align.x = x as isize;
+                            align.y = y as isize;
                         }
                     }
                 },
                                      0);
+
+                self.grids[qr_index].align = align;
             }
         }
 
         self.setup_qr_perspective(qr_index);
+        true
+    }
+
+    /// We've been unable to complete setup for this grid. Undo what we've
+    /// recorded and pretend it never happened.
+    pub(crate) fn unrecord_qr_grid(&mut self, index: usize) {
+        for i in 0..3 {
+            let cap = self.grids[index].caps[i];
+            self.capstones[cap as usize].qr_grid = -1;
+        }
+        self.num_grids -= 1;
     }
 
     fn test_neighbours(&mut self, i: u32, hlist: &NeighbourList, vlist: &NeighbourList) {
-        let mut best_score = 0f64;
-        let mut best_h = -1f64;
-        let mut best_v = -1f64;
+        let mut best_score: Coord = Default::default();
+        let mut best_h = -1isize;
+        let mut best_v = -1isize;
 
         // Test each possible grouping
         for j in 0..hlist.count {
             for k in 0..vlist.count {
                 let hn = &hlist.n[j];
                 let vn = &vlist.n[k];
-                let score = (1.0 - hn.distance / vn.distance).abs();
+                let score = perspective::ratio_error(hn.distance, vn.distance);
 
-                if score > 2.5 {
+                if score > tenths(25) {
                     continue;
                 }
 
-                if best_h < 0f64 || score < best_score {
-                    best_h = hn.index as f64;
-                    best_v = vn.index as f64;
+                if best_h < 0 || score < best_score {
+                    best_h = hn.index as isize;
+                    best_v = vn.index as isize;
                     best_score = score;
                 }
             }
         }
 
-        if best_h < 0 as f64 || best_v < 0 as f64 {
+        if best_h < 0 || best_v < 0 {
             return;
         }
 
         self.record_qr_grid(best_h as u32, i, best_v as u32);
     }
 
-    fn test_grouping(&mut self, i: u32) {
+    /// How far capstone `j`'s centre is from capstone `i`'s, along `i`'s rows and down its columns, in `i`'s cells,
+    /// or `None` if they're the same capstone.
+    ///
+    /// `j` can be a horizontal neighbour of `i` when the second is under a fifth of the first, and a vertical one the other way round.
+    pub(crate) fn neighbour_offsets(&self, i: usize, j: usize) -> Option<(Coord, Coord)> {
+        if i == j {
+            return None;
+        }
+
+        let (u, v) = perspective::unmap(&self.capstones[i].c, &self.capstones[j].center);
+        Some(((u - tenths(35)).abs(), (v - tenths(35)).abs()))
+    }
+
+    pub(crate) fn test_grouping(&mut self, i: u32) {
         if self.capstones[i as usize].qr_grid >= 0 {
             return;
         }
@@ -750,7 +951,7 @@ impl Quirc {
         let mut hlist = NeighbourList {
             n: [Neighbour {
                 index: 0,
-                distance: 0f64,
+                distance: Default::default(),
             }; QUIRC_MAX_CAPSTONES],
             count: 0,
         };
@@ -759,18 +960,16 @@ impl Quirc {
         // Look for potential neighbours by examining the relative gradients
         // from this capstone to others.
         for j in 0..self.num_capstones {
-            let c2 = &self.capstones[j];
-
-            if i as usize == j || c2.qr_grid >= 0 {
+            if self.capstones[j].qr_grid >= 0 {
                 continue;
             }
 
-            let (mut u, mut v) = perspective_unmap(&self.capstones[i as usize].c, &c2.center);
-
-            u = (u - 3.5).abs();
-            v = (v - 3.5).abs();
+            let (u, v) = match self.neighbour_offsets(i as usize, j) {
+                Some(offsets) => offsets,
+                None => continue,
+            };
 
-            if u < 0.2 * v {
+            if perspective::under_fifth(u, v) {
                 let n = &mut hlist.n[hlist.count];
                 hlist.count += 1;
 
@@ -778,7 +977,7 @@ impl Quirc {
                 n.distance = v;
             }
 
-            if v < 0.2 * u {
+            if perspective::under_fifth(v, u) {
                 let n = &mut vlist.n[vlist.count];
                 vlist.count += 1;
 
@@ -794,12 +993,8 @@ impl Quirc {
         self.test_neighbours(i, &hlist, &vlist);
     }
 
-    fn pixels_setup(&mut self) {
-        for y in 0..self.h {
-            for x in 0..self.w {
-                self.pixels[y * self.w + x] = self.image[y * self.w + x] as u16;
-            }
-        }
+    pub(crate) fn pixels_setup(&mut self) {
+        widen_pixels(SimdLevel::detect(), &self.image[..self.w * self.h], &mut self.pixels);
     }
 
     /// These functions are used to process images for QR-code recognition –
@@ -810,7 +1005,7 @@ impl Quirc {
         self.num_capstones = 0;
         self.num_grids = 0;
 
-        return &mut self.image;
+        &mut self.image
     }
 
     /// After filling the buffer, `end()` should be called to process
@@ -829,27 +1024,34 @@ impl Quirc {
     }
 
     /// Extract the QR-code specified by the given index
-    pub fn extract(&self, index: usize) -> Option<QuircCode> {
-        if index > self.num_grids {
-            return None;
+    pub fn extract(&self, index: usize) -> Result<QuircCode, ExtractError> {
+        if index >= self.num_grids {
+            return Err(ExtractError {
+                index,
+                count: self.num_grids,
+            });
         }
 
         let qr = &self.grids[index];
+        Ok(self.extract_grid(&qr.c, qr.grid_size))
+    }
 
+    /// `extract()` for a grid that needn't be one of the recognizer's.
+    pub(crate) fn extract_grid(&self, c: &QuircPerspective, grid_size: u32) -> QuircCode {
         let mut code = QuircCode {
-            corners: [perspective_map(&qr.c, 0.0, 0.0),
-                      perspective_map(&qr.c, qr.grid_size as f64, 0.0),
-                      perspective_map(&qr.c, qr.grid_size as f64, qr.grid_size as f64),
-                      perspective_map(&qr.c, 0.0, qr.grid_size as f64)],
-            size: qr.grid_size,
+            corners: [perspective::map(c, cells(0), cells(0)),
+                      perspective::map(c, cells(grid_size), cells(0)),
+                      perspective::map(c, cells(grid_size), cells(grid_size)),
+                      perspective::map(c, cells(0), cells(grid_size))],
+            size: grid_size,
             cell_bitmap: [0u8; QUIRC_MAX_BITMAP],
         };
 
 
         let mut i = 0u32;
-        for y in 0..qr.grid_size {
-            for x in 0..qr.grid_size {
-                if self.read_cell(index, x as usize, y as usize) > 0 {
+        for y in 0..grid_size {
+            for x in 0..grid_size {
+                if self.read_cell(c, x as usize, y as usize) > 0 {
                     code.cell_bitmap[(i >> 3) as usize] |= 1 << (i & 7);
                 }
 
@@ -857,7 +1059,7 @@ impl Quirc {
             }
         }
 
-        Some(code)
+        code
     }
 }
 
@@ -880,18 +1082,18 @@ impl QuircCapstone {
         }
 
         // Rotate the capstone
-        for j in 0..4 {
-            copy[j] = self.corners[((j + best) % 4) as usize];
+        for (j, corner) in copy.iter_mut().enumerate() {
+            *corner = self.corners[(j + best) % 4];
         }
         self.corners = copy;
-        self.c = perspective_setup(&self.corners, 7.0, 7.0);
+        self.c = perspective::setup(&self.corners, 7, 7);
     }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
 struct Neighbour {
     index: usize,
-    distance: f64,
+    distance: Coord,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
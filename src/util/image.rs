@@ -0,0 +1,57 @@
+//! Scanning `image` crate images
+
+
+use self::super::super::ops::{ImageError, ScanResult, Quirc};
+use image::{GenericImageView, ImageReader, Pixel, Primitive};
+use num_traits::ToPrimitive;
+use std::path::Path;
+use std::vec::Vec;
+
+
+/// A pixel's luma, scaled from its subpixel type's range to 0-255.
+pub fn image_luma<P: Pixel>(pixel: P) -> u8 {
+    let min = P::Subpixel::DEFAULT_MIN_VALUE.to_f64().unwrap_or(0.0);
+    let max = P::Subpixel::DEFAULT_MAX_VALUE.to_f64().unwrap_or(1.0);
+    let value = pixel.to_luma().0[0].to_f64().unwrap_or(min);
+
+    ((value - min) * 255.0 / (max - min)).round().clamp(0.0, 255.0) as u8
+}
+
+/// Read the image at `path`, in any format the `image` crate recognises, and decode every code in it.
+///
+/// There's one result for each code identified, as with `scan()`.
+pub fn decode_image_file<P: AsRef<Path>>(path: P) -> Result<Vec<ScanResult>, ImageError> {
+    let image = ImageReader::open(path)
+        .map_err(ImageError::Io)?
+        .with_guessed_format()
+        .map_err(ImageError::Io)?
+        .decode()
+        .map_err(ImageError::Decode)?;
+
+    Ok(Quirc::new().process_image(&image))
+}
+
+
+impl Quirc {
+    /// Resize to the image and load its luma into the image buffer, ready for `end()`.
+    ///
+    /// Colour is converted with the `image` crate's `Pixel::to_luma()`, and alpha ignored.
+    pub fn load_image<I: GenericImageView>(&mut self, image: &I) {
+        let (w, h) = image.dimensions();
+
+        self.resize(w as usize, h as usize);
+        for (px, (_, _, pixel)) in self.begin().iter_mut().zip(image.pixels()) {
+            *px = image_luma(pixel);
+        }
+    }
+
+    /// Load the image, identify the codes in it, and decode each one.
+    ///
+    /// There's one result for each code identified, as from `results()`; they stay available through `extract()` afterwards.
+    pub fn process_image<I: GenericImageView>(&mut self, image: &I) -> Vec<ScanResult> {
+        self.load_image(image);
+        self.end();
+
+        self.results().collect()
+    }
+}
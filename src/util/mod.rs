@@ -1,8 +1,55 @@
 mod identify;
 mod decode;
+#[cfg(feature = "alloc")]
+mod encode;
+#[cfg(feature = "alloc")]
+mod render;
+#[cfg(feature = "alloc")]
+mod netpbm;
+#[cfg(feature = "alloc")]
+mod scan;
+#[cfg(feature = "alloc")]
+mod tiled;
+#[cfg(feature = "std")]
+mod scene;
+#[cfg(feature = "std")]
+mod tracker;
+#[cfg(feature = "std")]
+mod fusion;
+#[cfg(feature = "image")]
+mod image;
 
-pub use self::identify::{perspective_setup, perspective_unmap, perspective_map, line_intersect};
-pub use self::decode::{GaloisField, Datastream, FORMAT_MAX_ERROR, FORMAT_SYNDROMES, FORMAT_BITS, GF256_EXP, GF256_LOG, GF16_EXP, GF16_LOG, GF256, GF16,
-                       berlekamp_massey, format_syndromes, block_syndromes, correct_format, codestream_ecc, decode_payload, decode_numeric, reserved_cell,
-                       numeric_tuple, correct_block, decode_alpha, decode_kanji, decode_byte, alpha_tuple, decode_eci, eloc_poly, poly_eval,
-                       poly_add /* align */, mask_bit};
+pub use self::identify::{perspective_setup, perspective_unmap, perspective_map, line_intersect, fixed_point};
+pub use self::decode::{GaloisField, FORMAT_MAX_ERROR, FORMAT_SYNDROMES, FORMAT_BITS, FORMAT_GENERATOR, FORMAT_MASK, FORMAT_XS, FORMAT_YS, GF256_EXP,
+                       GF256_LOG, GF16_EXP, GF16_LOG, GF256, GF16, berlekamp_massey, format_syndromes, block_syndromes, correct_format, reserved_cell,
+                       correct_block, eloc_poly, poly_eval, poly_add /* align */, mask_bit};
+#[cfg(feature = "alloc")]
+pub use self::identify::{QUIRC_PIXEL_WHITE, QUIRC_PIXEL_BLACK, QUIRC_PIXEL_REGION, FinderRuns, threshold_s, threshold_rows, threshold_state, LevelScale,
+                         level_to_image, downscale_image, upscale_image, QUIRC_CAPSTONE_SYNTHETIC, clip_rect, select_groups, inferred_grid_size,
+                         widen_pixels, binarise_row, threshold_rows_with, grid_corners, grid_setup, grid_contains, grid_contains_point};
+#[cfg(feature = "rayon")]
+pub use self::identify::band_rows;
+pub use self::decode::{ALPHA_MAP, Datastream, decode_code, read_format, codestream_ecc, codestream_data_offset, codestream_syndrome_weight, decode_payload,
+                       padding_consistent, decode_numeric, numeric_tuple, decode_alpha, decode_kanji, decode_byte, alpha_tuple, decode_eci};
+#[cfg(feature = "alloc")]
+pub use self::encode::{ECI_MODE, VERSION_GENERATOR, VERSION_RANGE_STARTS, Bitstream, version_range, char_count_bits, alpha_value, kanji_value, segment_chars,
+                       segment_bits, write_segment, optimal_segments, data_codewords, rs_generator_poly, rs_remainder, build_codestream, format_bits,
+                       version_bits, draw_function_patterns, write_data, write_format, dark_modules, mask_penalty, encode, encode_text, encode_shift_jis,
+                       encode_segments};
+#[cfg(feature = "alloc")]
+pub use self::render::{render_dimension, foreground_at, luma, crc32, adler32, deflate, zlib};
+#[cfg(feature = "alloc")]
+pub use self::scan::scan;
+#[cfg(feature = "alloc")]
+pub use self::tiled::{tile_starts, scan_tiled};
+#[cfg(feature = "alloc")]
+pub use self::netpbm::{skip_whitespace, read_number, netpbm_luma};
+#[cfg(feature = "std")]
+pub use self::tracker::track_window;
+#[cfg(feature = "std")]
+pub use self::fusion::vote_bitmap;
+#[cfg(feature = "std")]
+pub use self::scene::{QUIET_ZONE, SUPERSAMPLING, JPEG_LUMINANCE, SplitMix64, scene_corners, draw_symbol, gaussian_kernel, gaussian_blur, illuminate,
+                      add_noise, jpeg_quality_table, jpeg_quantise};
+#[cfg(feature = "image")]
+pub use self::image::{image_luma, decode_image_file};
@@ -0,0 +1,5 @@
+mod read;
+mod write;
+
+pub use self::read::{skip_whitespace, read_number};
+pub use self::write::netpbm_luma;
@@ -0,0 +1,214 @@
+//! Netpbm decoding
+
+
+use self::super::super::super::ops::netpbm::NetpbmImage;
+use self::super::super::super::ops::{NetpbmError, Quirc};
+use self::super::netpbm_luma;
+use alloc::vec::Vec;
+
+
+/// Skip whitespace and comments, which run from `#` to the end of the line.
+pub fn skip_whitespace(data: &[u8], pos: &mut usize) {
+    while let Some(&b) = data.get(*pos) {
+        if b == b'#' {
+            while data.get(*pos).map(|&b| b != b'\n' && b != b'\r').unwrap_or(false) {
+                *pos += 1;
+            }
+        } else if b.is_ascii_whitespace() {
+            *pos += 1;
+        } else {
+            break;
+        }
+    }
+}
+
+/// An unsigned decimal after any whitespace and comments, or `None` if there isn't one or it doesn't fit.
+pub fn read_number(data: &[u8], pos: &mut usize) -> Option<u32> {
+    skip_whitespace(data, pos);
+
+    let start = *pos;
+    let mut value = 0u32;
+    while let Some(&b) = data.get(*pos).filter(|b| b.is_ascii_digit()) {
+        value = value.checked_mul(10)?.checked_add((b - b'0') as u32)?;
+        *pos += 1;
+    }
+
+    if *pos == start { None } else { Some(value) }
+}
+
+fn header_number(data: &[u8], pos: &mut usize, field: &'static str) -> Result<u32, NetpbmError> {
+    match read_number(data, pos) {
+        Some(n) => Ok(n),
+        None if *pos >= data.len() => Err(NetpbmError::Truncated),
+        None => Err(NetpbmError::InvalidHeader(field)),
+    }
+}
+
+/// The width, height, depth and maxval from a PAM header, leaving `pos` after `ENDHDR`.
+fn pam_header(data: &[u8], pos: &mut usize) -> Result<(u32, u32, u32, u32), NetpbmError> {
+    let (mut width, mut height, mut depth, mut maxval) = (None, None, None, None);
+
+    loop {
+        skip_whitespace(data, pos);
+        let start = *pos;
+        while data.get(*pos).map(|b| !b.is_ascii_whitespace()).unwrap_or(false) {
+            *pos += 1;
+        }
+
+        match &data[start..*pos] {
+            b"WIDTH" => width = Some(header_number(data, pos, "width")?),
+            b"HEIGHT" => height = Some(header_number(data, pos, "height")?),
+            b"DEPTH" => depth = Some(header_number(data, pos, "depth")?),
+            b"MAXVAL" => maxval = Some(header_number(data, pos, "maxval")?),
+            b"TUPLTYPE" => {
+                // The depth says all that's needed
+                while data.get(*pos).map(|&b| b != b'\n').unwrap_or(false) {
+                    *pos += 1;
+                }
+            }
+            b"ENDHDR" => break,
+            b"" => return Err(NetpbmError::Truncated),
+            _ => return Err(NetpbmError::InvalidHeader("PAM header line")),
+        }
+    }
+    // ENDHDR ends with a newline
+    *pos += 1;
+
+    Ok((width.ok_or(NetpbmError::InvalidHeader("width"))?,
+        height.ok_or(NetpbmError::InvalidHeader("height"))?,
+        depth.ok_or(NetpbmError::InvalidHeader("depth"))?,
+        maxval.ok_or(NetpbmError::InvalidHeader("maxval"))?))
+}
+
+
+impl NetpbmImage {
+    /// Decode a PBM, PGM, PPM or PAM, in any of their variants.
+    ///
+    /// Only the first image of a multi-image file is read.
+    pub fn read(data: &[u8]) -> Result<NetpbmImage, NetpbmError> {
+        let format = match data.get(..2) {
+            Some(&[b'P', f]) if (b'1'..=b'7').contains(&f) => f,
+            _ => return Err(NetpbmError::UnknownFormat),
+        };
+        let mut pos = 2;
+
+        let (width, height, depth, maxval) = match format {
+            b'7' => pam_header(data, &mut pos)?,
+            _ => {
+                let width = header_number(data, &mut pos, "width")?;
+                let height = header_number(data, &mut pos, "height")?;
+                let (depth, maxval) = match format {
+                    b'1' | b'4' => (1, 1),
+                    b'2' | b'5' => (1, header_number(data, &mut pos, "maxval")?),
+                    _ => (3, header_number(data, &mut pos, "maxval")?),
+                };
+
+                // A single whitespace character separates the header from binary rasters
+                if format >= b'4' {
+                    pos += 1;
+                }
+                (width, height, depth, maxval)
+            }
+        };
+
+        if width == 0 {
+            return Err(NetpbmError::InvalidHeader("width"));
+        }
+        if height == 0 {
+            return Err(NetpbmError::InvalidHeader("height"));
+        }
+        if depth == 0 || depth > 4 {
+            return Err(NetpbmError::UnsupportedDepth(depth));
+        }
+        if maxval == 0 || maxval > 65535 {
+            return Err(NetpbmError::InvalidMaxval(maxval));
+        }
+
+        let (width, height, depth) = (width as usize, height as usize, depth as usize);
+        let count = width.checked_mul(height).and_then(|n| n.checked_mul(depth)).ok_or(NetpbmError::InvalidHeader("width"))?;
+        let mut samples = Vec::with_capacity(count.min(data.len() * 8));
+
+        match format {
+            b'1' => {
+                for _ in 0..count {
+                    skip_whitespace(data, &mut pos);
+                    match data.get(pos) {
+                        Some(b'0') => samples.push(1),
+                        Some(b'1') => samples.push(0),
+                        Some(_) => return Err(NetpbmError::InvalidSample { offset: pos }),
+                        None => return Err(NetpbmError::Truncated),
+                    }
+                    pos += 1;
+                }
+            }
+            b'2' | b'3' => {
+                for _ in 0..count {
+                    let start = pos;
+                    match read_number(data, &mut pos) {
+                        Some(s) if s <= maxval => samples.push(s as u16),
+                        None if pos >= data.len() => return Err(NetpbmError::Truncated),
+                        _ => return Err(NetpbmError::InvalidSample { offset: start }),
+                    }
+                }
+            }
+            b'4' => {
+                let row_bytes = width.div_ceil(8);
+                let raster = data.get(pos..pos + row_bytes * height).ok_or(NetpbmError::Truncated)?;
+                for row in raster.chunks(row_bytes) {
+                    samples.extend((0..width).map(|x| if (row[x >> 3] >> (7 - (x & 7))) & 1 != 0 { 0 } else { 1 }));
+                }
+            }
+            _ => {
+                let bytes_per = if maxval > 255 { 2 } else { 1 };
+                let raster = data.get(pos..pos + count * bytes_per).ok_or(NetpbmError::Truncated)?;
+                for (i, s) in raster.chunks(bytes_per).enumerate() {
+                    let s = s.iter().fold(0u32, |acc, &b| (acc << 8) | b as u32);
+                    if s > maxval {
+                        return Err(NetpbmError::InvalidSample { offset: pos + i * bytes_per });
+                    }
+                    samples.push(s as u16);
+                }
+            }
+        }
+
+        Ok(NetpbmImage {
+            width,
+            height,
+            depth,
+            maxval: maxval as u16,
+            samples,
+        })
+    }
+
+    /// A grayscale image with maxval 255, as `Quirc::begin()` takes it.
+    pub fn from_luma(width: usize, height: usize, pixels: &[u8]) -> NetpbmImage {
+        NetpbmImage {
+            width,
+            height,
+            depth: 1,
+            maxval: 255,
+            samples: pixels.iter().map(|&p| p as u16).collect(),
+        }
+    }
+
+    /// Each pixel's Rec. 601 luma, scaled to 0-255, with any alpha composited over white.
+    pub fn luma(&self) -> Vec<u8> {
+        let maxval = self.maxval as u32;
+
+        self.samples
+            .chunks(self.depth)
+            .map(|pixel| ((netpbm_luma(pixel, maxval) * 255 + maxval / 2) / maxval) as u8)
+            .collect()
+    }
+}
+
+impl Quirc {
+    /// Resize to the Netpbm image and load its luma into the image buffer, ready for `end()`.
+    pub fn load_netpbm(&mut self, data: &[u8]) -> Result<(), NetpbmError> {
+        let image = NetpbmImage::read(data)?;
+
+        self.resize(image.width, image.height);
+        self.begin().copy_from_slice(&image.luma());
+        Ok(())
+    }
+}
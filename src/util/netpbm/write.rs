@@ -0,0 +1,176 @@
+//! Netpbm encoding
+
+
+use self::super::super::super::ops::netpbm::{NetpbmFormat, NetpbmImage};
+use self::super::super::super::ops::Quirc;
+use self::super::super::{QUIRC_PIXEL_WHITE, QUIRC_PIXEL_BLACK};
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+
+/// The Rec. 601 luma of a pixel of 1 to 4 samples, on the same scale, with any alpha composited over white.
+pub fn netpbm_luma(pixel: &[u16], maxval: u32) -> u32 {
+    let (value, alpha) = match *pixel {
+        [g] => (g as u32, maxval),
+        [g, a] => (g as u32, a as u32),
+        [r, g, b] => ((299 * r as u32 + 587 * g as u32 + 114 * b as u32 + 500) / 1000, maxval),
+        [r, g, b, a, ..] => ((299 * r as u32 + 587 * g as u32 + 114 * b as u32 + 500) / 1000, a as u32),
+        [] => (maxval, maxval),
+    };
+
+    (value * alpha + maxval * (maxval - alpha) + maxval / 2) / maxval
+}
+
+fn write_header(out: &mut Vec<u8>, magic: &str, width: usize, height: usize, maxval: Option<u16>) {
+    out.extend_from_slice(magic.as_bytes());
+    out.push(b'\n');
+    out.extend_from_slice(width.to_string().as_bytes());
+    out.push(b' ');
+    out.extend_from_slice(height.to_string().as_bytes());
+    out.push(b'\n');
+    if let Some(maxval) = maxval {
+        out.extend_from_slice(maxval.to_string().as_bytes());
+        out.push(b'\n');
+    }
+}
+
+/// Samples as decimals, wrapping lines before they pass 70 characters.
+fn write_ascii(out: &mut Vec<u8>, samples: &[u16]) {
+    let mut line = 0;
+    for &s in samples {
+        let s = s.to_string();
+        if line != 0 && line + 1 + s.len() > 70 {
+            out.push(b'\n');
+            line = 0;
+        } else if line != 0 {
+            out.push(b' ');
+            line += 1;
+        }
+        out.extend_from_slice(s.as_bytes());
+        line += s.len();
+    }
+    out.push(b'\n');
+}
+
+fn write_binary(out: &mut Vec<u8>, samples: &[u16], maxval: u16) {
+    if maxval > 255 {
+        for &s in samples {
+            out.extend_from_slice(&s.to_be_bytes());
+        }
+    } else {
+        out.extend(samples.iter().map(|&s| s as u8));
+    }
+}
+
+
+impl NetpbmImage {
+    /// Encode as the given format, with ASCII rasters if `ascii` is set and the format has them.
+    ///
+    /// PGMs and PPMs keep the maxval; PBMs are thresholded at half of it.
+    pub fn write(&self, format: NetpbmFormat, ascii: bool) -> Vec<u8> {
+        let maxval = self.maxval as u32;
+        let gray = || self.samples.chunks(self.depth).map(|pixel| netpbm_luma(pixel, maxval) as u16);
+
+        let mut out = Vec::new();
+        match format {
+            NetpbmFormat::Pbm => {
+                // 1 is black in PBM
+                let bits: Vec<u16> = gray().map(|g| ((g as u32) * 2 < maxval) as u16).collect();
+                write_header(&mut out, if ascii { "P1" } else { "P4" }, self.width, self.height, None);
+                if ascii {
+                    write_ascii(&mut out, &bits);
+                } else {
+                    for row in bits.chunks(self.width) {
+                        let mut packed = Vec::with_capacity(row.len().div_ceil(8));
+                        for byte in row.chunks(8) {
+                            packed.push(byte.iter().enumerate().fold(0u8, |acc, (i, &b)| acc | ((b as u8) << (7 - i))));
+                        }
+                        out.extend_from_slice(&packed);
+                    }
+                }
+            }
+            NetpbmFormat::Pgm => {
+                let samples: Vec<u16> = gray().collect();
+                write_header(&mut out, if ascii { "P2" } else { "P5" }, self.width, self.height, Some(self.maxval));
+                if ascii {
+                    write_ascii(&mut out, &samples);
+                } else {
+                    write_binary(&mut out, &samples, self.maxval);
+                }
+            }
+            NetpbmFormat::Ppm => {
+                let samples: Vec<u16> = if self.depth >= 3 {
+                    self.samples.chunks(self.depth).flat_map(|pixel| pixel[..3].iter().cloned()).collect()
+                } else {
+                    gray().flat_map(|g| [g, g, g]).collect()
+                };
+                write_header(&mut out, if ascii { "P3" } else { "P6" }, self.width, self.height, Some(self.maxval));
+                if ascii {
+                    write_ascii(&mut out, &samples);
+                } else {
+                    write_binary(&mut out, &samples, self.maxval);
+                }
+            }
+            NetpbmFormat::Pam => {
+                let tuple_type = match self.depth {
+                    1 if self.maxval == 1 => "BLACKANDWHITE",
+                    1 => "GRAYSCALE",
+                    2 => "GRAYSCALE_ALPHA",
+                    3 => "RGB",
+                    _ => "RGB_ALPHA",
+                };
+                for line in &["P7".to_string(),
+                              ["WIDTH ", &self.width.to_string()].concat(),
+                              ["HEIGHT ", &self.height.to_string()].concat(),
+                              ["DEPTH ", &self.depth.to_string()].concat(),
+                              ["MAXVAL ", &self.maxval.to_string()].concat(),
+                              ["TUPLTYPE ", tuple_type].concat(),
+                              "ENDHDR".to_string()] {
+                    out.extend_from_slice(line.as_bytes());
+                    out.push(b'\n');
+                }
+                write_binary(&mut out, &self.samples, self.maxval);
+            }
+        }
+
+        out
+    }
+}
+
+impl Quirc {
+    /// The thresholded image after `end()`, as an RGB image for debugging.
+    ///
+    /// Light pixels are white and dark ones black, except those of regions:
+    /// capstone rings are red, capstone centres dark red, and every other region gets a colour of its own.
+    pub fn pixels_netpbm(&self) -> NetpbmImage {
+        let mut samples = Vec::with_capacity(self.pixels.len() * 3);
+
+        for &p in &self.pixels {
+            let colour = match p {
+                QUIRC_PIXEL_WHITE => [255, 255, 255],
+                QUIRC_PIXEL_BLACK => [0, 0, 0],
+                region => {
+                    let capstone = self.regions[region as usize].capstone;
+                    if capstone >= 0 && self.capstones[capstone as usize].ring == region as u32 {
+                        [255, 0, 0]
+                    } else if capstone >= 0 {
+                        [128, 0, 0]
+                    } else {
+                        // Spread neighbouring region numbers over the hues, keeping away from red and white
+                        let hash = (region as u32).wrapping_mul(0x9E37_79B9) >> 8;
+                        [(hash & 0x7F) as u16, (64 + ((hash >> 8) & 0x7F)) as u16, (64 + ((hash >> 16) & 0xBF)) as u16]
+                    }
+                }
+            };
+            samples.extend_from_slice(&colour);
+        }
+
+        NetpbmImage {
+            width: self.w,
+            height: self.h,
+            depth: 3,
+            maxval: 255,
+            samples,
+        }
+    }
+}
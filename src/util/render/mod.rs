@@ -0,0 +1,7 @@
+mod raster;
+mod png;
+mod svg;
+mod text;
+
+pub use self::raster::{render_dimension, foreground_at, luma};
+pub use self::png::{crc32, adler32, deflate, zlib};
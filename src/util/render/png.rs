@@ -0,0 +1,250 @@
+//! PNG output
+//!
+//! A 1-bit image with a two-entry palette, compressed with a small LZ77 and the fixed Huffman codes of RFC 1951.
+//! Rows of a cell repeat `module_size` times and cells are uniform, so the matches are long and the codes don't need tuning.
+
+
+use self::super::super::super::ops::render::RenderOptions;
+use self::super::super::super::ops::QuircCode;
+use self::super::{render_dimension, foreground_at};
+use alloc::vec::Vec;
+
+
+static PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+/// How many earlier positions with the same hash to try for each match.
+const MAX_CHAIN: usize = 64;
+
+/// First length of each length code (257 onwards), and its extra bits.
+static LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+static LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+/// First distance of each distance code, and its extra bits.
+static DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193,
+                                   12289, 16385, 24577];
+static DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+
+/// CRC-32 (ISO 3309, as used by PNG) of the data.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+/// Adler-32 (RFC 1950) of the data.
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut a = 1u32;
+    let mut b = 0u32;
+
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+
+    (b << 16) | a
+}
+
+
+/// Deflate output, LSB first.
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn push(&mut self, value: u32, count: u32) {
+        self.acc |= value << self.bits;
+        self.bits += count;
+
+        while self.bits >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    /// Huffman codes go most significant bit first.
+    fn push_code(&mut self, code: u32, count: u32) {
+        let mut reversed = 0;
+        for i in 0..count {
+            reversed |= ((code >> i) & 1) << (count - 1 - i);
+        }
+
+        self.push(reversed, count);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+/// Emit a literal/length symbol with the fixed Huffman code.
+fn push_symbol(w: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => w.push_code(0x30 + symbol, 8),
+        144..=255 => w.push_code(0x190 + symbol - 144, 9),
+        256..=279 => w.push_code(symbol - 256, 7),
+        _ => w.push_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn push_match(w: &mut BitWriter, length: usize, distance: usize) {
+    let lcode = LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap();
+    push_symbol(w, 257 + lcode as u32);
+    w.push((length - LENGTH_BASE[lcode] as usize) as u32, LENGTH_EXTRA[lcode] as u32);
+
+    let dcode = DISTANCE_BASE.iter().rposition(|&base| base as usize <= distance).unwrap();
+    w.push_code(dcode as u32, 5);
+    w.push((distance - DISTANCE_BASE[dcode] as usize) as u32, DISTANCE_EXTRA[dcode] as u32);
+}
+
+fn hash(data: &[u8], pos: usize) -> usize {
+    let v = (data[pos] as u32) << 16 | (data[pos + 1] as u32) << 8 | data[pos + 2] as u32;
+    (v.wrapping_mul(0x9e3779b1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Make `pos` the latest position with its hash.
+fn insert(data: &[u8], pos: usize, head: &mut [usize], prev: &mut [usize]) {
+    if pos + MIN_MATCH <= data.len() {
+        let h = hash(data, pos);
+        prev[pos % WINDOW_SIZE] = head[h];
+        head[h] = pos + 1;
+    }
+}
+
+/// A raw RFC 1951 stream: a single final block with the fixed Huffman codes, and greedy LZ77 matches.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter {
+        out: Vec::new(),
+        acc: 0,
+        bits: 0,
+    };
+    // BFINAL, BTYPE = fixed
+    w.push(1, 1);
+    w.push(1, 2);
+
+    // head[hash]: the latest position + 1 with that hash, prev[pos % WINDOW_SIZE]: the one before
+    let mut head = vec![0usize; 1 << HASH_BITS];
+    let mut prev = vec![0usize; WINDOW_SIZE];
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best = (0, 0);
+
+        if pos + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash(data, pos)];
+            let mut chain = 0;
+
+            while candidate > 0 && pos - (candidate - 1) <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let start = candidate - 1;
+                let max = (data.len() - pos).min(MAX_MATCH);
+                let length = (0..max).take_while(|&i| data[start + i] == data[pos + i]).count();
+
+                if length > best.0 {
+                    best = (length, pos - start);
+                    if length == max {
+                        break;
+                    }
+                }
+
+                let next = prev[start % WINDOW_SIZE];
+                if next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        if best.0 >= MIN_MATCH {
+            push_match(&mut w, best.0, best.1);
+            for p in pos..pos + best.0 {
+                insert(data, p, &mut head, &mut prev);
+            }
+            pos += best.0;
+        } else {
+            push_symbol(&mut w, data[pos] as u32);
+            insert(data, pos, &mut head, &mut prev);
+            pos += 1;
+        }
+    }
+
+    push_symbol(&mut w, 256);
+    w.finish()
+}
+
+/// An RFC 1950 stream wrapping `deflate()`.
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+    // CM = deflate with a 32K window, no dictionary, FCHECK making the header a multiple of 31
+    let mut out = vec![0x78, 0x01];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn push_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+
+impl QuircCode {
+    /// Render as a 1-bit palette PNG image.
+    pub fn render_png(&self, options: &RenderOptions) -> Vec<u8> {
+        let cells = render_dimension(self, options);
+        let side = cells * options.module_size;
+        let row_bytes = side.div_ceil(8) as usize;
+
+        // Each row: filter type 0, then the palette indices, 1 for the foreground
+        let mut scanlines = vec![0u8; (row_bytes + 1) * side as usize];
+        for y in 0..side {
+            let row = &mut scanlines[y as usize * (row_bytes + 1) + 1..(y as usize + 1) * (row_bytes + 1)];
+
+            for x in 0..side {
+                if foreground_at(self, options, x / options.module_size, y / options.module_size) {
+                    row[(x / 8) as usize] |= 0x80 >> (x % 8);
+                }
+            }
+        }
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&side.to_be_bytes());
+        ihdr.extend_from_slice(&side.to_be_bytes());
+        // Bit depth 1, colour type 3 (palette), deflate, adaptive filtering, no interlace
+        ihdr.extend_from_slice(&[1, 3, 0, 0, 0]);
+
+        let mut plte = Vec::with_capacity(6);
+        plte.extend_from_slice(&options.background);
+        plte.extend_from_slice(&options.foreground);
+
+        let mut out = PNG_SIGNATURE.to_vec();
+        push_chunk(&mut out, b"IHDR", &ihdr);
+        push_chunk(&mut out, b"PLTE", &plte);
+        push_chunk(&mut out, b"IDAT", &zlib(&scanlines));
+        push_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+}
@@ -0,0 +1,89 @@
+//! Pixel rendering and Netpbm output
+
+
+use self::super::super::super::ops::render::RenderOptions;
+use self::super::super::super::ops::QuircCode;
+use alloc::vec::Vec;
+
+
+/// Width and height, in cells, of the symbol plus its quiet zone.
+pub fn render_dimension(code: &QuircCode, options: &RenderOptions) -> u32 {
+    code.size + options.quiet_zone * 2
+}
+
+/// Whether the cell at (x, y), counted from the corner of the quiet zone, is drawn in the foreground colour.
+pub fn foreground_at(code: &QuircCode, options: &RenderOptions, x: u32, y: u32) -> bool {
+    let dark = if x < options.quiet_zone || y < options.quiet_zone || x >= options.quiet_zone + code.size || y >= options.quiet_zone + code.size {
+        false
+    } else {
+        let p = (y - options.quiet_zone) * code.size + (x - options.quiet_zone);
+        (code.cell_bitmap[(p >> 3) as usize] >> (p & 7)) & 1 != 0
+    };
+
+    dark != options.invert
+}
+
+/// Rec. 601 luma of an RGB colour.
+pub fn luma(rgb: [u8; 3]) -> u8 {
+    ((rgb[0] as u32 * 299 + rgb[1] as u32 * 587 + rgb[2] as u32 * 114 + 500) / 1000) as u8
+}
+
+/// Netpbm header, `P4` or `P5`.
+fn netpbm_header(magic: &str, side: u32, maxval: Option<u32>) -> Vec<u8> {
+    let header = match maxval {
+        Some(maxval) => format!("{}\n{} {}\n{}\n", magic, side, side, maxval),
+        None => format!("{}\n{} {}\n", magic, side, side),
+    };
+
+    header.into_bytes()
+}
+
+
+impl QuircCode {
+    /// Render as a binary (`P4`) PBM image, black where the foreground colour would be, white elsewhere.
+    pub fn render_pbm(&self, options: &RenderOptions) -> Vec<u8> {
+        let cells = render_dimension(self, options);
+        let side = cells * options.module_size;
+        let row_bytes = side.div_ceil(8) as usize;
+
+        let mut out = netpbm_header("P4", side, None);
+        let header_len = out.len();
+        out.resize(header_len + row_bytes * side as usize, 0);
+
+        for y in 0..side {
+            let row = &mut out[header_len + y as usize * row_bytes..header_len + (y as usize + 1) * row_bytes];
+
+            for x in 0..side {
+                // In PBM, 1 is black
+                if foreground_at(self, options, x / options.module_size, y / options.module_size) {
+                    row[(x / 8) as usize] |= 0x80 >> (x % 8);
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Render as a binary (`P5`) PGM image, in the luma of the colours.
+    pub fn render_pgm(&self, options: &RenderOptions) -> Vec<u8> {
+        let cells = render_dimension(self, options);
+        let side = cells * options.module_size;
+        let foreground = luma(options.foreground);
+        let background = luma(options.background);
+
+        let mut out = netpbm_header("P5", side, Some(255));
+        out.reserve((side * side) as usize);
+
+        for y in 0..side {
+            for x in 0..side {
+                out.push(if foreground_at(self, options, x / options.module_size, y / options.module_size) {
+                    foreground
+                } else {
+                    background
+                });
+            }
+        }
+
+        out
+    }
+}
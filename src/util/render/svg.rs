@@ -0,0 +1,64 @@
+//! SVG output
+//!
+//! All foreground cells are one path, made of a rectangle for each horizontal run, in cell units;
+//! `shape-rendering="crispEdges"` keeps anti-aliasing from showing the seams.
+
+
+use self::super::super::super::ops::render::RenderOptions;
+use self::super::super::super::ops::QuircCode;
+use self::super::{render_dimension, foreground_at};
+use alloc::string::String;
+use core::fmt::Write;
+
+
+impl QuircCode {
+    /// Render as an SVG image, `module_size` pixels per cell.
+    pub fn render_svg(&self, options: &RenderOptions) -> String {
+        let cells = render_dimension(self, options);
+        let side = cells * options.module_size;
+        let mut out = String::new();
+
+        let _ = write!(out,
+                       "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\" version=\"1.1\" width=\"{}\" height=\"{}\" \
+                        viewBox=\"0 0 {} {}\" shape-rendering=\"crispEdges\">\n",
+                       side,
+                       side,
+                       cells,
+                       cells);
+        let _ = write!(out,
+                       "<rect width=\"{}\" height=\"{}\" fill=\"#{:02x}{:02x}{:02x}\"/>\n<path fill=\"#{:02x}{:02x}{:02x}\" d=\"",
+                       cells,
+                       cells,
+                       options.background[0],
+                       options.background[1],
+                       options.background[2],
+                       options.foreground[0],
+                       options.foreground[1],
+                       options.foreground[2]);
+
+        let mut first = true;
+        for y in 0..cells {
+            let mut x = 0;
+            while x < cells {
+                if !foreground_at(self, options, x, y) {
+                    x += 1;
+                    continue;
+                }
+
+                let start = x;
+                while x < cells && foreground_at(self, options, x, y) {
+                    x += 1;
+                }
+
+                if !first {
+                    out.push(' ');
+                }
+                first = false;
+                let _ = write!(out, "M{} {}h{}v1h-{}z", start, y, x - start, x - start);
+            }
+        }
+
+        out.push_str("\"/>\n</svg>\n");
+        out
+    }
+}
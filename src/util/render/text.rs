@@ -0,0 +1,36 @@
+//! Terminal output
+//!
+//! Each character covers one cell across and two down, with the Unicode half blocks,
+//! which comes out roughly square in most terminal fonts.
+
+
+use self::super::super::super::ops::render::RenderOptions;
+use self::super::super::super::ops::QuircCode;
+use self::super::{render_dimension, foreground_at};
+use alloc::string::String;
+
+
+impl QuircCode {
+    /// Render as lines of `█`, `▀`, `▄` and spaces, blocks in the foreground colour, `module_size` and the colours ignored.
+    pub fn render_text(&self, options: &RenderOptions) -> String {
+        let cells = render_dimension(self, options);
+        let mut out = String::new();
+
+        for y in (0..cells).step_by(2) {
+            for x in 0..cells {
+                let top = foreground_at(self, options, x, y);
+                let bottom = y + 1 < cells && foreground_at(self, options, x, y + 1);
+
+                out.push(match (top, bottom) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+}
@@ -0,0 +1,77 @@
+//! One-shot scanning
+
+
+use self::super::super::ops::{QuircResults, ScanResult, Decoder, Quirc};
+use alloc::vec::Vec;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+
+/// Identify and decode every code in a grayscale image, `w` pixels wide and `h` high, one byte per pixel.
+///
+/// Equivalent to `resize()`, copying into `begin()`, `end()`, and collecting `results()`, with a new `Quirc`.
+///
+/// # Panics
+///
+/// If `image` isn't `w * h` bytes long.
+pub fn scan(image: &[u8], w: usize, h: usize) -> Vec<ScanResult> {
+    let mut quirc = Quirc::new();
+    quirc.resize(w, h);
+    quirc.begin().copy_from_slice(image);
+    quirc.end();
+
+    quirc.results().collect()
+}
+
+
+impl Quirc {
+    /// Extract and decode each code identified by the last `end()`, in the order of `extract()`.
+    pub fn results(&self) -> QuircResults<'_> {
+        QuircResults {
+            quirc: self,
+            index: 0,
+            decoder: Decoder::new(),
+        }
+    }
+
+    /// Extract and decode each code identified by the last `end()` or `par_end()` like `results()`, spread across the rayon thread pool.
+    #[cfg(feature = "rayon")]
+    pub fn par_results(&self) -> Vec<ScanResult> {
+        (0..self.count())
+            .into_par_iter()
+            .map_init(Decoder::new, |decoder, i| {
+                let code = self.extract(i).expect("index below count()");
+                ScanResult {
+                    corners: code.corners,
+                    data: decoder.decode(&code),
+                    code,
+                    inferred: self.grids[i].inferred,
+                }
+            })
+            .collect()
+    }
+}
+
+impl<'a> Iterator for QuircResults<'a> {
+    type Item = ScanResult;
+
+    fn next(&mut self) -> Option<ScanResult> {
+        let code = self.quirc.extract(self.index).ok()?;
+        let inferred = self.quirc.grids[self.index].inferred;
+        self.index += 1;
+
+        Some(ScanResult {
+            corners: code.corners,
+            data: self.decoder.decode(&code),
+            code,
+            inferred,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.quirc.count().saturating_sub(self.index);
+        (left, Some(left))
+    }
+}
+
+impl<'a> ExactSizeIterator for QuircResults<'a> {}
@@ -0,0 +1,147 @@
+//! Degrading a scene after the symbols are drawn
+
+
+use self::super::SplitMix64;
+use std::vec::Vec;
+
+
+/// The luminance quantisation table from Annex K of ITU-T T.81, in row order.
+pub static JPEG_LUMINANCE: [u8; 64] = [16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56, 14, 17, 22, 29, 51, 87,
+                                       80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113, 92, 49, 64, 78, 87, 103, 121, 120, 101, 72,
+                                       92, 95, 98, 112, 100, 103, 99];
+
+
+/// Normalised weights from `-radius` to `radius`, `radius` being 3σ rounded up.
+pub fn gaussian_kernel(sigma: f64) -> Vec<f64> {
+    let radius = (sigma * 3.0).ceil() as isize;
+    let mut kernel: Vec<f64> = (-radius..=radius).map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp()).collect();
+
+    let sum: f64 = kernel.iter().sum();
+    for w in &mut kernel {
+        *w /= sum;
+    }
+    kernel
+}
+
+/// Separable Gaussian blur, repeating the edge pixels outwards.
+pub fn gaussian_blur(pixels: &mut [f64], width: usize, sigma: f64) {
+    let height = pixels.len() / width;
+    let kernel = gaussian_kernel(sigma);
+    let radius = (kernel.len() / 2) as isize;
+    let mut tmp = vec![0f64; pixels.len()];
+
+    for y in 0..height {
+        for x in 0..width {
+            tmp[y * width + x] = kernel.iter()
+                .enumerate()
+                .map(|(i, w)| {
+                    let sx = (x as isize + i as isize - radius).max(0).min(width as isize - 1) as usize;
+                    w * pixels[y * width + sx]
+                })
+                .sum();
+        }
+    }
+
+    for y in 0..height {
+        for x in 0..width {
+            pixels[y * width + x] = kernel.iter()
+                .enumerate()
+                .map(|(i, w)| {
+                    let sy = (y as isize + i as isize - radius).max(0).min(height as isize - 1) as usize;
+                    w * tmp[sy * width + x]
+                })
+                .sum();
+        }
+    }
+}
+
+/// Darken the image linearly along a random direction, by nothing on one side and by `strength` of the brightness on the other.
+pub fn illuminate(pixels: &mut [f64], width: usize, strength: f64, rng: &mut SplitMix64) {
+    let height = pixels.len() / width;
+    let (sin, cos) = (rng.next_f64() * 2.0 * std::f64::consts::PI).sin_cos();
+
+    let cx = (width as f64 - 1.0) / 2.0;
+    let cy = (height as f64 - 1.0) / 2.0;
+    let reach = cx.hypot(cy).max(1.0);
+
+    for y in 0..height {
+        for x in 0..width {
+            let t = ((x as f64 - cx) * cos + (y as f64 - cy) * sin) / reach;
+            pixels[y * width + x] *= 1.0 - strength * (t + 1.0) / 2.0;
+        }
+    }
+}
+
+/// Add Gaussian noise with the given standard deviation to every pixel.
+pub fn add_noise(pixels: &mut [f64], sigma: f64, rng: &mut SplitMix64) {
+    for px in pixels {
+        *px += rng.next_gaussian() * sigma;
+    }
+}
+
+/// `JPEG_LUMINANCE` scaled to the quality, as libjpeg's `jpeg_quality_scaling()` does.
+pub fn jpeg_quality_table(quality: u8) -> [f64; 64] {
+    let quality = quality.clamp(1, 100) as u32;
+    let scale = if quality < 50 { 5000 / quality } else { 200 - quality * 2 };
+
+    let mut table = [0f64; 64];
+    for (q, &base) in table.iter_mut().zip(JPEG_LUMINANCE.iter()) {
+        *q = ((base as u32 * scale + 50) / 100).clamp(1, 255) as f64;
+    }
+    table
+}
+
+/// Round-trip each 8x8 block through a quantised DCT-II, as a baseline JPEG encoder and decoder would.
+///
+/// Partial blocks on the right and bottom edges are padded by repeating the last pixel; only the image's pixels are written back.
+pub fn jpeg_quantise(pixels: &mut [u8], width: usize, quality: u8) {
+    let height = pixels.len() / width;
+    let table = jpeg_quality_table(quality);
+
+    let mut basis = [[0f64; 8]; 8];
+    for (k, row) in basis.iter_mut().enumerate() {
+        let scale = if k == 0 { (0.125f64).sqrt() } else { 0.5 };
+        for (n, b) in row.iter_mut().enumerate() {
+            *b = scale * ((2 * n + 1) as f64 * k as f64 * std::f64::consts::PI / 16.0).cos();
+        }
+    }
+
+    for by in (0..height).step_by(8) {
+        for bx in (0..width).step_by(8) {
+            let mut block = [[0f64; 8]; 8];
+            for (y, row) in block.iter_mut().enumerate() {
+                for (x, b) in row.iter_mut().enumerate() {
+                    let sy = (by + y).min(height - 1);
+                    let sx = (bx + x).min(width - 1);
+                    *b = pixels[sy * width + sx] as f64 - 128.0;
+                }
+            }
+
+            let mut coefficients = [[0f64; 8]; 8];
+            for v in 0..8 {
+                for u in 0..8 {
+                    let mut sum = 0.0;
+                    for y in 0..8 {
+                        for x in 0..8 {
+                            sum += basis[v][y] * basis[u][x] * block[y][x];
+                        }
+                    }
+                    let q = table[v * 8 + u];
+                    coefficients[v][u] = (sum / q).round() * q;
+                }
+            }
+
+            for y in 0..8.min(height - by) {
+                for x in 0..8.min(width - bx) {
+                    let mut sum = 0.0;
+                    for v in 0..8 {
+                        for u in 0..8 {
+                            sum += basis[v][y] * basis[u][x] * coefficients[v][u];
+                        }
+                    }
+                    pixels[(by + y) * width + bx + x] = (sum + 128.0).round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+    }
+}
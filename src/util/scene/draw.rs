@@ -0,0 +1,192 @@
+//! Placing symbols into a scene
+
+
+use self::super::super::super::ops::scene::{ScenePlacement, SceneOptions, SceneTruth, Scene};
+use self::super::super::super::ops::{QuircPoint, QuircCode, Quirc};
+use self::super::super::super::ops::EncodeError;
+use self::super::super::{perspective_setup, perspective_unmap, perspective_map, encode};
+use self::super::{SplitMix64, gaussian_blur, illuminate, add_noise, jpeg_quantise};
+use std::vec::Vec;
+
+
+/// Width of the light border drawn around each symbol, in cells.
+pub const QUIET_ZONE: u32 = 4;
+
+/// Each pixel is the average of `SUPERSAMPLING`² samples, centred on the pixel's coordinates.
+pub const SUPERSAMPLING: isize = 3;
+
+
+/// Where the corners of the symbol, from top left, clockwise, land.
+pub fn scene_corners(placement: &ScenePlacement, size: u32) -> [QuircPoint; 4] {
+    let half = size as f64 * placement.module_size / 2.0;
+    let (sin, cos) = placement.rotation.to_radians().sin_cos();
+
+    let mut offsets = [(-half, -half), (half, -half), (half, half), (-half, half)];
+    // The right edge shrinks vertically, the bottom one horizontally
+    offsets[1].1 *= 1.0 - placement.tilt.0;
+    offsets[2].1 *= 1.0 - placement.tilt.0;
+    offsets[2].0 *= 1.0 - placement.tilt.1;
+    offsets[3].0 *= 1.0 - placement.tilt.1;
+
+    let mut corners = [QuircPoint { x: 0, y: 0 }; 4];
+    for (corner, &(dx, dy)) in corners.iter_mut().zip(offsets.iter()) {
+        corner.x = (placement.center.0 + dx * cos - dy * sin).round() as isize;
+        corner.y = (placement.center.1 + dx * sin + dy * cos).round() as isize;
+    }
+    corners
+}
+
+/// Draw the symbol and its quiet zone into the `width`-wide image, warped so that its corners land on `corners`.
+///
+/// Cells inside `occlusion`, given as `(u0, v0, u1, v1)` in cells from the top left corner of the symbol,
+/// are drawn halfway between `light` and `dark`.
+pub fn draw_symbol(pixels: &mut [f64], width: usize, code: &QuircCode, corners: &[QuircPoint; 4], light: f64, dark: f64,
+                   occlusion: Option<(f64, f64, f64, f64)>) {
+    let height = pixels.len() / width;
+    let size = code.size as f64;
+    let quiet = QUIET_ZONE as f64;
+
+    // Find the pixels the quiet zone's corners bound
+    let c = perspective_setup(corners, size, size);
+    let (mut x0, mut y0, mut x1, mut y1) = (isize::MAX, isize::MAX, isize::MIN, isize::MIN);
+    for &(u, v) in &[(-quiet, -quiet), (size + quiet, -quiet), (size + quiet, size + quiet), (-quiet, size + quiet)] {
+        let p = perspective_map(&c, u, v);
+        x0 = x0.min(p.x - 1);
+        y0 = y0.min(p.y - 1);
+        x1 = x1.max(p.x + 1);
+        y1 = y1.max(p.y + 1);
+    }
+    let x0 = x0.max(0) as usize;
+    let y0 = y0.max(0) as usize;
+    let x1 = x1.min(width as isize - 1);
+    let y1 = y1.min(height as isize - 1);
+    if x1 < x0 as isize || y1 < y0 as isize {
+        return;
+    }
+
+    // Pixel (x, y) of the supersampled image is at (x - 1, y - 1) / 3 in this one
+    let mut ss_corners = *corners;
+    for p in &mut ss_corners {
+        p.x = p.x * SUPERSAMPLING + SUPERSAMPLING / 2;
+        p.y = p.y * SUPERSAMPLING + SUPERSAMPLING / 2;
+    }
+    let ss = perspective_setup(&ss_corners, size, size);
+    let samples = (SUPERSAMPLING * SUPERSAMPLING) as f64;
+    let occluded = (light + dark) / 2.0;
+
+    for y in y0..=y1 as usize {
+        for x in x0..=x1 as usize {
+            let mut covered = 0.0;
+            let mut sum = 0.0;
+
+            for j in 0..SUPERSAMPLING {
+                for i in 0..SUPERSAMPLING {
+                    let p = QuircPoint {
+                        x: x as isize * SUPERSAMPLING + i,
+                        y: y as isize * SUPERSAMPLING + j,
+                    };
+                    let (u, v) = perspective_unmap(&ss, &p);
+                    if !(u >= -quiet && v >= -quiet && u < size + quiet && v < size + quiet) {
+                        continue;
+                    }
+
+                    covered += 1.0;
+                    sum += match occlusion {
+                        Some((u0, v0, u1, v1)) if u >= u0 && v >= v0 && u < u1 && v < v1 => occluded,
+                        _ => {
+                            let (cu, cv) = (u.floor(), v.floor());
+                            if cu >= 0.0 && cv >= 0.0 && cu < size && cv < size {
+                                let p = cv as u32 * code.size + cu as u32;
+                                if (code.cell_bitmap[(p >> 3) as usize] >> (p & 7)) & 1 != 0 {
+                                    dark
+                                } else {
+                                    light
+                                }
+                            } else {
+                                light
+                            }
+                        }
+                    };
+                }
+            }
+
+            if covered > 0.0 {
+                let px = &mut pixels[y * width + x];
+                *px = *px * (samples - covered) / samples + sum / samples;
+            }
+        }
+    }
+}
+
+
+impl SceneTruth {
+    /// Whether every corner of the code is within a cell and a half (and at least 3 pixels) of where it was drawn.
+    pub fn matches(&self, code: &QuircCode) -> bool {
+        let side = ((self.corners[1].x - self.corners[0].x) as f64).hypot((self.corners[1].y - self.corners[0].y) as f64);
+        self.matches_within(&code.corners, (side / code.size as f64 * 1.5).max(3.0))
+    }
+
+    /// Whether every one of `corners` is within `tolerance` pixels of where it was drawn.
+    pub fn matches_within(&self, corners: &[QuircPoint; 4], tolerance: f64) -> bool {
+        corners.iter().zip(self.corners.iter()).all(|(c, t)| ((c.x - t.x) as f64).hypot((c.y - t.y) as f64) <= tolerance)
+    }
+}
+
+impl Scene {
+    /// Draw the symbols in order, later ones over earlier ones, then degrade the image as `options` says.
+    pub fn generate(placements: &[ScenePlacement], options: &SceneOptions) -> Result<Scene, EncodeError> {
+        let mut rng = SplitMix64::new(options.seed);
+        let mut pixels = vec![options.light as f64; options.width * options.height];
+        let mut truth = Vec::with_capacity(placements.len());
+
+        for placement in placements {
+            let code = encode(&placement.payload, &placement.encode)?;
+            let corners = scene_corners(placement, code.size);
+
+            let occlusion = if options.occlusion > 0.0 {
+                let size = code.size as f64;
+                let side = size * options.occlusion.min(1.0).sqrt();
+                let u0 = rng.next_f64() * (size - side);
+                let v0 = rng.next_f64() * (size - side);
+                Some((u0, v0, u0 + side, v0 + side))
+            } else {
+                None
+            };
+
+            draw_symbol(&mut pixels, options.width, &code, &corners, options.light as f64, options.dark as f64, occlusion);
+            truth.push(SceneTruth {
+                corners,
+                version: (code.size - 17) / 4,
+                payload: placement.payload.clone(),
+            });
+        }
+
+        if options.blur > 0.0 {
+            gaussian_blur(&mut pixels, options.width, options.blur);
+        }
+        if options.illumination > 0.0 {
+            illuminate(&mut pixels, options.width, options.illumination, &mut rng);
+        }
+        if options.noise > 0.0 {
+            add_noise(&mut pixels, options.noise, &mut rng);
+        }
+
+        let mut pixels: Vec<u8> = pixels.into_iter().map(|p| p.round().clamp(0.0, 255.0) as u8).collect();
+        if options.jpeg_quality > 0 {
+            jpeg_quantise(&mut pixels, options.width, options.jpeg_quality);
+        }
+
+        Ok(Scene {
+            width: options.width,
+            height: options.height,
+            pixels,
+            truth,
+        })
+    }
+
+    /// Resize the recognizer to the scene and copy the image in, ready for `Quirc::end()`.
+    pub fn load(&self, quirc: &mut Quirc) {
+        quirc.resize(self.width, self.height);
+        quirc.begin().copy_from_slice(&self.pixels);
+    }
+}
@@ -0,0 +1,7 @@
+mod rng;
+mod draw;
+mod degrade;
+
+pub use self::rng::SplitMix64;
+pub use self::draw::{QUIET_ZONE, SUPERSAMPLING, scene_corners, draw_symbol};
+pub use self::degrade::{JPEG_LUMINANCE, gaussian_kernel, gaussian_blur, illuminate, add_noise, jpeg_quality_table, jpeg_quantise};
@@ -0,0 +1,287 @@
+//! Tiled scanning
+
+
+use self::super::super::ops::{QUIRC_MAX_GRIDS, QuircPoint, QuircRect, ScanResult, Quirc};
+use self::super::super::ops::tiled::TileOptions;
+use self::super::identify::{grid_setup, grid_contains, grid_contains_point};
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+
+
+/// The furthest apart two capstones of a code can be, in capstone sides: a version 40 code's are 170 cells apart, 24 sides, with some to spare.
+const CODE_SIDES: i64 = 26;
+
+
+/// Where tiles `tile` long start along an image `len` long so that neighbours share `overlap`, the last one ending at the edge.
+///
+/// # Panics
+///
+/// If `overlap` isn't shorter than `tile`.
+pub fn tile_starts(len: usize, tile: usize, overlap: usize) -> Vec<usize> {
+    assert!(overlap < tile, "tile overlap {} not shorter than tile {}", overlap, tile);
+
+    if len <= tile {
+        return vec![0];
+    }
+
+    let mut starts: Vec<usize> = (0..).map(|i| i * (tile - overlap)).take_while(|&s| s + tile < len).collect();
+    starts.push(len - tile);
+    starts
+}
+
+/// Identify and decode every code in a grayscale image, `w` pixels wide and `h` high, a tile at a time.
+///
+/// `read(rect, buf)` fills `buf` with the pixels of `rect`, one byte per pixel, row by row, `rect.width` to a row;
+/// only one tile or window is ever held at a time. Codes found in several tiles are returned once,
+/// and codes straddling tiles are looked for in a window around their capstones; codes that fail to decode are only returned if found whole.
+/// Corners are in the coordinates of the whole image.
+///
+/// An image no bigger than a tile is read once and scanned like `scan()`.
+///
+/// # Panics
+///
+/// If `options.overlap` isn't shorter than both tile dimensions.
+pub fn scan_tiled<F: FnMut(&QuircRect, &mut [u8])>(w: usize, h: usize, options: &TileOptions, mut read: F) -> Vec<ScanResult> {
+    let tile_w = options.tile_width.min(w);
+    let tile_h = options.tile_height.min(h);
+    let tiles: Vec<QuircRect> = tile_starts(h, options.tile_height, options.overlap)
+        .into_iter()
+        .flat_map(|y| {
+            tile_starts(w, options.tile_width, options.overlap).into_iter().map(move |x| {
+                QuircRect {
+                    x,
+                    y,
+                    width: tile_w,
+                    height: tile_h,
+                }
+            })
+        })
+        .collect();
+
+    let mut quirc = Quirc::new();
+    let mut results = Vec::new();
+    let mut loose = Vec::new();
+    for tile in &tiles {
+        scan_rect(&mut quirc, tile, &mut read, &mut results, Some(&mut loose), None);
+    }
+
+    // Capstones of decoded codes are accounted for; of the rest, those seen in several tiles are the same one
+    loose.retain(|&(center, _)| !decoded_at(&results, &center));
+    let mut capstones: Vec<(QuircPoint, i64)> = Vec::with_capacity(loose.len());
+    let mut buckets = Buckets::new(w, h, (tile_w, tile_h));
+    let mut largest = 0;
+    for (center, side) in loose {
+        let reach = isqrt(largest.max(side)) / 2 + 1;
+        if !buckets.near(&center, reach).any(|i| 4 * distance_sq(&capstones[i].0, &center) < capstones[i].1.max(side)) {
+            buckets.insert(&center, capstones.len());
+            capstones.push((center, side));
+            largest = largest.max(side);
+        }
+    }
+
+    // Smallest first, so that triples mixing one code's capstones with others' are passed over once it's decoded
+    for (window, triple) in capstone_windows(&capstones, &buckets, w, h) {
+        let seen = tiles.iter()
+            .any(|t| window.x >= t.x && window.y >= t.y && window.x + window.width <= t.x + t.width && window.y + window.height <= t.y + t.height);
+        if !seen && window.width * window.height <= options.max_window && !triple.iter().any(|&i| decoded_at(&results, &capstones[i].0)) {
+            let centre = QuircPoint {
+                x: triple.iter().map(|&i| capstones[i].0.x).sum::<isize>() / 3,
+                y: triple.iter().map(|&i| capstones[i].0.y).sum::<isize>() / 3,
+            };
+            scan_rect(&mut quirc, &window, &mut read, &mut results, None, Some(&centre));
+        }
+    }
+
+    results
+}
+
+
+/// Scan `rect` of the image with `quirc`, adding what's new to `results` and, if asked, the centres and sides of capstones not in a decoded code to `loose`.
+///
+/// Codes that failed to decode are only added if they're wholly inside `rect`, since those cut off by its edges are looked for elsewhere;
+/// with `around`, only the code over that point is.
+fn scan_rect<F: FnMut(&QuircRect, &mut [u8])>(quirc: &mut Quirc, rect: &QuircRect, read: &mut F, results: &mut Vec<ScanResult>,
+                                             loose: Option<&mut Vec<(QuircPoint, i64)>>, around: Option<&QuircPoint>) {
+    if quirc.size() != (rect.width, rect.height) {
+        quirc.resize(rect.width, rect.height);
+    }
+    read(rect, quirc.begin());
+    quirc.end();
+
+    let offset = |p: &QuircPoint| {
+        QuircPoint {
+            x: p.x + rect.x as isize,
+            y: p.y + rect.y as isize,
+        }
+    };
+
+    let mut decoded = [false; QUIRC_MAX_GRIDS];
+    for (i, mut result) in quirc.results().enumerate() {
+        decoded[i] = result.data.is_ok();
+        let inside = result.corners.iter().all(|c| c.x >= 0 && c.y >= 0 && (c.x as usize) < rect.width && (c.y as usize) < rect.height);
+        for corner in &mut result.corners {
+            *corner = offset(corner);
+        }
+        result.code.corners = result.corners;
+
+        if (decoded[i] || inside) && around.map(|p| grid_contains_point(&grid_setup(&result.corners, result.code.size), result.code.size, p)).unwrap_or(true) {
+            merge(results, result);
+        }
+    }
+
+    if let Some(loose) = loose {
+        for cap in &quirc.capstones[..quirc.num_capstones] {
+            if cap.qr_grid < 0 || !decoded[cap.qr_grid as usize] {
+                loose.push((offset(&cap.center), distance_sq(&cap.corners[0], &cap.corners[1]).max(distance_sq(&cap.corners[0], &cap.corners[3]))));
+            }
+        }
+    }
+}
+
+/// Whether `p` is inside a code of `results` that decoded.
+fn decoded_at(results: &[ScanResult], p: &QuircPoint) -> bool {
+    results.iter().any(|r| r.data.is_ok() && grid_contains_point(&grid_setup(&r.corners, r.code.size), r.code.size, p))
+}
+
+/// Add `result` to `results` unless it's a code already there, replacing that if only `result` decoded.
+fn merge(results: &mut Vec<ScanResult>, result: ScanResult) {
+    let c = grid_setup(&result.corners, result.code.size);
+    let same = results.iter().position(|r| {
+        let rc = grid_setup(&r.corners, r.code.size);
+        grid_contains(&rc, r.code.size, &c, result.code.size) || grid_contains(&c, result.code.size, &rc, r.code.size)
+    });
+
+    match same {
+        Some(i) => {
+            if results[i].data.is_err() && result.data.is_ok() {
+                results[i] = result;
+            }
+        }
+        None => results.push(result),
+    }
+}
+
+/// Windows around triples of capstones that could be one code's, clipped to the `w`×`h` image.
+///
+/// `capstones` are centres and squared sides, bucketed by `buckets`. A triple could be a code's if two of its capstones are about as far from the third,
+/// at about right angles, and no further than a version 40 code's; its window covers the triple's centres
+/// and half their span again and two capstone sides around them: the fourth corner of the code, its quiet zone, and some context for the threshold.
+/// Windows come with the indices of their triples, smallest first.
+fn capstone_windows(capstones: &[(QuircPoint, i64)], buckets: &Buckets, w: usize, h: usize) -> Vec<(QuircRect, [usize; 3])> {
+    let mut windows = Vec::new();
+    let mut seen = BTreeSet::new();
+    let mut arms = Vec::new();
+    for (j, &(corner, side_sq)) in capstones.iter().enumerate() {
+        // The others that could be a code's next to it, in order
+        arms.clear();
+        arms.extend(buckets.near(&corner, isqrt(CODE_SIDES * CODE_SIDES * side_sq) + 1).filter(|&i| i != j).filter_map(|i| {
+            let (c, s) = capstones[i];
+            let d = distance_sq(&c, &corner);
+            if 4 * s > 9 * side_sq || 4 * side_sq > 9 * s || d > CODE_SIDES * CODE_SIDES * side_sq {
+                None
+            } else {
+                Some((i, (c.x - corner.x) as i128, (c.y - corner.y) as i128, d as i128))
+            }
+        }));
+        arms.sort_unstable_by_key(|&(i, ..)| i);
+
+        for (n, &(i, ax, ay, a)) in arms.iter().enumerate() {
+            for &(k, bx, by, b) in &arms[n + 1..] {
+                let dot = ax * bx + ay * by;
+                if 4 * a > 9 * b || 4 * b > 9 * a || 8 * dot * dot > a * b {
+                    continue;
+                }
+
+                let centres = [capstones[i].0, corner, capstones[k].0];
+                let left = centres.iter().map(|c| c.x).min().unwrap();
+                let top = centres.iter().map(|c| c.y).min().unwrap();
+                let right = centres.iter().map(|c| c.x).max().unwrap();
+                let bottom = centres.iter().map(|c| c.y).max().unwrap();
+                let side = isqrt(side_sq.max(capstones[i].1).max(capstones[k].1)) as isize;
+                let margin = (right - left).max(bottom - top) / 2 + 2 * side + 1;
+
+                let x = (left - margin).max(0) as usize;
+                let y = (top - margin).max(0) as usize;
+                let r = ((right + margin + 1).max(0) as usize).min(w);
+                let b = ((bottom + margin + 1).max(0) as usize).min(h);
+                let window = QuircRect {
+                    x,
+                    y,
+                    width: r.saturating_sub(x),
+                    height: b.saturating_sub(y),
+                };
+                if window.width > 0 && window.height > 0 && seen.insert(window) {
+                    windows.push((window, [i, j, k]));
+                }
+            }
+        }
+    }
+
+    windows.sort_by_key(|&(w, _)| w.width * w.height);
+    windows
+}
+
+/// Indices of points by the cell of a grid over the image they're in, so that those near a point can be found without looking at every one.
+///
+/// Points outside the image go in the cells at its edges.
+struct Buckets {
+    cell: (usize, usize),
+    columns: usize,
+    rows: usize,
+    indices: Vec<Vec<usize>>,
+}
+
+impl Buckets {
+    /// No points yet, in cells `cell` big over a `w`×`h` image.
+    fn new(w: usize, h: usize, cell: (usize, usize)) -> Buckets {
+        let cell = (cell.0.max(1), cell.1.max(1));
+        let (columns, rows) = (w.div_ceil(cell.0), h.div_ceil(cell.1));
+        Buckets {
+            cell,
+            columns: columns.max(1),
+            rows: rows.max(1),
+            indices: vec![Vec::new(); columns.max(1) * rows.max(1)],
+        }
+    }
+
+    /// The column and row of the cell coordinates `x` and `y` are in.
+    fn cell_of(&self, x: isize, y: isize) -> (usize, usize) {
+        ((x.max(0) as usize / self.cell.0).min(self.columns - 1), (y.max(0) as usize / self.cell.1).min(self.rows - 1))
+    }
+
+    fn insert(&mut self, p: &QuircPoint, index: usize) {
+        let (column, row) = self.cell_of(p.x, p.y);
+        self.indices[row * self.columns + column].push(index);
+    }
+
+    /// The indices of every point within `reach` of `p`, and of some further away, in no particular order.
+    fn near<'a>(&'a self, p: &QuircPoint, reach: i64) -> impl Iterator<Item = usize> + 'a {
+        let reach = reach as isize;
+        let (left, top) = self.cell_of(p.x.saturating_sub(reach), p.y.saturating_sub(reach));
+        let (right, bottom) = self.cell_of(p.x.saturating_add(reach), p.y.saturating_add(reach));
+
+        (top..=bottom).flat_map(move |row| (left..=right).flat_map(move |column| self.indices[row * self.columns + column].iter().cloned()))
+    }
+}
+
+fn distance_sq(a: &QuircPoint, b: &QuircPoint) -> i64 {
+    let (dx, dy) = ((a.x - b.x) as i64, (a.y - b.y) as i64);
+    dx * dx + dy * dy
+}
+
+/// ⌊√`n`⌋, by Newton's method, 0 for negative `n`.
+fn isqrt(n: i64) -> i64 {
+    if n < 2 {
+        return n.max(0);
+    }
+
+    // Starting above the root, every step is an overestimate until the root's reached
+    let mut r = n / 2 + 1;
+    loop {
+        let next = (r + n / r) / 2;
+        if next >= r {
+            return r;
+        }
+        r = next;
+    }
+}
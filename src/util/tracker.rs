@@ -0,0 +1,247 @@
+//! Frame-to-frame tracking
+
+
+use self::super::super::ops::tracker::{TrackerOptions, TrackedCode, Track, QuircTracker};
+use self::super::super::ops::{ExtractError, ScanResult, QuircPerspective, QuircPoint, QuircGrid, QuircRect, QuircCode, Decoder, Quirc};
+use self::super::identify::{Coord, grid_corners, grid_setup, grid_contains, fraction, halve, round, mul};
+use std::vec::Vec;
+
+
+/// The pixels a code may be found in next frame: the bounding box of its corners, widened by `margin`.
+pub fn track_window(corners: &[QuircPoint; 4], margin: usize) -> QuircRect {
+    let margin = margin as isize;
+    let left = corners.iter().map(|p| p.x).min().unwrap_or(0) - margin;
+    let top = corners.iter().map(|p| p.y).min().unwrap_or(0) - margin;
+    let right = corners.iter().map(|p| p.x).max().unwrap_or(0) + margin + 1;
+    let bottom = corners.iter().map(|p| p.y).max().unwrap_or(0) + margin + 1;
+
+    QuircRect {
+        x: left.max(0) as usize,
+        y: top.max(0) as usize,
+        width: (right - left.max(0)).max(0) as usize,
+        height: (bottom - top.max(0)).max(0) as usize,
+    }
+}
+
+
+impl QuircTracker {
+    /// A tracker which hasn't seen any frames yet.
+    pub fn new(options: TrackerOptions) -> QuircTracker {
+        QuircTracker {
+            quirc: Quirc::new(),
+            options,
+            tracks: Vec::new(),
+            next_id: 0,
+            frame: 0,
+            last_detection: 0,
+        }
+    }
+
+    /// Forget every code being followed, so that the next frame is searched in full and every code in it gets a new ID.
+    pub fn reset(&mut self) {
+        self.tracks.clear();
+    }
+
+    /// The recognizer holding the latest frame.
+    ///
+    /// Its grids are those of the last run of the full detector, which may have been some frames ago;
+    /// the tracker's own `extract()` and `cell_values()` give the codes `track()` returned.
+    pub fn quirc(&self) -> &Quirc {
+        &self.quirc
+    }
+
+    /// Extract the code specified by the given index, in the order the last `track()` returned them.
+    pub fn extract(&self, index: usize) -> Result<QuircCode, ExtractError> {
+        let track = self.tracks.get(index).ok_or(ExtractError {
+            index,
+            count: self.tracks.len(),
+        })?;
+        Ok(self.quirc.extract_grid(&track.c, track.grid_size))
+    }
+
+    /// `Quirc::cell_values()` for the code specified by the given index, in the order the last `track()` returned them.
+    pub fn cell_values(&self, index: usize) -> Result<Vec<i8>, ExtractError> {
+        let track = self.tracks.get(index).ok_or(ExtractError {
+            index,
+            count: self.tracks.len(),
+        })?;
+        Ok(self.quirc.grid_cell_values(&track.c, track.grid_size))
+    }
+
+    /// Find the codes in the next frame, a grayscale image `w` pixels wide and `h` high, one byte per pixel.
+    ///
+    /// A change of size forgets every code being followed.
+    ///
+    /// # Panics
+    ///
+    /// If `image` isn't `w * h` bytes long.
+    pub fn track(&mut self, image: &[u8], w: usize, h: usize) -> Vec<TrackedCode> {
+        if self.quirc.size() != (w, h) {
+            self.quirc.resize(w, h);
+            self.tracks.clear();
+        }
+        self.quirc.begin().copy_from_slice(image);
+
+        let margin = self.options.search_radius;
+        let mut detect = self.tracks.is_empty() || self.frame - self.last_detection >= self.options.detect_interval.max(1) as u64;
+        let mut followed = Vec::with_capacity(self.tracks.len());
+
+        if !detect {
+            let windows: Vec<_> = self.tracks.iter().map(|t| track_window(&grid_corners(&t.c, t.grid_size), margin + 4 * t.grid_size as usize / 21)).collect();
+            self.quirc.threshold_rois(&windows);
+
+            for track in &self.tracks {
+                match self.quirc.follow(track.grid_size, &track.c, &self.options) {
+                    Some(c) => followed.push((Track { c, ..*track }, false)),
+                    None => detect = true,
+                }
+            }
+        }
+
+        if detect {
+            self.quirc.begin();
+            self.quirc.end();
+            self.last_detection = self.frame;
+
+            let mut claimed = vec![false; self.tracks.len()];
+            followed.clear();
+            for grid in &self.quirc.grids[..self.quirc.num_grids] {
+                // A code the last frame had keeps its ID
+                let previous = (0..self.tracks.len())
+                    .find(|&t| !claimed[t] && grid_contains(&self.tracks[t].c, self.tracks[t].grid_size, &grid.c, grid.grid_size));
+                let track = match previous {
+                    Some(t) => {
+                        claimed[t] = true;
+                        Track { grid_size: grid.grid_size, c: grid.c, ..self.tracks[t] }
+                    }
+                    None => {
+                        self.next_id += 1;
+                        Track {
+                            id: self.next_id - 1,
+                            first_frame: self.frame,
+                            grid_size: grid.grid_size,
+                            c: grid.c,
+                        }
+                    }
+                };
+                followed.push((track, true));
+            }
+
+            // The detector can miss what's still there to be followed
+            for (t, track) in self.tracks.iter().enumerate() {
+                if !claimed[t] {
+                    if let Some(c) = self.quirc.follow(track.grid_size, &track.c, &self.options) {
+                        if !followed.iter().any(|(f, _)| grid_contains(&f.c, f.grid_size, &c, track.grid_size)) {
+                            followed.push((Track { c, ..*track }, false));
+                        }
+                    }
+                }
+            }
+        }
+
+        let frame = self.frame;
+        let mut decoder = Decoder::new();
+        let codes = followed.iter()
+            .map(|&(track, detected)| {
+                let code = self.quirc.extract_grid(&track.c, track.grid_size);
+                TrackedCode {
+                    id: track.id,
+                    age: frame - track.first_frame,
+                    detected,
+                    result: ScanResult {
+                        corners: code.corners,
+                        data: decoder.decode(&code),
+                        code,
+                        inferred: false,
+                    },
+                }
+            })
+            .collect();
+
+        self.tracks = followed.into_iter().map(|(track, _)| track).collect();
+        self.frame += 1;
+        codes
+    }
+}
+
+impl Quirc {
+    /// Refit a grid from the last frame to this one, searching up to `options.search_radius` pixels around where it was,
+    /// or `None` if it doesn't fit well enough anywhere.
+    ///
+    /// The area around the grid has to have been thresholded already.
+    fn follow(&self, grid_size: u32, c: &QuircPerspective, options: &TrackerOptions) -> Option<QuircPerspective> {
+        let corners = grid_corners(c, grid_size);
+        let side = corners.iter().zip(corners.iter().skip(1)).map(|(a, b)| (a.x - b.x).abs().max((a.y - b.y).abs())).max().unwrap_or(0);
+        let step = (side / grid_size as isize).max(1);
+        let reach = options.search_radius as isize / step;
+
+        // Fitted on the side, so that the recognizer's grids are left alone
+        let mut qr = QuircGrid {
+            caps: [0; 3],
+
+            align_region: -1,
+            align: QuircPoint { x: 0, y: 0 },
+
+            tpep: [QuircPoint { x: 0, y: 0 }; 3],
+            hscan: 0,
+            vscan: 0,
+
+            grid_size,
+            c: *c,
+
+            inferred: false,
+        };
+
+        // Try every offset a cell apart
+        let mut best = (self.fitness_grid(&qr), corners);
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let moved = corners.map(|p| QuircPoint { x: p.x + dx * step, y: p.y + dy * step });
+                qr.c = grid_setup(&moved, grid_size);
+
+                let fitness = self.fitness_grid(&qr);
+                if fitness > best.0 {
+                    best = (fitness, moved);
+                }
+            }
+        }
+
+        // Then turn and scale the grid about its centre too, in smaller and smaller steps, keeping its shape
+        let centre = (fraction(corners.iter().map(|p| p.x).sum(), 4), fraction(corners.iter().map(|p| p.y).sum(), 4));
+        let place = |shift: &[Coord; 4]| {
+            corners.map(|p| {
+                let (x, y) = (fraction(p.x, 1) - centre.0, fraction(p.y, 1) - centre.1);
+                QuircPoint {
+                    x: round(centre.0 + shift[0] + mul(shift[2], x) - mul(shift[3], y)),
+                    y: round(centre.1 + shift[1] + mul(shift[3], x) + mul(shift[2], y)),
+                }
+            })
+        };
+        let mut shift = [fraction(best.1[0].x - corners[0].x, 1), fraction(best.1[0].y - corners[0].y, 1), fraction(1, 1), fraction(0, 1)];
+        let mut steps = [fraction(step, 2), fraction(step, 2), fraction(1, grid_size as isize), fraction(1, grid_size as isize)];
+        for _ in 0..4 {
+            for i in 0..4 {
+                for &up in &[true, false] {
+                    let mut moved = shift;
+                    moved[i] = if up { shift[i] + steps[i] } else { shift[i] - steps[i] };
+                    qr.c = grid_setup(&place(&moved), grid_size);
+
+                    let fitness = self.fitness_grid(&qr);
+                    if fitness > best.0 {
+                        best = (fitness, place(&moved));
+                        shift = moved;
+                    }
+                }
+            }
+            steps = steps.map(halve);
+        }
+        qr.c = grid_setup(&best.1, grid_size);
+        self.jiggle_grid(&mut qr);
+
+        if self.fitness_grid(&qr) * 100 >= Quirc::fitness_max(grid_size) * options.min_fitness as i64 {
+            Some(qr.c)
+        } else {
+            None
+        }
+    }
+}
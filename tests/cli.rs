@@ -0,0 +1,154 @@
+//! The `quirc` binary, on the corpus and on rendered symbols.
+
+
+extern crate quirc_impl;
+
+mod common;
+
+use common::{corpus, scratch};
+use quirc_impl::ops::encode::{EncodeOptions, Segment, encode, encode_segments};
+use quirc_impl::ops::render::RenderOptions;
+use std::process::{Command, Output, Stdio};
+use std::io::Write;
+use std::fs;
+
+
+fn quirc(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_quirc")).args(args).output().unwrap()
+}
+
+fn stdout(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stdout).into_owned()
+}
+
+
+#[test]
+fn single_image() {
+    let out = quirc(&[corpus().join("upright-v1.pgm").to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(stdout(&out), "quirc\n");
+}
+
+#[test]
+fn directory_and_glob() {
+    let out = quirc(&[corpus().to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(stdout(&out).lines().count(), 6);
+    assert!(stdout(&out).contains("upright-v1.pgm: quirc\n"), "{}", stdout(&out));
+
+    let out = quirc(&[corpus().join("*-v[12].pgm").to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(stdout(&out).lines().map(|l| l.rsplit(": ").next().unwrap()).collect::<Vec<_>>(),
+               ["http://example.com/", "tiny", "quirc"]);
+
+    let out = quirc(&[corpus().parent().unwrap().join("**").join("upright-*.pgm").to_str().unwrap()]);
+    assert_eq!(stdout(&out), "quirc\n");
+}
+
+#[test]
+fn stdin_and_ppm() {
+    let code = encode(b"from stdin", &EncodeOptions::default()).unwrap();
+    let pgm = code.render_pgm(&RenderOptions::default());
+
+    // The same image as an ASCII PPM, in colour
+    let header_end = pgm.iter().enumerate().filter(|&(_, &b)| b == b'\n').nth(2).unwrap().0 + 1;
+    let header: Vec<_> = String::from_utf8_lossy(&pgm[..header_end]).split_whitespace().map(str::to_string).collect();
+    let mut ppm = format!("P3\n# rendered\n{} {}\n255\n", header[1], header[2]);
+    for &v in &pgm[header_end..] {
+        ppm.push_str(&format!("{} {} {}\n", v, v / 2, 255 - v / 4));
+    }
+
+    for image in &[pgm, ppm.into_bytes()] {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_quirc")).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+        child.stdin.take().unwrap().write_all(image).unwrap();
+        let out = child.wait_with_output().unwrap();
+        assert_eq!(out.status.code(), Some(0));
+        assert_eq!(stdout(&out), "from stdin\n");
+    }
+}
+
+#[test]
+fn json() {
+    let dir = scratch("cli-json");
+    let segments = [Segment::Eci(26), Segment::Numeric(b"0123"), Segment::Byte("\"ż\"".as_bytes())];
+    let code = encode_segments(&segments, &EncodeOptions { mask: Some(3), ..EncodeOptions::default() }).unwrap();
+    let path = dir.join("segments.pgm");
+    fs::write(&path, code.render_pgm(&RenderOptions::default())).unwrap();
+
+    let out = quirc(&["--json", path.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+    let json = stdout(&out);
+    assert!(json.starts_with(&format!("[{{\"file\": \"{}\", \"width\": 116, \"height\": 116, \"codes\": [{{\"corners\": [[16, 16], ", path.display())),
+            "{}",
+            json);
+    assert!(json.contains("\"size\": 21, \"version\": 1, \"ecc_level\": \"Q\", \"mask\": 3, \"data_type\": 4, \"eci\": 26, \"segments\": [\
+                           {\"mode\": \"eci\", \"eci\": 26}, {\"mode\": \"numeric\", \"data\": \"0123\", \"data_hex\": \"30313233\"}, \
+                           {\"mode\": \"byte\", \"data\": \"\\\"ż\\\"\", \
+                           \"data_hex\": \"22c5bc22\"}], \"payload\": \"0123\\\"ż\\\"\", \"payload_hex\": \"3031323322c5bc22\", \"error\": null}]}]\n"),
+            "{}",
+            json);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn first() {
+    let dir = scratch("cli-first");
+
+    // Two symbols side by side
+    let codes = [encode(b"left", &EncodeOptions::default()).unwrap(), encode(b"right", &EncodeOptions::default()).unwrap()];
+    let options = RenderOptions { module_size: 3, ..RenderOptions::default() };
+    let images: Vec<_> = codes.iter().map(|c| c.render_pgm(&options)).collect();
+    let side = 29 * 3;
+    let mut pgm = format!("P5\n{} {}\n255\n", side * 2, side).into_bytes();
+    for y in 0..side {
+        for image in &images {
+            let raster = &image[image.len() - side * side..];
+            pgm.extend_from_slice(&raster[y * side..(y + 1) * side]);
+        }
+    }
+    let path = dir.join("two.pgm");
+    fs::write(&path, pgm).unwrap();
+
+    let mut all: Vec<_> = stdout(&quirc(&["--all", path.to_str().unwrap()])).lines().map(str::to_string).collect();
+    all.sort();
+    assert_eq!(all, ["left", "right"]);
+    assert_eq!(stdout(&quirc(&["--first", path.to_str().unwrap()])).lines().count(), 1);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn exit_codes() {
+    let dir = scratch("cli-exit");
+    let blank = dir.join("blank.pgm");
+    let mut pgm = b"P5\n64 64\n255\n".to_vec();
+    pgm.resize(pgm.len() + 64 * 64, 0xff);
+    fs::write(&blank, pgm).unwrap();
+
+    assert_eq!(quirc(&[blank.to_str().unwrap()]).status.code(), Some(1));
+    assert_eq!(quirc(&["--bogus"]).status.code(), Some(2));
+    assert_eq!(quirc(&[dir.join("missing.pgm").to_str().unwrap()]).status.code(), Some(3));
+    assert_eq!(quirc(&[dir.join("*.jpg").to_str().unwrap()]).status.code(), Some(3));
+
+    // Unreadable inputs take precedence over images without codes
+    let out = quirc(&[blank.to_str().unwrap(), dir.join("missing.pgm").to_str().unwrap(), corpus().join("small-v2.pgm").to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(3));
+    assert!(stdout(&out).ends_with("small-v2.pgm: tiny\n"), "{}", stdout(&out));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn png() {
+    let dir = scratch("cli-png");
+    let path = dir.join("code.png");
+    fs::write(&path, encode(b"portable network graphics", &EncodeOptions::default()).unwrap().render_png(&RenderOptions::default())).unwrap();
+
+    let out = quirc(&[path.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(stdout(&out), "portable network graphics\n");
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,52 @@
+//! Helpers shared between the integration tests.
+//!
+//! Each test crate includes this with `mod common;` and uses only some of it.
+
+#![allow(dead_code)]
+
+
+use quirc_impl::ops::QuircCode;
+use quirc_impl::util::{FORMAT_MASK, FORMAT_XS, FORMAT_YS, correct_format};
+use std::path::{Path, PathBuf};
+use std::fs;
+
+
+/// The reference corpus in `tests/corpus/`.
+pub fn corpus() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("corpus")
+}
+
+/// A fresh directory for this test's files.
+pub fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("quirc-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Paint cell (`x`, `y`) of `code` black or white.
+pub fn set_cell(code: &mut QuircCode, x: u32, y: u32, black: bool) {
+    let i = (y * code.size + x) as usize;
+    if black {
+        code.cell_bitmap[i >> 3] |= 1 << (i & 7);
+    } else {
+        code.cell_bitmap[i >> 3] &= !(1 << (i & 7));
+    }
+}
+
+/// Overwrite both copies of the format information with a word too far from every valid one to be corrected.
+pub fn wreck_format(code: &mut QuircCode) {
+    let unreadable = (0..1u16 << 15).find(|&f| correct_format(&mut { f }).is_err()).unwrap();
+    let stored = unreadable ^ FORMAT_MASK;
+    let size = code.size;
+
+    for i in 0..15 {
+        set_cell(code, FORMAT_XS[i], FORMAT_YS[i], (stored >> i) & 1 == 1);
+    }
+    for i in 0..7 {
+        set_cell(code, 8, size - 1 - i, (stored >> (14 - i)) & 1 == 1);
+    }
+    for i in 0..8 {
+        set_cell(code, size - 8 + i, 8, (stored >> (7 - i)) & 1 == 1);
+    }
+}
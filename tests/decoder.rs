@@ -0,0 +1,83 @@
+//! A `Decoder` reused across codes decodes each of them exactly as a fresh one would,
+//! whatever it decoded, or failed to decode, before.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::ops::encode::{EncodeOptions, encode};
+use quirc_impl::ops::{Decoder, QuircCode};
+use quirc_impl::constants::{QUIRC_ECC_LEVEL_L, QUIRC_ECC_LEVEL_H};
+
+
+fn encoded(payload: &[u8], version: u32, ecc_level: u8) -> QuircCode {
+    encode(payload,
+           &EncodeOptions {
+               version: Some(version),
+               ecc_level: Some(ecc_level),
+               mask: None,
+           })
+        .unwrap()
+}
+
+/// Codes from largest to smallest, so that every one is read over what's left of a longer codestream.
+fn codes() -> Vec<QuircCode> {
+    let long: Vec<u8> = (0..2000u32).map(|i| (i * 31 + 7) as u8).collect();
+
+    vec![encoded(&long, 40, QUIRC_ECC_LEVEL_L),
+         encoded(b"0123456789012345678901234567890123456789", 10, QUIRC_ECC_LEVEL_H),
+         encoded(b"HELLO WORLD", 2, QUIRC_ECC_LEVEL_L),
+         encoded(b"a", 1, QUIRC_ECC_LEVEL_H)]
+}
+
+/// Overwrite everything below and right of the format information, leaving it readable.
+fn wrecked(mut code: QuircCode) -> QuircCode {
+    for y in 9..code.size {
+        for x in 9..code.size {
+            let i = (y * code.size + x) as usize;
+            if (x * 7 + y * 3) % 5 < 2 {
+                code.cell_bitmap[i >> 3] |= 1 << (i & 7);
+            } else {
+                code.cell_bitmap[i >> 3] &= !(1 << (i & 7));
+            }
+        }
+    }
+    code
+}
+
+
+#[test]
+fn reused_like_fresh() {
+    let mut decoder = Decoder::new();
+
+    for code in codes() {
+        let fresh = code.decode().unwrap();
+        assert_eq!(decoder.decode(&code).unwrap(), fresh);
+        assert_eq!(decoder.decode_recover_format(&code).unwrap(), fresh);
+    }
+}
+
+#[test]
+fn reused_after_failure() {
+    let mut decoder = Decoder::new();
+
+    for code in codes() {
+        let fresh = code.decode().unwrap();
+
+        assert!(decoder.decode(&wrecked(code)).is_err());
+        assert_eq!(decoder.decode(&code).unwrap(), fresh);
+    }
+}
+
+#[test]
+fn reused_in_any_order() {
+    let codes = codes();
+    let fresh: Vec<_> = codes.iter().map(|c| c.decode().unwrap()).collect();
+    let mut decoder = Decoder::new();
+
+    for round in 0..3 {
+        for i in 0..codes.len() {
+            let i = (i * 3 + round) % codes.len();
+            assert_eq!(decoder.decode(&codes[i]).unwrap(), fresh[i], "{} {}", round, i);
+        }
+    }
+}
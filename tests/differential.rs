@@ -0,0 +1,317 @@
+//! Feed the same images to the Rust translation and to the original C quirc in `quirc/` (built by `build.rs`),
+//! and compare the codes they find: their number, corners, cell bitmaps and decoded data.
+//!
+//! Each image that the two disagree on gets a report of where, printed before the test fails;
+//! run with `--nocapture` to see the agreeing ones too.
+//!
+//! The C library identifies with `f64` perspective transforms, so the corners and cells are only expected to match without `fixed-point`:
+//! with it, a cell centre on a pixel boundary can round the other way and tip a marginal code either way,
+//! so only the data decoded successfully is compared, exactly.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::ffi::{QuircDecodeError, FfiQuircPoint, FfiQuircCode, FfiQuircData};
+use quirc_impl::ops::scene::{ScenePlacement, SceneOptions, Scene};
+use quirc_impl::ops::{QuircCode, Quirc};
+use quirc_impl::constants::{QUIRC_MAX_BITMAP, QUIRC_MAX_PAYLOAD};
+use quirc_impl::util::SplitMix64;
+use std::os::raw::c_int;
+use std::path::Path;
+use std::fs;
+
+
+/// `struct quirc`, only ever behind a pointer.
+enum CQuirc {}
+
+extern "C" {
+    fn c_quirc_new() -> *mut CQuirc;
+    fn c_quirc_destroy(q: *mut CQuirc);
+    fn c_quirc_resize(q: *mut CQuirc, w: c_int, h: c_int) -> c_int;
+    fn c_quirc_begin(q: *mut CQuirc, w: *mut c_int, h: *mut c_int) -> *mut u8;
+    fn c_quirc_end(q: *mut CQuirc);
+    fn c_quirc_count(q: *const CQuirc) -> c_int;
+    fn c_quirc_extract(q: *const CQuirc, index: c_int, code: *mut FfiQuircCode);
+    fn c_quirc_decode(code: *const FfiQuircCode, data: *mut FfiQuircData) -> QuircDecodeError;
+}
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Decoded {
+    version: u32,
+    ecc_level: u8,
+    mask: u8,
+    data_type: u8,
+    eci: u32,
+    payload: Vec<u8>,
+}
+
+/// What either implementation found, in a form both convert to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Found {
+    corners: [(isize, isize); 4],
+    size: u32,
+    cell_bitmap: Vec<u8>,
+    data: Result<Decoded, QuircDecodeError>,
+}
+
+fn found_rust(code: &QuircCode) -> Found {
+    Found {
+        corners: [(code.corners[0].x, code.corners[0].y),
+                  (code.corners[1].x, code.corners[1].y),
+                  (code.corners[2].x, code.corners[2].y),
+                  (code.corners[3].x, code.corners[3].y)],
+        size: code.size,
+        cell_bitmap: code.cell_bitmap.to_vec(),
+        data: code.decode()
+            .map(|data| {
+                Decoded {
+                    version: data.version,
+                    ecc_level: data.ecc_level,
+                    mask: data.mask,
+                    data_type: data.data_type,
+                    eci: data.eci,
+                    payload: data.payload().to_vec(),
+                }
+            })
+            .map_err(QuircDecodeError::from),
+    }
+}
+
+fn found_c(code: &FfiQuircCode) -> Found {
+    let point = |p: &FfiQuircPoint| (p.x as isize, p.y as isize);
+
+    let mut data = Box::new(FfiQuircData {
+        version: 0,
+        ecc_level: 0,
+        mask: 0,
+        data_type: 0,
+        payload: [0; QUIRC_MAX_PAYLOAD],
+        payload_len: 0,
+        eci: 0,
+    });
+    let err = unsafe { c_quirc_decode(code, &mut *data) };
+
+    Found {
+        corners: [point(&code.corners[0]), point(&code.corners[1]), point(&code.corners[2]), point(&code.corners[3])],
+        size: code.size as u32,
+        cell_bitmap: code.cell_bitmap.to_vec(),
+        data: if err == QuircDecodeError::QuircSuccess {
+            Ok(Decoded {
+                version: data.version as u32,
+                ecc_level: data.ecc_level as u8,
+                mask: data.mask as u8,
+                data_type: data.data_type as u8,
+                eci: data.eci,
+                payload: data.payload[..data.payload_len as usize].to_vec(),
+            })
+        } else {
+            Err(err)
+        },
+    }
+}
+
+fn scan_rust(width: usize, height: usize, pixels: &[u8]) -> Vec<Found> {
+    let mut quirc = Quirc::new();
+    quirc.resize(width, height);
+    quirc.begin().copy_from_slice(pixels);
+    quirc.end();
+
+    (0..quirc.count()).map(|i| found_rust(&quirc.extract(i).unwrap())).collect()
+}
+
+fn scan_c(width: usize, height: usize, pixels: &[u8]) -> Vec<Found> {
+    unsafe {
+        let quirc = c_quirc_new();
+        assert!(!quirc.is_null());
+        assert_eq!(c_quirc_resize(quirc, width as c_int, height as c_int), 0);
+        std::slice::from_raw_parts_mut(c_quirc_begin(quirc, std::ptr::null_mut(), std::ptr::null_mut()), width * height).copy_from_slice(pixels);
+        c_quirc_end(quirc);
+
+        let found = (0..c_quirc_count(quirc))
+            .map(|i| {
+                let mut code = Box::new(FfiQuircCode {
+                    corners: [FfiQuircPoint { x: 0, y: 0 }; 4],
+                    size: 0,
+                    cell_bitmap: [0; QUIRC_MAX_BITMAP],
+                });
+                c_quirc_extract(quirc, i, &mut *code);
+                found_c(&code)
+            })
+            .collect();
+
+        c_quirc_destroy(quirc);
+        found
+    }
+}
+
+/// Where the two disagree on the code at `index`, one line each.
+#[cfg(not(feature = "fixed-point"))]
+fn diff_code(index: usize, rust: &Found, c: &Found, report: &mut Vec<String>) {
+    if rust.corners != c.corners {
+        report.push(format!("code {}: corners {:?} in Rust, {:?} in C", index, rust.corners, c.corners));
+    }
+    if rust.size != c.size {
+        report.push(format!("code {}: size {} in Rust, {} in C", index, rust.size, c.size));
+    }
+
+    let cells: Vec<_> = (0..rust.size.min(c.size) * rust.size.min(c.size))
+        .filter(|&i| (rust.cell_bitmap[(i >> 3) as usize] ^ c.cell_bitmap[(i >> 3) as usize]) & (1 << (i & 7)) != 0)
+        .map(|i| (i % rust.size, i / rust.size))
+        .collect();
+    if !cells.is_empty() {
+        report.push(format!("code {}: {} cells differ, first at {:?}", index, cells.len(), &cells[..cells.len().min(8)]));
+    }
+
+    if rust.data != c.data {
+        let summary = |data: &Result<Decoded, QuircDecodeError>| match *data {
+            Ok(ref d) => {
+                format!("v{} ecc {} mask {} type {} eci {} {:?}",
+                        d.version,
+                        d.ecc_level,
+                        d.mask,
+                        d.data_type,
+                        d.eci,
+                        String::from_utf8_lossy(&d.payload))
+            }
+            Err(e) => format!("{:?}", e),
+        };
+        report.push(format!("code {}: decoded {} in Rust, {} in C", index, summary(&rust.data), summary(&c.data)));
+    }
+}
+
+/// Where the two disagree on the data of the codes they found, in any order, leaving out those that failed to decode.
+#[cfg(feature = "fixed-point")]
+fn diff_decoded(rust: &[Found], c: &[Found], report: &mut Vec<String>) {
+    let decoded = |found: &[Found]| {
+        let mut data: Vec<_> = found.iter().filter_map(|f| f.data.clone().ok()).collect();
+        data.sort_by(|a, b| a.payload.cmp(&b.payload));
+        data
+    };
+    let payloads = |data: &[Decoded]| data.iter().map(|d| String::from_utf8_lossy(&d.payload).into_owned()).collect::<Vec<_>>();
+
+    let (rust, c) = (decoded(rust), decoded(c));
+    if rust != c {
+        report.push(format!("decoded {:?} in Rust, {:?} in C", payloads(&rust), payloads(&c)));
+    }
+}
+
+/// Scan the image with both, print what was found, and return where they disagree.
+fn compare(name: &str, width: usize, height: usize, pixels: &[u8]) -> Vec<String> {
+    let rust = scan_rust(width, height, pixels);
+    let c = scan_c(width, height, pixels);
+
+    let mut report = Vec::new();
+    #[cfg(not(feature = "fixed-point"))]
+    {
+        if rust.len() != c.len() {
+            report.push(format!("{} codes in Rust, {} in C", rust.len(), c.len()));
+        }
+        for (i, (r, c)) in rust.iter().zip(c.iter()).enumerate() {
+            diff_code(i, r, c, &mut report);
+        }
+    }
+    #[cfg(feature = "fixed-point")]
+    diff_decoded(&rust, &c, &mut report);
+
+    if report.is_empty() {
+        println!("{}: {} codes, {} decoded, agree", name, rust.len(), rust.iter().filter(|f| f.data.is_ok()).count());
+    } else {
+        println!("{}: DISAGREE", name);
+        for line in &report {
+            println!("  {}", line);
+        }
+    }
+    report
+}
+
+/// Fail, naming the images the two disagreed on, if any.
+fn check(reports: Vec<(String, Vec<String>)>) {
+    let disagreeing: Vec<_> = reports.into_iter().filter(|(_, r)| !r.is_empty()).map(|(name, _)| name).collect();
+    assert!(disagreeing.is_empty(), "Rust and C disagree on {:?}", disagreeing);
+}
+
+
+#[test]
+fn corpus() {
+    let mut images: Vec<_> = fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("corpus"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().map(|e| e == "pgm").unwrap_or(false))
+        .collect();
+    images.sort();
+    assert!(!images.is_empty());
+
+    check(images.into_iter()
+        .map(|path| {
+            // Binary PGM, maxval 255, as written by the corpus generator: header fields separated by single whitespace
+            let data = fs::read(&path).unwrap();
+            let header: Vec<_> = String::from_utf8_lossy(&data[..32]).split_whitespace().take(4).map(str::to_string).collect();
+            let (width, height): (usize, usize) = (header[1].parse().unwrap(), header[2].parse().unwrap());
+            let pixels = &data[data.len() - width * height..];
+
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let report = compare(&name, width, height, pixels);
+            (name, report)
+        })
+        .collect());
+}
+
+#[test]
+fn scenes() {
+    let mut rng = SplitMix64::new(0x5eed);
+
+    check((0..60)
+        .map(|i| {
+            // Odd sizes, so rows don't line up with anything
+            let width = 200 + (rng.next_u64() % 250) as usize;
+            let height = 200 + (rng.next_u64() % 250) as usize;
+
+            let placements: Vec<_> = (0..1 + rng.next_u64() % 3)
+                .map(|_| {
+                    let module_size = 2.0 + rng.next_f64() * 3.0;
+                    ScenePlacement {
+                        payload: format!("scene {} code {}", i, rng.next_u64()).into_bytes(),
+                        center: (40.0 + rng.next_f64() * (width as f64 - 80.0), 40.0 + rng.next_f64() * (height as f64 - 80.0)),
+                        module_size,
+                        rotation: rng.next_f64() * 360.0,
+                        tilt: (rng.next_f64() * 0.4 - 0.2, rng.next_f64() * 0.4 - 0.2),
+                        ..ScenePlacement::default()
+                    }
+                })
+                .collect();
+            let options = SceneOptions {
+                width,
+                height,
+                blur: rng.next_f64(),
+                illumination: rng.next_f64() * 0.6,
+                noise: rng.next_f64() * 15.0,
+                jpeg_quality: if rng.next_u64() & 1 == 0 { 0 } else { 20 + (rng.next_u64() % 60) as u8 },
+                seed: rng.next_u64(),
+                ..SceneOptions::default()
+            };
+            let scene = Scene::generate(&placements, &options).unwrap();
+
+            let name = format!("scene {} ({}x{}, {} codes)", i, width, height, placements.len());
+            let report = compare(&name, scene.width, scene.height, &scene.pixels);
+            (name, report)
+        })
+        .collect());
+}
+
+/// Pure noise fills the region and capstone tables, and exercises their limits.
+#[test]
+fn noise() {
+    let mut rng = SplitMix64::new(0x4015e);
+
+    check((0..8)
+        .map(|i| {
+            let (width, height) = (64 + 61 * i, 48 + 53 * i);
+            let pixels: Vec<u8> = (0..width * height).map(|_| rng.next_u64() as u8).collect();
+
+            let name = format!("noise {} ({}x{})", i, width, height);
+            let report = compare(&name, width, height, &pixels);
+            (name, report)
+        })
+        .collect());
+}
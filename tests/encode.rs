@@ -0,0 +1,395 @@
+//! Everything the encoder produces has to decode back to what went in.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::constants::{QUIRC_MAX_BITMAP, QUIRC_ECC_LEVEL_M, QUIRC_ECC_LEVEL_L, QUIRC_ECC_LEVEL_H, QUIRC_ECC_LEVEL_Q, QUIRC_DATA_TYPE_NUMERIC,
+                            QUIRC_DATA_TYPE_ALPHA, QUIRC_DATA_TYPE_BYTE, QUIRC_DATA_TYPE_KANJI, QUIRC_ECI_UTF_8};
+use quirc_impl::ops::encode::{EncodeOptions, Segment, encode, encode_text, encode_shift_jis, encode_segments, optimal_segments};
+use quirc_impl::ops::{DecodeErrorKind, EncodeError, MaskPenalty, QuircCode, QuircData, QuircPoint};
+use quirc_impl::util::{data_codewords, segment_chars, segment_bits};
+
+
+/// Deterministic filler, so failures are reproducible.
+fn bytes(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(2654435761).wrapping_add(1);
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+fn round_trip(segments: &[Segment], options: &EncodeOptions) -> QuircData {
+    let code = encode_segments(segments, options).unwrap();
+    let data = code.decode().unwrap_or_else(|e| panic!("{:?} with {:?}: {}", segments, options, e));
+
+    if let Some(version) = options.version {
+        assert_eq!(data.version, version);
+    }
+    if let Some(ecc_level) = options.ecc_level {
+        assert_eq!(data.ecc_level, ecc_level);
+    }
+    if let Some(mask) = options.mask {
+        assert_eq!(data.mask, mask);
+    }
+
+    data
+}
+
+
+#[test]
+fn bytes_fill_every_version_and_ecc_level() {
+    for version in 1..41 {
+        for &ecc_level in &[QUIRC_ECC_LEVEL_M, QUIRC_ECC_LEVEL_L, QUIRC_ECC_LEVEL_H, QUIRC_ECC_LEVEL_Q] {
+            // Mode and 16-bit count take up 20 bits
+            let count_bytes = if version < 10 { 2 } else { 3 };
+            let payload = bytes(data_codewords(version, ecc_level) - count_bytes, version * 4 + ecc_level as u32);
+            let options = EncodeOptions {
+                version: Some(version),
+                ecc_level: Some(ecc_level),
+                mask: Some((version % 8) as u8),
+            };
+
+            let data = round_trip(&[Segment::Byte(&payload)], &options);
+            assert_eq!(data.payload(), &payload[..], "version {}, ECC level {}", version, ecc_level);
+            assert_eq!(data.data_type, QUIRC_DATA_TYPE_BYTE);
+
+            let one_more = bytes(payload.len() + 1, 0);
+            assert_eq!(encode_segments(&[Segment::Byte(&one_more)], &options).unwrap_err(),
+                       EncodeError::DataTooLong { version, ecc_level });
+        }
+    }
+}
+
+#[test]
+fn every_mask() {
+    for mask in 0..8 {
+        for &version in &[3, 7, 22] {
+            let options = EncodeOptions {
+                version: Some(version),
+                ecc_level: Some(QUIRC_ECC_LEVEL_Q),
+                mask: Some(mask),
+            };
+
+            let data = round_trip(&[Segment::Byte(b"https://github.com/dlbeer/quirc")], &options);
+            assert_eq!(data.payload(), b"https://github.com/dlbeer/quirc");
+        }
+    }
+}
+
+#[test]
+fn single_segment_modes() {
+    for &(payload, data_type) in &[(&b"01234567890123456789"[..], QUIRC_DATA_TYPE_NUMERIC),
+                                   (&b"0"[..], QUIRC_DATA_TYPE_NUMERIC),
+                                   (&b"01"[..], QUIRC_DATA_TYPE_NUMERIC),
+                                   (&b"HELLO WORLD $%*+-./:"[..], QUIRC_DATA_TYPE_ALPHA),
+                                   (&b"A"[..], QUIRC_DATA_TYPE_ALPHA),
+                                   (&b"Hello, world!"[..], QUIRC_DATA_TYPE_BYTE),
+                                   (&b""[..], QUIRC_DATA_TYPE_NUMERIC)] {
+        let data = encode(payload, &EncodeOptions::default()).unwrap().decode().unwrap();
+        assert_eq!(data.payload(), payload);
+        if !payload.is_empty() {
+            assert_eq!(data.data_type, data_type, "{:?}", payload);
+        }
+    }
+}
+
+#[test]
+fn kanji() {
+    // "点茗" and the extremes of both ranges
+    let payload = b"\x93\x5f\xe4\xaa\x81\x40\x9f\xfc\xe0\x40\xeb\xbf";
+    let data = round_trip(&[Segment::Kanji(payload)], &EncodeOptions::default());
+
+    assert_eq!(data.payload(), &payload[..]);
+    assert_eq!(data.data_type, QUIRC_DATA_TYPE_KANJI);
+}
+
+#[test]
+fn mixed_segments_and_eci() {
+    for &eci in &[QUIRC_ECI_UTF_8 as u32, 127, 128, 16383, 16384, 999999] {
+        let segments = [Segment::Eci(eci),
+                        Segment::Numeric(b"12345"),
+                        Segment::Alpha(b"ABC"),
+                        Segment::Byte("żółć".as_bytes()),
+                        Segment::Kanji(b"\x93\x5f")];
+        let data = round_trip(&segments, &EncodeOptions::default());
+
+        assert_eq!(data.eci, eci);
+        assert_eq!(data.payload(), &b"12345ABC\xc5\xbc\xc3\xb3\xc5\x82\xc4\x87\x93\x5f"[..]);
+        assert_eq!(data.data_type, QUIRC_DATA_TYPE_KANJI);
+        assert_eq!(data.segments(), segments);
+    }
+}
+
+#[test]
+fn automatic_version_and_ecc_level() {
+    let data = encode(b"HELLO", &EncodeOptions::default()).unwrap().decode().unwrap();
+    assert_eq!((data.version, data.ecc_level), (1, QUIRC_ECC_LEVEL_H));
+
+    // 14 bytes only fit in version 1 at M, and 16 at L
+    let data = encode(&bytes(14, 1), &EncodeOptions::default()).unwrap().decode().unwrap();
+    assert_eq!((data.version, data.ecc_level), (1, QUIRC_ECC_LEVEL_M));
+    let data = encode(&bytes(16, 1), &EncodeOptions::default()).unwrap().decode().unwrap();
+    assert_eq!((data.version, data.ecc_level), (2, QUIRC_ECC_LEVEL_Q));
+
+    // Version 40 holds 2331 bytes at M and 2953 at L
+    let data = encode(&bytes(2953, 2), &EncodeOptions::default()).unwrap().decode().unwrap();
+    assert_eq!((data.version, data.ecc_level), (40, QUIRC_ECC_LEVEL_L));
+    assert_eq!(encode(&bytes(2954, 2), &EncodeOptions::default()).unwrap_err(),
+               EncodeError::DataTooLong {
+                   version: 40,
+                   ecc_level: QUIRC_ECC_LEVEL_L,
+               });
+
+    let data = encode(b"HELLO",
+                      &EncodeOptions {
+                          ecc_level: Some(QUIRC_ECC_LEVEL_L),
+                          ..EncodeOptions::default()
+                      })
+        .unwrap()
+        .decode()
+        .unwrap();
+    assert_eq!((data.version, data.ecc_level), (1, QUIRC_ECC_LEVEL_L));
+}
+
+#[test]
+fn invalid_input() {
+    let invalid = |segments: &[Segment], options| encode_segments(segments, &options).unwrap_err();
+
+    assert_eq!(invalid(&[],
+                       EncodeOptions {
+                           version: Some(0),
+                           ..EncodeOptions::default()
+                       }),
+               EncodeError::InvalidVersion(0));
+    assert_eq!(invalid(&[],
+                       EncodeOptions {
+                           version: Some(41),
+                           ..EncodeOptions::default()
+                       }),
+               EncodeError::InvalidVersion(41));
+    assert_eq!(invalid(&[],
+                       EncodeOptions {
+                           ecc_level: Some(4),
+                           ..EncodeOptions::default()
+                       }),
+               EncodeError::InvalidEccLevel(4));
+    assert_eq!(invalid(&[],
+                       EncodeOptions {
+                           mask: Some(8),
+                           ..EncodeOptions::default()
+                       }),
+               EncodeError::InvalidMask(8));
+
+    assert_eq!(invalid(&[Segment::Eci(1000000)], EncodeOptions::default()), EncodeError::InvalidEci(1000000));
+    assert_eq!(invalid(&[Segment::Byte(b"ok"), Segment::Numeric(b"12a4")], EncodeOptions::default()),
+               EncodeError::InvalidCharacter {
+                   segment: 1,
+                   offset: 2,
+               });
+    assert_eq!(invalid(&[Segment::Alpha(b"abc")], EncodeOptions::default()),
+               EncodeError::InvalidCharacter {
+                   segment: 0,
+                   offset: 0,
+               });
+    assert_eq!(invalid(&[Segment::Kanji(b"\x93\x5f\x93")], EncodeOptions::default()),
+               EncodeError::InvalidCharacter {
+                   segment: 0,
+                   offset: 2,
+               });
+    assert_eq!(invalid(&[Segment::Kanji(b"\xa0\x40")], EncodeOptions::default()),
+               EncodeError::InvalidCharacter {
+                   segment: 0,
+                   offset: 0,
+               });
+}
+
+
+/// The fewest bits any split of `data` into valid segments takes,
+/// by trying every segment starting at each position rather than going character by character.
+fn brute_force_bits(data: &[u8], version: u32, kanji: bool) -> usize {
+    let mut best = vec![usize::MAX; data.len() + 1];
+    best[data.len()] = 0;
+
+    for start in (0..data.len()).rev() {
+        for end in start + 1..data.len() + 1 {
+            let piece = &data[start..end];
+            let mut candidates = vec![Segment::Numeric(piece), Segment::Alpha(piece), Segment::Byte(piece)];
+            if kanji {
+                candidates.push(Segment::Kanji(piece));
+            }
+
+            for segment in candidates {
+                if segment_chars(&segment, 0).is_ok() && best[end] != usize::MAX {
+                    best[start] = best[start].min(segment_bits(&segment, version).unwrap() + best[end]);
+                }
+            }
+        }
+    }
+
+    best[0]
+}
+
+#[test]
+fn segmentation_is_optimal() {
+    for &data in &[&b"ABC-123"[..],
+                   b"a1234567b",
+                   b"12AB34CD5678",
+                   b"0000A0000a",
+                   b"HTTP://X.Y/1234",
+                   b"\x93\x5f12\x93\x5fA\xe4",
+                   b"\x81\x40\x81\x40\x81"] {
+        for &version in &[1, 10, 27] {
+            for &kanji in &[false, true] {
+                let segments = optimal_segments(data, version, kanji);
+                let bits: usize = segments.iter().map(|s| segment_bits(s, version).unwrap()).sum();
+                assert_eq!(bits, brute_force_bits(data, version, kanji), "{:?} in version {} ({:?})", data, version, segments);
+
+                let payload: Vec<u8> = segments.iter()
+                    .flat_map(|s| match *s {
+                        Segment::Numeric(d) | Segment::Alpha(d) | Segment::Byte(d) | Segment::Kanji(d) => d.to_vec(),
+                        Segment::Eci(_) => vec![],
+                    })
+                    .collect();
+                assert_eq!(payload, data);
+            }
+        }
+    }
+}
+
+#[test]
+fn mixed_content_text() {
+    let text = "ABC-123 üñí 漢字 0123456789012345678901234567890123456789";
+    let code = encode_text(text, &EncodeOptions::default()).unwrap();
+    let data = code.decode().unwrap();
+    assert_eq!(data.payload(), text.as_bytes());
+    assert_eq!(data.eci, QUIRC_ECI_UTF_8 as u32);
+
+    let single = encode_segments(&[Segment::Eci(QUIRC_ECI_UTF_8 as u32), Segment::Byte(text.as_bytes())], &EncodeOptions::default()).unwrap();
+    assert!(code.size < single.size, "{} vs {}", code.size, single.size);
+
+    let data = encode_text("plain ASCII", &EncodeOptions::default()).unwrap().decode().unwrap();
+    assert_eq!(data.payload(), b"plain ASCII");
+    assert_eq!(data.eci, 0);
+}
+
+#[test]
+fn shift_jis_uses_kanji_mode() {
+    // "漢字 QR 2024"
+    let text = b"\x8a\xbf\x8e\x9a QR 2024";
+    assert_eq!(optimal_segments(text, 1, true)[0], Segment::Kanji(b"\x8a\xbf\x8e\x9a"));
+    assert!(!optimal_segments(text, 1, false).iter().any(|s| matches!(*s, Segment::Kanji(_))));
+
+    let data = encode_shift_jis(text, &EncodeOptions::default()).unwrap().decode().unwrap();
+    assert_eq!(data.payload(), &text[..]);
+    assert_eq!(data.data_type, QUIRC_DATA_TYPE_KANJI);
+}
+
+/// No symbol holds more characters than the count fields do, but the segments should be valid anyway.
+#[test]
+fn long_segments_are_split() {
+    // Versions 10-26 count at most 4095 digits in one segment
+    let digits: Vec<u8> = bytes(4500, 3).into_iter().map(|b| b'0' + b % 10).collect();
+    let segments = optimal_segments(&digits, 26, false);
+    assert_eq!(segments, [Segment::Numeric(&digits[..4095]), Segment::Numeric(&digits[4095..])]);
+
+    assert_eq!(encode_segments(&segments,
+                               &EncodeOptions {
+                                   version: Some(26),
+                                   ..EncodeOptions::default()
+                               })
+                   .unwrap_err(),
+               EncodeError::DataTooLong {
+                   version: 26,
+                   ecc_level: QUIRC_ECC_LEVEL_L,
+               });
+
+    let data = encode(&digits, &EncodeOptions::default()).unwrap().decode().unwrap();
+    assert_eq!(data.payload(), &digits[..]);
+}
+
+
+#[test]
+fn mask_penalty_of_blank_symbol() {
+    let code = QuircCode {
+        corners: [QuircPoint { x: 0, y: 0 }; 4],
+        size: 21,
+        cell_bitmap: [0; QUIRC_MAX_BITMAP],
+    };
+
+    // Every row and column is one run of 21, every 2x2 block matches, and there are no dark cells at all
+    assert_eq!(code.mask_penalty(),
+               MaskPenalty {
+                   n1: 42 * (3 + 16),
+                   n2: 20 * 20 * 3,
+                   n3: 0,
+                   n4: 100,
+               });
+}
+
+#[test]
+fn mask_penalty_of_empty_symbol() {
+    let code = QuircCode {
+        corners: [QuircPoint { x: 0, y: 0 }; 4],
+        size: 0,
+        cell_bitmap: [0; QUIRC_MAX_BITMAP],
+    };
+
+    assert_eq!(code.mask_penalty(), MaskPenalty::default());
+}
+
+#[test]
+fn mask_penalties_of_invalid_grid_size() {
+    let mut code = encode(b"HELLO WORLD", &EncodeOptions::default()).unwrap();
+    let data = code.decode().unwrap();
+
+    code.size += 1;
+    assert_eq!(code.mask_penalties(&data).unwrap_err().kind, DecodeErrorKind::InvalidGridSize);
+}
+
+/// The example symbol in ISO/IEC 18004 Annex I is "01234567" at 1-M, with mask 2.
+#[test]
+fn automatic_mask_matches_iso_example() {
+    let options = EncodeOptions {
+        version: Some(1),
+        ecc_level: Some(QUIRC_ECC_LEVEL_M),
+        mask: None,
+    };
+    let data = round_trip(&[Segment::Numeric(b"01234567")], &options);
+    assert_eq!(data.mask, 2);
+}
+
+#[test]
+fn mask_penalties_of_decoded_symbols() {
+    for &(payload, version) in &[(&b"HELLO WORLD"[..], 1), (b"https://github.com/dlbeer/quirc", 4), (b"0123456789", 7)] {
+        let automatic = encode(payload,
+                               &EncodeOptions {
+                                   version: Some(version),
+                                   ..EncodeOptions::default()
+                               })
+            .unwrap();
+        let data = automatic.decode().unwrap();
+        let penalties = automatic.mask_penalties(&data).unwrap();
+
+        assert_eq!(penalties[data.mask as usize], automatic.mask_penalty());
+        let best = penalties.iter().map(MaskPenalty::total).min().unwrap();
+        assert_eq!(penalties[data.mask as usize].total(), best);
+        assert_eq!(penalties.iter().position(|p| p.total() == best), Some(data.mask as usize));
+
+        // Remasking a decoded symbol scores the same as encoding with that mask
+        for mask in 0..8 {
+            let code = encode(payload,
+                              &EncodeOptions {
+                                  version: Some(version),
+                                  ecc_level: Some(data.ecc_level),
+                                  mask: Some(mask),
+                              })
+                .unwrap();
+            assert_eq!(code.mask_penalty(), penalties[mask as usize], "{:?} with mask {}", payload, mask);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Errors say what failed and what was known about the code by then,
+//! and the C interface leaves the data where C quirc would for each of them.
+
+
+extern crate quirc_impl;
+
+mod common;
+
+use common::{set_cell, wreck_format};
+use quirc_impl::ffi::{QuircDecodeError, FfiQuircCode, FfiQuircData, quirc_decode};
+use quirc_impl::ops::{DecodeErrorKind, DecodeError, ExtractError, QuircCode, QuircData, Quirc};
+use quirc_impl::ops::encode::{EncodeOptions, encode};
+use quirc_impl::constants::QUIRC_MAX_PAYLOAD;
+use std::convert::TryFrom;
+
+
+static PAYLOAD: &[u8] = b"Errors in context";
+
+
+/// Version 4, ECC level 2, mask 5.
+fn encoded() -> QuircCode {
+    encode(PAYLOAD,
+           &EncodeOptions {
+               version: Some(4),
+               ecc_level: Some(2),
+               mask: Some(5),
+           })
+        .unwrap()
+}
+
+/// Overwrite the data region, leaving the format information readable.
+fn wreck_data(code: &mut QuircCode) {
+    for y in 9..code.size - 9 {
+        for x in 9..code.size - 9 {
+            set_cell(code, x, y, (x * 7 + y * 3) % 5 < 2);
+        }
+    }
+}
+
+/// A `quirc_data` with every field set to something decoding never produces.
+fn poisoned_data() -> Box<FfiQuircData> {
+    Box::new(FfiQuircData {
+        version: -7,
+        ecc_level: -7,
+        mask: -7,
+        data_type: -7,
+        payload: [0xA5; QUIRC_MAX_PAYLOAD],
+        payload_len: -7,
+        eci: 0xA5A5_A5A5,
+    })
+}
+
+fn ffi_decode(code: &FfiQuircCode) -> (QuircDecodeError, Box<FfiQuircData>) {
+    let mut data = poisoned_data();
+    let err = unsafe { quirc_decode(code, &mut *data) };
+    (err, data)
+}
+
+
+#[test]
+fn descriptions() {
+    assert_eq!(DecodeErrorKind::InvalidGridSize.to_string(), "Invalid grid size");
+    assert_eq!(DecodeErrorKind::InvalidVersion.to_string(), "Invalid version");
+    assert_eq!(DecodeErrorKind::FormatEcc.to_string(), "Format data ECC failure");
+    assert_eq!(DecodeErrorKind::DataEcc.to_string(), "ECC failure");
+    assert_eq!(DecodeErrorKind::UnknownDataType.to_string(), "Unknown data type");
+    assert_eq!(DecodeErrorKind::DataOverflow.to_string(), "Data overflow");
+    assert_eq!(DecodeErrorKind::DataUnderflow.to_string(), "Data underflow");
+
+    assert_eq!(DecodeError::new(DecodeErrorKind::DataUnderflow).to_string(), "Data underflow");
+    assert_eq!(DecodeError {
+                       version: Some(3),
+                       format_bits: [Some(0x1234), None],
+                       block: Some(1),
+                       ..DecodeErrorKind::DataEcc.into()
+                   }
+                   .to_string(),
+               "ECC failure in block 1 (version 3, format bits 0x1234)");
+    assert_eq!(DecodeError {
+                       version: Some(40),
+                       format_bits: [Some(0x0001), Some(0x7fff)],
+                       ..DecodeErrorKind::FormatEcc.into()
+                   }
+                   .to_string(),
+               "Format data ECC failure (version 40, format bits 0x0001/0x7fff)");
+
+    assert_eq!(ExtractError { index: 2, count: 1 }.to_string(), "No code at index 2, only 1 identified");
+}
+
+#[test]
+fn grid_size_context() {
+    let mut code = encoded();
+
+    code.size = 22;
+    assert_eq!(code.decode().unwrap_err(), DecodeErrorKind::InvalidGridSize.into());
+
+    code.size = 13;
+    assert_eq!(code.decode().unwrap_err(), DecodeErrorKind::InvalidGridSize.into());
+
+    code.size = 17;
+    assert_eq!(code.decode().unwrap_err(),
+               DecodeError {
+                   version: Some(0),
+                   ..DecodeErrorKind::InvalidVersion.into()
+               });
+}
+
+#[test]
+fn format_context() {
+    let mut code = encoded();
+    wreck_format(&mut code);
+
+    let err = code.decode().unwrap_err();
+    assert_eq!(err.kind, DecodeErrorKind::FormatEcc);
+    assert_eq!(err.version, Some(4));
+    assert!(err.format_bits[0].is_some() && err.format_bits[1].is_some(), "{:?}", err);
+    assert_eq!(err.block, None);
+}
+
+#[test]
+fn data_context() {
+    let mut code = encoded();
+    wreck_data(&mut code);
+
+    let err = code.decode().unwrap_err();
+    assert_eq!(err.kind, DecodeErrorKind::DataEcc);
+    assert_eq!(err.version, Some(4));
+    // The first copy reads fine, so the second isn't tried
+    assert!(err.format_bits[0].is_some() && err.format_bits[1].is_none(), "{:?}", err);
+    assert!(err.block.is_some(), "{:?}", err);
+}
+
+#[test]
+fn extract_out_of_range() {
+    let mut quirc = Quirc::new();
+    quirc.resize(64, 64);
+    for pixel in quirc.begin() {
+        *pixel = 0xFF;
+    }
+    quirc.end();
+
+    assert_eq!(quirc.count(), 0);
+    assert_eq!(quirc.extract(0).unwrap_err(), ExtractError { index: 0, count: 0 });
+    assert_eq!(quirc.extract(5).unwrap_err(), ExtractError { index: 5, count: 0 });
+}
+
+#[test]
+fn ffi_success() {
+    let (err, data) = ffi_decode(&encoded().into());
+
+    assert_eq!(err, QuircDecodeError::QuircSuccess);
+    assert_eq!((data.version, data.ecc_level, data.mask), (4, 2, 5));
+    assert_eq!(&data.payload[..data.payload_len as usize], PAYLOAD);
+    assert_eq!(data.payload[data.payload_len as usize], 0);
+}
+
+/// C quirc returns before touching the data at all.
+#[test]
+fn ffi_invalid_grid_size() {
+    let mut code: FfiQuircCode = encoded().into();
+    code.size = 22;
+    let (err, data) = ffi_decode(&code);
+
+    assert_eq!(err, QuircDecodeError::QuircErrorInvalidGridSize);
+    assert_eq!((data.version, data.ecc_level, data.mask, data.data_type, data.payload_len, data.eci), (-7, -7, -7, -7, -7, 0xA5A5_A5A5));
+    assert!(data.payload.iter().all(|&b| b == 0xA5));
+}
+
+/// C quirc zeroes the data, and sets the version, however out of range.
+#[test]
+fn ffi_invalid_version() {
+    let mut code: FfiQuircCode = encoded().into();
+    code.size = 13;
+    let (err, data) = ffi_decode(&code);
+
+    assert_eq!(err, QuircDecodeError::QuircErrorInvalidVersion);
+    assert_eq!((data.version, data.ecc_level, data.mask, data.data_type, data.payload_len, data.eci), (-1, 0, 0, 0, 0, 0));
+    assert!(data.payload.iter().all(|&b| b == 0));
+}
+
+#[test]
+fn ffi_format_ecc() {
+    let mut code = encoded();
+    wreck_format(&mut code);
+    let (err, data) = ffi_decode(&code.into());
+
+    assert_eq!(err, QuircDecodeError::QuircErrorFormatEcc);
+    assert_eq!((data.version, data.ecc_level, data.mask, data.data_type, data.payload_len, data.eci), (4, 0, 0, 0, 0, 0));
+}
+
+/// The format information was read before the data failed to correct, so C quirc leaves it filled in.
+#[test]
+fn ffi_data_ecc() {
+    let mut code = encoded();
+    wreck_data(&mut code);
+    let (err, data) = ffi_decode(&code.into());
+
+    assert_eq!(err, QuircDecodeError::QuircErrorDataEcc);
+    assert_eq!((data.version, data.ecc_level, data.mask, data.data_type, data.payload_len, data.eci), (4, 2, 5, 0, 0, 0));
+    assert!(data.payload.iter().all(|&b| b == 0));
+}
+
+/// Decoded payloads always leave space for the nul terminator, so they convert both ways.
+#[test]
+fn ffi_data_round_trip() {
+    let data = encoded().decode().unwrap();
+    let ffi_data = FfiQuircData::try_from(data.clone()).unwrap();
+
+    assert_eq!(&ffi_data.payload[..ffi_data.payload_len as usize], PAYLOAD);
+    assert_eq!(QuircData::from(ffi_data).payload(), data.payload());
+}
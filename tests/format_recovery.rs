@@ -0,0 +1,123 @@
+//! Codes whose both copies of the format information are beyond repair have to be recovered by trying every ECC level and mask,
+//! and only with the combination they were encoded with.
+
+
+extern crate quirc_impl;
+
+mod common;
+
+use common::{set_cell, wreck_format};
+use quirc_impl::util::{Datastream, codestream_syndrome_weight, padding_consistent};
+use quirc_impl::ops::{DecodeErrorKind, QuircFormatRecovery, QuircCode};
+use quirc_impl::constants::QUIRC_ECC_LEVEL_M;
+use quirc_impl::ops::encode::{EncodeOptions, encode};
+
+
+static PAYLOAD: &[u8] = b"Format information recovery";
+
+/// The version 1-M block of "HELLO WORLD" in alphanumeric mode: 16 data bytes followed by 10 parity bytes.
+static HELLO_WORLD: [u8; 26] = [32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17, 196, 35, 39, 119, 235, 215, 231, 226, 93, 23];
+
+
+fn encoded(ecc_level: u8, mask: u8) -> QuircCode {
+    encode(PAYLOAD,
+           &EncodeOptions {
+               version: Some(4),
+               ecc_level: Some(ecc_level),
+               mask: Some(mask),
+           })
+        .unwrap()
+}
+
+fn datastream(bytes: &[u8], ptr: u32) -> Datastream<Box<[u8]>> {
+    let mut ds = Datastream::new();
+    ds.data[..bytes.len()].copy_from_slice(bytes);
+    ds.data_bits = bytes.len() as u32 * 8;
+    ds.ptr = ptr;
+    ds
+}
+
+
+#[test]
+fn every_combination_recovered() {
+    for ecc_level in 0..4 {
+        for mask in 0..8 {
+            let mut code = encoded(ecc_level, mask);
+            wreck_format(&mut code);
+
+            assert_eq!(code.decode().unwrap_err().kind, DecodeErrorKind::FormatEcc, "{} {}", ecc_level, mask);
+
+            let data = code.decode_recover_format().unwrap_or_else(|e| panic!("{} {}: {}", ecc_level, mask, e));
+            assert_eq!(data.payload(), PAYLOAD, "{} {}", ecc_level, mask);
+            assert_eq!((data.ecc_level, data.mask), (ecc_level, mask));
+            assert_eq!(data.format_recovery,
+                       Some(QuircFormatRecovery {
+                           ecc_level,
+                           mask,
+                           confident: true,
+                       }));
+        }
+    }
+}
+
+#[test]
+fn readable_format_not_recovered() {
+    let data = encoded(0, 5).decode_recover_format().unwrap();
+    assert_eq!(data.payload(), PAYLOAD);
+    assert_eq!(data.format_recovery, None);
+}
+
+/// With the data region wrecked too, no combination corrects, so none is accepted.
+#[test]
+fn uncorrectable_candidates_rejected() {
+    let mut code = encoded(1, 2);
+    wreck_format(&mut code);
+    for y in 9..code.size - 9 {
+        for x in 9..code.size - 9 {
+            set_cell(&mut code, x, y, (x * 7 + y * 3) % 5 < 2);
+        }
+    }
+
+    assert_eq!(code.decode_recover_format().unwrap_err().kind, DecodeErrorKind::FormatEcc);
+}
+
+/// Candidates are ranked by how far their codestream is from correct.
+#[test]
+fn syndrome_weight() {
+    let data = encode(b"HELLO WORLD",
+                      &EncodeOptions {
+                          version: Some(1),
+                          ecc_level: Some(QUIRC_ECC_LEVEL_M),
+                          mask: None,
+                      })
+        .unwrap()
+        .decode()
+        .unwrap();
+
+    let mut ds = Datastream::new();
+    ds.raw[..HELLO_WORLD.len()].copy_from_slice(&HELLO_WORLD);
+    assert_eq!(codestream_syndrome_weight(&data, &ds), 0);
+
+    ds.raw[3] ^= 0x10;
+    ds.raw[20] ^= 0x81;
+    let weight = codestream_syndrome_weight(&data, &ds);
+    assert!(weight > 0 && weight <= HELLO_WORLD.len() - 16, "{}", weight);
+}
+
+#[test]
+fn padding() {
+    // Payload, terminator, then pad codewords
+    assert!(padding_consistent(&datastream(&[0x40, 0xec, 0x11, 0xec], 8)));
+    // Terminator and zero bits up to the codeword boundary
+    assert!(padding_consistent(&datastream(&[0x40, 0xec], 6)));
+    // No room for a whole terminator
+    assert!(padding_consistent(&datastream(&[0x40, 0x00], 14)));
+
+    // Terminator not zero
+    assert!(!padding_consistent(&datastream(&[0x48, 0xec], 8)));
+    // Bits set before the codeword boundary
+    assert!(!padding_consistent(&datastream(&[0x41, 0xec], 6)));
+    // Pad codewords out of order
+    assert!(!padding_consistent(&datastream(&[0x40, 0x11, 0xec], 8)));
+    assert!(!padding_consistent(&datastream(&[0x40, 0xec, 0xec], 8)));
+}
@@ -0,0 +1,108 @@
+//! A code no single frame can be read from, because of glare moving across it, has to decode from the frames together.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::ops::scene::{ScenePlacement, SceneOptions, Scene};
+use quirc_impl::ops::tracker::{TrackerOptions, QuircTracker};
+use quirc_impl::ops::fusion::QuircFusion;
+use quirc_impl::ops::encode::EncodeOptions;
+use quirc_impl::constants::QUIRC_ECC_LEVEL_L;
+use quirc_impl::util::vote_bitmap;
+
+
+const PAYLOAD: &[u8] = b"glare moves, the code doesn't";
+
+/// The code drifting right, with a blown-out disc of glare sweeping across its data the other way.
+fn glare_frame(i: usize) -> Scene {
+    let placement = ScenePlacement {
+        payload: PAYLOAD.to_vec(),
+        encode: EncodeOptions { ecc_level: Some(QUIRC_ECC_LEVEL_L), ..EncodeOptions::default() },
+        center: (150.0 + i as f64, 160.0),
+        ..ScenePlacement::default()
+    };
+    let mut scene = Scene::generate(&[placement], &SceneOptions { noise: 3.0, seed: i as u64, ..SceneOptions::default() }).unwrap();
+
+    let corners = scene.truth[0].corners;
+    let at = |u: f64, v: f64| {
+        (corners[0].x as f64 + u * (corners[1].x - corners[0].x) as f64 + v * (corners[3].x - corners[0].x) as f64,
+         corners[0].y as f64 + u * (corners[1].y - corners[0].y) as f64 + v * (corners[3].y - corners[0].y) as f64)
+    };
+    let side = (corners[1].x - corners[0].x) as f64;
+    let (gx, gy) = at(0.85 - 0.08 * i as f64, [0.32, 0.68][i % 2]);
+    let radius = side * 0.22;
+
+    for y in 0..scene.height {
+        for x in 0..scene.width {
+            if (x as f64 - gx).hypot(y as f64 - gy) < radius {
+                scene.pixels[y * scene.width + x] = 0xff;
+            }
+        }
+    }
+    scene
+}
+
+
+#[test]
+fn fuses_glare() {
+    let mut tracker = QuircTracker::new(TrackerOptions::default());
+    let mut fusion = QuircFusion::new();
+
+    let mut single = 0;
+    let mut last = None;
+    for i in 0..8 {
+        let scene = glare_frame(i);
+        let codes = tracker.track(&scene.pixels, scene.width, scene.height);
+        assert_eq!(codes.len(), 1, "frame {}", i);
+        single += codes[0].result.data.is_ok() as usize;
+
+        let fused = fusion.add(&tracker, &codes);
+        assert_eq!(fused.len(), 1);
+        assert_eq!((fused[0].id, fused[0].frames), (codes[0].id, i as u32 + 1));
+        assert_eq!(fused[0].result.corners, codes[0].result.corners);
+        last = Some(fused.into_iter().next().unwrap());
+    }
+    assert_eq!(single, 0, "glare spoils every frame");
+
+    let last = last.unwrap();
+    assert_eq!(last.result.data.unwrap().payload(), PAYLOAD);
+    assert_eq!(last.frames, 8);
+}
+
+#[test]
+fn forgets_lost_codes() {
+    let mut tracker = QuircTracker::new(TrackerOptions::default());
+    let mut fusion = QuircFusion::new();
+    let clean = Scene::generate(&[ScenePlacement { payload: PAYLOAD.to_vec(), ..ScenePlacement::default() }], &SceneOptions::default()).unwrap();
+    let blank = Scene::generate(&[], &SceneOptions::default()).unwrap();
+
+    let mut add = |fusion: &mut QuircFusion, scene: &Scene| {
+        let codes = tracker.track(&scene.pixels, scene.width, scene.height);
+        fusion.add(&tracker, &codes).into_iter().map(|f| (f.id, f.frames)).collect::<Vec<_>>()
+    };
+    assert_eq!(add(&mut fusion, &clean), [(0, 1)]);
+    assert_eq!(add(&mut fusion, &clean), [(0, 2)]);
+    assert_eq!(add(&mut fusion, &blank), []);
+    assert_eq!(add(&mut fusion, &clean), [(1, 1)]);
+
+    fusion.reset();
+    assert_eq!(add(&mut fusion, &clean), [(1, 1)]);
+}
+
+#[test]
+fn cell_values() {
+    let scene = Scene::generate(&[ScenePlacement { payload: PAYLOAD.to_vec(), ..ScenePlacement::default() }], &SceneOptions::default()).unwrap();
+    let mut tracker = QuircTracker::new(TrackerOptions::default());
+    let codes = tracker.track(&scene.pixels, scene.width, scene.height);
+
+    let quirc = tracker.quirc();
+    let values = quirc.cell_values(0).unwrap();
+    let code = quirc.extract(0).unwrap();
+    assert_eq!(values.len(), (code.size * code.size) as usize);
+    for (i, &value) in values.iter().enumerate() {
+        assert_eq!(value.abs(), 9, "cell {} of a clean code is sampled the same all over", i);
+        assert_eq!(value > 0, code.cell_bitmap[i >> 3] & (1 << (i & 7)) != 0, "cell {}", i);
+    }
+    assert!(quirc.cell_values(codes.len()).is_err());
+    assert_eq!(vote_bitmap(&values.iter().map(|&v| v as i32).collect::<Vec<_>>())[..], code.cell_bitmap[..]);
+}
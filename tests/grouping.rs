@@ -0,0 +1,110 @@
+//! Global grouping has to pick the best assignment of capstones to codes, and find every code on a tightly packed sheet.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::ops::scene::{ScenePlacement, SceneOptions, Scene};
+use quirc_impl::ops::grouping::{GroupCandidate, GroupRejection};
+use quirc_impl::ops::Quirc;
+use quirc_impl::util::select_groups;
+use std::fs;
+
+
+/// Eight labels, 63 pixels across, 90 pixels apart, in two rows of four, turned by `rotation` degrees,
+/// with the second row shifted `stagger` pixels right and every other column `stagger` pixels down.
+fn label_sheet(rotation: f64, stagger: f64) -> Scene {
+    let mut placements = Vec::new();
+    for row in 0..2 {
+        for col in 0..4 {
+            placements.push(ScenePlacement {
+                payload: format!("label {}-{}", row, col).into_bytes(),
+                center: (60.0 + 90.0 * col as f64 + stagger * row as f64, 60.0 + 90.0 * row as f64 + stagger * (col % 2) as f64),
+                module_size: 3.0,
+                rotation,
+                ..ScenePlacement::default()
+            });
+        }
+    }
+    Scene::generate(&placements, &SceneOptions { width: 520, height: 360, ..SceneOptions::default() }).unwrap()
+}
+
+/// Load `scene`, check that `end()` misses some of its codes and `end_global()` finds them all, and return the candidates.
+fn decode_globally(scene: &Scene) -> Vec<GroupCandidate> {
+    let mut quirc = Quirc::new();
+
+    scene.load(&mut quirc);
+    quirc.end();
+    assert!(payloads(&quirc).len() < scene.truth.len());
+
+    scene.load(&mut quirc);
+    let candidates = quirc.end_global();
+    let mut expected: Vec<_> = scene.truth.iter().map(|t| t.payload.clone()).collect();
+    expected.sort();
+    assert_eq!(payloads(&quirc), expected);
+
+    assert_eq!(candidates.iter().filter(|c| c.rejection.is_none()).count(), scene.truth.len());
+    candidates
+}
+
+fn payloads(quirc: &Quirc) -> Vec<Vec<u8>> {
+    let mut payloads: Vec<_> = quirc.results().filter_map(|r| r.data.ok()).map(|d| d.payload().to_vec()).collect();
+    payloads.sort();
+    payloads
+}
+
+
+#[test]
+fn select() {
+    // Greedy takes the best group and is left with nothing else; the two others make for more codes
+    let groups = [[0, 1, 2], [2, 3, 4], [0, 5, 6]];
+    assert_eq!(select_groups(&groups, &[0, 1, 1], 8), [1, 2]);
+    assert_eq!(select_groups(&groups, &[0, 1, 1], 1), [0]);
+
+    // As many codes either way, so the lower total wins
+    let groups = [[0, 1, 2], [3, 4, 5], [0, 4, 6], [2, 3, 7]];
+    assert_eq!(select_groups(&groups, &[5, 5, 1, 1], 8), [2, 3]);
+
+    assert!(select_groups(&[], &[], 8).is_empty());
+}
+
+#[test]
+#[should_panic]
+fn select_past_64_capstones() {
+    select_groups(&[[0, 1, 64]], &[0], 8);
+}
+
+#[test]
+fn label_sheet_decodes() {
+    let candidates = decode_globally(&label_sheet(0.0, 0.0));
+    for rejection in &[GroupRejection::ModuleSize, GroupRejection::Conflict] {
+        assert!(candidates.iter().any(|c| c.rejection == Some(*rejection)), "{:?}", rejection);
+    }
+}
+
+/// Turned and staggered, capstones of neighbouring labels line up off square with each other.
+#[test]
+fn staggered_sheet_decodes() {
+    let candidates = decode_globally(&label_sheet(30.0, 30.0));
+    assert!(candidates.iter().any(|c| c.rejection == Some(GroupRejection::Shape)));
+}
+
+#[test]
+fn corpus_unchanged() {
+    let mut quirc = Quirc::new();
+
+    for entry in fs::read_dir("tests/corpus").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map(|e| e != "pgm").unwrap_or(true) {
+            continue;
+        }
+        let data = fs::read(&path).unwrap();
+
+        quirc.load_netpbm(&data).unwrap();
+        quirc.end();
+        let greedy: Vec<_> = quirc.results().collect();
+
+        quirc.load_netpbm(&data).unwrap();
+        quirc.end_global();
+        assert_eq!(quirc.results().collect::<Vec<_>>(), greedy, "{}", path.display());
+    }
+}
@@ -0,0 +1,88 @@
+//! Identification has to cope with any image it's given, and sample the same cells from it the original C quirc does.
+//!
+//! The reference corpus in `tests/corpus/` holds, for each image, the cell bitmap the original C quirc samples from it.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::util::perspective_map;
+use quirc_impl::ops::{QuircCode, QuircPoint, Quirc};
+use std::path::{Path, PathBuf};
+use std::fs;
+
+
+fn corpus() -> Vec<(PathBuf, Vec<String>)> {
+    let mut images: Vec<_> = fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("corpus"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().map(|e| e == "pgm").unwrap_or(false))
+        .collect();
+    images.sort();
+    assert!(!images.is_empty());
+
+    images.into_iter()
+        .map(|p| {
+            let cells = fs::read_to_string(p.with_extension("cells")).unwrap().lines().map(str::to_string).collect();
+            (p, cells)
+        })
+        .collect()
+}
+
+fn cell_rows(code: &QuircCode) -> Vec<String> {
+    (0..code.size)
+        .map(|y| {
+            (0..code.size)
+                .map(|x| {
+                    let i = y * code.size + x;
+                    if (code.cell_bitmap[(i >> 3) as usize] >> (i & 7)) & 1 == 1 { '#' } else { '.' }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+
+/// Thresholding reads each row of pixels from its own place in the image, so even images only a row or two high are fine.
+#[test]
+fn thin_images() {
+    let mut quirc = Quirc::new();
+    for h in 1..4 {
+        for w in 1..64 {
+            quirc.resize(w, h);
+            for (i, pixel) in quirc.begin().iter_mut().enumerate() {
+                *pixel = if (i / 3) % 2 == 0 { 0 } else { 255 };
+            }
+            quirc.end();
+
+            assert_eq!(quirc.count(), 0, "{}x{}", w, h);
+        }
+    }
+}
+
+/// Every region, capstone, alignment pattern and timing pattern has to be found for a code to be sampled at all,
+/// and sampled where C quirc samples it for every cell to match.
+#[test]
+fn cell_bitmaps_match_reference() {
+    for (path, expected) in corpus() {
+        let mut quirc = Quirc::new();
+        quirc.load_netpbm(&fs::read(&path).unwrap()).unwrap();
+        quirc.end();
+
+        assert_eq!(quirc.count(), 1, "{}", path.display());
+        let code = quirc.extract(0).unwrap();
+        assert_eq!(cell_rows(&code), expected, "{}", path.display());
+        assert!(code.decode().is_ok(), "{}", path.display());
+    }
+}
+
+/// C quirc rounds mapped points with `rint()`, to nearest with ties to even.
+#[test]
+fn mapped_points_round_ties_to_even() {
+    let identity = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+
+    assert_eq!(perspective_map(&identity, 0.5, 1.5), QuircPoint { x: 0, y: 2 });
+    assert_eq!(perspective_map(&identity, 2.5, 3.5), QuircPoint { x: 2, y: 4 });
+    assert_eq!(perspective_map(&identity, -0.5, -1.5), QuircPoint { x: 0, y: -2 });
+    assert_eq!(perspective_map(&identity, 2.4, 2.6), QuircPoint { x: 2, y: 3 });
+    assert_eq!(perspective_map(&identity, -2.6, -2.4), QuircPoint { x: -3, y: -2 });
+}
@@ -0,0 +1,110 @@
+//! Images from the `image` crate have to scan the same whatever their pixel type.
+
+
+extern crate quirc_impl;
+extern crate image;
+
+use image::{DynamicImage, ImageBuffer, ImageFormat, Luma, Rgb, Rgba};
+use quirc_impl::ops::encode::{EncodeOptions, encode};
+use quirc_impl::ops::render::RenderOptions;
+use quirc_impl::ops::{ImageError, Quirc};
+use quirc_impl::util::image_luma;
+use quirc_impl::decode_image_file;
+use std::io::Cursor;
+use std::fs;
+
+
+const PAYLOAD: &[u8] = b"https://docs.rs/image";
+
+fn rendered() -> DynamicImage {
+    let png = encode(PAYLOAD, &EncodeOptions::default()).unwrap().render_png(&RenderOptions::default());
+    image::load_from_memory(&png).unwrap()
+}
+
+fn payloads(quirc: &mut Quirc, image: &DynamicImage) -> Vec<Vec<u8>> {
+    quirc.process_image(image).into_iter().map(|result| result.data.unwrap().payload().to_vec()).collect()
+}
+
+
+#[test]
+fn luma_scaling() {
+    assert_eq!(image_luma(Luma([0u8])), 0);
+    assert_eq!(image_luma(Luma([200u8])), 200);
+    assert_eq!(image_luma(Luma([65535u16])), 255);
+    assert_eq!(image_luma(Luma([0x8080u16])), 128);
+    assert_eq!(image_luma(Luma([0.5f32])), 128);
+    assert_eq!(image_luma(Luma([2.0f32])), 255, "out-of-range floats are clamped");
+    assert_eq!(image_luma(Rgb([255u8, 255, 255])), 255);
+    assert_eq!(image_luma(Rgba([0u8, 0, 0, 0])), 0);
+}
+
+#[test]
+fn every_pixel_type() {
+    let gray = rendered();
+    let mut quirc = Quirc::new();
+
+    for image in &[gray.clone(),
+                   DynamicImage::ImageRgb8(gray.to_rgb8()),
+                   DynamicImage::ImageRgba8(gray.to_rgba8()),
+                   DynamicImage::ImageLuma16(gray.to_luma16()),
+                   DynamicImage::ImageRgb32F(gray.to_rgb32f())] {
+        assert_eq!(payloads(&mut quirc, image), [PAYLOAD], "{:?}", image.color());
+        assert_eq!(quirc.size(), (gray.width() as usize, gray.height() as usize));
+        assert_eq!(quirc.count(), 1);
+        assert!(quirc.extract(0).is_ok(), "codes stay available after processing");
+    }
+}
+
+#[test]
+fn views_and_buffers() {
+    let gray = rendered().to_luma8();
+    let (w, h) = gray.dimensions();
+
+    // A code in the bottom right of a larger canvas, scanned through a view of that corner
+    let mut canvas = ImageBuffer::from_pixel(w * 2, h * 2, Luma([255u8]));
+    image::imageops::replace(&mut canvas, &gray, w as i64, h as i64);
+
+    let mut quirc = Quirc::new();
+    assert_eq!(quirc.process_image(&canvas).len(), 1);
+    let view = image::imageops::crop_imm(&canvas, w, h, w, h);
+    let results = quirc.process_image(&*view);
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].data.as_ref().unwrap().payload(), PAYLOAD);
+    assert_eq!(quirc.size(), (w as usize, h as usize));
+
+    let blank = ImageBuffer::from_pixel(64, 64, Luma([255u8]));
+    assert!(quirc.process_image(&blank).is_empty());
+}
+
+#[test]
+fn files() {
+    let dir = std::env::temp_dir().join(format!("quirc-image-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let image = rendered();
+    for &(name, format) in &[("code.png", ImageFormat::Png), ("code.bmp", ImageFormat::Bmp), ("code.tiff", ImageFormat::Tiff),
+                             ("no-extension", ImageFormat::Jpeg)] {
+        let mut data = Cursor::new(Vec::new());
+        image.write_to(&mut data, format).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, data.into_inner()).unwrap();
+
+        let results = decode_image_file(&path).unwrap();
+        assert_eq!(results.len(), 1, "{}", name);
+        assert_eq!(results[0].data.as_ref().unwrap().payload(), PAYLOAD, "{}", name);
+    }
+
+    match decode_image_file(dir.join("missing.png")) {
+        Err(ImageError::Io(_)) => {}
+        other => panic!("{:?}", other),
+    }
+
+    let garbage = dir.join("garbage.png");
+    fs::write(&garbage, b"not an image at all").unwrap();
+    match decode_image_file(&garbage) {
+        Err(ImageError::Decode(_)) => {}
+        other => panic!("{:?}", other),
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,178 @@
+//! Netpbm images have to survive being written and read back, in every format and variant.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::ops::netpbm::{NetpbmFormat, NetpbmImage};
+use quirc_impl::ops::{NetpbmError, Quirc};
+use std::fs;
+
+
+/// A gradient with a few pure black and white pixels.
+fn gradient(width: usize, height: usize, maxval: u16) -> NetpbmImage {
+    let samples = (0..width * height)
+        .map(|i| match i % 7 {
+            0 => 0,
+            1 => maxval,
+            _ => ((i * maxval as usize) / (width * height)) as u16,
+        })
+        .collect();
+
+    NetpbmImage {
+        width,
+        height,
+        depth: 1,
+        maxval,
+        samples,
+    }
+}
+
+fn colour(width: usize, height: usize, depth: usize, maxval: u16) -> NetpbmImage {
+    NetpbmImage {
+        width,
+        height,
+        depth,
+        maxval,
+        samples: (0..width * height * depth).map(|i| ((i * 37) % (maxval as usize + 1)) as u16).collect(),
+    }
+}
+
+
+#[test]
+fn grayscale_round_trips() {
+    for &maxval in &[255, 15, 1000, 65535] {
+        let image = gradient(13, 5, maxval);
+        for &ascii in &[false, true] {
+            let data = image.write(NetpbmFormat::Pgm, ascii);
+            assert_eq!(&data[..2], if ascii { b"P2" } else { b"P5" });
+            assert_eq!(NetpbmImage::read(&data), Ok(image.clone()), "maxval {}, ascii {}", maxval, ascii);
+        }
+
+        let data = image.write(NetpbmFormat::Pam, false);
+        assert_eq!(NetpbmImage::read(&data), Ok(image.clone()), "maxval {} PAM", maxval);
+    }
+}
+
+#[test]
+fn binary_16_bit_is_big_endian() {
+    let image = NetpbmImage {
+        width: 2,
+        height: 1,
+        depth: 1,
+        maxval: 65535,
+        samples: vec![0x1234, 0xfedc],
+    };
+
+    assert_eq!(image.write(NetpbmFormat::Pgm, false), b"P5\n2 1\n65535\n\x12\x34\xfe\xdc");
+}
+
+#[test]
+fn colour_round_trips() {
+    for &maxval in &[255, 4095] {
+        let image = colour(7, 3, 3, maxval);
+        for &ascii in &[false, true] {
+            let data = image.write(NetpbmFormat::Ppm, ascii);
+            assert_eq!(NetpbmImage::read(&data), Ok(image.clone()), "maxval {}, ascii {}", maxval, ascii);
+        }
+    }
+
+    for depth in 1..=4 {
+        let image = colour(5, 4, depth, 200);
+        let data = image.write(NetpbmFormat::Pam, false);
+        assert_eq!(NetpbmImage::read(&data), Ok(image), "depth {}", depth);
+    }
+}
+
+#[test]
+fn bitmaps_round_trip() {
+    // 11 columns, so the binary rows are padded
+    let image = NetpbmImage {
+        width: 11,
+        height: 3,
+        depth: 1,
+        maxval: 1,
+        samples: (0..33).map(|i| ((i * 5) % 3 == 0) as u16).collect(),
+    };
+
+    for &ascii in &[false, true] {
+        let data = image.write(NetpbmFormat::Pbm, ascii);
+        assert_eq!(&data[..2], if ascii { b"P1" } else { b"P4" });
+        assert_eq!(NetpbmImage::read(&data), Ok(image.clone()), "ascii {}", ascii);
+    }
+
+    let data = image.write(NetpbmFormat::Pam, false);
+    assert!(String::from_utf8_lossy(&data).contains("TUPLTYPE BLACKANDWHITE\n"));
+    assert_eq!(NetpbmImage::read(&data), Ok(image));
+}
+
+#[test]
+fn hand_written_headers() {
+    let image = NetpbmImage::read(b"P2 # comment right after the magic\n# a whole line\n3 1 # trailing\n10\n0 5\n10").unwrap();
+    assert_eq!((image.width, image.height, image.maxval), (3, 1, 10));
+    assert_eq!(image.samples, [0, 5, 10]);
+    assert_eq!(image.luma(), [0, 128, 255]);
+
+    // ASCII bitmaps don't need whitespace between pixels
+    let image = NetpbmImage::read(b"P1\n4 2\n0110\n1 0 0 1\n").unwrap();
+    assert_eq!(image.samples, [1, 0, 0, 1, 0, 1, 1, 0]);
+
+    let image = NetpbmImage::read(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 2\nMAXVAL 255\nTUPLTYPE GRAYSCALE_ALPHA\nENDHDR\n\x00\x00").unwrap();
+    assert_eq!(image.luma(), [255], "fully transparent black is composited over white");
+}
+
+#[test]
+fn colour_to_gray() {
+    let image = NetpbmImage::read(b"P3 3 1 255 255 0 0  0 255 0  0 0 255").unwrap();
+    assert_eq!(image.luma(), [76, 150, 29]);
+
+    let gray = NetpbmImage::read(&image.write(NetpbmFormat::Pgm, false)).unwrap();
+    assert_eq!(gray.samples, [76, 150, 29]);
+    let bits = NetpbmImage::read(&image.write(NetpbmFormat::Pbm, false)).unwrap();
+    assert_eq!(bits.samples, [0, 1, 0]);
+}
+
+#[test]
+fn errors() {
+    assert_eq!(NetpbmImage::read(b""), Err(NetpbmError::UnknownFormat));
+    assert_eq!(NetpbmImage::read(b"\x89PNG"), Err(NetpbmError::UnknownFormat));
+    assert_eq!(NetpbmImage::read(b"P8 1 1 255\n"), Err(NetpbmError::UnknownFormat));
+    assert_eq!(NetpbmImage::read(b"P5 x 1 255\n"), Err(NetpbmError::InvalidHeader("width")));
+    assert_eq!(NetpbmImage::read(b"P5 1 0 255\n"), Err(NetpbmError::InvalidHeader("height")));
+    assert_eq!(NetpbmImage::read(b"P5 1 1 0\n\0"), Err(NetpbmError::InvalidMaxval(0)));
+    assert_eq!(NetpbmImage::read(b"P5 1 1 65536\n\0\0"), Err(NetpbmError::InvalidMaxval(65536)));
+    assert_eq!(NetpbmImage::read(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 5\nMAXVAL 255\nENDHDR\n"), Err(NetpbmError::UnsupportedDepth(5)));
+    assert_eq!(NetpbmImage::read(b"P7\nWIDTH 1\nHEIGHT 1\nMAXVAL 255\nENDHDR\n"), Err(NetpbmError::InvalidHeader("depth")));
+    assert_eq!(NetpbmImage::read(b"P2 2 1 10 3 11"), Err(NetpbmError::InvalidSample { offset: 11 }));
+    assert_eq!(NetpbmImage::read(b"P1 2 1 0 2"), Err(NetpbmError::InvalidSample { offset: 9 }));
+    assert_eq!(NetpbmImage::read(b"P5 2 1 10 \x03\x0b"), Err(NetpbmError::InvalidSample { offset: 11 }));
+    assert_eq!(NetpbmImage::read(b"P5 2 2 255 \0\0\0"), Err(NetpbmError::Truncated));
+    assert_eq!(NetpbmImage::read(b"P2 2 1 10 3"), Err(NetpbmError::Truncated));
+    assert_eq!(NetpbmImage::read(b"P6 2 2"), Err(NetpbmError::Truncated));
+}
+
+#[test]
+fn load_corpus() {
+    let manifest = fs::read_to_string("tests/corpus/manifest").unwrap();
+    let mut quirc = Quirc::new();
+
+    for line in manifest.lines().filter(|l| !l.starts_with('#') && !l.is_empty()) {
+        let (file, payload) = line.split_at(line.find(' ').unwrap_or(line.len()));
+        let data = fs::read(format!("tests/corpus/{}", file)).unwrap();
+
+        // Written back out as a PPM, it has to scan just the same
+        let image = NetpbmImage::read(&data).unwrap();
+        for &(format, ascii) in &[(NetpbmFormat::Pgm, false), (NetpbmFormat::Ppm, true), (NetpbmFormat::Pam, false)] {
+            quirc.load_netpbm(&image.write(format, ascii)).unwrap();
+            assert_eq!(quirc.size(), (image.width, image.height));
+            quirc.end();
+
+            let decoded: Vec<_> = (0..quirc.count()).flat_map(|i| quirc.extract(i).ok()).flat_map(|c| c.decode().ok()).collect();
+            assert!(decoded.iter().any(|d| d.payload() == payload.trim_start().as_bytes()), "{} as {:?}", file, format);
+        }
+
+        let debug = quirc.pixels_netpbm();
+        assert_eq!((debug.width, debug.height, debug.depth), (image.width, image.height, 3));
+        assert!(debug.samples.chunks(3).any(|p| p == [255, 0, 0]), "{} has capstone rings", file);
+        assert_eq!(NetpbmImage::read(&debug.write(NetpbmFormat::Ppm, false)), Ok(debug));
+    }
+}
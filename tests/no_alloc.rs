@@ -0,0 +1,95 @@
+//! Codes decode without an allocator, into the caller's buffers, just as they do into `QuircData`.
+//!
+//! Needs no features, so that `cargo test --no-default-features` checks it too.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::ops::{DecodeErrorKind, QuircPoint, QuircCode};
+use quirc_impl::constants::{QUIRC_DATA_TYPE_BYTE, QUIRC_MAX_BITMAP};
+use quirc_impl::util::Datastream;
+
+
+/// The codewords in a version 40 code.
+const CODEWORDS: usize = 3706;
+
+/// Byte mode codes at versions 1-M, 3-Q and 5-L: size, cell bitmap, and payload.
+static ENCODED: [(u32, &str, &str); 3] =
+    [(21,
+      "7fcb3fe80b763ddd2ea6db357583b4e05ff507c000f9371d206a248965c106ea1644003ad45ffd08da395d5fb86bc37419e52031ffd73000",
+      "no_std"),
+     (29,
+      concat!("7f31c33fa8190a76b976dd2e89a5db45c97583f8ade05f55f507b02f00c2592d38a7df715c31101494e95a808e9e2d7928bfe75db1d420f576fd506611ac50c5",
+              "3bac7923df88384051172d5f01ea33cadf3bd70992c8185d8effbbcb270075b181afa06f60fba7b34c00"),
+      "Decoded without an allocator"),
+     (37,
+      concat!("7fefeec63f2811110876898868dd2ecc4ca3db9599397583eeee86e05f5555f5071811a10067bbbbedb93222325b33d1ddbf5f9cedae247145668eac3fcdccf7",
+              "28dc23a2931f2511a1d151bcbb4982d722725b3ac1dd67d154efae642014661e2d21c9ccbab0b522a2b6ed05110592cfb8bb5982b02272194ec3dd6745c8ed6e",
+              "5af536664ebf00cecc23e29f22a2550d3a1181585dbe3bfbbb0b22b2e777c5dd7bfca0eeae0ef117660e2301"),
+      "into the caller's buffers, just as into QuircData")];
+
+
+fn codes() -> Vec<(QuircCode, &'static str)> {
+    ENCODED.iter()
+        .map(|&(size, hex, payload)| {
+            let mut code = QuircCode {
+                corners: [QuircPoint { x: 0, y: 0 }; 4],
+                size,
+                cell_bitmap: [0; QUIRC_MAX_BITMAP],
+            };
+            for i in 0..hex.len() / 2 {
+                code.cell_bitmap[i] = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+            }
+            (code, payload)
+        })
+        .collect()
+}
+
+
+#[test]
+fn codes_decode() {
+    let (mut raw, mut data) = ([0u8; CODEWORDS], [0u8; CODEWORDS]);
+    let mut ds = Datastream::with_buffers(&mut raw[..], &mut data[..]);
+
+    let codes = codes();
+    assert!(!codes.is_empty());
+    for (code, payload) in codes {
+        let mut buf = [0u8; 64];
+        let data = code.decode_buf(&mut ds, &mut buf).unwrap_or_else(|e| panic!("{}: {}", payload, e));
+
+        assert_eq!(data.payload(), payload.as_bytes());
+        assert_eq!(data.version, (code.size - 17) / 4);
+        assert_eq!(data.data_type, QUIRC_DATA_TYPE_BYTE, "{}", payload);
+    }
+}
+
+#[test]
+fn payload_exactly_fits() {
+    let (mut raw, mut data) = ([0u8; CODEWORDS], [0u8; CODEWORDS]);
+    let mut ds = Datastream::with_buffers(&mut raw[..], &mut data[..]);
+
+    for (code, payload) in codes() {
+        let mut buf = vec![0u8; payload.len()];
+        assert_eq!(code.decode_buf(&mut ds, &mut buf).unwrap().payload(), payload.as_bytes());
+
+        let mut buf = vec![0u8; payload.len() - 1];
+        assert_eq!(code.decode_buf(&mut ds, &mut buf).unwrap_err().kind, DecodeErrorKind::DataOverflow, "{}", payload);
+    }
+}
+
+#[test]
+fn invalid_grid_size() {
+    let (mut raw, mut data) = ([0u8; CODEWORDS], [0u8; CODEWORDS]);
+    let mut ds = Datastream::with_buffers(&mut raw[..], &mut data[..]);
+
+    let (mut code, _) = codes().remove(0);
+    code.size += 1;
+    assert_eq!(code.decode_buf(&mut ds, &mut [0u8; 64]).unwrap_err().kind, DecodeErrorKind::InvalidGridSize);
+}
+
+#[test]
+#[should_panic]
+fn buffers_too_small() {
+    let (mut raw, mut data) = ([0u8; CODEWORDS - 1], [0u8; CODEWORDS]);
+    Datastream::with_buffers(&mut raw[..], &mut data[..]);
+}
@@ -0,0 +1,87 @@
+//! The multithreaded pipeline has to give exactly what the serial one does.
+
+
+extern crate quirc_impl;
+extern crate rayon;
+
+use quirc_impl::ops::scene::{ScenePlacement, SceneOptions, Scene};
+use quirc_impl::util::{SplitMix64, threshold_rows, threshold_state};
+use quirc_impl::ops::Quirc;
+use rayon::ThreadPoolBuilder;
+
+
+fn scenes() -> Vec<Scene> {
+    let codes = |n: usize| {
+        (0..n)
+            .map(|i| {
+                ScenePlacement {
+                    payload: format!("code {}", i).into_bytes(),
+                    center: (75.0 + 140.0 * (i % 3) as f64, 75.0 + 140.0 * (i / 3) as f64),
+                    module_size: 3.0 + (i % 2) as f64,
+                    rotation: 7.0 * i as f64,
+                    ..ScenePlacement::default()
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+
+    vec![Scene::generate(&codes(1), &SceneOptions::default()).unwrap(),
+         Scene::generate(&codes(6), &SceneOptions { width: 433, height: 291, noise: 4.0, seed: 1, ..SceneOptions::default() }).unwrap(),
+         Scene::generate(&codes(4),
+                         &SceneOptions { width: 401, height: 333, illumination: 0.5, jpeg_quality: 40, seed: 2, ..SceneOptions::default() })
+             .unwrap(),
+         Scene::generate(&[], &SceneOptions { width: 17, height: 9, noise: 40.0, ..SceneOptions::default() }).unwrap(),
+         Scene::generate(&[], &SceneOptions { width: 1, height: 1, ..SceneOptions::default() }).unwrap()]
+}
+
+fn process(scene: &Scene, parallel: Option<usize>) -> Quirc {
+    let mut quirc = Quirc::new();
+    scene.load(&mut quirc);
+    match parallel {
+        None => quirc.end(),
+        Some(threads) => ThreadPoolBuilder::new().num_threads(threads).build().unwrap().install(|| quirc.par_end()),
+    }
+    quirc
+}
+
+
+#[test]
+fn same_as_serial() {
+    for (i, scene) in scenes().iter().enumerate() {
+        let serial = process(scene, None);
+        for &threads in &[1, 3, 8, 32] {
+            let parallel = process(scene, Some(threads));
+            assert!(parallel == serial, "scene {}, {} threads", i, threads);
+            assert_eq!(parallel.par_results(), serial.results().collect::<Vec<_>>(), "scene {}, {} threads", i, threads);
+        }
+    }
+
+    // With 32 threads the bands are as thin as they get, so most capstones in the busy scene straddle two
+    assert_eq!(process(&scenes()[1], None).count(), 6);
+}
+
+#[test]
+fn threshold_state_exact() {
+    let mut rng = SplitMix64::new(7);
+    for &(w, h) in &[(1, 40), (7, 30), (64, 48), (333, 20)] {
+        // Noise is the slowest for the averages to settle on
+        let image: Vec<u8> = (0..w * h).map(|_| rng.next_u64() as u8).collect();
+
+        for &(left, right, top) in &[(0, w, 0), (w / 3, w - w / 4, 5)] {
+            let mut rows: Vec<u16> = image.iter().map(|&p| p as u16).collect();
+            let mut row_average = vec![0u64; w];
+            let mut avg = (0, 0);
+            for y in top..h {
+                assert_eq!(threshold_state(&image, w, left, right, top, y), avg, "{}x{}, columns {}..{} from {}, row {}", w, h, left, right, top, y);
+                threshold_rows(&mut rows[y * w..(y + 1) * w], &mut row_average, w, left, right, y, &mut avg);
+            }
+        }
+    }
+}
+
+#[test]
+#[should_panic]
+fn short_row_average() {
+    let mut rows = vec![128u16; 64];
+    threshold_rows(&mut rows, &mut [0; 63], 64, 0, 64, 0, &mut (0, 0));
+}
@@ -0,0 +1,176 @@
+//! The fixed-point perspective transforms have to map cells where the `f64` ones do,
+//! for the codes in `tests/corpus/` and for symbols the scene generator warps every which way,
+//! and the identification pipeline has to sample the cells of the latter as they were drawn, with either.
+//!
+//! `tests/identify.rs` checks the cells sampled against C quirc's; run it with and without the `fixed-point` feature to check both paths.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::util::{fixed_point, perspective_setup, perspective_unmap, perspective_map, SplitMix64};
+use quirc_impl::ops::scene::{ScenePlacement, SceneOptions, Scene};
+use quirc_impl::ops::{QuircPoint, Quirc};
+use quirc_impl::ops::encode::encode;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::fs;
+
+
+fn corpus() -> Vec<PathBuf> {
+    let mut images: Vec<_> = fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("corpus"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().map(|e| e == "pgm").unwrap_or(false))
+        .collect();
+    images.sort();
+    assert!(!images.is_empty());
+    images
+}
+
+/// Upright and rotated, flat and tilted, with 2 to 5 pixel cells, one in the middle of each scene.
+fn scenes() -> Vec<(ScenePlacement, Scene)> {
+    let mut rng = SplitMix64::new(0x9e35);
+
+    (0..150)
+        .map(|i| {
+            let width = 200 + (rng.next_u64() % 250) as usize;
+            let height = 200 + (rng.next_u64() % 250) as usize;
+
+            let placement = ScenePlacement {
+                payload: format!("perspective {} {}", i, rng.next_u64()).into_bytes(),
+                center: (width as f64 / 2.0, height as f64 / 2.0),
+                module_size: 2.0 + rng.next_f64() * 3.0,
+                rotation: if i % 4 == 0 { 90.0 * (i / 4 % 4) as f64 } else { rng.next_f64() * 360.0 },
+                tilt: (rng.next_f64() * 0.4 - 0.2, rng.next_f64() * 0.4 - 0.2),
+                ..ScenePlacement::default()
+            };
+            let options = SceneOptions {
+                width,
+                height,
+                seed: rng.next_u64(),
+                ..SceneOptions::default()
+            };
+            let scene = Scene::generate(slice::from_ref(&placement), &options).unwrap();
+            (placement, scene)
+        })
+        .collect()
+}
+
+/// Map the centre of every cell of a `size`-cell grid with the specified corners both ways, and unmap it again.
+///
+/// The affine parameters are within 2^-17 of exact and the projective ones within 2^-33, so a point `size` cells in
+/// is within `size` * (2^-16 + its distance from the origin * 2^-32) pixels of it,
+/// and the two paths can only round differently that close to a rounding boundary.
+fn check_maps(name: &str, corners: &[QuircPoint; 4], size: u32) {
+    let size = size as i64;
+    let float_c = perspective_setup(corners, size as f64, size as f64);
+    let fixed_c = fixed_point::perspective_setup(corners, size, size);
+    let tolerance = |exact: f64| size as f64 * (1.0 / fixed_point::FIXED_ONE as f64 + exact.abs() / (1u64 << 32) as f64);
+
+    for y in 0..size {
+        for x in 0..size {
+            let (u, v) = (x as f64 + 0.5, y as f64 + 0.5);
+            let float_p = perspective_map(&float_c, u, v);
+            let fixed_p = fixed_point::perspective_map(&fixed_c,
+                                                       (x << fixed_point::FIXED_FRAC_BITS) + fixed_point::FIXED_ONE / 2,
+                                                       (y << fixed_point::FIXED_FRAC_BITS) + fixed_point::FIXED_ONE / 2);
+
+            if fixed_p != float_p {
+                let den = float_c[6] * u + float_c[7] * v + 1.0;
+                let exact_x = (float_c[0] * u + float_c[1] * v + float_c[2]) / den;
+                let exact_y = (float_c[3] * u + float_c[4] * v + float_c[5]) / den;
+                let near_boundary = |exact: f64, fixed: isize, float: isize| fixed == float || (exact - exact.floor() - 0.5).abs() <= tolerance(exact);
+
+                assert!(near_boundary(exact_x, fixed_p.x, float_p.x) && near_boundary(exact_y, fixed_p.y, float_p.y),
+                        "{}: cell ({}, {}) at ({}, {}) mapped to {:?}, not {:?}",
+                        name,
+                        x,
+                        y,
+                        exact_x,
+                        exact_y,
+                        fixed_p,
+                        float_p);
+            }
+
+            let (fixed_u, fixed_v) = fixed_point::perspective_unmap(&fixed_c, &float_p);
+            let (float_u, float_v) = perspective_unmap(&float_c, &float_p);
+            let tolerance = tolerance(float_p.x.abs().max(float_p.y.abs()) as f64);
+            assert!((fixed_u as f64 / fixed_point::FIXED_ONE as f64 - float_u).abs() <= tolerance &&
+                    (fixed_v as f64 / fixed_point::FIXED_ONE as f64 - float_v).abs() <= tolerance,
+                    "{}: {:?} unmapped to ({}, {}), not ({}, {})",
+                    name,
+                    float_p,
+                    fixed_u,
+                    fixed_v,
+                    float_u,
+                    float_v);
+        }
+    }
+}
+
+
+#[test]
+fn fixed_point_maps_corpus_like_float() {
+    for path in corpus() {
+        let mut quirc = Quirc::new();
+        quirc.load_netpbm(&fs::read(&path).unwrap()).unwrap();
+        quirc.end();
+        let code = quirc.extract(0).unwrap();
+
+        check_maps(&path.display().to_string(), &code.corners, code.size);
+    }
+}
+
+#[test]
+fn fixed_point_maps_scenes_like_float() {
+    for (i, (_, scene)) in scenes().into_iter().enumerate() {
+        let truth = &scene.truth[0];
+        check_maps(&format!("scene {}", i), &truth.corners, truth.version * 4 + 17);
+    }
+}
+
+/// Far from any image, where the products of the parameters don't fit in an `i64`.
+#[test]
+fn fixed_point_huge_coordinates() {
+    let corners = [QuircPoint { x: 3_000_000, y: 1_000_000 },
+                   QuircPoint { x: 3_100_000, y: 1_020_000 },
+                   QuircPoint { x: 3_090_000, y: 1_110_000 },
+                   QuircPoint { x: 2_990_000, y: 1_100_000 }];
+    check_maps("huge", &corners, 177);
+}
+
+/// Clean scenes leave nothing to misread but the geometry: nearly every symbol with cells of 3 pixels or more
+/// has to be found and decoded, with only the odd cell on the edge of a warped module sampled wrong, with either arithmetic.
+///
+/// The few the steepest tilts defeat are the same few with either, and the cells sampled wrong differ by one at most.
+#[test]
+fn scene_cells_sampled() {
+    let (mut tried, mut read, mut wrong) = (0, 0, 0);
+    for (i, (placement, scene)) in scenes().into_iter().enumerate().filter(|&(_, (ref p, _))| p.module_size >= 3.0) {
+        let expected = encode(&placement.payload, &placement.encode).unwrap();
+
+        let mut quirc = Quirc::new();
+        scene.load(&mut quirc);
+        quirc.end();
+        tried += 1;
+
+        let code = match quirc.extract(0) {
+            Ok(code) => code,
+            Err(_) => continue,
+        };
+        let decoded = match code.decode() {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+        assert_eq!(decoded.payload(), &placement.payload[..], "scene {}", i);
+        read += 1;
+
+        let cells = (0..(code.size * code.size) as usize)
+            .filter(|&c| (code.cell_bitmap[c >> 3] ^ expected.cell_bitmap[c >> 3]) & (1 << (c & 7)) != 0)
+            .count();
+        assert!(cells <= 8, "scene {}: {} cells sampled wrong", i, cells);
+        wrong += cells;
+    }
+    assert!(read * 20 >= tried * 19, "only {} of {} scenes read", read, tried);
+    assert!(wrong <= read / 4, "{} cells sampled wrong in {} scenes", wrong, read);
+}
@@ -0,0 +1,182 @@
+//! Pyramid levels have to find what the native level misses, without losing or duplicating what it finds,
+//! and always report corners in image space.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::ops::pyramid::PyramidOptions;
+use quirc_impl::ops::scene::{ScenePlacement, SceneOptions, Scene};
+use quirc_impl::ops::{QuircPoint, Quirc};
+use quirc_impl::util::{SplitMix64, level_to_image, downscale_image, upscale_image};
+use std::fs;
+
+
+/// How many of `scenes` codes with cells `module_size` pixels wide decode where they were drawn, with and without the pyramid.
+fn tiny_codes(module_size: f64, scenes: usize) -> (usize, usize) {
+    let mut rng = SplitMix64::new(42);
+    let (mut native, mut pyramid) = (0, 0);
+
+    for _ in 0..scenes {
+        let placement = ScenePlacement {
+            payload: b"tiny".to_vec(),
+            center: (100.0, 100.0),
+            module_size,
+            rotation: rng.next_f64() * 30.0 - 15.0,
+            ..ScenePlacement::default()
+        };
+        let scene = Scene::generate(&[placement], &SceneOptions { width: 200, height: 200, seed: rng.next_u64(), ..SceneOptions::default() }).unwrap();
+        let mut quirc = Quirc::new();
+
+        let found = |quirc: &Quirc| {
+            quirc.results().any(|r| scene.truth[0].matches(&r.code) && r.data.map(|d| d.payload() == b"tiny").unwrap_or(false)) as usize
+        };
+        scene.load(&mut quirc);
+        quirc.end();
+        native += found(&quirc);
+
+        scene.load(&mut quirc);
+        quirc.end_pyramid(&PyramidOptions::default());
+        assert!(quirc.count() <= 1);
+        pyramid += found(&quirc);
+    }
+
+    (native, pyramid)
+}
+
+
+#[test]
+fn levels() {
+    assert_eq!(level_to_image(&QuircPoint { x: 0, y: 10 }, (4, 1)), QuircPoint { x: 2, y: 42 });
+    assert_eq!(level_to_image(&QuircPoint { x: 0, y: 10 }, (1, 2)), QuircPoint { x: 0, y: 5 });
+    assert_eq!(level_to_image(&QuircPoint { x: 3, y: 4 }, (1, 1)), QuircPoint { x: 3, y: 4 });
+
+    let image: Vec<u8> = (0..6 * 5).map(|i| (i * 8) as u8).collect();
+    let (down, w, h) = downscale_image(&image, 6, 5, 2);
+    assert_eq!((w, h), (3, 2));
+    assert_eq!(down, [28, 44, 60, 124, 140, 156]);
+
+    let (up, w, h) = upscale_image(&[0, 200, 100, 100], 2, 2, 2);
+    assert_eq!((w, h), (4, 4));
+    assert_eq!(&up[..4], [0, 50, 150, 200]);
+    assert_eq!(&up[12..], [100, 100, 100, 100]);
+    assert_eq!(upscale_image(&[77; 12], 4, 3, 3).0, [77; 108]);
+}
+
+#[test]
+fn same_as_native_on_corpus() {
+    let mut quirc = Quirc::new();
+
+    for entry in fs::read_dir("tests/corpus").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map(|e| e != "pgm").unwrap_or(true) {
+            continue;
+        }
+        let data = fs::read(&path).unwrap();
+
+        quirc.load_netpbm(&data).unwrap();
+        quirc.end();
+        let native: Vec<_> = quirc.results().collect();
+
+        quirc.load_netpbm(&data).unwrap();
+        quirc.end_pyramid(&PyramidOptions::default());
+        assert_eq!(quirc.results().collect::<Vec<_>>(), native, "{}", path.display());
+    }
+}
+
+#[test]
+fn downscaled_search() {
+    let placements = [ScenePlacement {
+                          payload: b"top left".to_vec(),
+                          center: (300.0, 250.0),
+                          module_size: 14.0,
+                          rotation: 10.0,
+                          ..ScenePlacement::default()
+                      },
+                      ScenePlacement {
+                          payload: b"bottom right".to_vec(),
+                          center: (900.0, 650.0),
+                          module_size: 12.0,
+                          rotation: -20.0,
+                          ..ScenePlacement::default()
+                      }];
+    let scene = Scene::generate(&placements, &SceneOptions { width: 1200, height: 900, noise: 4.0, seed: 5, ..SceneOptions::default() }).unwrap();
+    let mut quirc = Quirc::new();
+
+    scene.load(&mut quirc);
+    quirc.end();
+    let mut native: Vec<_> = quirc.results().collect();
+    native.sort_by_key(|r| r.corners[0]);
+    assert_eq!(native.len(), 2);
+
+    // Only the rows around the codes found on the level are thresholded and scanned, so the corners come out the same
+    scene.load(&mut quirc);
+    quirc.end_pyramid(&PyramidOptions { native: false, downscale: 4, upscale: 1, ..PyramidOptions::default() });
+    let mut pyramid: Vec<_> = quirc.results().collect();
+    pyramid.sort_by_key(|r| r.corners[0]);
+    assert_eq!(pyramid, native);
+    for (result, truth) in pyramid.iter().zip(&scene.truth) {
+        assert!(truth.matches(&result.code));
+        assert_eq!(result.data.as_ref().unwrap().payload(), &truth.payload[..]);
+    }
+
+    // Levels too coarse to find anything leave nothing behind
+    scene.load(&mut quirc);
+    quirc.end_pyramid(&PyramidOptions { native: false, downscale: 16, upscale: 1, ..PyramidOptions::default() });
+    assert_eq!(quirc.count(), 0);
+}
+
+#[test]
+fn upscaled_search() {
+    let (native, pyramid) = tiny_codes(2.5, 20);
+    println!("2.5-pixel cells: {} natively, {} with the pyramid", native, pyramid);
+    assert!(pyramid >= native + 3, "{} <= {}", pyramid, native);
+}
+
+#[test]
+fn upscaled_only() {
+    // Found on the upscaled level alone, but still reported in image space
+    let placement = ScenePlacement { payload: b"image space".to_vec(), center: (90.0, 110.0), module_size: 2.5, ..ScenePlacement::default() };
+    let scene = Scene::generate(&[placement], &SceneOptions { width: 200, height: 200, seed: 1, ..SceneOptions::default() }).unwrap();
+    let mut quirc = Quirc::new();
+
+    scene.load(&mut quirc);
+    quirc.end_pyramid(&PyramidOptions { native: false, downscale: 1, upscale: 2, ..PyramidOptions::default() });
+    assert_eq!(quirc.count(), 1);
+    let code = quirc.extract(0).unwrap();
+    assert!(scene.truth[0].matches(&code), "{:?} vs {:?}", code.corners, scene.truth[0].corners);
+    assert_eq!(quirc.size(), (200, 200));
+}
+
+#[test]
+fn stops_at_first_level() {
+    // The large code is found on the downscaled level, the tiny one only on the upscaled one
+    let placements = [ScenePlacement { payload: b"large".to_vec(), center: (250.0, 250.0), module_size: 12.0, rotation: 5.0, ..ScenePlacement::default() },
+                      ScenePlacement { payload: b"image space".to_vec(), center: (700.0, 600.0), module_size: 2.5, ..ScenePlacement::default() }];
+    let scene = Scene::generate(&placements, &SceneOptions { width: 900, height: 800, seed: 3, ..SceneOptions::default() }).unwrap();
+    let mut quirc = Quirc::new();
+    let payloads = |quirc: &Quirc| {
+        let mut payloads: Vec<_> = quirc.results().filter_map(|r| r.data.ok()).map(|d| d.payload().to_vec()).collect();
+        payloads.sort();
+        payloads
+    };
+
+    scene.load(&mut quirc);
+    quirc.end_pyramid(&PyramidOptions::default());
+    assert_eq!(payloads(&quirc), [b"large".to_vec()]);
+
+    scene.load(&mut quirc);
+    quirc.end_pyramid(&PyramidOptions { exhaustive: true, ..PyramidOptions::default() });
+    assert_eq!(payloads(&quirc), [b"image space".to_vec(), b"large".to_vec()]);
+
+    // Cells a pixel wide on the downscaled level are too small to find there, so the image itself is searched as by end()
+    let placement = ScenePlacement { payload: b"native".to_vec(), center: (200.0, 200.0), module_size: 4.0, rotation: 15.0, ..ScenePlacement::default() };
+    let scene = Scene::generate(&[placement], &SceneOptions { width: 400, height: 400, seed: 3, ..SceneOptions::default() }).unwrap();
+    scene.load(&mut quirc);
+    quirc.end();
+    let native: Vec<_> = quirc.results().collect();
+    assert_eq!(payloads(&quirc), [b"native".to_vec()]);
+
+    scene.load(&mut quirc);
+    quirc.end_pyramid(&PyramidOptions::default());
+    assert_eq!(quirc.results().collect::<Vec<_>>(), native);
+}
@@ -0,0 +1,75 @@
+//! The `qrtest` binary, run on `tests/corpus/` against the baseline stored there.
+
+
+extern crate quirc_impl;
+
+mod common;
+
+use common::{corpus, scratch};
+use std::path::Path;
+use std::process::{Command, Output};
+use std::fs;
+
+
+fn qrtest(args: &[&Path]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_qrtest")).args(args).output().unwrap()
+}
+
+
+#[test]
+fn corpus_meets_baseline() {
+    let out = qrtest(&[Path::new("-b"), &corpus().join("baseline"), &corpus()]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+
+    assert!(out.status.success(), "{}", stdout);
+    assert!(stdout.contains("6 files, 6 codes, 6 decoded (0 failures), 100% success rate"), "{}", stdout);
+    assert!(stdout.contains("6 expected, 6 correct, precision 100.0%, recall 100.0%"), "{}", stdout);
+}
+
+#[test]
+fn global_grouping_meets_baseline() {
+    let out = qrtest(&[Path::new("-g"), Path::new("-b"), &corpus().join("baseline"), &corpus()]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+
+    assert!(out.status.success(), "{}", stdout);
+    assert!(stdout.contains("6 expected, 6 correct, precision 100.0%, recall 100.0%"), "{}", stdout);
+}
+
+#[test]
+fn regression_fails() {
+    let dir = scratch("qrtest-regression");
+    let baseline = dir.join("baseline");
+    fs::write(&baseline, "recall 1.0\ndecode_rate 1.000001\n").unwrap();
+
+    let out = qrtest(&[Path::new("-b"), &baseline, &corpus()]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(out.status.code(), Some(1), "{}", stdout);
+    assert!(stdout.contains("REGRESSION: decode_rate 1.000000 < baseline 1.000001"), "{}", stdout);
+    assert!(!stdout.contains("REGRESSION: recall"), "{}", stdout);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn precision_and_recall() {
+    let dir = scratch("qrtest-manifest");
+    let corpus = corpus();
+    let manifest = dir.join("manifest");
+    fs::write(&manifest,
+              format!("# upright-v1 has one code, small-v2 another payload\n\
+                       {0}/upright-v1.pgm quirc\n\
+                       {0}/upright-v1.pgm \\x71uirc, but twice\n\
+                       {0}/small-v2.pgm tiny\\n\n",
+                      corpus.display()))
+        .unwrap();
+
+    let baseline = dir.join("baseline");
+    let out = qrtest(&[Path::new("-w"), &baseline, &manifest]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stdout));
+
+    // 3 expected, 2 decoded, 1 of them expected
+    assert_eq!(fs::read_to_string(&baseline).unwrap().lines().skip(1).collect::<Vec<_>>(),
+               ["decode_rate 1.000000", "precision 0.500000", "recall 0.333333"]);
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,98 @@
+//! Codes with a capstone hidden or out of frame have to be recovered from the other two, and flagged as such.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::ops::scene::{ScenePlacement, SceneOptions, Scene};
+use quirc_impl::ops::{ScanResult, Quirc};
+use quirc_impl::util::inferred_grid_size;
+use std::fs;
+
+
+const PAYLOAD: &[u8] = b"a longer payload to make a version 2 or 3 code";
+
+/// The results of `end()` and of `end_recover()` on the scene.
+fn scan_both(scene: &Scene) -> (Vec<ScanResult>, Vec<ScanResult>) {
+    let mut quirc = Quirc::new();
+    scene.load(&mut quirc);
+    quirc.end();
+    let plain = quirc.results().collect();
+
+    scene.load(&mut quirc);
+    quirc.end_recover();
+    for i in 0..quirc.count() {
+        assert_eq!(quirc.inferred(i), quirc.results().nth(i).unwrap().inferred);
+    }
+    assert!(!quirc.inferred(quirc.count()));
+    (plain, quirc.results().collect())
+}
+
+
+#[test]
+fn grid_sizes() {
+    // Version 1 and 4 with 4-pixel cells: capstones 28 pixels across, their centres 14 and 26 cells apart
+    assert_eq!(inferred_grid_size(56 * 56, 28 * 28, false), 21);
+    assert_eq!(inferred_grid_size(2 * 56 * 56, 28 * 28, true), 21);
+    assert_eq!(inferred_grid_size(104 * 104, 28 * 28, false), 33);
+    assert_eq!(inferred_grid_size(2 * 104 * 104, 28 * 28, true), 33);
+    assert_eq!(inferred_grid_size(100 * 100, 28 * 28, false), 33);
+    assert_eq!(inferred_grid_size(0, 28 * 28, false), 21);
+}
+
+#[test]
+fn hidden_capstone() {
+    // Each capstone in turn covered by a light patch, with its separator
+    for &(corner, dx, dy) in &[(0, 0, 0), (1, -32, 0), (3, 0, -32)] {
+        let mut scene = Scene::generate(&[ScenePlacement { payload: PAYLOAD.to_vec(), ..ScenePlacement::default() }], &SceneOptions::default()).unwrap();
+        let at = scene.truth[0].corners[corner];
+        for y in at.y + dy - 2..at.y + dy + 34 {
+            for x in at.x + dx - 2..at.x + dx + 34 {
+                scene.pixels[y as usize * scene.width + x as usize] = 200;
+            }
+        }
+
+        let (plain, recovered) = scan_both(&scene);
+        assert!(plain.iter().all(|r| r.data.is_err()), "corner {}", corner);
+        assert_eq!(recovered.len(), 1, "corner {}", corner);
+        assert_eq!(recovered[0].data.as_ref().map(|d| d.payload()), Ok(PAYLOAD), "corner {}", corner);
+        assert!(recovered[0].inferred);
+        assert!(scene.truth[0].matches_within(&recovered[0].corners, 4.0), "corner {}: {:?} {:?}", corner, recovered[0].corners, scene.truth[0].corners);
+    }
+}
+
+#[test]
+fn out_of_frame() {
+    // The top left capstone above the frame, diagonally opposite the two left, and the top right one partly off its right edge
+    for &(center, rotation) in &[((160.0, 60.0), 45.0), ((285.0, 160.0), 15.0)] {
+        let placements = [ScenePlacement { payload: PAYLOAD.to_vec(), center, rotation, ..ScenePlacement::default() }];
+        let scene = Scene::generate(&placements, &SceneOptions::default()).unwrap();
+
+        let (plain, recovered) = scan_both(&scene);
+        assert!(plain.is_empty());
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(recovered[0].data.as_ref().unwrap().payload(), PAYLOAD);
+        assert!(recovered[0].inferred);
+    }
+}
+
+#[test]
+fn corpus_unchanged() {
+    let mut quirc = Quirc::new();
+
+    for entry in fs::read_dir("tests/corpus").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map(|e| e != "pgm").unwrap_or(true) {
+            continue;
+        }
+        let data = fs::read(&path).unwrap();
+
+        quirc.load_netpbm(&data).unwrap();
+        quirc.end();
+        let plain: Vec<_> = quirc.results().collect();
+        assert!(plain.iter().all(|r| !r.inferred));
+
+        quirc.load_netpbm(&data).unwrap();
+        quirc.end_recover();
+        assert_eq!(quirc.results().collect::<Vec<_>>(), plain, "{}", path.display());
+    }
+}
@@ -0,0 +1,62 @@
+//! Reed-Solomon error correction has to restore blocks with up to half as many errors as they have parity bytes.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::util::{block_syndromes, correct_block};
+use quirc_impl::ops::version_db::QuircRsParams;
+
+
+/// The version 1-M block of "HELLO WORLD" in alphanumeric mode: 16 data bytes followed by 10 parity bytes.
+static HELLO_WORLD: [u8; 26] = [32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17, 196, 35, 39, 119, 235, 215, 231, 226, 93, 23];
+
+static PARAMS: QuircRsParams = QuircRsParams {
+    bs: 26,
+    dw: 16,
+    ns: 1,
+};
+
+
+fn corrected(errors: &[(usize, u8)]) -> Vec<u8> {
+    let mut block = HELLO_WORLD.to_vec();
+    for &(at, error) in errors {
+        block[at] ^= error;
+    }
+
+    let _ = correct_block(&mut block, &PARAMS);
+    block
+}
+
+fn has_errors(block: &[u8]) -> bool {
+    // One for every coefficient of the largest polynomial
+    let mut s = [0u8; 64];
+    block_syndromes(block, PARAMS.bs as usize, (PARAMS.bs - PARAMS.dw) as usize, &mut s)
+}
+
+
+#[test]
+fn valid_block() {
+    assert!(!has_errors(&HELLO_WORLD));
+    assert_eq!(corrected(&[]), &HELLO_WORLD[..]);
+}
+
+#[test]
+fn single_errors() {
+    for at in 0..HELLO_WORLD.len() {
+        for &error in &[0x01, 0x80, 0xFF, 0x5A] {
+            assert_eq!(corrected(&[(at, error)]), &HELLO_WORLD[..], "0x{:02X} at {}", error, at);
+        }
+    }
+}
+
+#[test]
+fn up_to_five_errors() {
+    for count in 2..6 {
+        for start in 0..HELLO_WORLD.len() {
+            let errors: Vec<(usize, u8)> = (0..count).map(|i| ((start + i * 5) % HELLO_WORLD.len(), (i as u8 + 1) * 0x33)).collect();
+            let block = corrected(&errors);
+            assert_eq!(block, &HELLO_WORLD[..], "{:?}", errors);
+            assert!(!has_errors(&block));
+        }
+    }
+}
@@ -0,0 +1,329 @@
+//! Rendered symbols have to scan back to the code they were rendered from.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::ops::encode::{EncodeOptions, encode};
+use quirc_impl::ops::render::RenderOptions;
+use quirc_impl::ops::{QuircCode, Quirc};
+use quirc_impl::util::{crc32, adler32, deflate};
+
+
+fn sample_code() -> QuircCode {
+    encode(b"https://github.com/dlbeer/quirc", &EncodeOptions::default()).unwrap()
+}
+
+/// Split a binary Netpbm image into its header fields and raster.
+fn netpbm(data: &[u8], fields: usize) -> (Vec<String>, &[u8]) {
+    let mut header = Vec::new();
+    let mut pos = 0;
+    while header.len() < fields {
+        while data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        let start = pos;
+        while !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        header.push(String::from_utf8_lossy(&data[start..pos]).into_owned());
+    }
+
+    (header, &data[pos + 1..])
+}
+
+fn scan(w: usize, h: usize, pixels: &[u8]) -> QuircCode {
+    let mut quirc = Quirc::new();
+    quirc.resize(w, h);
+    quirc.begin().copy_from_slice(pixels);
+    quirc.end();
+
+    assert_eq!(quirc.count(), 1);
+    quirc.extract(0).unwrap()
+}
+
+fn same_cells(a: &QuircCode, b: &QuircCode) -> bool {
+    let bytes = (a.size * a.size).div_ceil(8) as usize;
+    a.size == b.size && a.cell_bitmap[..bytes] == b.cell_bitmap[..bytes]
+}
+
+
+/// Just enough of RFC 1951 to read back `deflate()`: one final block with the fixed codes.
+fn inflate_fixed(data: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let mut bit = |n: u32| -> u32 {
+        let mut v = 0;
+        for i in 0..n {
+            v |= (((data[pos / 8] >> (pos % 8)) & 1) as u32) << i;
+            pos += 1;
+        }
+        v
+    };
+    let lengths = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+    let distances = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289,
+                     16385, 24577];
+
+    assert_eq!(bit(3), 0b011, "not a single fixed block");
+
+    let mut out: Vec<u8> = Vec::new();
+    loop {
+        // Huffman codes are MSB first
+        let mut code = 0;
+        let mut len = 0;
+        let symbol = loop {
+            code = (code << 1) | bit(1);
+            len += 1;
+            match (len, code) {
+                (7, 0x00..=0x17) => break code + 256,
+                (8, 0x30..=0xbf) => break code - 0x30,
+                (8, 0xc0..=0xc7) => break code - 0xc0 + 280,
+                (9, 0x190..=0x1ff) => break code - 0x190 + 144,
+                (9, _) => panic!("invalid code"),
+                _ => {}
+            }
+        };
+
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => break,
+            _ => {
+                let l = (symbol - 257) as usize;
+                let extra = if l < 8 || l == 28 { 0 } else { (l as u32 - 4) / 4 };
+                let length = lengths[l] + bit(extra) as usize;
+
+                let mut d = 0;
+                for _ in 0..5 {
+                    d = (d << 1) | bit(1);
+                }
+                let d = d as usize;
+                let extra = if d < 4 { 0 } else { (d as u32 - 2) / 2 };
+                let distance = distances[d] + bit(extra) as usize;
+
+                for _ in 0..length {
+                    let b = out[out.len() - distance];
+                    out.push(b);
+                }
+            }
+        }
+    }
+    out
+}
+
+
+#[test]
+fn pgm_scans_back() {
+    let code = sample_code();
+
+    for &(module_size, quiet_zone) in &[(4, 4), (3, 2), (7, 4)] {
+        let options = RenderOptions {
+            module_size,
+            quiet_zone,
+            foreground: [0x20, 0x40, 0x80],
+            ..RenderOptions::default()
+        };
+        let pgm = code.render_pgm(&options);
+        let (header, pixels) = netpbm(&pgm, 4);
+
+        let side = (code.size + quiet_zone * 2) * module_size;
+        assert_eq!(header, ["P5".to_string(), side.to_string(), side.to_string(), "255".to_string()]);
+        assert_eq!(pixels.len(), (side * side) as usize);
+        assert!(pixels.iter().all(|&p| p == 0x3e || p == 0xff));
+
+        let scanned = scan(side as usize, side as usize, pixels);
+        assert!(same_cells(&scanned, &code), "module size {}, quiet zone {}", module_size, quiet_zone);
+        assert_eq!(scanned.decode().unwrap().payload(), b"https://github.com/dlbeer/quirc");
+
+        // Re-rendering what was scanned gives a clean copy
+        assert_eq!(scanned.render_pgm(&options), pgm);
+    }
+}
+
+#[test]
+fn pbm_matches_pgm() {
+    let code = sample_code();
+    let options = RenderOptions {
+        module_size: 3,
+        ..RenderOptions::default()
+    };
+    let pgm = code.render_pgm(&options);
+    let (_, grey) = netpbm(&pgm, 4);
+    let pbm = code.render_pbm(&options);
+    let (header, bits) = netpbm(&pbm, 3);
+
+    let side: usize = header[1].parse().unwrap();
+    let row_bytes = side.div_ceil(8);
+    assert_eq!(bits.len(), row_bytes * side);
+    for y in 0..side {
+        for x in 0..side {
+            let black = (bits[y * row_bytes + x / 8] >> (7 - x % 8)) & 1 == 1;
+            assert_eq!(black, grey[y * side + x] == 0, "({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn inversion_swaps_colours() {
+    let code = sample_code();
+    let plain = code.render_pgm(&RenderOptions::default());
+    let inverted = code.render_pgm(&RenderOptions {
+        invert: true,
+        ..RenderOptions::default()
+    });
+    let (_, plain) = netpbm(&plain, 4);
+    let (_, inverted) = netpbm(&inverted, 4);
+
+    assert!(plain.iter().zip(inverted).all(|(&p, &i)| p == 255 - i));
+}
+
+#[test]
+fn checksums() {
+    assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    assert_eq!(adler32(b""), 1);
+}
+
+#[test]
+fn deflate_round_trips() {
+    let mut state = 1u32;
+    let noisy: Vec<u8> = (0..100000)
+        .map(|i| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            if i % 5 == 0 { state as u8 } else { (i / 700) as u8 }
+        })
+        .collect();
+
+    for data in &[&b""[..], b"a", b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", b"abcabcabcabd", &noisy] {
+        assert_eq!(&inflate_fixed(&deflate(data))[..], *data);
+    }
+    assert!(deflate(&vec![0; 100000]).len() < 1000);
+}
+
+#[test]
+fn png_matches_pgm() {
+    let code = sample_code();
+    let options = RenderOptions {
+        module_size: 5,
+        quiet_zone: 3,
+        foreground: [0x20, 0x40, 0x80],
+        background: [0xf0, 0xe0, 0xd0],
+        invert: false,
+    };
+    let png = code.render_png(&options);
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+
+    let mut chunks = Vec::new();
+    let mut pos = 8;
+    while pos < png.len() {
+        let len = u32::from_be_bytes([png[pos], png[pos + 1], png[pos + 2], png[pos + 3]]) as usize;
+        let kind = &png[pos + 4..pos + 8];
+        let body = &png[pos + 8..pos + 8 + len];
+        let crc = u32::from_be_bytes([png[pos + 8 + len], png[pos + 9 + len], png[pos + 10 + len], png[pos + 11 + len]]);
+        assert_eq!(crc32(&png[pos + 4..pos + 8 + len]), crc);
+
+        chunks.push((kind.to_vec(), body.to_vec()));
+        pos += 12 + len;
+    }
+    let kinds: Vec<&[u8]> = chunks.iter().map(|c| &c.0[..]).collect();
+    assert_eq!(kinds, [&b"IHDR"[..], b"PLTE", b"IDAT", b"IEND"]);
+
+    let side = (code.size + 6) * 5;
+    let mut ihdr = side.to_be_bytes().to_vec();
+    ihdr.extend_from_slice(&side.to_be_bytes());
+    ihdr.extend_from_slice(&[1, 3, 0, 0, 0]);
+    assert_eq!(chunks[0].1, ihdr);
+    assert_eq!(chunks[1].1, [0xf0, 0xe0, 0xd0, 0x20, 0x40, 0x80]);
+
+    let zlib = &chunks[2].1;
+    assert_eq!(((zlib[0] as u32) << 8 | zlib[1] as u32) % 31, 0);
+    let scanlines = inflate_fixed(&zlib[2..zlib.len() - 4]);
+    assert_eq!(zlib[zlib.len() - 4..], adler32(&scanlines).to_be_bytes());
+
+    let pgm = code.render_pgm(&RenderOptions {
+        foreground: [0, 0, 0],
+        background: [255, 255, 255],
+        ..options
+    });
+    let (_, grey) = netpbm(&pgm, 4);
+    let side = side as usize;
+    let row_bytes = side.div_ceil(8);
+    for y in 0..side {
+        assert_eq!(scanlines[y * (row_bytes + 1)], 0);
+        for x in 0..side {
+            let foreground = (scanlines[y * (row_bytes + 1) + 1 + x / 8] >> (7 - x % 8)) & 1 == 1;
+            assert_eq!(foreground, grey[y * side + x] == 0, "({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn svg_runs_cover_foreground() {
+    let code = sample_code();
+    let options = RenderOptions {
+        module_size: 10,
+        quiet_zone: 2,
+        ..RenderOptions::default()
+    };
+    let svg = code.render_svg(&options);
+    let cells = (code.size + 4) as usize;
+
+    assert!(svg.contains(&format!("width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\"", cells * 10, cells * 10, cells, cells)));
+    assert_eq!(svg.matches("<path").count(), 1);
+
+    let d_start = svg.find(" d=\"").unwrap() + 4;
+    let d = &svg[d_start..d_start + svg[d_start..].find('"').unwrap()];
+
+    let mut painted = vec![false; cells * cells];
+    for rect in d.split(' ').collect::<Vec<_>>().chunks(2).map(|pair| pair.join(" ")) {
+        let numbers: Vec<usize> = rect.split(|c: char| !c.is_ascii_digit()).filter(|n| !n.is_empty()).map(|n| n.parse().unwrap()).collect();
+        let (x, y, w) = (numbers[0], numbers[1], numbers[2]);
+        assert_eq!(rect, format!("M{} {}h{}v1h-{}z", x, y, w, w));
+
+        for i in x..x + w {
+            assert!(!painted[y * cells + i]);
+            painted[y * cells + i] = true;
+        }
+    }
+
+    for y in 0..cells {
+        for x in 0..cells {
+            let dark = x >= 2 && y >= 2 && x < cells - 2 && y < cells - 2 && {
+                let p = (y - 2) * code.size as usize + (x - 2);
+                (code.cell_bitmap[p >> 3] >> (p & 7)) & 1 == 1
+            };
+            assert_eq!(painted[y * cells + x], dark, "({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn text_uses_half_blocks() {
+    let code = sample_code();
+    let cells = (code.size + 8) as usize;
+
+    let text = code.render_text(&RenderOptions::default());
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), cells.div_ceil(2));
+    assert!(lines.iter().all(|l| l.chars().count() == cells));
+    assert!(lines[0].chars().all(|c| c == ' '));
+    // The top-left finder starts on an even row, four cells in
+    assert_eq!(lines[2].chars().skip(4).take(7).collect::<String>(), "█▀▀▀▀▀█");
+
+    let inverted = code.render_text(&RenderOptions {
+        invert: true,
+        ..RenderOptions::default()
+    });
+    // Rows past the bottom edge aren't drawn in either colour, so the last line of an odd-sized render differs
+    for (line, inverted_line) in lines.iter().zip(inverted.lines()).take(cells / 2) {
+        let expected: String = line.chars()
+            .map(|c| match c {
+                ' ' => '█',
+                '█' => ' ',
+                '▀' => '▄',
+                _ => '▀',
+            })
+            .collect();
+        assert_eq!(inverted_line, expected);
+    }
+}
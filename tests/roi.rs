@@ -0,0 +1,114 @@
+//! Scanning regions of interest has to find what's inside them, as `end()` does, and nothing else.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::ops::scene::{ScenePlacement, SceneOptions, Scene};
+use quirc_impl::ops::{QuircRect, ScanResult, Quirc};
+use quirc_impl::util::clip_rect;
+use std::fs;
+
+
+/// Two codes side by side in a 640×320 frame.
+fn two_codes() -> Scene {
+    let placements = [ScenePlacement { payload: b"left".to_vec(), center: (160.0, 160.0), rotation: 15.0, ..ScenePlacement::default() },
+                      ScenePlacement { payload: b"right".to_vec(), center: (480.0, 150.0), module_size: 5.0, ..ScenePlacement::default() }];
+    Scene::generate(&placements, &SceneOptions { width: 640, ..SceneOptions::default() }).unwrap()
+}
+
+fn payloads(quirc: &Quirc) -> Vec<Vec<u8>> {
+    let mut payloads: Vec<_> = quirc.results().filter_map(|r| r.data.ok()).map(|d| d.payload().to_vec()).collect();
+    payloads.sort();
+    payloads
+}
+
+fn results(quirc: &Quirc) -> Vec<ScanResult> {
+    let mut results: Vec<_> = quirc.results().collect();
+    results.sort_by_key(|r| r.corners[0]);
+    results
+}
+
+
+#[test]
+fn clipping() {
+    assert_eq!(clip_rect(&QuircRect { x: 10, y: 20, width: 30, height: 40 }, 100, 100), (10, 40, 20, 60));
+    assert_eq!(clip_rect(&QuircRect { x: 90, y: 80, width: 30, height: 40 }, 100, 100), (90, 100, 80, 100));
+    assert_eq!(clip_rect(&QuircRect { x: 200, y: 0, width: usize::MAX, height: 10 }, 100, 100), (100, 100, 0, 10));
+}
+
+#[test]
+fn whole_image_is_end() {
+    let mut quirc = Quirc::new();
+
+    for entry in fs::read_dir("tests/corpus").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map(|e| e != "pgm").unwrap_or(true) {
+            continue;
+        }
+        let data = fs::read(&path).unwrap();
+
+        quirc.load_netpbm(&data).unwrap();
+        quirc.end();
+        let native = results(&quirc);
+        let (w, h) = quirc.size();
+
+        quirc.load_netpbm(&data).unwrap();
+        quirc.end_roi(&QuircRect { x: 0, y: 0, width: w, height: h });
+        assert_eq!(results(&quirc), native, "{}", path.display());
+    }
+}
+
+#[test]
+fn one_region() {
+    let scene = two_codes();
+    let mut quirc = Quirc::new();
+
+    scene.load(&mut quirc);
+    quirc.end();
+    let native = results(&quirc);
+    assert_eq!(native.len(), 2);
+
+    // Corners stay in frame coordinates, the same as without the region
+    scene.load(&mut quirc);
+    quirc.end_roi(&QuircRect { x: 340, y: 20, width: 280, height: 260 });
+    assert_eq!(payloads(&quirc), [b"right".to_vec()]);
+    assert_eq!(results(&quirc)[..], native[1..]);
+
+    scene.load(&mut quirc);
+    quirc.end_roi(&QuircRect { x: 60, y: 60, width: 200, height: 200 });
+    assert_eq!(payloads(&quirc), [b"left".to_vec()]);
+
+    // Nothing is left over from the last image outside the region
+    scene.load(&mut quirc);
+    quirc.end();
+    quirc.begin();
+    quirc.end_roi(&QuircRect { x: 300, y: 0, width: 20, height: 320 });
+    assert_eq!(quirc.count(), 0);
+    assert!(quirc.pixels_netpbm().samples.iter().all(|&s| s == 255));
+}
+
+#[test]
+fn several_regions() {
+    let scene = two_codes();
+    let mut quirc = Quirc::new();
+
+    scene.load(&mut quirc);
+    quirc.end_rois(&[QuircRect { x: 60, y: 60, width: 200, height: 200 }, QuircRect { x: 340, y: 20, width: 400, height: 400 }]);
+    assert_eq!(payloads(&quirc), [b"left".to_vec(), b"right".to_vec()]);
+
+    // Overlapping halves of a code add up to the whole of it
+    scene.load(&mut quirc);
+    quirc.end_rois(&[QuircRect { x: 60, y: 60, width: 200, height: 110 }, QuircRect { x: 60, y: 150, width: 200, height: 110 }]);
+    assert_eq!(payloads(&quirc), [b"left".to_vec()]);
+
+    // A capstone cut off by the edge of a region isn't found
+    scene.load(&mut quirc);
+    quirc.end_roi(&QuircRect { x: 60, y: 60, width: 200, height: 80 });
+    assert_eq!(quirc.count(), 0);
+
+    scene.load(&mut quirc);
+    quirc.end_rois(&[]);
+    assert_eq!(quirc.count(), 0);
+    quirc.end_rois(&[QuircRect { x: 1000, y: 1000, width: 10, height: 10 }, QuircRect::default()]);
+    assert_eq!(quirc.count(), 0);
+}
@@ -0,0 +1,97 @@
+//! `scan()` and `Quirc::results()` have to agree with extracting and decoding by hand.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::ops::encode::{EncodeOptions, encode};
+use quirc_impl::ops::render::RenderOptions;
+use quirc_impl::ops::netpbm::NetpbmImage;
+use quirc_impl::ops::Quirc;
+use quirc_impl::scan;
+use std::fs;
+
+
+/// Two codes side by side, the second too damaged to decode.
+fn two_codes() -> (usize, usize, Vec<u8>) {
+    let options = RenderOptions::default();
+    let left = encode(b"left", &EncodeOptions::default()).unwrap();
+    let mut right = encode(b"right", &EncodeOptions::default()).unwrap();
+    // Invert both copies of the format information
+    let size = right.size as usize;
+    let mut flip = |x: usize, y: usize| right.cell_bitmap[(y * size + x) >> 3] ^= 1 << ((y * size + x) & 7);
+    for i in (0..9).filter(|&i| i != 6) {
+        flip(i, 8);
+        flip(8, i);
+    }
+    for i in 0..8 {
+        flip(size - 1 - i, 8);
+        flip(8, size - 1 - i);
+    }
+
+    let left = NetpbmImage::read(&left.render_pgm(&options)).unwrap();
+    let right = NetpbmImage::read(&right.render_pgm(&options)).unwrap();
+    let (w, h) = (left.width + right.width, left.height.max(right.height));
+    let mut pixels = vec![255; w * h];
+    for y in 0..left.height {
+        for x in 0..left.width {
+            pixels[y * w + x] = left.samples[y * left.width + x] as u8;
+        }
+    }
+    for y in 0..right.height {
+        for x in 0..right.width {
+            pixels[y * w + left.width + x] = right.samples[y * right.width + x] as u8;
+        }
+    }
+
+    (w, h, pixels)
+}
+
+
+#[test]
+fn matches_extract_and_decode() {
+    let (w, h, pixels) = two_codes();
+    let results = scan(&pixels, w, h);
+
+    let mut quirc = Quirc::new();
+    quirc.resize(w, h);
+    quirc.begin().copy_from_slice(&pixels);
+    quirc.end();
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(quirc.results().len(), 2);
+    assert_eq!(quirc.results().collect::<Vec<_>>(), results);
+    for (i, result) in results.iter().enumerate() {
+        let code = quirc.extract(i).unwrap();
+        assert_eq!(result.code, code);
+        assert_eq!(result.corners, code.corners);
+        assert_eq!(result.data, code.decode());
+    }
+
+    let mut payloads: Vec<_> = results.iter().filter_map(|r| r.data.as_ref().ok()).map(|d| d.payload().to_vec()).collect();
+    payloads.sort();
+    assert_eq!(payloads, [b"left".to_vec()]);
+    assert_eq!(results.iter().filter(|r| r.data.is_err()).count(), 1);
+}
+
+#[test]
+fn iterator_is_exact_and_reusable() {
+    let data = fs::read("tests/corpus/upright-v1.pgm").unwrap();
+    let mut quirc = Quirc::new();
+    assert_eq!(quirc.results().count(), 0);
+
+    quirc.load_netpbm(&data).unwrap();
+    quirc.end();
+    let mut results = quirc.results();
+    assert_eq!(results.size_hint(), (1, Some(1)));
+    assert!(results.next().unwrap().data.is_ok());
+    assert_eq!(results.size_hint(), (0, Some(0)));
+    assert!(results.next().is_none());
+
+    assert_eq!(quirc.results().len(), 1, "results() starts over");
+}
+
+#[test]
+fn nothing_to_find() {
+    assert!(scan(&[255; 100 * 80], 100, 80).is_empty());
+    assert!(scan(&[], 0, 0).is_empty());
+}
@@ -0,0 +1,193 @@
+//! Detection and decode rates on synthetic scenes, per kind of distortion.
+//!
+//! Each class generates `SCENES` scenes with parameters drawn from a fixed seed, so the rates are the same on every run;
+//! the thresholds sit a margin below the rates at the time of writing, to catch regressions rather than to benchmark.
+//! Run with `--nocapture` to see them.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::ops::scene::{ScenePlacement, SceneOptions, Scene};
+use quirc_impl::ops::Quirc;
+use quirc_impl::util::SplitMix64;
+
+
+const SCENES: usize = 20;
+
+
+fn payload(rng: &mut SplitMix64) -> Vec<u8> {
+    let len = 8 + (rng.next_u64() % 40) as usize;
+    (0..len).map(|_| b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789:/."[(rng.next_u64() % 65) as usize]).collect()
+}
+
+fn uniform(rng: &mut SplitMix64, from: f64, to: f64) -> f64 {
+    from + rng.next_f64() * (to - from)
+}
+
+/// How many of the scenes' symbols were found where they were drawn, and how many of those decoded to their payloads.
+fn measure<F: FnMut(&mut SplitMix64) -> (Vec<ScenePlacement>, SceneOptions)>(class: &str, seed: u64, mut scene: F) -> (f64, f64) {
+    let mut rng = SplitMix64::new(seed);
+    let mut quirc = Quirc::new();
+    let (mut total, mut detected, mut decoded) = (0, 0, 0);
+
+    for _ in 0..SCENES {
+        let (placements, options) = scene(&mut rng);
+        let scene = Scene::generate(&placements, &options).unwrap();
+        scene.load(&mut quirc);
+        quirc.end();
+
+        let codes: Vec<_> = (0..quirc.count()).filter_map(|i| quirc.extract(i).ok()).collect();
+        for truth in &scene.truth {
+            total += 1;
+            if let Some(code) = codes.iter().find(|c| truth.matches(c)) {
+                detected += 1;
+                if code.decode().map(|d| d.payload() == &truth.payload[..]).unwrap_or(false) {
+                    decoded += 1;
+                }
+            }
+        }
+    }
+
+    let rates = (detected as f64 / total as f64, decoded as f64 / total as f64);
+    println!("{:<13} detected {:>5.1}%, decoded {:>5.1}% of {}", class, rates.0 * 100.0, rates.1 * 100.0, total);
+    rates
+}
+
+fn check(rates: (f64, f64), min_detected: f64, min_decoded: f64) {
+    assert!(rates.0 >= min_detected, "detected {} < {}", rates.0, min_detected);
+    assert!(rates.1 >= min_decoded, "decoded {} < {}", rates.1, min_decoded);
+}
+
+/// One symbol in the middle of the default scene, changed by `f`.
+fn single<F: FnMut(&mut SplitMix64, &mut ScenePlacement, &mut SceneOptions)>(mut f: F) -> impl FnMut(&mut SplitMix64) -> (Vec<ScenePlacement>, SceneOptions) {
+    move |rng| {
+        let mut placement = ScenePlacement { payload: payload(rng), ..ScenePlacement::default() };
+        let mut options = SceneOptions { seed: rng.next_u64(), ..SceneOptions::default() };
+        f(rng, &mut placement, &mut options);
+        (vec![placement], options)
+    }
+}
+
+
+#[test]
+fn truth_matches_drawing() {
+    let placement = ScenePlacement { payload: b"truth".to_vec(), ..ScenePlacement::default() };
+    let scene = Scene::generate(&[placement], &SceneOptions::default()).unwrap();
+    let truth = &scene.truth[0];
+    assert_eq!(truth.version, 1);
+
+    // 21 cells, 4 pixels each, centred on (160, 160)
+    let corners: Vec<_> = truth.corners.iter().map(|p| (p.x, p.y)).collect();
+    assert_eq!(corners, [(118, 118), (202, 118), (202, 202), (118, 202)]);
+
+    // The finder pattern's outer ring is dark, the quiet zone light
+    assert_eq!(scene.pixels[120 * 320 + 120], 0x00);
+    assert_eq!(scene.pixels[120 * 320 + 110], 0xff);
+
+    let mut quirc = Quirc::new();
+    scene.load(&mut quirc);
+    quirc.end();
+    assert_eq!(quirc.count(), 1);
+    let code = quirc.extract(0).unwrap();
+    assert!(truth.matches(&code));
+    assert_eq!(code.decode().unwrap().payload(), b"truth");
+}
+
+#[test]
+fn generation_is_deterministic() {
+    let placements = [ScenePlacement { payload: b"again".to_vec(), rotation: 30.0, ..ScenePlacement::default() }];
+    let options = SceneOptions {
+        occlusion: 0.05,
+        blur: 1.0,
+        illumination: 0.5,
+        noise: 20.0,
+        jpeg_quality: 30,
+        seed: 7,
+        ..SceneOptions::default()
+    };
+
+    let first = Scene::generate(&placements, &options).unwrap();
+    assert_eq!(first, Scene::generate(&placements, &options).unwrap());
+    assert!(first != Scene::generate(&placements, &SceneOptions { seed: 8, ..options }).unwrap());
+}
+
+#[test]
+fn clean() {
+    check(measure("clean", 1, single(|_, _, _| ())), 1.0, 1.0);
+}
+
+#[test]
+fn rotation() {
+    check(measure("rotation", 2, single(|rng, p, _| p.rotation = uniform(rng, 0.0, 360.0))), 0.65, 0.6);
+}
+
+#[test]
+fn perspective() {
+    check(measure("perspective",
+                  3,
+                  single(|rng, p, _| {
+                      p.rotation = uniform(rng, 0.0, 360.0);
+                      p.tilt = (uniform(rng, -0.3, 0.3), uniform(rng, -0.3, 0.3));
+                  })),
+          0.9,
+          0.9);
+}
+
+#[test]
+fn scale() {
+    check(measure("scale", 4, single(|rng, p, _| p.module_size = uniform(rng, 1.5, 8.0))), 0.8, 0.75);
+}
+
+#[test]
+fn blur() {
+    check(measure("blur", 5, single(|rng, _, o| o.blur = uniform(rng, 0.5, 2.0))), 0.5, 0.25);
+}
+
+#[test]
+fn noise() {
+    check(measure("noise", 6, single(|rng, _, o| o.noise = uniform(rng, 10.0, 50.0))), 0.8, 0.7);
+}
+
+#[test]
+fn illumination() {
+    check(measure("illumination", 7, single(|rng, _, o| o.illumination = uniform(rng, 0.3, 0.8))), 0.9, 0.9);
+}
+
+#[test]
+fn jpeg() {
+    check(measure("jpeg", 8, single(|rng, _, o| o.jpeg_quality = uniform(rng, 10.0, 50.0) as u8)), 0.9, 0.9);
+}
+
+#[test]
+fn occlusion() {
+    check(measure("occlusion", 9, single(|rng, _, o| o.occlusion = uniform(rng, 0.02, 0.1))), 0.4, 0.4);
+}
+
+#[test]
+fn several_codes() {
+    check(measure("several codes",
+                  10,
+                  |rng| {
+                      let placements = [(120.0, 120.0), (360.0, 120.0), (120.0, 360.0), (360.0, 360.0)]
+                          .iter()
+                          .map(|&center| {
+                              ScenePlacement {
+                                  payload: payload(rng),
+                                  center,
+                                  module_size: 3.0,
+                                  rotation: uniform(rng, -20.0, 20.0),
+                                  ..ScenePlacement::default()
+                              }
+                          })
+                          .collect();
+                      (placements,
+                       SceneOptions {
+                           width: 480,
+                           height: 480,
+                           seed: rng.next_u64(),
+                           ..SceneOptions::default()
+                       })
+                  }),
+          0.5,
+          0.4);
+}
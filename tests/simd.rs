@@ -0,0 +1,121 @@
+//! Every vectorised path has to binarise exactly like the scalar thresholding, bit for bit.
+//!
+//! Only the levels the CPU running the tests has are checked.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::util::{threshold_rows_with, threshold_rows, binarise_row, widen_pixels, threshold_s, SplitMix64};
+use quirc_impl::ops::SimdLevel;
+
+
+const LEVELS: [SimdLevel; 4] = [SimdLevel::Scalar, SimdLevel::Sse2, SimdLevel::Avx2, SimdLevel::Neon];
+
+/// Noise, a gradient with a little noise on it, and blocks of flat grey, all edges against the averages.
+fn images(w: usize, h: usize, rng: &mut SplitMix64) -> Vec<Vec<u8>> {
+    vec![(0..w * h).map(|_| rng.next_u64() as u8).collect(),
+         (0..w * h).map(|i| ((i % w * 255 / w.max(1)) as u64 + rng.next_u64() % 7).min(255) as u8).collect(),
+         (0..w * h).map(|i| [0x60, 0xa0][(i % w / 5 + i / w / 3) % 2]).collect()]
+}
+
+
+/// Threshold columns `left` up to `right` from row `top` with every level, and compare to `threshold_rows()`.
+fn check(image: &[u8], w: usize, h: usize, left: usize, right: usize, top: usize) {
+    let mut expected: Vec<u16> = image.iter().map(|&p| p as u16).collect();
+    let mut row_average = vec![0u64; w];
+    let mut expected_avg = (0, 0);
+    threshold_rows(&mut expected[top * w..], &mut row_average, w, left, right, top, &mut expected_avg);
+
+    for &level in LEVELS.iter().filter(|l| l.available()) {
+        let mut pixels = vec![0; w * h];
+        widen_pixels(level, image, &mut pixels);
+        let mut scratch = vec![0u64; w];
+        let mut avg = (0, 0);
+        threshold_rows_with(level, &mut pixels[top * w..], &mut scratch, w, left, right, top, &mut avg);
+
+        assert!(pixels == expected, "{:?}, {}x{}, columns {}..{} from row {}", level, w, h, left, right, top);
+        assert_eq!((avg, &scratch), (expected_avg, &row_average), "{:?}, {}x{}", level, w, h);
+    }
+}
+
+
+#[test]
+fn levels() {
+    assert!(SimdLevel::Scalar.available());
+    assert!(SimdLevel::detect().available());
+    assert!(!(SimdLevel::Sse2.available() || SimdLevel::Avx2.available()) || !SimdLevel::Neon.available());
+}
+
+#[test]
+fn widen() {
+    let mut rng = SplitMix64::new(1);
+    for len in (0..70).chain(Some(1000)) {
+        let image: Vec<u8> = (0..len).map(|_| rng.next_u64() as u8).collect();
+        let expected: Vec<u16> = image.iter().map(|&p| p as u16).collect();
+
+        for &level in LEVELS.iter().filter(|l| l.available()) {
+            let mut pixels = vec![0xdead; len + 3];
+            widen_pixels(level, &image, &mut pixels);
+            assert_eq!(pixels[..len], expected[..], "{:?}, {} pixels", level, len);
+            assert_eq!(pixels[len..], [0xdead; 3], "{:?}, {} pixels", level, len);
+        }
+    }
+}
+
+#[test]
+fn binarise_boundaries() {
+    let mut rng = SplitMix64::new(2);
+    // Including widths too wide for the vector multiplications, which fall back to scalar
+    for &w in &[1, 8, 17, 640, 4000, 1 << 20, 200 << 20] {
+        let d = (200 * threshold_s(w)) as u64;
+
+        // Averages right at, and either side of, where each pixel turns black
+        let mut row = Vec::new();
+        let mut row_average = Vec::new();
+        for _ in 0..203 {
+            let p = rng.next_u64() % 256;
+            let edge = ((p + 1) * d).div_ceil(95);
+            for &a in &[edge - 1, edge, edge + 1, rng.next_u64() % (510 * threshold_s(w) as u64 + 1)] {
+                row.push(p as u16);
+                row_average.push(a);
+            }
+        }
+        let expected: Vec<u16> = row.iter().zip(&row_average).map(|(&p, &a)| ((p as u64) < a * 95 / d) as u16).collect();
+
+        for &level in LEVELS.iter().filter(|l| l.available()) {
+            let mut binarised = row.clone();
+            binarise_row(level, &mut binarised, &row_average, w);
+            assert_eq!(binarised, expected, "{:?}, {} wide", level, w);
+        }
+    }
+}
+
+#[test]
+fn bit_exact() {
+    let mut rng = SplitMix64::new(3);
+    for &(w, h) in &[(1, 5), (7, 9), (16, 16), (31, 12), (333, 27), (1280, 6), (4099, 3)] {
+        for image in images(w, h, &mut rng) {
+            // The whole image, and a window carrying the averages in from its top row
+            for &(left, right, top) in &[(0, w, 0), (w / 5, w - w / 3, h / 2)] {
+                check(&image, w, h, left, right, top);
+            }
+        }
+    }
+}
+
+#[test]
+fn reciprocal_limit() {
+    // Either side of where the averages get too big to scale down with a reciprocal
+    let mut rng = SplitMix64::new(4);
+    for &w in &[(8 << 18) - 8, 8 << 18] {
+        let image: Vec<u8> = (0..w * 2).map(|_| rng.next_u64() as u8).collect();
+        check(&image, w, 2, 0, w, 0);
+    }
+}
+
+#[test]
+#[should_panic]
+fn short_row_average() {
+    let mut rows = vec![128u16; 64];
+    threshold_rows_with(SimdLevel::detect(), &mut rows, &mut [0; 63], 64, 0, 64, 0, &mut (0, 0));
+}
@@ -0,0 +1,133 @@
+//! Scanning in tiles has to find every code once, whole or straddling tiles, with corners in image coordinates.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::ops::scene::{ScenePlacement, SceneOptions, Scene};
+use quirc_impl::ops::tiled::TileOptions;
+use quirc_impl::ops::{QuircRect, ScanResult};
+use quirc_impl::util::tile_starts;
+use quirc_impl::{scan_tiled, scan};
+use std::slice;
+
+
+/// 192×192 tiles overlapping by 96 pixels, and windows of up to 600×600.
+const SMALL_TILES: TileOptions = TileOptions {
+    tile_width: 192,
+    tile_height: 192,
+    overlap: 96,
+    max_window: 600 * 600,
+};
+
+
+/// Codes across the 192-pixel tiles of a 640×480 frame: two bigger than a tile, one whole in two tiles, and one in a corner.
+fn tiled_scene() -> Scene {
+    let long = |s: &str| format!("{} straddles the tiles", s).into_bytes();
+    let placements = [ScenePlacement { payload: long("first"), center: (300.0, 170.0), module_size: 8.0, ..ScenePlacement::default() },
+                      ScenePlacement { payload: long("second"), center: (500.0, 370.0), module_size: 6.0, rotation: 30.0, ..ScenePlacement::default() },
+                      ScenePlacement { payload: b"overlap".to_vec(), center: (142.0, 370.0), ..ScenePlacement::default() },
+                      ScenePlacement { payload: b"corner".to_vec(), center: (580.0, 60.0), module_size: 3.0, ..ScenePlacement::default() }];
+    Scene::generate(&placements, &SceneOptions { width: 640, height: 480, ..SceneOptions::default() }).unwrap()
+}
+
+/// Scan the scene in tiles, reading from its pixels, and return the results and the largest buffer read.
+fn scan_scene(scene: &Scene, options: &TileOptions) -> (Vec<ScanResult>, usize) {
+    let mut largest = 0;
+    let results = scan_tiled(scene.width, scene.height, options, |rect: &QuircRect, buf: &mut [u8]| {
+        assert_eq!(buf.len(), rect.width * rect.height);
+        assert!(rect.x + rect.width <= scene.width && rect.y + rect.height <= scene.height, "{:?}", rect);
+        largest = largest.max(buf.len());
+        for (y, row) in buf.chunks_mut(rect.width).enumerate() {
+            let start = (rect.y + y) * scene.width + rect.x;
+            row.copy_from_slice(&scene.pixels[start..start + rect.width]);
+        }
+    });
+    (results, largest)
+}
+
+fn payload(result: &ScanResult) -> Option<Vec<u8>> {
+    result.data.as_ref().ok().map(|d| d.payload().to_vec())
+}
+
+
+#[test]
+fn starts() {
+    assert_eq!(tile_starts(100, 160, 32), [0]);
+    assert_eq!(tile_starts(160, 160, 32), [0]);
+    assert_eq!(tile_starts(161, 160, 32), [0, 1]);
+    assert_eq!(tile_starts(480, 160, 32), [0, 128, 256, 320]);
+    assert_eq!(tile_starts(640, 160, 32), [0, 128, 256, 384, 480]);
+}
+
+#[test]
+fn one_tile_is_scan() {
+    let scene = tiled_scene();
+    let options = TileOptions { tile_width: 640, tile_height: 480, ..TileOptions::default() };
+    let (results, largest) = scan_scene(&scene, &options);
+    assert_eq!(results, scan(&scene.pixels, scene.width, scene.height));
+    assert_eq!(largest, 640 * 480);
+}
+
+#[test]
+fn every_code_once() {
+    let scene = tiled_scene();
+    let (results, largest) = scan_scene(&scene, &SMALL_TILES);
+    assert!(largest <= SMALL_TILES.max_window);
+
+    assert_eq!(results.len(), scene.truth.len(), "{:?}", results.iter().map(payload).collect::<Vec<_>>());
+    for truth in &scene.truth {
+        let found: Vec<_> = results.iter().filter(|r| payload(r).as_ref() == Some(&truth.payload)).collect();
+        assert_eq!(found.len(), 1, "{}", String::from_utf8_lossy(&truth.payload));
+        assert!(truth.matches_within(&found[0].corners, 3.0), "{:?} {:?}", found[0].corners, truth.corners);
+        assert_eq!(found[0].code.corners, found[0].corners);
+    }
+}
+
+#[test]
+fn windows_bounded() {
+    // Without room for a window, the codes straddling tiles aren't found, and nothing bigger than a tile is read
+    let scene = tiled_scene();
+    let (results, largest) = scan_scene(&scene, &TileOptions { max_window: 0, ..SMALL_TILES });
+    assert_eq!(largest, 192 * 192);
+
+    let mut payloads: Vec<_> = results.iter().filter_map(payload).collect();
+    payloads.sort();
+    assert_eq!(payloads, [b"corner".to_vec(), b"overlap".to_vec()]);
+}
+
+#[test]
+#[should_panic]
+fn overlap_too_big() {
+    let scene = tiled_scene();
+    scan_scene(&scene, &TileOptions { overlap: 192, ..SMALL_TILES });
+}
+
+/// Enough codes across enough tiles that their capstones are only ever compared with their neighbours':
+/// each that can be read on its own has to be read among the others.
+#[test]
+fn many_codes() {
+    let options = SceneOptions { width: 1060, height: 720, seed: 9, ..SceneOptions::default() };
+    let placements: Vec<_> = (0..24)
+        .map(|i| {
+            ScenePlacement {
+                payload: format!("code {} of many", i).into_bytes(),
+                center: (100.0 + 170.0 * (i % 6) as f64, 100.0 + 170.0 * (i / 6) as f64),
+                module_size: 3.7 + 0.4 * (i % 3) as f64,
+                rotation: (7 * i % 30) as f64,
+                ..ScenePlacement::default()
+            }
+        })
+        .collect();
+    let scene = Scene::generate(&placements, &options).unwrap();
+
+    let (results, _) = scan_scene(&scene, &TileOptions { tile_width: 320, tile_height: 240, overlap: 140, ..SMALL_TILES });
+    let found: Vec<_> = results.iter().filter_map(payload).collect();
+    assert!(found.len() >= 20, "{} found", found.len());
+
+    for placement in &placements {
+        let alone = Scene::generate(slice::from_ref(placement), &options).unwrap();
+        if scan(&alone.pixels, alone.width, alone.height).iter().any(|r| r.data.is_ok()) {
+            assert_eq!(found.iter().filter(|&p| *p == placement.payload).count(), 1, "{}", String::from_utf8_lossy(&placement.payload));
+        }
+    }
+}
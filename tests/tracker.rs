@@ -0,0 +1,107 @@
+//! Codes moving through a sequence of frames have to keep their IDs, and be followed without running the full detector.
+
+
+extern crate quirc_impl;
+
+use quirc_impl::ops::scene::{ScenePlacement, SceneOptions, Scene};
+use quirc_impl::ops::tracker::{TrackerOptions, TrackedCode, QuircTracker};
+
+
+fn frame(placements: &[ScenePlacement], seed: u64) -> Scene {
+    Scene::generate(placements, &SceneOptions { noise: 4.0, seed, ..SceneOptions::default() }).unwrap()
+}
+
+fn track(tracker: &mut QuircTracker, scene: &Scene) -> Vec<TrackedCode> {
+    let mut codes = tracker.track(&scene.pixels, scene.width, scene.height);
+    codes.sort_by_key(|c| c.id);
+    codes
+}
+
+/// A code drifting and turning a little every frame.
+fn drifting(i: usize) -> ScenePlacement {
+    ScenePlacement {
+        payload: b"drifting".to_vec(),
+        center: (110.0 + 4.0 * i as f64, 130.0 + 2.5 * i as f64),
+        rotation: 0.7 * i as f64,
+        ..ScenePlacement::default()
+    }
+}
+
+
+#[test]
+fn follows_moving_code() {
+    let mut tracker = QuircTracker::new(TrackerOptions { detect_interval: 10, ..TrackerOptions::default() });
+
+    for i in 0..25 {
+        let scene = frame(&[drifting(i)], i as u64);
+        let codes = track(&mut tracker, &scene);
+
+        assert_eq!(codes.len(), 1, "frame {}", i);
+        let code = &codes[0];
+        assert_eq!((code.id, code.age), (0, i as u64), "frame {}", i);
+        assert_eq!(code.detected, i % 10 == 0, "frame {}: the detector only runs every 10 frames", i);
+        assert!(scene.truth[0].matches(&code.result.code), "frame {}: {:?} vs {:?}", i, code.result.corners, scene.truth[0].corners);
+        assert_eq!(code.result.data.as_ref().map(|d| d.payload()), Ok(&b"drifting"[..]), "frame {}", i);
+
+        // The tracker extracts the same code again
+        assert_eq!(tracker.extract(0).unwrap(), code.result.code);
+        assert!(tracker.extract(1).is_err());
+    }
+}
+
+#[test]
+fn several_codes() {
+    let mut tracker = QuircTracker::new(TrackerOptions::default());
+    let placement = |i: usize| {
+        [ScenePlacement { payload: b"left".to_vec(), center: (80.0 + i as f64, 100.0), module_size: 3.0, ..ScenePlacement::default() },
+         ScenePlacement { payload: b"right".to_vec(), center: (230.0, 200.0 - 2.0 * i as f64), module_size: 3.0, ..ScenePlacement::default() }]
+    };
+
+    let mut ids = Vec::new();
+    for i in 0..8 {
+        let codes = track(&mut tracker, &frame(&placement(i), i as u64));
+        assert_eq!(codes.len(), 2);
+
+        let mut by_payload: Vec<_> = codes.iter().map(|c| (c.result.data.as_ref().unwrap().payload().to_vec(), c.id)).collect();
+        by_payload.sort();
+        if i == 0 {
+            ids = by_payload.clone();
+            assert!(ids[0].1 != ids[1].1);
+        }
+        assert_eq!(by_payload, ids, "frame {}", i);
+    }
+}
+
+#[test]
+fn lost_and_found() {
+    let mut tracker = QuircTracker::new(TrackerOptions::default());
+    let blank = Scene::generate(&[], &SceneOptions::default()).unwrap();
+
+    assert_eq!(track(&mut tracker, &frame(&[drifting(0)], 0))[0].id, 0);
+    assert_eq!(track(&mut tracker, &frame(&[drifting(1)], 1))[0].id, 0);
+
+    // Jumping further than the search radius, but not off the last position, is picked up by the detector straight away
+    let jumped = [ScenePlacement { center: (140.0, 150.0), ..drifting(1) }];
+    let codes = track(&mut tracker, &frame(&jumped, 2));
+    assert_eq!(codes.len(), 1);
+    assert!(codes[0].detected);
+    assert_eq!(codes[0].id, 0);
+
+    // Once gone, a code comes back as a new one
+    assert!(track(&mut tracker, &blank).is_empty());
+    let codes = track(&mut tracker, &frame(&jumped, 3));
+    assert_eq!((codes[0].id, codes[0].age), (1, 0));
+
+    // A code appearing next to a tracked one is found when the detector next runs
+    let mut tracker = QuircTracker::new(TrackerOptions { detect_interval: 4, ..TrackerOptions::default() });
+    let first = ScenePlacement { payload: b"first".to_vec(), center: (80.0, 80.0), module_size: 3.0, ..ScenePlacement::default() };
+    let second = ScenePlacement { payload: b"second".to_vec(), center: (230.0, 230.0), module_size: 3.0, ..ScenePlacement::default() };
+    let (alone, both) = ([first.clone()], [first.clone(), second.clone()]);
+    let counts: Vec<_> = (0..6).map(|i| track(&mut tracker, &frame(if i < 2 { &alone[..] } else { &both[..] }, i)).len()).collect();
+    assert_eq!(counts, [1, 1, 1, 1, 2, 2]);
+
+    // As are all of them after a reset, with new IDs
+    tracker.reset();
+    let codes = track(&mut tracker, &frame(&[first, second], 9));
+    assert_eq!(codes.iter().map(|c| (c.id, c.detected)).collect::<Vec<_>>(), [(2, true), (3, true)]);
+}